@@ -0,0 +1,134 @@
+//! The `info` command.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use clap::Args;
+use openslide_rs::{OpenSlide, OpenSlideError, Size, SlideProperties};
+use serde::Serialize;
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// The slide file
+    slide: PathBuf,
+    /// Print the description as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+/// The description of a slide.
+#[derive(Serialize)]
+struct SlideInfo {
+    path: PathBuf,
+    dimensions: Size,
+    #[serde(flatten)]
+    properties: SlideProperties,
+    associated_images: Vec<AssociatedImage>,
+}
+
+#[derive(Serialize)]
+struct AssociatedImage {
+    name: String,
+    dimensions: Size,
+}
+
+pub fn run(args: &InfoArgs) -> Result<(), OpenSlideError> {
+    let info = describe(args.slide.clone())?;
+    if args.json {
+        let json = serde_json::to_string_pretty(&info)
+            .map_err(|e| OpenSlideError::InternalError(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        print!("{}", render(&info));
+    }
+    Ok(())
+}
+
+fn describe(path: PathBuf) -> Result<SlideInfo, OpenSlideError> {
+    let slide = OpenSlide::open(&path)?;
+    let associated_images = slide
+        .associated_image_names()?
+        .into_iter()
+        .map(|name| {
+            let dimensions = slide
+                .associated_image_dimensions(&name)?
+                .unwrap_or(Size { w: 0, h: 0 });
+            Ok(AssociatedImage { name, dimensions })
+        })
+        .collect::<Result<_, OpenSlideError>>()?;
+    Ok(SlideInfo {
+        dimensions: slide.dimensions()?,
+        properties: slide.slide_properties()?,
+        associated_images,
+        path,
+    })
+}
+
+/// Render a description as aligned `key: value` lines and a level table.
+fn render(info: &SlideInfo) -> String {
+    let properties = &info.properties;
+    let unknown = || "unknown".to_string();
+    let mut text = String::new();
+    let mut line = |key: &str, value: String| {
+        writeln!(text, "{:<18}{}", format!("{}:", key), value).unwrap();
+    };
+
+    line("path", info.path.display().to_string());
+    line("vendor", properties.vendor.clone().unwrap_or_else(unknown));
+    line(
+        "quickhash",
+        properties.quickhash.clone().unwrap_or_else(unknown),
+    );
+    line("dimensions", size(info.dimensions));
+    line(
+        "mpp",
+        properties
+            .mpp
+            .map_or_else(unknown, |(x, y)| format!("{} x {}", x, y)),
+    );
+    line(
+        "objective power",
+        properties
+            .objective_power
+            .map_or_else(unknown, |power| power.to_string()),
+    );
+    line(
+        "bounds",
+        properties.bounds.map_or_else(
+            || "none".to_string(),
+            |(address, dimensions)| format!("{} {}", address, size(dimensions)),
+        ),
+    );
+
+    writeln!(text, "levels:").unwrap();
+    writeln!(
+        text,
+        "  {:<7}{:<16}{:<12}tile size",
+        "level", "dimensions", "downsample"
+    )
+    .unwrap();
+    for (index, level) in properties.levels.iter().enumerate() {
+        writeln!(
+            text,
+            "  {:<7}{:<16}{:<12.3}{}",
+            index,
+            size(level.dimensions),
+            level.downsample,
+            level.tile_size.map_or_else(|| "-".to_string(), size)
+        )
+        .unwrap();
+    }
+
+    writeln!(text, "associated images:").unwrap();
+    if info.associated_images.is_empty() {
+        writeln!(text, "  none").unwrap();
+    }
+    for image in &info.associated_images {
+        writeln!(text, "  {}: {}", image.name, size(image.dimensions)).unwrap();
+    }
+    text
+}
+
+fn size(size: Size) -> String {
+    format!("{} x {}", size.w, size.h)
+}
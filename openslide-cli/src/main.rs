@@ -0,0 +1,46 @@
+//! The `openslide-rs` command line tool, to inspect whole slide images without
+//! writing a program.
+
+use std::process;
+
+use clap::{Parser, Subcommand};
+use openslide_rs::OpenSlideError;
+
+mod info;
+mod output;
+mod region;
+mod thumbnail;
+
+/// Inspect whole slide images.
+#[derive(Parser)]
+#[clap(name = "openslide-rs", version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the vendor, dimensions, levels, resolution and associated images of a
+    /// slide
+    Info(info::InfoArgs),
+    /// Extract a region of a slide, at a level or at a resolution
+    Region(region::RegionArgs),
+    /// Write a thumbnail of a slide, or of every slide of a directory
+    Thumbnail(thumbnail::ThumbnailArgs),
+}
+
+fn run(cli: Cli) -> Result<(), OpenSlideError> {
+    match cli.command {
+        Command::Info(args) => info::run(&args),
+        Command::Region(args) => region::run(&args),
+        Command::Thumbnail(args) => thumbnail::run(&args),
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Cli::parse()) {
+        eprintln!("openslide-rs: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,23 @@
+//! Writing images to files.
+
+use std::path::Path;
+
+use image::buffer::ConvertBuffer;
+use image::{RgbImage, RgbaImage};
+use openslide_rs::OpenSlideError;
+
+/// Save an image in the format given by the extension of `path`, dropping the
+/// alpha channel for formats without transparency.
+pub fn save(image: &RgbaImage, path: &Path) -> Result<(), OpenSlideError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => {
+            let rgb: RgbImage = image.convert();
+            rgb.save(path)?;
+        }
+        _ => image.save(path)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! The `region` command.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use openslide_rs::jobs::{run_jobs, JobOptions, RegionJob};
+use openslide_rs::{OpenSlideError, Parallelism};
+
+#[derive(Args)]
+pub struct RegionArgs {
+    /// The slide file
+    slide: PathBuf,
+    /// The level 0 abscissa of the top left corner
+    #[clap(long)]
+    x: u32,
+    /// The level 0 ordinate of the top left corner
+    #[clap(long)]
+    y: u32,
+    /// The level to read at
+    #[clap(long, default_value = "0")]
+    level: u32,
+    /// The resolution to read at, in micrometers per pixel, instead of a level: the
+    /// region is read at the best level and resized
+    #[clap(long, conflicts_with = "level")]
+    mpp: Option<f32>,
+    /// The width of the output image, in pixels
+    #[clap(long)]
+    width: u32,
+    /// The height of the output image, in pixels
+    #[clap(long)]
+    height: u32,
+    /// The output image; its extension, png, jpg, jpeg or webp, gives its format
+    #[clap(long)]
+    out: PathBuf,
+    /// The quality of JPEG and WebP outputs, from 1 (worst) to 100 (best)
+    #[clap(long, default_value = "90")]
+    quality: u8,
+}
+
+pub fn run(args: &RegionArgs) -> Result<(), OpenSlideError> {
+    let job = RegionJob {
+        slide: args.slide.clone(),
+        level: match args.mpp {
+            Some(_) => None,
+            None => Some(args.level),
+        },
+        mpp: args.mpp,
+        x: args.x,
+        y: args.y,
+        w: args.width,
+        h: args.height,
+        output: args.out.clone(),
+    };
+    let options = JobOptions {
+        parallelism: Parallelism::Sequential,
+        retries: 0,
+        quality: args.quality,
+    };
+
+    let report = run_jobs(&[job], options, |_, _| {})?;
+    if let Some(error) = report.failed().find_map(|outcome| outcome.error.clone()) {
+        return Err(OpenSlideError::InternalError(error));
+    }
+    println!("{}", args.out.display());
+    Ok(())
+}
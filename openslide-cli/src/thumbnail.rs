@@ -0,0 +1,107 @@
+//! The `thumbnail` command.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{ArgEnum, Args};
+use openslide_rs::{OpenSlide, OpenSlideError, ResizeFilter};
+
+use crate::output;
+
+#[derive(Args)]
+pub struct ThumbnailArgs {
+    /// The slide file, or a directory of slides
+    slide: PathBuf,
+    /// The largest width and height of the thumbnails
+    #[clap(long, default_value = "1024")]
+    size: u32,
+    /// The resampling filter
+    #[clap(long, arg_enum, default_value = "lanczos3")]
+    filter: Filter,
+    /// The output image, or the output directory when given a directory of slides
+    #[clap(long)]
+    out: PathBuf,
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+enum Filter {
+    Nearest,
+    Triangle,
+    Lanczos3,
+}
+
+impl From<Filter> for ResizeFilter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => ResizeFilter::Nearest,
+            Filter::Triangle => ResizeFilter::Triangle,
+            Filter::Lanczos3 => ResizeFilter::Lanczos3,
+        }
+    }
+}
+
+pub fn run(args: &ThumbnailArgs) -> Result<(), OpenSlideError> {
+    if args.size == 0 {
+        return Err(OpenSlideError::InvalidArgument(
+            "Thumbnail size must be positive".to_string(),
+        ));
+    }
+    if args.slide.is_dir() {
+        batch(args)
+    } else {
+        thumbnail(&args.slide, &args.out, args)
+    }
+}
+
+/// Write a PNG thumbnail of every slide of a directory to the output directory,
+/// named after the slide, such as for a quality control gallery.
+///
+/// Slides failing are reported and skipped, and fail the command once the others
+/// are written.
+fn batch(args: &ThumbnailArgs) -> Result<(), OpenSlideError> {
+    let mut slides = Vec::new();
+    for entry in fs::read_dir(&args.slide)? {
+        let path = entry?.path();
+        if path.is_file() && OpenSlide::detect_vendor(&path).is_ok() {
+            slides.push(path);
+        }
+    }
+    slides.sort();
+    fs::create_dir_all(&args.out)?;
+
+    let mut failed = 0;
+    for slide in &slides {
+        let stem = slide.file_stem().unwrap_or_default().to_string_lossy();
+        let out = args.out.join(format!("{}.png", stem));
+        if let Err(error) = thumbnail(slide, &out, args) {
+            eprintln!("openslide-rs: {}: {}", slide.display(), error);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(OpenSlideError::InternalError(format!(
+            "{} of {} thumbnails failed",
+            failed,
+            slides.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Write the thumbnail of a slide, streamed from the level best matching its size
+/// so that memory stays bounded whatever the slide size.
+fn thumbnail(slide: &Path, out: &Path, args: &ThumbnailArgs) -> Result<(), OpenSlideError> {
+    let slide = OpenSlide::open(slide)?;
+    let dimensions = slide.dimensions()?;
+
+    // Fit in the requested size, preserving the aspect ratio, without upscaling
+    let scale = (f64::from(args.size) / f64::from(dimensions.w.max(dimensions.h))).min(1.);
+    let width = ((f64::from(dimensions.w) * scale).round() as u32).max(1);
+    let height = ((f64::from(dimensions.h) * scale).round() as u32).max(1);
+
+    let image = slide.downscale_to(width, height, args.filter.into())?;
+    output::save(&image, out)?;
+    println!("{}", out.display());
+    Ok(())
+}
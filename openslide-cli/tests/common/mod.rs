@@ -0,0 +1,9 @@
+use std::process::{Command, Output};
+
+/// Run the command line tool with the given arguments.
+pub fn openslide_rs(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_openslide-rs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
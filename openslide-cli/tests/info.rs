@@ -0,0 +1,42 @@
+mod common;
+
+use common::openslide_rs;
+
+#[test]
+fn test_info() {
+    let output = openslide_rs(&["info", "../tests/assets/boxes.tiff"]);
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+
+    assert!(text.contains("vendor:           generic-tiff\n"));
+    assert!(text.contains("dimensions:       300 x 250\n"));
+    assert!(text.contains("mpp:              unknown\n"));
+    assert!(text.contains("  1      150 x 125       2.000"));
+    assert!(text.contains("  3      37 x 31"));
+}
+
+#[test]
+fn test_info_json() {
+    let output = openslide_rs(&["info", "--json", "../tests/assets/boxes.tiff"]);
+    assert!(output.status.success());
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(info["vendor"], "generic-tiff");
+    assert_eq!(info["dimensions"]["w"], 300);
+    assert_eq!(info["mpp"], serde_json::Value::Null);
+    let levels = info["levels"].as_array().unwrap();
+    assert_eq!(levels.len(), 4);
+    assert_eq!(levels[1]["dimensions"]["h"], 125);
+    assert_eq!(levels[1]["downsample"], 2.0);
+    assert!(info["associated_images"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_info_errors() {
+    let output = openslide_rs(&["info", "../tests/assets/__missing"]);
+    assert!(!output.status.success());
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.starts_with("openslide-rs: File ../tests/assets/__missing does not exist"));
+
+    assert!(!openslide_rs(&["info"]).status.success());
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::openslide_rs;
+
+#[test]
+fn test_region() {
+    let out = "../tests/artifacts/test_cli_region.png";
+    let output = openslide_rs(&[
+        "region",
+        "../tests/assets/boxes.tiff",
+        "--x",
+        "100",
+        "--y",
+        "50",
+        "--level",
+        "1",
+        "--width",
+        "40",
+        "--height",
+        "30",
+        "--out",
+        out,
+    ]);
+    assert!(output.status.success());
+    let region = image::open(out).unwrap();
+    assert_eq!((region.width(), region.height()), (40, 30));
+}
+
+#[test]
+fn test_region_mpp() {
+    let out = "../tests/artifacts/test_cli_region_mpp.jpg";
+    let output = openslide_rs(&[
+        "region",
+        "../tests/assets/small.svs",
+        "--x",
+        "0",
+        "--y",
+        "0",
+        "--mpp",
+        "1",
+        "--width",
+        "20",
+        "--height",
+        "10",
+        "--out",
+        out,
+    ]);
+    assert!(output.status.success());
+    let region = image::open(out).unwrap();
+    assert_eq!((region.width(), region.height()), (20, 10));
+
+    // The resolution needs the slide MPP properties
+    let output = openslide_rs(&[
+        "region",
+        "../tests/assets/boxes.tiff",
+        "--x",
+        "0",
+        "--y",
+        "0",
+        "--mpp",
+        "1",
+        "--width",
+        "20",
+        "--height",
+        "10",
+        "--out",
+        out,
+    ]);
+    assert!(!output.status.success());
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("Slide has no valid openslide.mpp-x property"));
+}
+
+#[test]
+fn test_region_errors() {
+    let region = |extra: &[&str]| {
+        let mut args = vec![
+            "region",
+            "../tests/assets/boxes.tiff",
+            "--x",
+            "0",
+            "--y",
+            "0",
+            "--width",
+            "10",
+            "--height",
+            "10",
+        ];
+        args.extend_from_slice(extra);
+        openslide_rs(&args)
+    };
+
+    // Level and resolution are exclusive
+    let output = region(&[
+        "--level",
+        "1",
+        "--mpp",
+        "1",
+        "--out",
+        "../tests/artifacts/test_cli_region_errors.png",
+    ]);
+    assert!(!output.status.success());
+
+    let output = region(&["--out", "../tests/artifacts/test_cli_region_errors.bmp"]);
+    assert!(!output.status.success());
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("Unsupported output format"));
+
+    let output = region(&["--out", "../tests/artifacts/test_cli_region_errors.png"]);
+    assert!(output.status.success());
+    let output = openslide_rs(&[
+        "region",
+        "../tests/assets/__missing",
+        "--x",
+        "0",
+        "--y",
+        "0",
+        "--width",
+        "10",
+        "--height",
+        "10",
+        "--out",
+        "../tests/artifacts/test_cli_region_errors.png",
+    ]);
+    assert!(!output.status.success());
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("does not exist"));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::openslide_rs;
+
+#[test]
+fn test_thumbnail() {
+    let out = "../tests/artifacts/test_cli_thumbnail.png";
+    let output = openslide_rs(&[
+        "thumbnail",
+        "../tests/assets/boxes.tiff",
+        "--size",
+        "100",
+        "--out",
+        out,
+    ]);
+    assert!(output.status.success());
+
+    let thumbnail = image::open(out).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (100, 83));
+
+    // Slides are not upscaled
+    let output = openslide_rs(&[
+        "thumbnail",
+        "../tests/assets/boxes.tiff",
+        "--filter",
+        "nearest",
+        "--out",
+        out,
+    ]);
+    assert!(output.status.success());
+    let thumbnail = image::open(out).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (300, 250));
+}
+
+#[test]
+fn test_thumbnail_batch() {
+    let out = Path::new("../tests/artifacts/test_cli_thumbnails");
+    if out.exists() {
+        fs::remove_dir_all(out).unwrap();
+    }
+    let output = openslide_rs(&[
+        "thumbnail",
+        "../tests/assets",
+        "--size",
+        "64",
+        "--out",
+        out.to_str().unwrap(),
+    ]);
+
+    // Slides failing to open are reported, and the others written
+    assert!(!output.status.success());
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("unopenable.tiff"));
+    assert!(!error.contains("boxes.tiff"));
+
+    let thumbnail = image::open(out.join("boxes.png")).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (64, 53));
+    assert!(out.join("small.png").exists());
+    assert!(!out.join("annotations.png").exists());
+}
+
+#[test]
+fn test_thumbnail_errors() {
+    let out = "../tests/artifacts/test_cli_thumbnail_errors.png";
+    let output = openslide_rs(&[
+        "thumbnail",
+        "../tests/assets/boxes.tiff",
+        "--size",
+        "0",
+        "--out",
+        out,
+    ]);
+    assert!(!output.status.success());
+
+    let output = openslide_rs(&[
+        "thumbnail",
+        "../tests/assets/boxes.tiff",
+        "--filter",
+        "cubic",
+        "--out",
+        out,
+    ]);
+    assert!(!output.status.success());
+}
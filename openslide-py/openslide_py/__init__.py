@@ -10,7 +10,35 @@ PROPERTY_NAME_BOUNDS_Y = u'openslide.bounds-y'
 PROPERTY_NAME_BOUNDS_WIDTH = u'openslide.bounds-width'
 PROPERTY_NAME_BOUNDS_HEIGHT = u'openslide.bounds-height'
 
-from .open_slide import OpenSlide
-from .openslide_py import OpenSlideError, OpenSlideUnsupportedFormatError
+from .geometry import Address, Bounds, Region, Size
+from .open_slide import Cache, LevelInfo, OpenSlide, OpenSlideCache, SlideInfo
+from .openslide_py import (OpenSlideError, OpenSlideUnsupportedFormatError,
+                          forward_native_logs, get_num_threads, set_num_threads)
 
-__all__=["OpenSlide", "OpenSlideError", "OpenSlideUnsupportedFormatError"]
+__all__ = [
+    "Address",
+    "Bounds",
+    "Cache",
+    "LevelInfo",
+    "OpenSlide",
+    "OpenSlideCache",
+    "OpenSlideError",
+    "OpenSlideUnsupportedFormatError",
+    "Region",
+    "Size",
+    "SlideInfo",
+    "forward_native_logs",
+    "get_num_threads",
+    "set_num_threads",
+    "PROPERTY_NAME_COMMENT",
+    "PROPERTY_NAME_VENDOR",
+    "PROPERTY_NAME_QUICKHASH1",
+    "PROPERTY_NAME_BACKGROUND_COLOR",
+    "PROPERTY_NAME_OBJECTIVE_POWER",
+    "PROPERTY_NAME_MPP_X",
+    "PROPERTY_NAME_MPP_Y",
+    "PROPERTY_NAME_BOUNDS_X",
+    "PROPERTY_NAME_BOUNDS_Y",
+    "PROPERTY_NAME_BOUNDS_WIDTH",
+    "PROPERTY_NAME_BOUNDS_HEIGHT",
+]
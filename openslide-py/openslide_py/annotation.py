@@ -0,0 +1,79 @@
+"""Support for slide annotations.
+
+This module reads and writes GeoJSON annotations, as exported by QuPath, and
+renders them into masks of slide regions.
+"""
+from pathlib import Path
+from typing import Any, Dict, List, Tuple, Union
+
+import numpy as np
+
+from openslide_py import OpenSlide
+from openslide_py.openslide_py import _AnnotationSet
+
+ANNOTATED = 255
+
+
+class AnnotationSet:
+    """Annotations of a slide, in level 0 coordinates."""
+
+    def __init__(self, annotations: _AnnotationSet):
+        self._annotations = annotations
+
+    @classmethod
+    def from_geojson(cls, text: str) -> "AnnotationSet":
+        """Parse GeoJSON annotations."""
+        return cls(_AnnotationSet.from_geojson(text))
+
+    @classmethod
+    def open(cls, filename: Union[str, Path]) -> "AnnotationSet":
+        """Read GeoJSON annotations from a file."""
+        return cls(_AnnotationSet.open_geojson(str(filename)))
+
+    def __repr__(self) -> str:
+        return f"{self.__class__.__name__}(annotations={len(self)})"
+
+    def __len__(self) -> int:
+        return len(self._annotations)
+
+    @property
+    def classifications(self) -> List[str]:
+        """The distinct classifications of the annotations."""
+        return self._annotations.classifications
+
+    @property
+    def annotations(self) -> List[Dict[str, Any]]:
+        """The annotations, as dicts of their 'id', 'name', 'classification',
+        'color', 'polygons' and 'measurements'."""
+        return self._annotations.annotations
+
+    def with_classifications(self, classifications: List[str]) -> "AnnotationSet":
+        """The annotations of the given classes."""
+        return AnnotationSet(self._annotations.with_classifications(list(classifications)))
+
+    def to_geojson(self) -> str:
+        """Serialize the annotations as a GeoJSON FeatureCollection."""
+        return self._annotations.to_geojson()
+
+    def save(self, filename: Union[str, Path]):
+        """Write the annotations to a GeoJSON file."""
+        self._annotations.save_geojson(str(filename))
+
+    def rasterize(self, slide: OpenSlide, location: Tuple[int, int], level: int,
+                  size: Tuple[int, int], limit_bounds: bool = False) -> np.ndarray:
+        """Render the annotations into a mask matching, pixel for pixel, the
+        region read_region() returns.
+
+        slide:        the annotated slide.
+        location:     (x, y) tuple giving the top left pixel in the level 0
+                      reference frame.
+        level:        the level number.
+        size:         (width, height) tuple giving the region size.
+        limit_bounds: True if annotation coordinates are relative to the
+                      non-empty slide region, as exported by QuPath.
+
+        Returns a (height, width) uint8 array, annotated pixels being
+        ANNOTATED and others 0."""
+        slide._check_closed()
+        return self._annotations.rasterize(slide._osr, location, level, size,
+                                           limit_bounds)
@@ -22,20 +22,31 @@
 This module provides functionality for generating Deep Zoom images from
 OpenSlide objects.
 """
+import asyncio
 import math
 
+import numpy as np
+
 from PIL import Image
 from io import BytesIO
-from typing import Tuple, List
+from typing import Iterator, Tuple, Optional, Union
 from xml.etree.ElementTree import ElementTree, Element, SubElement
 
 from openslide_py import OpenSlide
+from openslide_py.open_slide import array_to_image
+from openslide_py.openslide_py import _DeepZoom
 from openslide_py import PROPERTY_NAME_BOUNDS_X, PROPERTY_NAME_BOUNDS_Y, \
     PROPERTY_NAME_BOUNDS_WIDTH, PROPERTY_NAME_BOUNDS_HEIGHT, PROPERTY_NAME_BACKGROUND_COLOR
 
+_MEDIA_TYPES = {'jpeg': 'image/jpeg', 'jpg': 'image/jpeg', 'png': 'image/png',
+                'webp': 'image/webp'}
+
 
 class DeepZoomGenerator:
-    """Generates Deep Zoom tiles and metadata."""
+    """Generates Deep Zoom tiles and metadata.
+
+    Slides opened with OpenSlide are tiled by the Rust generator; other slide
+    objects, such as openslide-python ones, by the pure Python implementation."""
 
     BOUNDS_OFFSET_PROPS = (PROPERTY_NAME_BOUNDS_X,
                            PROPERTY_NAME_BOUNDS_Y)
@@ -65,22 +76,27 @@ class DeepZoomGenerator:
         self._z_overlap = overlap
         self._limit_bounds = limit_bounds
 
+        self._dz = None
+        if isinstance(osr, OpenSlide):
+            osr._check_closed()
+            self._dz = _DeepZoom(osr._osr, tile_size, overlap, limit_bounds)
+            return
+
         # Precompute dimensions
         # Slide level and offset
         if limit_bounds:
-            # Level 0 coordinate offset
-            self._l0_offset = tuple(int(osr.properties.get(prop, 0))
-                                    for prop in self.BOUNDS_OFFSET_PROPS)
+            # Level 0 coordinate offset and dimensions of active area
+            self._l0_offset, l0_bounds = _effective_bounds(osr)
             # Slide level dimensions scale factor in each axis
-            size_scale = tuple(int(osr.properties.get(prop, l0_lim)) / l0_lim
-                               for prop, l0_lim in zip(self.BOUNDS_SIZE_PROPS,
-                                                       osr.dimensions))
+            size_scale = tuple(l0_bound / l0_lim
+                               for l0_bound, l0_lim in zip(l0_bounds,
+                                                           osr.dimensions))
             # Dimensions of active area
             self._l_dimensions = tuple(tuple(int(math.ceil(l_lim * scale))
                                              for l_lim, scale in zip(l_size, size_scale))
                                        for l_size in osr.level_dimensions)
         else:
-            self._l_dimensions = osr.level_dimensions
+            self._l_dimensions = tuple(tuple(l_size) for l_size in osr.level_dimensions)
             self._l0_offset = (0, 0)
         self._l0_dimensions = self._l_dimensions[0]
         # Deep Zoom level
@@ -123,24 +139,53 @@ class DeepZoomGenerator:
                f"tile_size={self._z_t_downsample}, overlap={self._z_overlap}, " \
                f"limit_bounds={self._limit_bounds})"
 
+    def __reduce__(self):
+        """Pickle the generator by its slide and parameters."""
+        return self.__class__, (self._osr, self._z_t_downsample, self._z_overlap,
+                                self._limit_bounds)
+
+    @property
+    def l0_offset(self) -> Tuple[int, int]:
+        """The level 0 (x, y) position of the rendered region: the slide bounds
+        offset with limit_bounds, the slide origin otherwise."""
+        if self._dz is not None:
+            return self._dz.l0_offset
+        return self._l0_offset
+
+    @property
+    def l0_dimensions(self) -> Tuple[int, int]:
+        """The level 0 (width, height) of the rendered region: the slide bounds
+        size with limit_bounds, the slide dimensions otherwise."""
+        if self._dz is not None:
+            return self._dz.l0_dimensions
+        return self._l0_dimensions
+
     @property
     def level_count(self) -> int:
         """The number of Deep Zoom levels in the image."""
+        if self._dz is not None:
+            return self._dz.level_count
         return self._dz_levels
 
     @property
-    def level_tiles(self) -> List[int]:
-        """A list of (tiles_x, tiles_y) tuples for each Deep Zoom level."""
+    def level_tiles(self) -> Tuple[Tuple[int, int], ...]:
+        """A tuple of (tiles_x, tiles_y) tuples for each Deep Zoom level."""
+        if self._dz is not None:
+            return tuple(self._dz.level_tiles)
         return self._t_dimensions
 
     @property
-    def level_dimensions(self) -> List[Tuple[int, int]]:
-        """A list of (pixels_x, pixels_y) tuples for each Deep Zoom level."""
+    def level_dimensions(self) -> Tuple[Tuple[int, int], ...]:
+        """A tuple of (pixels_x, pixels_y) tuples for each Deep Zoom level."""
+        if self._dz is not None:
+            return tuple(self._dz.level_dimensions)
         return self._z_dimensions
 
     @property
     def tile_count(self) -> int:
         """The total number of Deep Zoom tiles in the image."""
+        if self._dz is not None:
+            return self._dz.tile_count
         return sum(t_cols * t_rows for t_cols, t_rows in self._t_dimensions)
 
     def get_tile(self, level: int, address: Tuple[int, int]) -> Image.Image:
@@ -150,6 +195,10 @@ class DeepZoomGenerator:
         address:   the address of the tile within the level as a (col, row)
                    tuple."""
 
+        if self._dz is not None:
+            return self._osr._attach_profile(
+                array_to_image(self._dz.get_tile(level, address, 'RGB')))
+
         # Read tile
         args, z_size = self._get_tile_info(level, address)
         tile = self._osr.read_region(*args)
@@ -164,6 +213,94 @@ class DeepZoomGenerator:
 
         return tile
 
+    def get_tile_bytes(self, level: int, address: Tuple[int, int],
+                       format: str = 'jpeg', quality: int = 75) -> bytes:
+        """Return a tile encoded as get_tile() renders it, ready to be sent as
+        an HTTP response body. Slides opened with OpenSlide are read and
+        encoded without holding the GIL.
+
+        level:     the Deep Zoom level.
+        address:   the address of the tile within the level as a (col, row)
+                   tuple.
+        format:    the format of the tile: 'jpeg', 'png' or 'webp'.
+        quality:   the JPEG or WebP quality, from 1 (worst) to 100 (best)."""
+
+        if self._dz is not None:
+            return self._dz.get_tile_bytes(level, address, format, quality)
+
+        if format.lower() not in ('jpeg', 'jpg', 'png', 'webp'):
+            raise ValueError(f"format must be one of ['jpeg', 'png', 'webp']. "
+                             f"Given {format}")
+        if not 1 <= quality <= 100:
+            raise ValueError(f"Quality {quality} is not in the 1-100 range")
+        buf = BytesIO()
+        pil_format = 'JPEG' if format.lower() == 'jpg' else format.upper()
+        self.get_tile(level, address).save(buf, pil_format, quality=quality)
+        return buf.getvalue()
+
+    def get_tile_response(self, level: int, col: int, row: int,
+                          format: str = 'jpeg', quality: int = 75) -> Tuple[bytes, str]:
+        """Return a tile encoded as get_tile_bytes() does, with its media type,
+        so that a web handler can serve it in one line:
+
+            body, content_type = dz.get_tile_response(level, col, row, 'png')
+            return Response(body, media_type=content_type)
+
+        level:     the Deep Zoom level.
+        col, row:  the address of the tile within the level.
+        format:    the format of the tile: 'jpeg', 'png' or 'webp'.
+        quality:   the JPEG or WebP quality, from 1 (worst) to 100 (best)."""
+
+        if self._dz is not None:
+            return self._dz.get_tile_response(level, col, row, format, quality)
+
+        body = self.get_tile_bytes(level, (col, row), format, quality)
+        return body, _MEDIA_TYPES[format.lower()]
+
+    async def get_tile_async(self, level: int, address: Tuple[int, int]) -> Image.Image:
+        """Return an RGB PIL.Image for a tile as get_tile() does, without
+        blocking the event loop.
+
+        level:     the Deep Zoom level.
+        address:   the address of the tile within the level as a (col, row)
+                   tuple."""
+
+        if self._dz is not None and hasattr(self._dz, 'get_tile_async'):
+            return self._osr._attach_profile(
+                array_to_image(await self._dz.get_tile_async(level, address, 'RGB')))
+
+        loop = asyncio.get_running_loop()
+        return await loop.run_in_executor(None, self.get_tile, level, address)
+
+    def tiles(self, level: int, order: str = 'row-major', as_pil: bool = False) \
+            -> Iterator[Tuple[Tuple[int, int], Union[np.ndarray, Image.Image]]]:
+        """Iterate over the tiles of a level as ((col, row), array) pairs.
+
+        The arrays are RGB, as get_tile() images are. Slides opened with
+        OpenSlide are read without holding the GIL.
+
+        level:     the Deep Zoom level.
+        order:     'row-major', or with OpenSlide slides 'column-major' or
+                   'hilbert', which visits neighboring tiles in turn.
+        as_pil:    yield PIL.Images, as get_tile() returns, instead of
+                   arrays."""
+
+        if self._dz is not None:
+            for address in self._dz.tile_addresses(level, order):
+                tile = self._dz.get_tile(level, address, 'RGB')
+                yield address, array_to_image(tile) if as_pil else tile
+            return
+
+        if order != 'row-major':
+            raise ValueError(f"order {order} requires an OpenSlide slide")
+        if level < 0 or level >= self._dz_levels:
+            raise ValueError("Invalid level")
+        t_cols, t_rows = self._t_dimensions[level]
+        for row in range(t_rows):
+            for col in range(t_cols):
+                tile = self.get_tile(level, (col, row))
+                yield (col, row), tile if as_pil else np.asarray(tile)
+
     def _get_tile_info(self, dz_level: int, t_location: Tuple[int, int]) \
             -> Tuple[Tuple[Tuple[int, int], int, Tuple[int, int]], Tuple[int, int]]:
         # Check parameters
@@ -214,7 +351,8 @@ class DeepZoomGenerator:
     def _z_from_t(self, t):
         return self._z_t_downsample * t
 
-    def get_tile_coordinates(self, level: int, address: Tuple[int, int]) -> Tuple[int, int]:
+    def get_tile_coordinates(self, level: int, address: Tuple[int, int]) \
+            -> Tuple[Tuple[int, int], int, Tuple[int, int]]:
         """Return the OpenSlide.read_region() arguments for the specified tile.
 
         Most users should call get_tile() rather than calling
@@ -223,6 +361,8 @@ class DeepZoomGenerator:
         level:     the Deep Zoom level.
         address:   the address of the tile within the level as a (col, row)
                    tuple."""
+        if self._dz is not None:
+            return self._dz.get_tile_coordinates(level, address)
         return self._get_tile_info(level, address)[0]
 
     def get_tile_dimensions(self, level: int, address: Tuple[int, int]) -> Tuple[int, int]:
@@ -231,12 +371,16 @@ class DeepZoomGenerator:
         level:     the Deep Zoom level.
         address:   the address of the tile within the level as a (col, row)
                    tuple."""
+        if self._dz is not None:
+            return self._dz.get_tile_dimensions(level, address)
         return self._get_tile_info(level, address)[1]
 
     def get_dzi(self, format: str):
         """Return a string containing the XML metadata for the .dzi file.
 
         format:    the format of the individual tiles ('png' or 'jpeg')"""
+        if self._dz is not None:
+            return self._dz.get_dzi(format)
         if format not in ['png', 'jpeg']:
             raise ValueError(f"format must be one of ['png', 'jpeg']. "
                              f"Given {format}")
@@ -250,3 +394,33 @@ class DeepZoomGenerator:
         buf = BytesIO()
         tree.write(buf, encoding='UTF-8')
         return buf.getvalue().decode('UTF-8')
+
+
+def _parse_bound(value) -> Optional[int]:
+    try:
+        bound = int(str(value).strip())
+    except ValueError:
+        return None
+    return bound if bound >= 0 else None
+
+
+def _effective_bounds(osr: OpenSlide) -> Tuple[Tuple[int, int], Tuple[int, int]]:
+    """Return the level 0 offset and dimensions of the non-empty slide region.
+
+    Vendors fill the bounds properties, so invalid values are ignored rather than
+    trusted: an axis whose offset or size cannot be parsed, is zero or lies outside
+    the slide, falls back to the whole slide, and sizes are clamped to the slide
+    edge."""
+    offset, size = [], []
+    for offset_prop, size_prop, l0_lim in zip(DeepZoomGenerator.BOUNDS_OFFSET_PROPS,
+                                               DeepZoomGenerator.BOUNDS_SIZE_PROPS,
+                                               osr.dimensions):
+        l0_off = _parse_bound(osr.properties.get(offset_prop, 0)) or 0
+        l0_size = _parse_bound(osr.properties.get(size_prop, l0_lim - l0_off))
+        if l0_off >= l0_lim or l0_size == 0:
+            l0_off, l0_size = 0, l0_lim
+        elif l0_size is None:
+            l0_size = l0_lim - l0_off
+        offset.append(l0_off)
+        size.append(min(l0_size, l0_lim - l0_off))
+    return tuple(offset), tuple(size)
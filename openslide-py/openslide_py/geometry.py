@@ -0,0 +1,87 @@
+"""Positions and sizes of slide regions.
+
+These named tuples mirror the Address, Size and Region types of the Rust
+library. Plain tuples are accepted wherever they are, and converted with of().
+Bounds describes the non-empty region of a slide.
+"""
+from numbers import Integral
+from typing import Any, NamedTuple
+
+
+class Address(NamedTuple):
+    """The position of a pixel, such as the top left corner of a region."""
+    x: int
+    y: int
+
+    @classmethod
+    def of(cls, value: Any) -> "Address":
+        """Convert an (x, y) tuple to an Address.
+
+        Raises TypeError if the value is not a pair of integers."""
+        return _convert(cls, value, "an (x, y) tuple")
+
+
+class Size(NamedTuple):
+    """The width and height of a region or an image, in pixels."""
+    w: int
+    h: int
+
+    @classmethod
+    def of(cls, value: Any) -> "Size":
+        """Convert a (width, height) tuple to a Size.
+
+        Raises TypeError if the value is not a pair of integers."""
+        return _convert(cls, value, "a (width, height) tuple")
+
+
+class Region(NamedTuple):
+    """A region of a slide level, at a level 0 address."""
+    address: Address
+    level: int
+    size: Size
+
+    @classmethod
+    def of(cls, value: Any) -> "Region":
+        """Convert a (location, level, size) tuple, as read_region() takes, to
+        a Region.
+
+        Raises TypeError if the value is not such a tuple."""
+        try:
+            address, level, size = value
+        except (TypeError, ValueError):
+            raise TypeError(
+                f"Region must be a (location, level, size) tuple, not {value!r}") from None
+        if not isinstance(level, Integral):
+            raise TypeError(f"Region level must be an integer, not {level!r}")
+        return cls(Address.of(address), int(level), Size.of(size))
+
+
+class Bounds(NamedTuple):
+    """The level 0 region of a slide holding its non-empty pixels, as given by
+    the openslide.bounds-* properties."""
+    x: int
+    y: int
+    width: int
+    height: int
+
+    @property
+    def address(self) -> Address:
+        """The top left corner of the region."""
+        return Address(self.x, self.y)
+
+    @property
+    def size(self) -> Size:
+        """The size of the region."""
+        return Size(self.width, self.height)
+
+
+def _convert(cls, value: Any, expected: str):
+    if isinstance(value, cls):
+        return value
+    try:
+        first, second = value
+    except (TypeError, ValueError):
+        raise TypeError(f"{cls.__name__} must be {expected}, not {value!r}") from None
+    if not isinstance(first, Integral) or not isinstance(second, Integral):
+        raise TypeError(f"{cls.__name__} must be {expected} of integers, not {value!r}")
+    return cls(int(first), int(second))
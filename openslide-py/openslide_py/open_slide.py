@@ -16,13 +16,33 @@
 # along with this library; if not, write to the Free Software Foundation,
 # Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 #
+import asyncio
+import functools
+import operator
+import numpy as np
+
 from PIL import Image
-from typing import Union, List, Callable, Any, Tuple
+from typing import Union, List, Callable, Any, NamedTuple, Optional, Tuple
 from pathlib import Path
 from collections.abc import Mapping
 
-from . import PROPERTY_NAME_BACKGROUND_COLOR
-from .openslide_py import _OpenSlide, OpenSlideError
+from . import (PROPERTY_NAME_BACKGROUND_COLOR, PROPERTY_NAME_QUICKHASH1,
+               PROPERTY_NAME_VENDOR)
+from .geometry import Address, Bounds, Region, Size
+from .openslide_py import _Cache, _OpenSlide, OpenSlideError
+
+
+def array_to_image(arr: np.ndarray) -> Image.Image:
+    """Wrap a (height, width) or (height, width, 3 or 4) uint8 array in an L,
+    RGB or RGBA PIL.Image.
+
+    The image is built from the raw buffer of the array: RGBA and L images
+    share its memory instead of copying it, and are read-only until modified.
+    """
+    arr = np.ascontiguousarray(arr, dtype=np.uint8)
+    mode = 'L' if arr.ndim == 2 else {3: 'RGB', 4: 'RGBA'}[arr.shape[2]]
+    height, width = arr.shape[:2]
+    return Image.frombuffer(mode, (width, height), arr, 'raw', mode, 0, 1)
 
 
 class OpenSlideMap(Mapping):
@@ -44,6 +64,161 @@ class OpenSlideMap(Mapping):
             raise KeyError(key)
         return self.closure(key)
 
+    def __repr__(self):
+        return '<%s %r>' % (self.__class__.__name__, dict(self))
+
+
+class AssociatedImageMap(OpenSlideMap):
+    """A lazy map: associated image name -> PIL.Image.
+
+    An image is decoded the first time it is accessed, then cached.
+    ``arrays`` maps the same names to the cached RGBA numpy arrays.
+    """
+
+    def __init__(self, osr: _OpenSlide):
+        super().__init__(osr.associated_image_names, self._image)
+        self._osr = osr
+        self._arrays = {}
+        self.arrays = OpenSlideMap(self.names, self._array)
+
+    def _array(self, name: str) -> np.ndarray:
+        arr = self._arrays.get(name)
+        if arr is None:
+            arr = self._osr.associated_image(name)
+            arr.flags.writeable = False
+            self._arrays[name] = arr
+        return arr
+
+    def _image(self, name: str) -> Image.Image:
+        return array_to_image(self._array(name))
+
+
+class LevelInfo(NamedTuple):
+    """The properties of a slide level: tile_size is the size of the tiles
+    the level is stored in, or None if it is not tiled."""
+    dimensions: Size
+    downsample: float
+    tile_size: Optional[Size]
+
+
+class SlideInfo(NamedTuple):
+    """The standard properties of a slide, parsed. Missing or invalid
+    properties are None."""
+    vendor: Optional[str]
+    quickhash: Optional[str]
+    mpp: Optional[Tuple[float, float]]
+    objective_power: Optional[float]
+    bounds: Optional[Bounds]
+    levels: Tuple[LevelInfo, ...]
+
+
+class SlideLevel:
+    """A slide level indexed like a (height, width, channels) numpy array.
+
+    Row and column indices and slices, of step 1, are level pixels: negative
+    ones count from the level edges and slices are clamped to them, as numpy
+    does. The indexed region is read on access, without holding the GIL.
+
+        >>> slide.level(2)[1000:1512, 2000:2512].shape
+        (512, 512, 4)
+    """
+
+    def __init__(self, slide: "OpenSlide", level: int, mode: str = 'RGBA'):
+        if mode not in ('RGB', 'RGBA'):
+            raise ValueError("mode must be one of ['RGB', 'RGBA']. Given %s" % mode)
+        if not 0 <= level < slide.level_count:
+            raise IndexError(f"level {level} out of range for {slide.level_count} levels")
+        self._slide = slide
+        self.level = level
+        self.mode = mode
+        self.dimensions = slide.level_dimensions[level]
+        self.downsample = slide.level_downsamples[level]
+
+    def __repr__(self) -> str:
+        return f"{self.__class__.__name__}({self._slide}, level={self.level}, mode={self.mode!r})"
+
+    @property
+    def shape(self) -> Tuple[int, int, int]:
+        """The (height, width, channels) shape of the whole level."""
+        return self.dimensions.h, self.dimensions.w, len(self.mode)
+
+    def __len__(self) -> int:
+        return self.dimensions.h
+
+    def __getitem__(self, key) -> np.ndarray:
+        if not isinstance(key, tuple):
+            key = (key,)
+        if len(key) > 3:
+            raise IndexError(f"too many indices for a level: {len(key)} were given")
+        rows, columns, channels = key + (slice(None),) * (3 - len(key))
+
+        y0, y1, squeeze_y = _axis_range(rows, self.dimensions.h)
+        x0, x1, squeeze_x = _axis_range(columns, self.dimensions.w)
+        w, h = max(x1 - x0, 0), max(y1 - y0, 0)
+        if w == 0 or h == 0:
+            arr = np.zeros((h, w, len(self.mode)), dtype=np.uint8)
+        else:
+            self._slide._check_closed()
+            location = (round(x0 * self.downsample), round(y0 * self.downsample))
+            arr = self._slide._osr.read_region(location, self.level, (w, h), None, self.mode)
+
+        if squeeze_x:
+            arr = arr[:, 0]
+        if squeeze_y:
+            arr = arr[0]
+        return arr[..., channels]
+
+
+def _axis_range(index, length: int) -> Tuple[int, int, bool]:
+    """The [start, stop) range of a level axis an integer or a slice indexes, and
+    whether the axis is dropped."""
+    if isinstance(index, slice):
+        start, stop, step = index.indices(length)
+        if step != 1:
+            raise ValueError(f"level slices must have a step of 1, not {index.step}")
+        return start, stop, False
+    try:
+        position = operator.index(index)
+    except TypeError:
+        raise IndexError(f"levels are indexed by integers and slices, not {index!r}") from None
+    if not -length <= position < length:
+        raise IndexError(f"index {position} is out of bounds for an axis of size {length}")
+    position %= length
+    return position, position + 1, True
+
+
+class OpenSlideCache:
+    """An in-memory tile cache.
+
+    A cache can be attached to several slides with OpenSlide.set_cache(), to
+    bound the memory of a process holding many open slides. It is freed once
+    released and no longer attached to an open slide.
+
+    Parameters
+    ----------
+    capacity: int
+        Cache size in bytes
+    """
+
+    def __init__(self, capacity: int):
+        self._cache = _Cache(capacity)
+
+    def __repr__(self) -> str:
+        return f"{self.__class__.__name__}({self.capacity})"
+
+    @property
+    def capacity(self) -> int:
+        """
+        Returns
+        -------
+        capacity: int
+            The cache size in bytes
+        """
+        return self._cache.capacity
+
+
+Cache = OpenSlideCache
+
 
 class OpenSlide:
     """An open whole-slide image.
@@ -61,6 +236,9 @@ class OpenSlide:
     filename: Union[str, Path]
     cache_size: int = 1024 * 1024 * 32
         Cache size in bytes
+    attach_color_profile: bool = False
+        Attach the slide color_profile, if any, to the PIL.Images of regions,
+        thumbnails and Deep Zoom tiles, as their 'icc_profile' info
 
     Raises
     ------
@@ -68,19 +246,93 @@ class OpenSlide:
     OpenSlideUnsupportedFormatError
     """
 
-    def __init__(self, filename: Union[str, Path], cache_size: int = 1024 * 1024 * 32):
+    def __init__(self, filename: Union[str, Path], cache_size: int = 1024 * 1024 * 32,
+                 attach_color_profile: bool = False):
         if isinstance(filename, str):
             filename = Path(filename)
 
-        self._filename = filename
-        self._osr = _OpenSlide(str(filename))
+        self._setup(filename, _OpenSlide(str(filename)), attach_color_profile)
         self.cache_size = cache_size
 
+    @classmethod
+    def _from_handle(cls, filename: Union[str, Path], osr: _OpenSlide,
+                     attach_color_profile: bool = False) -> "OpenSlide":
+        """Wrap a handle shared with its owner, such as a SlidePool, keeping
+        the cache of the handle."""
+        slide = cls.__new__(cls)
+        slide._setup(Path(filename), osr, attach_color_profile)
+        slide._cache_size = 1024 * 1024 * 32
+        slide._cache = None
+        return slide
+
+    def _setup(self, filename: Path, osr: _OpenSlide, attach_color_profile: bool):
+        self._filename = filename
+        self._osr = osr
+        self._associated_images = None
+        self._color_profile = None
+        self.attach_color_profile = attach_color_profile
+
+        # Slides are compared by content when the format provides a hash of it,
+        # computed once so that the hash of a slide survives close()
+        properties = self.properties
+        if PROPERTY_NAME_QUICKHASH1 in properties:
+            self._key = ('quickhash', properties[PROPERTY_NAME_QUICKHASH1])
+        else:
+            self._key = ('path', str(filename.resolve()))
+        self._vendor = properties.get(PROPERTY_NAME_VENDOR)
+        self._dimensions = self.dimensions
+
     def close(self):
-        self._osr = None
+        """Close the slide, releasing its file handles.
+
+        Any later operation raises OpenSlideError. Closing a closed slide
+        does nothing.
+        """
+        self._osr.close()
+
+    @property
+    def closed(self) -> bool:
+        """
+        Returns
+        -------
+        closed: bool
+            Whether the slide was closed.
+        """
+        return self._osr.closed
+
+    @property
+    def error(self) -> Optional[str]:
+        """
+        Returns
+        -------
+        error: Optional[str]
+            The error the slide is stuck on, or None if it is healthy or
+            closed. Once an operation fails in the C library, every later one
+            raises OpenSlideError with this message until reopen().
+        """
+        return self._osr.error
+
+    def reopen(self):
+        """Open the slide file again, replacing the current handle, to
+        recover from an error or after close().
+
+        The slide keeps its cache, or its cache size. DeepZoomGenerators and
+        PatchSamplers created before keep the previous handle and must be
+        created again.
+
+        Raises
+        ------
+        FileNotFoundError
+        OpenSlideUnsupportedFormatError
+        """
+        self._osr.reopen()
+        if self._cache is None:
+            self._osr.set_cache_size(self._cache_size)
+        else:
+            self._osr.set_cache(self._cache._cache)
 
     def _check_closed(self):
-        if self._osr is None:
+        if self._osr.closed:
             raise OpenSlideError("Slide object was closed")
 
     def __enter__(self) -> "OpenSlide":
@@ -91,10 +343,27 @@ class OpenSlide:
         return False
 
     def __repr__(self) -> str:
-        return f"{self.__class__.__name__}({self._filename})"
+        state = 'closed' if self.closed else 'open'
+        return (f"{self.__class__.__name__}({str(self._filename)!r}, "
+                f"dimensions={tuple(self._dimensions)}, vendor={self._vendor!r}, {state})")
+
+    def __eq__(self, other) -> bool:
+        """Slides are equal if they have the same quickhash, or, for formats
+        without one, the same resolved path."""
+        if not isinstance(other, OpenSlide):
+            return NotImplemented
+        return self._key == other._key
+
+    def __hash__(self) -> int:
+        return hash(self._key)
+
+    def __reduce__(self):
+        """Pickle the slide by path, so that worker processes reopen it."""
+        return self.__class__, (self._filename, self._cache_size,
+                                self.attach_color_profile)
 
     @classmethod
-    def detect_format(cls, filename: Union[str, Path]) -> str:
+    def detect_format(cls, filename: Union[str, Path]) -> Optional[str]:
         """
         Parameters
         ----------
@@ -102,12 +371,13 @@ class OpenSlide:
 
         Returns
         -------
-        format: str
-            A string describing the format vendor of the specified file.
+        format: Optional[str]
+            A string describing the format vendor of the specified file,
+            or None if the format is not recognized.
 
         Raises
         ------
-        OpenSlideUnsupportedFormatError
+        FileNotFoundError
         """
         if isinstance(filename, Path):
             filename = str(filename)
@@ -126,9 +396,44 @@ class OpenSlide:
 
     @cache_size.setter
     def cache_size(self, size: int):
+        self.set_cache_size(size)
+
+    def set_cache_size(self, size: int):
+        """Give the slide a cache of its own, replacing its current cache.
+
+        Parameters
+        ----------
+        size: int
+            Cache size in bytes
+
+        Raises
+        ------
+        ValueError
+            If the slide is used by a DeepZoomGenerator or a PatchSampler, or
+            comes from a SlidePool.
+        """
         self._check_closed()
         self._osr.set_cache_size(size)
         self._cache_size = size
+        self._cache = None
+
+    def set_cache(self, cache: OpenSlideCache):
+        """Use a cache shared with other slides, replacing the current cache.
+
+        Parameters
+        ----------
+        cache: OpenSlideCache
+
+        Raises
+        ------
+        ValueError
+            If the slide is used by a DeepZoomGenerator or a PatchSampler, or
+            comes from a SlidePool.
+        """
+        self._check_closed()
+        self._osr.set_cache(cache._cache)
+        self._cache_size = cache.capacity
+        self._cache = cache
 
     @property
     def level_count(self) -> int:
@@ -142,37 +447,37 @@ class OpenSlide:
         return self._osr.level_count
 
     @property
-    def level_dimensions(self) -> List[Tuple[int, int]]:
+    def level_dimensions(self) -> Tuple[Size, ...]:
         """
         Returns
         -------
-        level_dimensions: List[Tuple[int, int]]
-            A list of (width, height) tuples, one for each level of the image.
+        level_dimensions: Tuple[Size, ...]
+            A tuple of (width, height) sizes, one for each level of the image.
             level_dimensions[n] contains the dimensions of level n.
         """
 
         self._check_closed()
-        return self._osr.all_level_dimensions
+        return tuple(Size(*dimensions) for dimensions in self._osr.all_level_dimensions)
 
     @property
-    def level_downsamples(self) -> List[int]:
+    def level_downsamples(self) -> Tuple[float, ...]:
         """
         Returns
         -------
-        level_downsamples: List[int]
-            A list of downsampling factors for each level of the image.
+        level_downsamples: Tuple[float, ...]
+            A tuple of downsampling factors for each level of the image.
             level_downsample[n] contains the downsample factor of level n.
         """
         self._check_closed()
-        return self._osr.all_level_downsample
+        return tuple(self._osr.all_level_downsample)
 
     @property
-    def dimensions(self) -> Tuple[int, int]:
+    def dimensions(self) -> Size:
         """
         Returns
         -------
-        dimensions: Tuple[int, int]
-            A (width, height) tuple for level 0 of the image.
+        dimensions: Size
+            A (width, height) size for level 0 of the image.
         """
         return self.level_dimensions[0]
 
@@ -190,20 +495,134 @@ class OpenSlide:
                             lambda name: self._osr.property(name))
 
     @property
-    def associated_images(self) -> OpenSlideMap:
+    def mpp(self) -> Optional[Tuple[float, float]]:
+        """
+        Returns
+        -------
+        mpp: Optional[Tuple[float, float]]
+            The (x, y) level 0 resolution in micrometers per pixel, or None if
+            the openslide.mpp-x or openslide.mpp-y property is missing or not
+            a positive number.
+        """
+        self._check_closed()
+        return self._osr.mpp
+
+    @property
+    def magnification(self) -> Optional[float]:
+        """
+        Returns
+        -------
+        magnification: Optional[float]
+            The magnification of the scanning objective, or None if the
+            openslide.objective-power property is missing or not a positive
+            number.
+        """
+        self._check_closed()
+        return self._osr.magnification
+
+    @property
+    def bounds(self) -> Optional[Bounds]:
+        """
+        Returns
+        -------
+        bounds: Optional[Bounds]
+            The level 0 region holding the non-empty pixels, or None if the
+            slide has no openslide.bounds-* property. Invalid properties are
+            ignored, an axis then covering the whole slide.
+        """
+        self._check_closed()
+        bounds = self._osr.bounds
+        return None if bounds is None else Bounds(*bounds[0], *bounds[1])
+
+    @property
+    def info(self) -> SlideInfo:
+        """
+        Returns
+        -------
+        info: SlideInfo
+            The vendor, quickhash, resolution, objective power, bounds and
+            levels of the slide, parsed from its properties.
+        """
+        self._check_closed()
+        info = self._osr.info
+        bounds = info['bounds']
+        return SlideInfo(
+            vendor=info['vendor'],
+            quickhash=info['quickhash'],
+            mpp=info['mpp'],
+            objective_power=info['objective_power'],
+            bounds=None if bounds is None else Bounds(*bounds[0], *bounds[1]),
+            levels=tuple(
+                LevelInfo(Size(*dimensions), downsample,
+                          None if tile_size is None else Size(*tile_size))
+                for dimensions, downsample, tile_size in info['levels']))
+
+    @property
+    def color_profile(self) -> Optional[bytes]:
+        """The ICC profile of the slide levels, read once, for color transforms
+        of regions with PIL.ImageCms:
+
+            >>> profile = ImageCms.ImageCmsProfile(BytesIO(slide.color_profile))
+
+        Returns
+        -------
+        color_profile: Optional[bytes]
+            The profile, or None if the slide has none.
+        """
+        self._check_closed()
+        if self._color_profile is None:
+            self._color_profile = (self._osr.color_profile,)
+        return self._color_profile[0]
+
+    def _attach_profile(self, image: Image.Image) -> Image.Image:
+        if self.attach_color_profile:
+            profile = self.color_profile
+            if profile is not None:
+                image.info['icc_profile'] = profile
+        return image
+
+    @property
+    def associated_images(self) -> AssociatedImageMap:
         """
         Images associated with this whole-slide image.
         Unlike in the C interface, the images accessible via this property
         are not premultiplied.
+        The images are decoded on first access and cached.
 
         Returns
         -------
-        associated_images: OpenSlideMap
+        associated_images: AssociatedImageMap
             This is a map: image name -> PIL.Image.
         """
         self._check_closed()
-        return OpenSlideMap(self._osr.associated_image_names,
-                            lambda name: Image.fromarray(self._osr.associated_image(name)))
+        if self._associated_images is None:
+            self._associated_images = AssociatedImageMap(self._osr)
+        return self._associated_images
+
+    def level(self, level: int, mode: str = 'RGBA') -> SlideLevel:
+        """A level indexed like a numpy array, such as level(2)[y0:y1, x0:x1].
+
+        Parameters
+        ----------
+        level: int
+            The level number
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
+
+        Returns
+        -------
+        level: SlideLevel
+            A view reading the regions it is indexed with as uint8 arrays.
+
+        Raises
+        ------
+        IndexError
+            If the level does not exist.
+        ValueError
+            If the mode is unknown.
+        """
+        self._check_closed()
+        return SlideLevel(self, level, mode)
 
     def get_best_level_for_downsample(self, downsample: float) -> int:
         """
@@ -215,28 +634,342 @@ class OpenSlide:
         self._check_closed()
         return self._osr.best_level_for_downsample(downsample)
 
-    def read_region(self, location: Tuple[int, int], level: int,
-                    size: Tuple[int, int]) -> Image.Image:
+    def read_region(self, location: Union[Address, Tuple[int, int]], level: int,
+                    size: Union[Size, Tuple[int, int]], out: Optional[np.ndarray] = None,
+                    mode: str = 'RGBA') -> Union[Image.Image, np.ndarray]:
         """
         Parameters
         ----------
-        location: Tuple[int, int]
+        location: Union[Address, Tuple[int, int]]
             (x, y) tuple giving the top left pixel in the level 0 reference frame.
         level: int
             The level number
-        size: Tuple[int, int]
+        size: Union[Size, Tuple[int, int]]
             (width, height) tuple giving the region size.
+        out: Optional[np.ndarray] = None
+            A preallocated C-contiguous uint8 array of shape
+            (height, width, channels) the region is read into, in place.
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
 
         Returns
         -------
-        region: Image.Image
-            A PIL.Image containing the contents of the region.
+        region: Union[Image.Image, np.ndarray]
+            A PIL.Image of the given mode containing the contents of the
+            region, or `out` if given.
+            Pixels outside the slide, including those at negative
+            coordinates, are opaque white, where openslide-python
+            returns transparent pixels.
             Unlike in the C interface, the image data returned by this
             function is not premultiplied.
+
+        Raises
+        ------
+        OpenSlideError
+            If the width or height is negative.
+        TypeError
+            If the location or the size is not a pair of integers.
+        ValueError
+            If the mode is not 'RGB' or 'RGBA', or if `out` is not a writeable
+            C-contiguous array of the region shape.
+        """
+        self._check_closed()
+        x, y = location = Address.of(location)
+        w, h = size = Size.of(size)
+        if mode not in ('RGB', 'RGBA'):
+            raise ValueError("mode must be one of ['RGB', 'RGBA']. Given %s" % mode)
+        if w < 0 or h < 0:
+            raise OpenSlideError(
+                "negative width or height not allowed in %r" % (size,))
+        if w == 0 or h == 0:
+            return out if out is not None else self._attach_profile(Image.new(mode, (w, h)))
+
+        # The bindings only take positive coordinates: pad pixels left of or
+        # above the slide as the ones past its edges are
+        downsample = (self.level_downsamples[level]
+                      if 0 <= level < self.level_count else 1)
+        offset_x = min(w, round(-x / downsample)) if x < 0 else 0
+        offset_y = min(h, round(-y / downsample)) if y < 0 else 0
+        if offset_x == 0 and offset_y == 0:
+            arr = self._osr.read_region((max(x, 0), max(y, 0)), level, (w, h),
+                                        out, mode)
+            return out if out is not None else self._attach_profile(array_to_image(arr))
+
+        region = Image.new(mode, (w, h), 'white')
+        if offset_x < w and offset_y < h:
+            arr = self._osr.read_region((max(x, 0), max(y, 0)), level,
+                                        (w - offset_x, h - offset_y), None, mode)
+            region.paste(array_to_image(arr), (offset_x, offset_y))
+        if out is not None:
+            out[...] = np.asarray(region)
+            return out
+        return self._attach_profile(region)
+
+    def read_region_raw(self, location: Union[Address, Tuple[int, int]], level: int,
+                        size: Union[Size, Tuple[int, int]]) -> np.ndarray:
+        """Read a region as the C library returns it, skipping the per-pixel
+        decode of read_region(), for callers converting pixels themselves,
+        such as on a GPU.
+
+        Parameters
+        ----------
+        location: Union[Address, Tuple[int, int]]
+            (x, y) tuple giving the top left pixel in the level 0 reference
+            frame, of non-negative coordinates.
+        level: int
+            The level number
+        size: Union[Size, Tuple[int, int]]
+            (width, height) tuple giving the region size.
+
+        Returns
+        -------
+        region: np.ndarray
+            A (height, width) uint32 array of premultiplied 0xAARRGGBB pixels:
+            in native byte order, so B, G, R, A bytes on little-endian
+            machines. Pixels outside the slide are transparent black.
+
+        Raises
+        ------
+        OpenSlideError
+            If the width or height is negative.
+        ValueError
+            If the location is negative.
+        """
+        self._check_closed()
+        x, y = location = Address.of(location)
+        w, h = size = Size.of(size)
+        if w < 0 or h < 0:
+            raise OpenSlideError(
+                "negative width or height not allowed in %r" % (size,))
+        if x < 0 or y < 0:
+            raise ValueError("read_region_raw takes non-negative locations, not %r" % (location,))
+        return self._osr.read_region_raw(location, level, size)
+
+    def read_region_buffer(self, location: Union[Address, Tuple[int, int]], level: int,
+                           size: Union[Size, Tuple[int, int]],
+                           mode: str = 'RGBA') -> memoryview:
+        """Read a region as read_region() does, into a buffer consumers such as
+        torch.frombuffer(), PIL.Image.frombuffer() or np.frombuffer() wrap
+        without copying it.
+
+        Parameters
+        ----------
+        location: Union[Address, Tuple[int, int]]
+            (x, y) tuple giving the top left pixel in the level 0 reference frame.
+        level: int
+            The level number
+        size: Union[Size, Tuple[int, int]]
+            (width, height) tuple giving the region size.
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
+
+        Returns
+        -------
+        region: memoryview
+            A writable C-contiguous view of format 'B' and shape
+            (height, width, channels) over the pixels, alive as long as the
+            view or a consumer of it is.
+        """
+        self._check_closed()
+        x, y = location = Address.of(location)
+        w, h = size = Size.of(size)
+        if x < 0 or y < 0 or w <= 0 or h <= 0:
+            # Padded and empty regions are rare: read them as read_region() does
+            region = self.read_region(location, level, size, mode=mode)
+            return memoryview(np.asarray(region).reshape(h, w, len(mode)))
+        return memoryview(self._osr.read_region_buffer(location, level, size, mode))
+
+    def thumbnail(self, size: Union[Size, Tuple[int, int]], filter: str = 'lanczos3',
+                  mode: str = 'RGBA',
+                  as_array: bool = False) -> Union[Image.Image, np.ndarray]:
+        """A thumbnail of the image, resized by the library from the best
+        matching level, without the background compositing of get_thumbnail().
+
+        Parameters
+        ----------
+        size: Union[Size, Tuple[int, int]]
+            (width, height) tuple giving the maximum size of the thumbnail.
+        filter: str = 'lanczos3'
+            The resize filter: 'nearest', 'triangle' or 'lanczos3'.
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
+        as_array: bool = False
+            Return a numpy array instead of a PIL.Image.
+
+        Returns
+        -------
+        thumbnail: Union[Image.Image, np.ndarray]
+            The thumbnail, preserving the aspect ratio of the image.
+
+        Raises
+        ------
+        ValueError
+            If the filter or the mode is unknown.
+        """
+        self._check_closed()
+        arr = self._osr.thumbnail(Size.of(size), filter, mode)
+        return arr if as_array else self._attach_profile(array_to_image(arr))
+
+    def export_level(self, level: int, path: Union[str, Path],
+                     threads: Optional[int] = None) -> np.memmap:
+        """Write a whole level to a flat array file, read and written tile by
+        tile without holding the GIL, so that levels larger than memory can be
+        exported.
+
+        Parameters
+        ----------
+        level: int
+            The level number
+        path: Union[str, Path]
+            The destination file, replaced if it exists: a NPY file, readable
+            with numpy.load(path, mmap_mode='r'), if it has the .npy
+            extension, and raw pixels otherwise.
+        threads: Optional[int] = None
+            The number of threads reading tiles, get_num_threads() by default.
+
+        Returns
+        -------
+        pixels: np.memmap
+            A read-only (height, width, 3) uint8 RGB view of the pixels in the
+            file.
+
+        Raises
+        ------
+        IndexError
+            If the level does not exist.
+        IOError
+            If the file could not be written.
         """
         self._check_closed()
-        arr = self._osr.read_region(location, level, size)
-        return Image.fromarray(arr)
+        offset = self._osr.export_level(level, str(path), threads)
+        w, h = self.level_dimensions[level]
+        return np.memmap(path, dtype=np.uint8, mode='r', offset=offset, shape=(h, w, 3))
+
+    def read_regions(self, regions: List[Union[Region, Tuple[Tuple[int, int], int, Tuple[int, int]]]],
+                     mode: str = 'RGBA', stack: bool = False,
+                     as_pil: bool = False,
+                     threads: Optional[int] = None) -> Union[List[np.ndarray], np.ndarray,
+                                                             List[Image.Image]]:
+        """Read many regions in parallel, in a single call releasing the GIL.
+
+        Parameters
+        ----------
+        regions: List[Union[Region, Tuple[Tuple[int, int], int, Tuple[int, int]]]]
+            Regions, or (location, level, size) tuples as read_region() takes,
+            of non-negative locations.
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
+        stack: bool = False
+            Return a single (n, height, width, channels) array; the regions
+            must then all be of the same size.
+        as_pil: bool = False
+            Return PIL.Images built from the raw buffers instead of arrays.
+        threads: Optional[int] = None
+            The number of threads reading regions, get_num_threads() by
+            default.
+
+        Returns
+        -------
+        regions: Union[List[np.ndarray], np.ndarray, List[Image.Image]]
+            The uint8 arrays of the regions, in order, their stack, or their
+            PIL.Images.
+
+        Raises
+        ------
+        TypeError
+            If a region is not a (location, level, size) tuple.
+        ValueError
+            If the mode is unknown, stacked regions differ in size, or both
+            stack and as_pil are set.
+        """
+        self._check_closed()
+        if stack and as_pil:
+            raise ValueError("stack and as_pil are exclusive")
+        regions = [Region.of(region) for region in regions]
+        arrays = self._osr.read_regions(regions, mode, stack, threads)
+        if as_pil:
+            return [self._attach_profile(array_to_image(arr)) for arr in arrays]
+        return arrays
+
+    def read_patches(self, coords: Union[np.ndarray, List[Union[Address, Tuple[int, int]]]],
+                     level: int, size: Union[Size, Tuple[int, int]],
+                     mode: str = 'RGBA', threads: Optional[int] = None) -> np.ndarray:
+        """Read same-sized patches of a level into a single array, filled in
+        parallel in a single call releasing the GIL.
+
+        Parameters
+        ----------
+        coords: Union[np.ndarray, List[Union[Address, Tuple[int, int]]]]
+            The non-negative (x, y) level 0 positions of the top left corners
+            of the patches, such as an (n, 2) integer array.
+        level: int
+            The level number
+        size: Union[Size, Tuple[int, int]]
+            (width, height) tuple giving the patch size.
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
+        threads: Optional[int] = None
+            The number of threads reading patches, get_num_threads() by
+            default.
+
+        Returns
+        -------
+        patches: np.ndarray
+            The (n, height, width, channels) uint8 array of the patches, in
+            order.
+
+        Raises
+        ------
+        TypeError
+            If a position is not an (x, y) pair of integers.
+        ValueError
+            If the mode is unknown or a position is negative.
+        """
+        self._check_closed()
+        if isinstance(coords, np.ndarray):
+            if coords.size and not np.issubdtype(coords.dtype, np.integer):
+                raise TypeError(f"coords must be an integer array, not {coords.dtype}")
+            coords = coords.reshape(-1, 2).tolist()
+        addresses = [Address.of(address) for address in coords]
+        if any(x < 0 or y < 0 for x, y in addresses):
+            raise ValueError("negative patch positions are not allowed")
+        return self._osr.read_patches(addresses, level, Size.of(size), mode, threads)
+
+    async def read_region_async(self, location: Union[Address, Tuple[int, int]], level: int,
+                                size: Union[Size, Tuple[int, int]],
+                                mode: str = 'RGBA') -> Image.Image:
+        """Read a region as read_region() does, without blocking the event loop.
+
+        Built with the asyncio feature, reads run on the Rust blocking pool;
+        otherwise, and for regions read_region() pads, in the default executor.
+
+        Parameters
+        ----------
+        location: Union[Address, Tuple[int, int]]
+            (x, y) tuple giving the top left pixel in the level 0 reference frame.
+        level: int
+            The level number
+        size: Union[Size, Tuple[int, int]]
+            (width, height) tuple giving the region size.
+        mode: str = 'RGBA'
+            'RGBA', or 'RGB' to drop the alpha channel.
+
+        Returns
+        -------
+        region: Image.Image
+            A PIL.Image of the given mode containing the contents of the region.
+        """
+        self._check_closed()
+        x, y = location = Address.of(location)
+        w, h = size = Size.of(size)
+        if hasattr(self._osr, 'read_region_async') and x >= 0 and y >= 0 \
+                and w > 0 and h > 0:
+            arr = await self._osr.read_region_async(location, level, size, mode)
+            return self._attach_profile(array_to_image(arr))
+
+        loop = asyncio.get_running_loop()
+        return await loop.run_in_executor(
+            None, functools.partial(self.read_region, location, level, size, mode=mode))
 
     def get_thumbnail(self, size: Tuple[int, int]) -> Image.Image:
         """
@@ -263,4 +996,4 @@ class OpenSlide:
         thumb = Image.new('RGB', tile.size, bg_color)
         thumb.paste(tile, None, tile)
         thumb.thumbnail(size, Image.ANTIALIAS)
-        return thumb
+        return self._attach_profile(thumb)
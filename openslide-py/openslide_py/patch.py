@@ -0,0 +1,208 @@
+"""Support for tissue patch sampling.
+
+This module samples patches of a slide on a regular grid, restricted to
+tissue, and iterates over their pixels, either directly or through background
+reader threads suited to a PyTorch IterableDataset.
+"""
+from collections import namedtuple
+from typing import Iterator, List, Optional, Tuple, Union
+
+import numpy as np
+
+from PIL import Image
+
+from openslide_py import OpenSlide
+from openslide_py.open_slide import array_to_image
+from openslide_py.openslide_py import _PatchSampler, get_num_threads
+
+Patch = namedtuple('Patch', ['location', 'level', 'size', 'tissue_fraction'])
+Patch.__doc__ = """A sampled patch.
+
+location:        the (x, y) level 0 position of its top left corner.
+level:           the slide level it is read from.
+size:            the (width, height) read from that level, before scaling to
+                 the patch size.
+tissue_fraction: the share of the patch covered by tissue, from 0 to 1."""
+
+
+class PatchSampler:
+    """Patches of a slide on a regular grid, at a target resolution and
+    restricted to tissue."""
+
+    def __init__(self, slide: OpenSlide, patch_size: int,
+                 stride: Optional[int] = None, target_mpp: Optional[float] = None,
+                 min_tissue_fraction: float = 0.0):
+        """Sample the patches of a slide.
+
+        slide:               an OpenSlide slide.
+        patch_size:          the width and height of a patch, in pixels at
+                             target_mpp.
+        stride:              the distance between two patches, in pixels at
+                             target_mpp; patch_size by default.
+        target_mpp:          the resolution of the patches in micrometers per
+                             pixel, or None for the slide level 0 resolution.
+        min_tissue_fraction: the share of a patch that must be tissue, from
+                             0 to 1: 0 keeps every patch."""
+
+        slide._check_closed()
+        self._slide = slide
+        self._sampler = _PatchSampler(slide._osr, patch_size,
+                                      patch_size if stride is None else stride,
+                                      target_mpp, min_tissue_fraction)
+        self._patches = [Patch(*patch) for patch in self._sampler.patches]
+
+    def __repr__(self) -> str:
+        return f"{self.__class__.__name__}({self._slide}, " \
+               f"patch_size={self.patch_size}, patches={len(self)})"
+
+    def __len__(self) -> int:
+        return len(self._patches)
+
+    def __iter__(self) -> Iterator[Tuple[Patch, np.ndarray]]:
+        """Iterate over the patches and their RGBA pixels."""
+        return self.iter_pixels()
+
+    @property
+    def patch_size(self) -> int:
+        """The width and height of a patch."""
+        return self._sampler.patch_size
+
+    @property
+    def patches(self) -> List[Patch]:
+        """The sampled patches."""
+        return self._patches
+
+    def read_patch(self, index: int, mode: str = 'RGBA',
+                   as_pil: bool = False) -> Union[np.ndarray, Image.Image]:
+        """Return the pixels of a patch, scaled to the patch size, read
+        without holding the GIL.
+
+        index:     the index of the patch in patches.
+        mode:      'RGBA', or 'RGB' to drop the alpha channel.
+        as_pil:    return a PIL.Image instead of an array."""
+        pixels = self._sampler.read_patch(index, mode)
+        return array_to_image(pixels) if as_pil else pixels
+
+    def iter_pixels(self, mode: str = 'RGBA', as_pil: bool = False) \
+            -> Iterator[Tuple[Patch, Union[np.ndarray, Image.Image]]]:
+        """Iterate over the patches and their pixels as (patch, array) pairs.
+
+        mode:      'RGBA', or 'RGB' to drop the alpha channel.
+        as_pil:    yield PIL.Images instead of arrays."""
+        for index, patch in enumerate(self._patches):
+            yield patch, self.read_patch(index, mode, as_pil)
+
+
+class PatchReader:
+    """The patches of a slide, read by background Rust threads, for a
+    torch.utils.data.IterableDataset to iterate over.
+
+    The reader pickles by slide path and sampling parameters: each DataLoader
+    worker samples the patches again and starts its own reader threads when
+    iterated. Shard the patches between workers with worker_init()::
+
+        class Patches(torch.utils.data.IterableDataset):
+            def __init__(self, reader):
+                self.reader = reader
+
+            def __iter__(self):
+                self.reader.worker_init()
+                return iter(self.reader)
+    """
+
+    def __init__(self, slide: OpenSlide, patch_size: int,
+                 stride: Optional[int] = None, target_mpp: Optional[float] = None,
+                 min_tissue_fraction: float = 0.0, mode: str = 'RGB',
+                 threads: Optional[int] = None, capacity: int = 64):
+        """Prepare reading the patches of a slide.
+
+        slide, patch_size, stride, target_mpp, min_tissue_fraction: as for
+                   PatchSampler.
+        mode:      'RGB', or 'RGBA' to keep the alpha channel.
+        threads:   the number of reader threads, get_num_threads() by
+                   default.
+        capacity:  the number of decoded patches that may wait to be consumed."""
+
+        if mode not in ('RGB', 'RGBA'):
+            raise ValueError(f"mode must be one of ['RGB', 'RGBA']. Given {mode}")
+        if threads is None:
+            threads = get_num_threads()
+        elif threads < 1:
+            raise ValueError(f"threads must be at least 1. Given {threads}")
+        self._slide = slide
+        self._params = (patch_size, stride, target_mpp, min_tissue_fraction)
+        self.mode = mode
+        self.threads = threads
+        self.capacity = capacity
+        self._shard = (0, 1)
+        self._sampler = None
+
+    def __repr__(self) -> str:
+        index, count = self._shard
+        return f"{self.__class__.__name__}({self._slide}, " \
+               f"patch_size={self._params[0]}, shard={index}/{count})"
+
+    def __reduce__(self):
+        """Pickle the reader without its sampled patches, so that worker
+        processes sample them again."""
+        return self.__class__, (self._slide, *self._params, self.mode,
+                                self.threads, self.capacity), \
+            {'_shard': self._shard}
+
+    def __len__(self) -> int:
+        """The number of patches of the shard."""
+        index, count = self._shard
+        return len(range(index, len(self.sampler), count))
+
+    def __iter__(self) -> Iterator[Tuple[np.ndarray, Tuple[int, int]]]:
+        """Iterate over the patches of the shard as (array, (x, y)) pairs,
+        the (x, y) level 0 position of each patch, in completion order.
+
+        Patches are read and decoded without holding the GIL."""
+        index, count = self._shard
+        loader = self.sampler._sampler.loader(
+            self.threads, self.capacity, self.mode, index, count)
+        try:
+            yield from loader
+        finally:
+            loader.shutdown()
+
+    @property
+    def sampler(self) -> PatchSampler:
+        """The sampled patches of the whole slide, sampled on first use."""
+        if self._sampler is None:
+            self._sampler = PatchSampler(self._slide, *self._params)
+        return self._sampler
+
+    @property
+    def shard(self) -> Tuple[int, int]:
+        """The (index, count) shard of the patches read: every count-th
+        patch, starting from the index-th."""
+        return self._shard
+
+    def worker_init(self, worker_id: Optional[int] = None,
+                    num_workers: Optional[int] = None):
+        """Read only the share of the patches of a DataLoader worker.
+
+        worker_id, num_workers: the worker and the number of workers, taken
+                   from torch.utils.data.get_worker_info() by default. Out of
+                   a worker, the reader keeps every patch."""
+        if worker_id is None or num_workers is None:
+            info = _torch_worker_info()
+            if info is None:
+                worker_id, num_workers = 0, 1
+            else:
+                worker_id = info.id if worker_id is None else worker_id
+                num_workers = info.num_workers if num_workers is None else num_workers
+        if not 0 <= worker_id < num_workers:
+            raise ValueError(
+                f"Worker {worker_id} is not below the worker count {num_workers}")
+        self._shard = (worker_id, num_workers)
+
+
+def _torch_worker_info():
+    try:
+        from torch.utils.data import get_worker_info
+    except ImportError:
+        return None
+    return get_worker_info()
@@ -0,0 +1,82 @@
+"""Support for reading from many slides.
+
+This module opens slides on demand by path, keeping a bounded number of them
+open, as tile servers and dataset readers serving many slides need.
+"""
+from pathlib import Path
+from typing import Optional, Tuple, Union
+
+import numpy as np
+
+from PIL import Image
+
+from openslide_py.geometry import Address, Size
+from openslide_py.open_slide import OpenSlide
+from openslide_py.openslide_py import _SlidePool
+
+
+class SlidePool:
+    """Slides opened on first access by path, at most max_open of them kept
+    open: when the limit is reached, the least recently used slide is closed.
+
+    Paths are compared as given, so that a slide accessed through a relative
+    and an absolute path is opened twice. Slides are opened without holding
+    the GIL, and the pool may be shared by threads."""
+
+    def __init__(self, max_open: int = 16, attach_color_profile: bool = False):
+        """Create an empty pool.
+
+        max_open:             the maximum number of slides kept open, at
+                              least 1.
+        attach_color_profile: attach the slide color profiles to the images
+                              read, as OpenSlide does."""
+        self._pool = _SlidePool(max_open)
+        self.attach_color_profile = attach_color_profile
+
+    def __repr__(self) -> str:
+        return f"{self.__class__.__name__}(max_open={self.max_open}, open={len(self)})"
+
+    def __len__(self) -> int:
+        """The number of slides currently open."""
+        return len(self._pool)
+
+    def __contains__(self, path: Union[str, Path]) -> bool:
+        """Whether the slide at path is open."""
+        return str(path) in self._pool
+
+    @property
+    def max_open(self) -> int:
+        """The maximum number of slides kept open."""
+        return self._pool.capacity
+
+    def get(self, path: Union[str, Path]) -> OpenSlide:
+        """Return the slide at path, opening it if it is not open.
+
+        The slide shares the pooled handle and its cache, which therefore
+        cannot be changed; it stays usable after the pool closes the handle,
+        until released.
+
+        Raises
+        ------
+        FileNotFoundError
+        OpenSlideUnsupportedFormatError
+        """
+        return OpenSlide._from_handle(path, self._pool.get(str(path)),
+                                      self.attach_color_profile)
+
+    def read_region(self, path: Union[str, Path],
+                    location: Union[Address, Tuple[int, int]], level: int,
+                    size: Union[Size, Tuple[int, int]], out: Optional[np.ndarray] = None,
+                    mode: str = 'RGBA') -> Union[Image.Image, np.ndarray]:
+        """Read a region of the slide at path, as OpenSlide.read_region()
+        does, opening the slide if it is not open."""
+        return self.get(path).read_region(location, level, size, out, mode)
+
+    def close(self, path: Union[str, Path]):
+        """Close the slide at path, if open, so that it is opened again on
+        next access, for instance after the file was replaced."""
+        self._pool.remove(str(path))
+
+    def clear(self):
+        """Close every slide."""
+        self._pool.clear()
@@ -0,0 +1,58 @@
+"""Support for tissue detection.
+
+This module computes tissue masks of slides and traces the contours of masks
+into polygons.
+"""
+from typing import Any, Dict, List, Optional, Tuple
+
+import numpy as np
+
+from openslide_py import OpenSlide
+from openslide_py import openslide_py as _lib
+
+TISSUE = 255
+BACKGROUND = 0
+
+
+def tissue_mask(slide: OpenSlide, thumbnail_size: int = 1024,
+                threshold: Optional[int] = None, opening_radius: int = 1,
+                closing_radius: int = 2) -> np.ndarray:
+    """Compute a tissue mask of a slide, on a thumbnail converted to luminance.
+
+    slide:          an OpenSlide slide.
+    thumbnail_size: the longest side of the thumbnail, in pixels.
+    threshold:      the luminance at or below which a pixel is tissue, or None
+                    to select it with Otsu's method.
+    opening_radius: the radius of the opening removing isolated tissue pixels,
+                    0 to disable.
+    closing_radius: the radius of the closing filling small holes in the
+                    tissue, 0 to disable.
+
+    Returns a (height, width) uint8 array of the thumbnail dimensions, tissue
+    pixels being TISSUE and others BACKGROUND."""
+    slide._check_closed()
+    return _lib.tissue_mask(slide._osr, thumbnail_size, threshold,
+                            opening_radius, closing_radius)
+
+
+def otsu_threshold(image: np.ndarray) -> int:
+    """Select the threshold separating the two classes of a (height, width)
+    uint8 image with Otsu's method: pixels at or below it form the darker
+    class."""
+    return _lib.otsu_threshold(np.asarray(image, dtype=np.uint8))
+
+
+def mask_contours(mask: np.ndarray, dimensions: Tuple[int, int],
+                  tolerance: float = 0.0) -> List[Dict[str, Any]]:
+    """Trace the contours of the non-zero areas of a mask into polygons.
+
+    mask:       a (height, width) uint8 mask covering the whole slide, such as
+                a tissue mask.
+    dimensions: the level 0 (width, height) of the slide.
+    tolerance:  the maximum distance between a contour and its simplification,
+                in level 0 pixels: 0 only removes aligned points.
+
+    Returns level 0 polygons as {'exterior': [(x, y), ...], 'holes': [...]}
+    dicts."""
+    return _lib.mask_contours(np.asarray(mask, dtype=np.uint8), dimensions,
+                              tolerance)
@@ -1,15 +1,26 @@
-use pyo3::exceptions::{PyFileNotFoundError, PyIndexError, PyKeyError};
+use pyo3::exceptions::{
+    PyBufferError, PyFileNotFoundError, PyIOError, PyIndexError, PyKeyError, PyValueError,
+};
 use pyo3::prelude::*;
+use pyo3::{ffi, AsPyPointer};
 
+use std::os::raw::{c_char, c_int, c_void};
 use std::path::Path;
+use std::ptr;
+use std::sync::{Arc, Mutex};
 
+use image::GrayImage;
+use ndarray::{s, Array2, Array3, Array4};
 use ndarray_image::{NdColor, NdImage};
-use numpy::{IntoPyArray, PyArray3};
+use numpy::{IntoPyArray, PyArray2, PyArray3, PyArray4, PyReadonlyArray2};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use pyo3::create_exception;
 use pyo3::exceptions::PyException;
+use pyo3::once_cell::GILOnceCell;
 
-use pyo3::types::PyType;
+use pyo3::types::{PyBytes, PyDict, PyType};
 
 create_exception!(openslide_py, OpenSlideError, PyException);
 create_exception!(openslide_py, OpenSlideUnsupportedFormatError, PyException);
@@ -21,46 +32,318 @@ fn match_error(error: openslide_rs::OpenSlideError) -> PyErr {
             OpenSlideUnsupportedFormatError::new_err(m)
         }
         openslide_rs::OpenSlideError::IndexError(m) => PyIndexError::new_err(m),
+        openslide_rs::OpenSlideError::OutOfBounds(m) => PyIndexError::new_err(m),
+        openslide_rs::OpenSlideError::InvalidArgument(m) => PyValueError::new_err(m),
+        openslide_rs::OpenSlideError::IoError(m) => PyIOError::new_err(m),
+        openslide_rs::OpenSlideError::ImageError(m) => OpenSlideError::new_err(m),
         openslide_rs::OpenSlideError::InternalError(m) => OpenSlideError::new_err(m),
     }
 }
 
+/// The number of channels of an array mode.
+fn mode_channels(mode: &str) -> PyResult<usize> {
+    match mode {
+        "RGB" => Ok(3),
+        "RGBA" => Ok(4),
+        _ => Err(PyValueError::new_err(format!(
+            "mode must be one of ['RGB', 'RGBA']. Given {}",
+            mode
+        ))),
+    }
+}
+
+/// The tile order of a name.
+fn tile_order(order: &str) -> PyResult<openslide_rs::TileOrder> {
+    match order {
+        "row-major" => Ok(openslide_rs::TileOrder::RowMajor),
+        "column-major" => Ok(openslide_rs::TileOrder::ColumnMajor),
+        "hilbert" => Ok(openslide_rs::TileOrder::Hilbert),
+        _ => Err(PyValueError::new_err(format!(
+            "order must be one of ['row-major', 'column-major', 'hilbert']. Given {}",
+            order
+        ))),
+    }
+}
+
+/// The resize filter of a name.
+fn resize_filter(filter: &str) -> PyResult<openslide_rs::ResizeFilter> {
+    match filter {
+        "nearest" => Ok(openslide_rs::ResizeFilter::Nearest),
+        "triangle" => Ok(openslide_rs::ResizeFilter::Triangle),
+        "lanczos3" => Ok(openslide_rs::ResizeFilter::Lanczos3),
+        _ => Err(PyValueError::new_err(format!(
+            "filter must be one of ['nearest', 'triangle', 'lanczos3']. Given {}",
+            filter
+        ))),
+    }
+}
+
+/// The image format of a name, as Pillow calls it, and a lossy quality.
+fn image_format(format: &str, quality: u8) -> PyResult<openslide_rs::Format> {
+    match format.to_ascii_lowercase().as_str() {
+        "jpeg" | "jpg" => Ok(openslide_rs::Format::Jpeg { quality }),
+        "png" => Ok(openslide_rs::Format::Png),
+        "webp" => Ok(openslide_rs::Format::Webp { quality }),
+        _ => Err(PyValueError::new_err(format!(
+            "format must be one of ['jpeg', 'png', 'webp']. Given {}",
+            format
+        ))),
+    }
+}
+
+/// Copy the first `channels` channels of an image into an array.
+fn to_array(image: NdColor, channels: usize) -> Array3<u8> {
+    image.slice(s![.., .., ..channels]).to_owned()
+}
+
+/// The thread pool set by `set_num_threads`, `None` for the global rayon pool.
+static THREAD_POOL: GILOnceCell<Mutex<Option<Arc<ThreadPool>>>> = GILOnceCell::new();
+
+/// The thread pool of a parallel call: a dedicated one of `threads` threads if
+/// given, the one set by `set_num_threads` otherwise, if any.
+fn thread_pool(py: Python, threads: Option<usize>) -> PyResult<Option<Arc<ThreadPool>>> {
+    match threads {
+        Some(threads) => build_thread_pool(threads).map(Some),
+        None => Ok(THREAD_POOL
+            .get_or_init(py, || Mutex::new(None))
+            .lock()
+            .unwrap()
+            .clone()),
+    }
+}
+
+fn build_thread_pool(threads: usize) -> PyResult<Arc<ThreadPool>> {
+    if threads == 0 {
+        return Err(PyValueError::new_err("threads must be at least 1"));
+    }
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| OpenSlideError::new_err(e.to_string()))?;
+    Ok(Arc::new(pool))
+}
+
+/// Run `f` in `pool`, or on the global rayon pool.
+fn install<R: Send>(pool: Option<&ThreadPool>, f: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// Set the number of threads of batch reads, level exports and patch loading, or
+/// with `None` use the global rayon pool again.
+#[pyfunction]
+fn set_num_threads(py: Python, threads: Option<usize>) -> PyResult<()> {
+    let pool = threads.map(build_thread_pool).transpose()?;
+    *THREAD_POOL
+        .get_or_init(py, || Mutex::new(None))
+        .lock()
+        .unwrap() = pool;
+    Ok(())
+}
+
+/// The number of threads of batch reads, level exports and patch loading.
+#[pyfunction]
+fn get_num_threads(py: Python) -> PyResult<usize> {
+    let pool = thread_pool(py, None)?;
+    Ok(pool.map_or_else(rayon::current_num_threads, |pool| {
+        pool.current_num_threads()
+    }))
+}
+
+/// Send the warnings of the C libraries to the `logging` module, under the
+/// `openslide` logger, instead of the standard error.
+///
+/// This replaces the glib default log handler of the whole process, capturing the
+/// messages of every other glib user too. Messages logged while reading without the
+/// GIL wait for it: a thread holding the GIL while calling into OpenSlide, waited
+/// for by a reading thread, never gets to log. Calling this function again does
+/// nothing.
+#[pyfunction]
+fn forward_native_logs() {
+    openslide_rs::forward_native_logs();
+}
+
+/// Read `(w, h)` regions in parallel without holding the GIL, into a single
+/// `(n, h, w, channels)` array.
+fn read_stack<'py>(
+    py: Python<'py>,
+    slide: &openslide_rs::OpenSlide,
+    regions: Vec<openslide_rs::Region>,
+    (w, h): (u32, u32),
+    channels: usize,
+    threads: Option<usize>,
+) -> PyResult<&'py PyArray4<u8>> {
+    let n = regions.len();
+    let pixels = (w as usize) * (h as usize);
+    let mut buffer = vec![0; n * pixels * channels];
+    if pixels > 0 {
+        let pool = thread_pool(py, threads)?;
+        py.allow_threads(|| {
+            install(pool.as_deref(), || {
+                buffer
+                    .par_chunks_mut(pixels * channels)
+                    .zip(regions)
+                    .try_for_each(|(dest, region)| read_region_into(slide, region, dest, channels))
+            })
+        })
+        .map_err(match_error)?;
+    }
+    let array = Array4::from_shape_vec((n, h as usize, w as usize, channels), buffer)
+        .map_err(|e| OpenSlideError::new_err(e.to_string()))?;
+    Ok(array.into_pyarray(py))
+}
+
+/// Read a region into `dest`, of `channels` bytes per pixel.
+fn read_region_into(
+    slide: &openslide_rs::OpenSlide,
+    region: openslide_rs::Region,
+    dest: &mut [u8],
+    channels: usize,
+) -> Result<(), openslide_rs::OpenSlideError> {
+    if channels == 4 {
+        return slide.read_region_into(region, dest);
+    }
+    let mut rgba = vec![0; dest.len() / channels * 4];
+    slide.read_region_into(region, &mut rgba)?;
+    for (pixel, rgb) in rgba.chunks_exact(4).zip(dest.chunks_exact_mut(channels)) {
+        rgb.copy_from_slice(&pixel[..channels]);
+    }
+    Ok(())
+}
+
+/// Run a blocking read on the tokio blocking pool, as a Python awaitable resolving
+/// to its array.
+#[cfg(feature = "asyncio")]
+fn read_async<F>(py: Python, read: F) -> PyResult<&PyAny>
+where
+    F: FnOnce() -> Result<Array3<u8>, openslide_rs::OpenSlideError> + Send + 'static,
+{
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let array = tokio::task::spawn_blocking(read)
+            .await
+            .map_err(|e| OpenSlideError::new_err(e.to_string()))?
+            .map_err(match_error)?;
+        Python::with_gil(|py| Ok(array.into_pyarray(py).into_py(py)))
+    })
+}
+
 #[pyclass]
 struct _OpenSlide {
-    inner: openslide_rs::OpenSlide,
+    /// The slide, or `None` once closed
+    inner: Option<Arc<openslide_rs::OpenSlide>>,
+    path: String,
+}
+
+impl _OpenSlide {
+    /// The open slide, raising `OpenSlideError` once closed.
+    fn slide(&self) -> PyResult<&Arc<openslide_rs::OpenSlide>> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| OpenSlideError::new_err("Slide object was closed"))
+    }
+
+    /// The open slide, for changes that slide pools, Deep Zoom generators and patch
+    /// samplers sharing it would not expect.
+    fn slide_mut(&mut self) -> PyResult<&mut openslide_rs::OpenSlide> {
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| OpenSlideError::new_err("Slide object was closed"))?;
+        Arc::get_mut(inner).ok_or_else(|| {
+            PyValueError::new_err("Cache cannot change while the slide is pooled, tiled or sampled")
+        })
+    }
+}
+
+#[pyclass]
+struct _Cache {
+    inner: openslide_rs::Cache,
+}
+
+#[pymethods]
+impl _Cache {
+    #[new]
+    fn new(capacity: usize) -> Self {
+        _Cache {
+            inner: openslide_rs::Cache::new(capacity),
+        }
+    }
+
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
 }
 
 #[pymethods]
 impl _OpenSlide {
     #[classmethod]
-    fn detect_format(_cls: &PyType, filename: &str) -> PyResult<String> {
-        openslide_rs::OpenSlide::detect_vendor(Path::new(filename)).map_err(match_error)
+    fn detect_format(_cls: &PyType, filename: &str) -> PyResult<Option<String>> {
+        match openslide_rs::OpenSlide::detect_vendor(Path::new(filename)) {
+            Ok(vendor) => Ok(Some(vendor)),
+            // openslide-python only raises for missing files
+            Err(openslide_rs::OpenSlideError::UnsupportedFile(_)) => Ok(None),
+            Err(e) => Err(match_error(e)),
+        }
     }
 
     #[new]
     fn new(filename: &str) -> PyResult<Self> {
         let inner = openslide_rs::OpenSlide::open(Path::new(filename)).map_err(match_error)?;
-        Ok(_OpenSlide { inner })
+        Ok(_OpenSlide {
+            inner: Some(Arc::new(inner)),
+            path: filename.to_string(),
+        })
+    }
+
+    /// Release the slide. Deep Zoom generators of the slide keep it open until they
+    /// are released too.
+    fn close(&mut self) {
+        self.inner = None;
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    /// The error the slide is stuck on, or `None` if it is healthy or closed.
+    #[getter]
+    fn error(&self) -> Option<String> {
+        self.inner.as_ref().and_then(|slide| slide.error())
+    }
+
+    /// Open the file again as a new slide, replacing the current one, closed or
+    /// not. Deep Zoom generators of the slide keep the previous one.
+    fn reopen(&mut self, py: Python) -> PyResult<()> {
+        let path = Path::new(&self.path);
+        let inner = py
+            .allow_threads(|| openslide_rs::OpenSlide::open(path))
+            .map_err(match_error)?;
+        self.inner = Some(Arc::new(inner));
+        Ok(())
     }
 
     fn level_dimensions(&self, level: u32) -> PyResult<(u64, u64)> {
         let openslide_rs::Size { w, h } =
-            self.inner.level_dimensions(level).map_err(match_error)?;
+            self.slide()?.level_dimensions(level).map_err(match_error)?;
         Ok((w as u64, h as u64))
     }
 
     fn level_downsample(&self, level: u32) -> PyResult<f32> {
-        self.inner.level_downsample(level).map_err(match_error)
+        self.slide()?.level_downsample(level).map_err(match_error)
     }
 
     fn best_level_for_downsample(&self, downsample: f32) -> PyResult<u32> {
-        self.inner
+        self.slide()?
             .best_level_for_downsample(downsample)
             .map_err(match_error)
     }
 
     fn property(&self, name: &str) -> PyResult<String> {
-        match self.inner.property(name).map_err(match_error)? {
+        match self.slide()?.property(name).map_err(match_error)? {
             None => Err(PyKeyError::new_err(format!(
                 "Property {} does not exist.",
                 name
@@ -69,23 +352,35 @@ impl _OpenSlide {
         }
     }
 
-    fn associated_image<'py>(&self, py: Python<'py>, name: &str) -> PyResult<&'py PyArray3<u8>> {
-        let image = match self.inner.associated_image(name).map_err(match_error)? {
-            None => {
-                return Err(PyKeyError::new_err(format!(
-                    "Image {} does not exist.",
-                    name
-                )))
-            }
-            Some(v) => v,
-        };
-        let image: NdColor = NdImage(&image).into();
-        Ok(image.to_owned().into_pyarray(py))
+    #[args(mode = "\"RGBA\"")]
+    fn associated_image<'py>(
+        &self,
+        py: Python<'py>,
+        name: &str,
+        mode: &str,
+    ) -> PyResult<&'py PyArray3<u8>> {
+        let channels = mode_channels(mode)?;
+        let slide = self.slide()?;
+        // Decoding is blocking C code, other Python threads may run meanwhile
+        let image = py
+            .allow_threads(|| {
+                slide
+                    .associated_image(name)
+                    .map(|image| image.map(|image| to_array(NdImage(&image).into(), channels)))
+            })
+            .map_err(match_error)?;
+        match image {
+            None => Err(PyKeyError::new_err(format!(
+                "Image {} does not exist.",
+                name
+            ))),
+            Some(v) => Ok(v.into_pyarray(py)),
+        }
     }
 
     #[getter]
     fn level_count(&self) -> PyResult<u32> {
-        self.inner.level_count().map_err(match_error)
+        self.slide()?.level_count().map_err(match_error)
     }
 
     #[getter]
@@ -106,43 +401,1031 @@ impl _OpenSlide {
 
     #[getter]
     fn property_names(&self) -> PyResult<Vec<String>> {
-        self.inner.property_names().map_err(match_error)
+        self.slide()?.property_names().map_err(match_error)
+    }
+
+    #[getter]
+    fn color_profile(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let slide = self.slide()?;
+        let profile = py
+            .allow_threads(|| slide.icc_profile())
+            .map_err(match_error)?;
+        Ok(profile.map(|profile| PyBytes::new(py, &profile).into_py(py)))
+    }
+
+    #[getter]
+    fn mpp(&self) -> PyResult<Option<(f32, f32)>> {
+        self.slide()?.mpp().map_err(match_error)
+    }
+
+    #[getter]
+    fn magnification(&self) -> PyResult<Option<f32>> {
+        self.slide()?.magnification().map_err(match_error)
+    }
+
+    /// The `((x, y), (w, h))` non-empty region, if the slide has bounds properties.
+    #[getter]
+    fn bounds(&self) -> PyResult<Option<((u32, u32), (u32, u32))>> {
+        let bounds = self.slide()?.bounds().map_err(match_error)?;
+        Ok(bounds.map(|(address, size)| ((address.x, address.y), (size.w, size.h))))
+    }
+
+    /// The standard properties, as a dictionary of plain values: sizes and addresses
+    /// are tuples, and levels `((w, h), downsample, tile_size)` tuples.
+    #[getter]
+    fn info(&self, py: Python) -> PyResult<PyObject> {
+        let properties = self.slide()?.slide_properties().map_err(match_error)?;
+        let info = PyDict::new(py);
+        info.set_item("vendor", properties.vendor)?;
+        info.set_item("quickhash", properties.quickhash)?;
+        info.set_item("mpp", properties.mpp)?;
+        info.set_item("objective_power", properties.objective_power)?;
+        info.set_item(
+            "bounds",
+            properties
+                .bounds
+                .map(|(address, size)| ((address.x, address.y), (size.w, size.h))),
+        )?;
+        let levels: Vec<_> = properties
+            .levels
+            .iter()
+            .map(|level| {
+                (
+                    (level.dimensions.w, level.dimensions.h),
+                    level.downsample,
+                    level.tile_size.map(|size| (size.w, size.h)),
+                )
+            })
+            .collect();
+        info.set_item("levels", levels)?;
+        Ok(info.into())
     }
 
     #[getter]
     fn associated_image_names(&self) -> PyResult<Vec<String>> {
-        self.inner.associated_image_names().map_err(match_error)
+        self.slide()?.associated_image_names().map_err(match_error)
     }
 
     fn set_cache_size(&mut self, cache_size: u32) -> PyResult<()> {
-        self.inner.set_cache_size(cache_size).map_err(match_error)
+        self.slide_mut()?
+            .set_cache_size(cache_size)
+            .map_err(match_error)
+    }
+
+    fn set_cache(&mut self, cache: &_Cache) -> PyResult<()> {
+        self.slide_mut()?
+            .set_cache(&cache.inner)
+            .map_err(match_error)
+    }
+
+    /// Export a level to a flat RGB array file, returning the offset of the pixels in
+    /// the file.
+    #[args(threads = "None")]
+    fn export_level(
+        &self,
+        py: Python,
+        level: u32,
+        path: &str,
+        threads: Option<usize>,
+    ) -> PyResult<usize> {
+        let slide = self.slide()?;
+        let path = Path::new(path);
+        let pool = thread_pool(py, threads)?;
+        let memmap = py
+            .allow_threads(|| {
+                install(pool.as_deref(), || {
+                    openslide_rs::export_level_memmap(
+                        slide,
+                        level,
+                        path,
+                        openslide_rs::Parallelism::Auto,
+                    )
+                })
+            })
+            .map_err(match_error)?;
+        Ok(memmap.offset())
+    }
+
+    /// Read many `((x, y), level, (w, h))` regions in parallel, as a list of arrays,
+    /// or with `stack` as a single `(n, h, w, channels)` array of same-sized regions.
+    #[args(mode = "\"RGBA\"", stack = "false", threads = "None")]
+    fn read_regions(
+        &self,
+        py: Python,
+        regions: Vec<((u32, u32), u32, (u32, u32))>,
+        mode: &str,
+        stack: bool,
+        threads: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let channels = mode_channels(mode)?;
+        let slide = self.slide()?;
+        let region =
+            |&(address, level, size): &((u32, u32), u32, (u32, u32))| openslide_rs::Region {
+                address: openslide_rs::Address::from(address),
+                level: level as _,
+                size: openslide_rs::Size::from(size),
+            };
+
+        if !stack {
+            let pool = thread_pool(py, threads)?;
+            let arrays = py
+                .allow_threads(|| {
+                    install(pool.as_deref(), || {
+                        regions
+                            .par_iter()
+                            .map(|coordinates| {
+                                slide
+                                    .read_region(region(coordinates))
+                                    .map(|pixels| to_array(NdImage(&pixels).into(), channels))
+                            })
+                            .collect::<Result<Vec<Array3<u8>>, _>>()
+                    })
+                })
+                .map_err(match_error)?;
+            let arrays: Vec<PyObject> = arrays
+                .into_iter()
+                .map(|array| array.into_pyarray(py).into_py(py))
+                .collect();
+            return Ok(arrays.into_py(py));
+        }
+
+        let (w, h) = regions.first().map_or((0, 0), |(_, _, size)| *size);
+        if regions.iter().any(|(_, _, size)| *size != (w, h)) {
+            return Err(PyValueError::new_err(
+                "Stacked regions must all be of the same size",
+            ));
+        }
+        let regions = regions.iter().map(region).collect();
+        let array = read_stack(py, slide, regions, (w, h), channels, threads)?;
+        Ok(array.into_py(py))
+    }
+
+    /// Read same-sized patches of a level at `(x, y)` level 0 addresses in parallel,
+    /// into a single `(n, h, w, channels)` array.
+    #[args(mode = "\"RGBA\"", threads = "None")]
+    fn read_patches<'py>(
+        &self,
+        py: Python<'py>,
+        addresses: Vec<(u32, u32)>,
+        level: u32,
+        size: (u32, u32),
+        mode: &str,
+        threads: Option<usize>,
+    ) -> PyResult<&'py PyArray4<u8>> {
+        let channels = mode_channels(mode)?;
+        let slide = self.slide()?;
+        let regions = addresses
+            .into_iter()
+            .map(|address| openslide_rs::Region {
+                address: openslide_rs::Address::from(address),
+                level: level as _,
+                size: openslide_rs::Size::from(size),
+            })
+            .collect();
+        read_stack(py, slide, regions, size, channels, threads)
+    }
+
+    #[cfg(feature = "asyncio")]
+    #[args(mode = "\"RGBA\"")]
+    fn read_region_async<'py>(
+        &self,
+        py: Python<'py>,
+        address: (u32, u32),
+        level: u32,
+        size: (u32, u32),
+        mode: &str,
+    ) -> PyResult<&'py PyAny> {
+        let channels = mode_channels(mode)?;
+        let slide = self.slide()?.clone();
+        let region_coordinates = openslide_rs::Region {
+            address: openslide_rs::Address::from(address),
+            level: level as _,
+            size: openslide_rs::Size::from(size),
+        };
+        read_async(py, move || {
+            slide
+                .read_region(region_coordinates)
+                .map(|region| to_array(NdImage(&region).into(), channels))
+        })
+    }
+
+    #[args(filter = "\"lanczos3\"", mode = "\"RGBA\"")]
+    fn thumbnail<'py>(
+        &self,
+        py: Python<'py>,
+        size: (u32, u32),
+        filter: &str,
+        mode: &str,
+    ) -> PyResult<&'py PyArray3<u8>> {
+        let filter = resize_filter(filter)?;
+        let channels = mode_channels(mode)?;
+        let slide = self.slide()?;
+        let thumbnail = py
+            .allow_threads(|| {
+                slide
+                    .thumbnail_with_filter(openslide_rs::Size::from(size), filter)
+                    .map(|thumbnail| to_array(NdImage(&thumbnail).into(), channels))
+            })
+            .map_err(match_error)?;
+        Ok(thumbnail.into_pyarray(py))
     }
 
+    /// Read a region as a new array, or into `out`, a C-contiguous `(h, w, channels)`
+    /// uint8 array, in place. `mode` is `"RGBA"` or `"RGB"`, dropping alpha.
+    #[args(out = "None", mode = "\"RGBA\"")]
     fn read_region<'py>(
         &self,
         py: Python<'py>,
         address: (u32, u32),
         level: u32,
         size: (u32, u32),
+        out: Option<&'py PyArray3<u8>>,
+        mode: &str,
     ) -> PyResult<&'py PyArray3<u8>> {
+        let channels = mode_channels(mode)?;
         let region_coordinates = openslide_rs::Region {
             address: openslide_rs::Address::from(address),
             level: level as _,
             size: openslide_rs::Size::from(size),
         };
+        let slide = self.slide()?;
+        if let Some(out) = out {
+            let (w, h) = size;
+            if out.shape() != [h as usize, w as usize, channels] {
+                return Err(PyValueError::new_err(format!(
+                    "out must be of shape ({}, {}, {}), not {:?}",
+                    h,
+                    w,
+                    channels,
+                    out.shape()
+                )));
+            }
+            let mut array = out
+                .try_readwrite()
+                .map_err(|_| PyValueError::new_err("out must be writeable"))?;
+            let dest = array
+                .as_slice_mut()
+                .map_err(|_| PyValueError::new_err("out must be C-contiguous"))?;
+            py.allow_threads(|| read_region_into(slide, region_coordinates, dest, channels))
+                .map_err(match_error)?;
+            return Ok(out);
+        }
+        let region = py
+            .allow_threads(|| {
+                slide
+                    .read_region(region_coordinates)
+                    .map(|region| to_array(NdImage(&region).into(), channels))
+            })
+            .map_err(match_error)?;
+        Ok(region.into_pyarray(py))
+    }
+
+    /// Read a region as a `(h, w)` uint32 array of premultiplied ARGB pixels, as
+    /// returned by the C library.
+    fn read_region_raw<'py>(
+        &self,
+        py: Python<'py>,
+        address: (u32, u32),
+        level: u32,
+        size: (u32, u32),
+    ) -> PyResult<&'py PyArray2<u32>> {
+        let region = openslide_rs::Region {
+            address: openslide_rs::Address::from(address),
+            level: level as _,
+            size: openslide_rs::Size::from(size),
+        };
+        let slide = self.slide()?;
+        let raw = py
+            .allow_threads(|| slide.read_region_raw(region))
+            .map_err(match_error)?;
+        let (w, h) = size;
+        Ok(Array2::from_shape_vec((h as usize, w as usize), raw)
+            .unwrap()
+            .into_pyarray(py))
+    }
+
+    /// Read a region into a new buffer exporting its `(h, w, channels)` uint8 pixels
+    /// through the buffer protocol. `mode` is `"RGBA"` or `"RGB"`, dropping alpha.
+    #[args(mode = "\"RGBA\"")]
+    fn read_region_buffer(
+        &self,
+        py: Python,
+        address: (u32, u32),
+        level: u32,
+        size: (u32, u32),
+        mode: &str,
+    ) -> PyResult<_RegionBuffer> {
+        let channels = mode_channels(mode)?;
+        let (w, h) = size;
+        let region = openslide_rs::Region {
+            address: openslide_rs::Address::from(address),
+            level: level as _,
+            size: openslide_rs::Size::from(size),
+        };
+        let slide = self.slide()?;
+        let mut data = vec![0; w as usize * h as usize * channels];
+        py.allow_threads(|| read_region_into(slide, region, &mut data, channels))
+            .map_err(match_error)?;
+        Ok(_RegionBuffer {
+            data,
+            shape: [h as isize, w as isize, channels as isize],
+            strides: [(w as usize * channels) as isize, channels as isize, 1],
+        })
+    }
+}
+
+/// The pixels of a region, exported without copies through the buffer protocol as a
+/// writable C-contiguous `(h, w, channels)` array of unsigned bytes.
+#[pyclass]
+struct _RegionBuffer {
+    data: Vec<u8>,
+    shape: [isize; 3],
+    strides: [isize; 3],
+}
+
+#[pymethods]
+impl _RegionBuffer {
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Fill `view` with the pixels, as 3 dimensions if the consumer requests shapes,
+    /// as a flat run of bytes otherwise.
+    unsafe fn __getbuffer__(
+        mut slf: PyRefMut<Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        (*view).obj = slf.as_ptr();
+        ffi::Py_INCREF((*view).obj);
+        (*view).buf = slf.data.as_mut_ptr() as *mut c_void;
+        (*view).len = slf.data.len() as isize;
+        (*view).readonly = 0;
+        (*view).itemsize = 1;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            b"B\0".as_ptr() as *mut c_char
+        } else {
+            ptr::null_mut()
+        };
+        if flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+            (*view).ndim = 3;
+            (*view).shape = slf.shape.as_mut_ptr();
+        } else {
+            (*view).ndim = 1;
+            (*view).shape = ptr::null_mut();
+        }
+        (*view).strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            slf.strides.as_mut_ptr()
+        } else {
+            ptr::null_mut()
+        };
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+        Ok(())
+    }
+}
+
+#[pyclass]
+struct _DeepZoom {
+    inner: Arc<openslide_rs::DeepZoom<Arc<openslide_rs::OpenSlide>>>,
+}
+
+impl _DeepZoom {
+    /// Check a tile address, raising `ValueError` as openslide-python does.
+    fn tile_address(
+        &self,
+        level: i64,
+        address: (i64, i64),
+    ) -> PyResult<(usize, openslide_rs::Address)> {
+        if level < 0 || level as usize >= self.inner.level_count() {
+            return Err(PyValueError::new_err("Invalid level"));
+        }
+        let level = level as usize;
+        let tiles = self.inner.level_tiles()[level];
+        let (x, y) = address;
+        if x < 0 || y < 0 || x >= i64::from(tiles.w) || y >= i64::from(tiles.h) {
+            return Err(PyValueError::new_err("Invalid address"));
+        }
+        Ok((
+            level,
+            openslide_rs::Address {
+                x: x as u32,
+                y: y as u32,
+            },
+        ))
+    }
+
+    /// Encode a tile without holding the GIL.
+    fn tile_bytes(
+        &self,
+        py: Python,
+        level: i64,
+        address: (i64, i64),
+        format: openslide_rs::Format,
+    ) -> PyResult<PyObject> {
+        let (level, address) = self.tile_address(level, address)?;
+        let deepzoom = &self.inner;
+        let bytes = py
+            .allow_threads(|| deepzoom.tile_bytes(level, address, format))
+            .map_err(match_error)?;
+        Ok(PyBytes::new(py, &bytes).into_py(py))
+    }
+}
+
+#[pymethods]
+impl _DeepZoom {
+    #[new]
+    fn new(osr: &_OpenSlide, tile_size: u32, overlap: u32, limit_bounds: bool) -> PyResult<Self> {
+        let inner =
+            openslide_rs::DeepZoom::new(osr.slide()?.clone(), tile_size, overlap, limit_bounds)
+                .map_err(match_error)?;
+        Ok(_DeepZoom {
+            inner: Arc::new(inner),
+        })
+    }
+
+    #[getter]
+    fn level_count(&self) -> usize {
+        self.inner.level_count()
+    }
+
+    #[getter]
+    fn level_tiles(&self) -> Vec<(u32, u32)> {
+        self.inner
+            .level_tiles()
+            .iter()
+            .map(|size| (size.w, size.h))
+            .collect()
+    }
+
+    #[getter]
+    fn level_dimensions(&self) -> Vec<(u32, u32)> {
+        self.inner
+            .level_dimensions()
+            .iter()
+            .map(|size| (size.w, size.h))
+            .collect()
+    }
+
+    #[getter]
+    fn tile_count(&self) -> u64 {
+        self.inner.tile_count()
+    }
+
+    #[getter]
+    fn l0_offset(&self) -> (u32, u32) {
+        let openslide_rs::Address { x, y } = self.inner.l0_offset();
+        (x, y)
+    }
+
+    #[getter]
+    fn l0_dimensions(&self) -> (u32, u32) {
+        let openslide_rs::Size { w, h } = self.inner.l0_dimensions();
+        (w, h)
+    }
+
+    fn get_dzi(&self, format: &str) -> PyResult<String> {
+        let dzi_format = match format {
+            "png" => openslide_rs::Format::Png,
+            "jpeg" => openslide_rs::Format::Jpeg { quality: 75 },
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "format must be one of ['png', 'jpeg']. Given {}",
+                    format
+                )))
+            }
+        };
+        // openslide-python names JPEG tiles .jpeg
+        Ok(self
+            .inner
+            .dzi(dzi_format)
+            .replacen(r#"Format="jpg""#, r#"Format="jpeg""#, 1))
+    }
+
+    #[args(mode = "\"RGBA\"")]
+    fn get_tile<'py>(
+        &self,
+        py: Python<'py>,
+        level: i64,
+        address: (i64, i64),
+        mode: &str,
+    ) -> PyResult<&'py PyArray3<u8>> {
+        let channels = mode_channels(mode)?;
+        let (level, address) = self.tile_address(level, address)?;
+        let deepzoom = &self.inner;
+        let tile = py
+            .allow_threads(|| {
+                deepzoom
+                    .read_tile(level, address)
+                    .map(|tile| to_array(NdImage(&tile).into(), channels))
+            })
+            .map_err(match_error)?;
+        Ok(tile.into_pyarray(py))
+    }
+
+    #[cfg(feature = "asyncio")]
+    #[args(mode = "\"RGBA\"")]
+    fn get_tile_async<'py>(
+        &self,
+        py: Python<'py>,
+        level: i64,
+        address: (i64, i64),
+        mode: &str,
+    ) -> PyResult<&'py PyAny> {
+        let channels = mode_channels(mode)?;
+        let (level, address) = self.tile_address(level, address)?;
+        let deepzoom = self.inner.clone();
+        read_async(py, move || {
+            deepzoom
+                .read_tile(level, address)
+                .map(|tile| to_array(NdImage(&tile).into(), channels))
+        })
+    }
+
+    /// Encode a tile, ready to be served. `quality` only applies to JPEG and WebP.
+    #[args(format = "\"jpeg\"", quality = "75")]
+    fn get_tile_bytes(
+        &self,
+        py: Python,
+        level: i64,
+        address: (i64, i64),
+        format: &str,
+        quality: u8,
+    ) -> PyResult<PyObject> {
+        let format = image_format(format, quality)?;
+        self.tile_bytes(py, level, address, format)
+    }
+
+    /// Encode the tile at `(col, row)`, returning it with its media type.
+    #[args(format = "\"jpeg\"", quality = "75")]
+    fn get_tile_response(
+        &self,
+        py: Python,
+        level: i64,
+        col: i64,
+        row: i64,
+        format: &str,
+        quality: u8,
+    ) -> PyResult<(PyObject, &'static str)> {
+        let format = image_format(format, quality)?;
+        let bytes = self.tile_bytes(py, level, (col, row), format)?;
+        Ok((bytes, format.mime_type()))
+    }
+
+    #[args(order = "\"row-major\"")]
+    fn tile_addresses(&self, level: i64, order: &str) -> PyResult<Vec<(u32, u32)>> {
+        let order = tile_order(order)?;
+        if level < 0 || level as usize >= self.inner.level_count() {
+            return Err(PyValueError::new_err("Invalid level"));
+        }
+        Ok(self
+            .inner
+            .tile_addresses(level as usize, order)
+            .map_err(match_error)?
+            .map(|address| (address.x, address.y))
+            .collect())
+    }
+
+    fn get_tile_coordinates(
+        &self,
+        level: i64,
+        address: (i64, i64),
+    ) -> PyResult<((u32, u32), usize, (u32, u32))> {
+        let (level, address) = self.tile_address(level, address)?;
         let region = self
             .inner
-            .read_region(region_coordinates)
+            .tile_region(level, address)
+            .map_err(match_error)?;
+        Ok((
+            (region.address.x, region.address.y),
+            region.level,
+            (region.size.w, region.size.h),
+        ))
+    }
+
+    fn get_tile_dimensions(&self, level: i64, address: (i64, i64)) -> PyResult<(u32, u32)> {
+        let (level, address) = self.tile_address(level, address)?;
+        let openslide_rs::Size { w, h } = self
+            .inner
+            .tile_dimensions(level, address)
+            .map_err(match_error)?;
+        Ok((w, h))
+    }
+}
+
+#[pyclass]
+struct _SlidePool {
+    inner: openslide_rs::SlidePool,
+}
+
+#[pymethods]
+impl _SlidePool {
+    #[new]
+    fn new(capacity: usize) -> Self {
+        _SlidePool {
+            inner: openslide_rs::SlidePool::new(capacity),
+        }
+    }
+
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __contains__(&self, path: &str) -> bool {
+        self.inner.contains(Path::new(path))
+    }
+
+    /// A handle on the pooled slide at `path`, opened without holding the GIL.
+    fn get(&self, py: Python, path: &str) -> PyResult<_OpenSlide> {
+        let pool = &self.inner;
+        let slide = py
+            .allow_threads(|| pool.get(Path::new(path)))
+            .map_err(match_error)?;
+        Ok(_OpenSlide {
+            inner: Some(slide),
+            path: path.to_string(),
+        })
+    }
+
+    fn remove(&self, path: &str) {
+        self.inner.remove(Path::new(path));
+    }
+
+    fn clear(&self) {
+        self.inner.clear();
+    }
+}
+
+#[pyclass]
+struct _PatchSampler {
+    inner: openslide_rs::PatchSampler<Arc<openslide_rs::OpenSlide>>,
+}
+
+#[pymethods]
+impl _PatchSampler {
+    #[new]
+    fn new(
+        py: Python,
+        osr: &_OpenSlide,
+        patch_size: u32,
+        stride: u32,
+        target_mpp: Option<f32>,
+        min_tissue_fraction: f32,
+    ) -> PyResult<Self> {
+        let slide = osr.slide()?.clone();
+        // Sampling reads a thumbnail to detect tissue
+        let inner = py
+            .allow_threads(|| {
+                openslide_rs::PatchSampler::grid(
+                    slide,
+                    patch_size,
+                    stride,
+                    target_mpp,
+                    min_tissue_fraction,
+                )
+            })
+            .map_err(match_error)?;
+        Ok(_PatchSampler { inner })
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[getter]
+    fn patch_size(&self) -> u32 {
+        self.inner.patch_size()
+    }
+
+    /// The patches, as `((x, y), level, (w, h), tissue_fraction)` tuples.
+    #[getter]
+    fn patches(&self) -> Vec<((u32, u32), usize, (u32, u32), f32)> {
+        self.inner
+            .patches()
+            .iter()
+            .map(|patch| {
+                (
+                    (patch.address.x, patch.address.y),
+                    patch.level,
+                    (patch.size.w, patch.size.h),
+                    patch.tissue_fraction,
+                )
+            })
+            .collect()
+    }
+
+    #[args(mode = "\"RGBA\"")]
+    fn read_patch<'py>(
+        &self,
+        py: Python<'py>,
+        index: usize,
+        mode: &str,
+    ) -> PyResult<&'py PyArray3<u8>> {
+        let channels = mode_channels(mode)?;
+        let patch = self
+            .inner
+            .patches()
+            .get(index)
+            .ok_or_else(|| PyIndexError::new_err(format!("Patch {} does not exist.", index)))?;
+        let sampler = &self.inner;
+        let pixels = py
+            .allow_threads(|| {
+                sampler
+                    .read_patch(patch)
+                    .map(|pixels| to_array(NdImage(&pixels).into(), channels))
+            })
+            .map_err(match_error)?;
+        Ok(pixels.into_pyarray(py))
+    }
+
+    /// Start reading the `index`-th of `count` shards of the patches on background
+    /// threads.
+    #[args(mode = "\"RGBA\"", shard_index = "0", shard_count = "1")]
+    fn loader(
+        &self,
+        threads: usize,
+        capacity: usize,
+        mode: &str,
+        shard_index: usize,
+        shard_count: usize,
+    ) -> PyResult<_PatchLoader> {
+        let channels = mode_channels(mode)?;
+        let inner = self
+            .inner
+            .clone()
+            .shard(shard_index, shard_count)
+            .and_then(|sampler| sampler.loader(threads, capacity))
+            .map_err(match_error)?;
+        Ok(_PatchLoader {
+            inner: Some(inner),
+            channels,
+        })
+    }
+}
+
+#[pyclass]
+struct _PatchLoader {
+    inner: Option<openslide_rs::PatchLoader>,
+    channels: usize,
+}
+
+#[pymethods]
+impl _PatchLoader {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    /// The next `(pixels, (x, y))` pair, waiting for it without holding the GIL.
+    fn __next__(
+        mut slf: PyRefMut<Self>,
+        py: Python,
+    ) -> PyResult<Option<(Py<PyArray3<u8>>, (u32, u32))>> {
+        let channels = slf.channels;
+        let loader = match slf.inner.as_mut() {
+            Some(loader) => loader,
+            None => return Ok(None),
+        };
+        let next = py.allow_threads(|| {
+            loader.next().map(|result| {
+                result.map(|(patch, pixels)| (patch, to_array(NdImage(&pixels).into(), channels)))
+            })
+        });
+        match next {
+            None => Ok(None),
+            Some(result) => {
+                let (patch, pixels) = result.map_err(match_error)?;
+                Ok(Some((
+                    pixels.into_pyarray(py).into(),
+                    (patch.address.x, patch.address.y),
+                )))
+            }
+        }
+    }
+
+    /// Stop the reader threads, ending the iteration.
+    fn shutdown(&mut self, py: Python) {
+        if let Some(loader) = self.inner.take() {
+            py.allow_threads(|| loader.shutdown());
+        }
+    }
+}
+
+/// Convert a mask to a `(h, w)` array.
+fn mask_to_array(mask: GrayImage) -> Array2<u8> {
+    let (w, h) = mask.dimensions();
+    Array2::from_shape_vec((h as usize, w as usize), mask.into_raw()).unwrap()
+}
+
+/// Convert a `(h, w)` array, contiguous or not, to a mask.
+fn array_to_mask(array: PyReadonlyArray2<u8>) -> GrayImage {
+    let array = array.as_array();
+    let (h, w) = array.dim();
+    GrayImage::from_raw(w as u32, h as u32, array.iter().copied().collect()).unwrap()
+}
+
+/// Convert a polygon to a `{"exterior": [(x, y), ...], "holes": [[(x, y), ...], ...]}`
+/// dict.
+fn polygon_to_dict(py: Python, polygon: &openslide_rs::Polygon) -> PyResult<PyObject> {
+    let ring = |points: &[openslide_rs::Point]| -> Vec<(f64, f64)> {
+        points.iter().map(|point| (point.x, point.y)).collect()
+    };
+    let dict = PyDict::new(py);
+    dict.set_item("exterior", ring(&polygon.exterior))?;
+    dict.set_item(
+        "holes",
+        polygon
+            .holes
+            .iter()
+            .map(|hole| ring(hole))
+            .collect::<Vec<_>>(),
+    )?;
+    Ok(dict.into())
+}
+
+/// Compute a tissue mask of the slide on a thumbnail, tissue pixels being 255.
+#[pyfunction(
+    thumbnail_size = "1024",
+    threshold = "None",
+    opening_radius = "1",
+    closing_radius = "2"
+)]
+fn tissue_mask<'py>(
+    py: Python<'py>,
+    osr: &_OpenSlide,
+    thumbnail_size: u32,
+    threshold: Option<u8>,
+    opening_radius: u32,
+    closing_radius: u32,
+) -> PyResult<&'py PyArray2<u8>> {
+    let params = openslide_rs::tissue::TissueParams {
+        thumbnail_size,
+        threshold,
+        opening_radius,
+        closing_radius,
+    };
+    let slide = osr.slide()?;
+    let mask = py
+        .allow_threads(|| openslide_rs::tissue::tissue_mask(slide, params).map(mask_to_array))
+        .map_err(match_error)?;
+    Ok(mask.into_pyarray(py))
+}
+
+/// Select the luminance threshold of a `(h, w)` uint8 image with Otsu's method.
+#[pyfunction]
+fn otsu_threshold(image: PyReadonlyArray2<u8>) -> u8 {
+    openslide_rs::tissue::otsu_threshold(&array_to_mask(image))
+}
+
+/// Trace the contours of the non-zero areas of a mask covering the whole slide into
+/// level 0 polygons.
+#[pyfunction(tolerance = "0.0")]
+fn mask_contours(
+    py: Python,
+    mask: PyReadonlyArray2<u8>,
+    dimensions: (u32, u32),
+    tolerance: f64,
+) -> PyResult<Vec<PyObject>> {
+    let mask = array_to_mask(mask);
+    let polygons = py.allow_threads(|| {
+        openslide_rs::mask_contours(&mask, openslide_rs::Size::from(dimensions), tolerance)
+    });
+    polygons
+        .iter()
+        .map(|polygon| polygon_to_dict(py, polygon))
+        .collect()
+}
+
+#[pyclass]
+struct _AnnotationSet {
+    inner: openslide_rs::AnnotationSet,
+}
+
+#[pymethods]
+impl _AnnotationSet {
+    #[staticmethod]
+    fn from_geojson(text: &str) -> PyResult<Self> {
+        let inner = openslide_rs::AnnotationSet::from_geojson(text).map_err(match_error)?;
+        Ok(_AnnotationSet { inner })
+    }
+
+    #[staticmethod]
+    fn open_geojson(filename: &str) -> PyResult<Self> {
+        let inner =
+            openslide_rs::AnnotationSet::open_geojson(Path::new(filename)).map_err(match_error)?;
+        Ok(_AnnotationSet { inner })
+    }
+
+    fn to_geojson(&self) -> String {
+        self.inner.to_geojson()
+    }
+
+    fn save_geojson(&self, filename: &str) -> PyResult<()> {
+        self.inner
+            .save_geojson(Path::new(filename))
+            .map_err(match_error)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[getter]
+    fn classifications(&self) -> Vec<&str> {
+        self.inner.classifications()
+    }
+
+    /// The annotations, as dicts of their id, name, classification, color, polygons
+    /// and measurements.
+    #[getter]
+    fn annotations(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.inner
+            .annotations
+            .iter()
+            .map(|annotation| {
+                let dict = PyDict::new(py);
+                dict.set_item("id", &annotation.id)?;
+                dict.set_item("name", &annotation.name)?;
+                dict.set_item("classification", &annotation.classification)?;
+                dict.set_item("color", annotation.color.map(|[r, g, b]| (r, g, b)))?;
+                dict.set_item(
+                    "polygons",
+                    annotation
+                        .polygons
+                        .iter()
+                        .map(|polygon| polygon_to_dict(py, polygon))
+                        .collect::<PyResult<Vec<PyObject>>>()?,
+                )?;
+                dict.set_item("measurements", &annotation.measurements)?;
+                Ok(dict.into())
+            })
+            .collect()
+    }
+
+    fn with_classifications(&self, classifications: Vec<&str>) -> Self {
+        _AnnotationSet {
+            inner: self.inner.with_classifications(&classifications),
+        }
+    }
+
+    /// Render the annotations into a `(h, w)` mask matching the region read by
+    /// `read_region`, annotated pixels being 255.
+    #[args(limit_bounds = "false")]
+    fn rasterize<'py>(
+        &self,
+        py: Python<'py>,
+        osr: &_OpenSlide,
+        address: (u32, u32),
+        level: u32,
+        size: (u32, u32),
+        limit_bounds: bool,
+    ) -> PyResult<&'py PyArray2<u8>> {
+        let region = openslide_rs::Region {
+            address: openslide_rs::Address::from(address),
+            level: level as _,
+            size: openslide_rs::Size::from(size),
+        };
+        let slide = osr.slide()?;
+        let annotations = &self.inner;
+        let mask = py
+            .allow_threads(|| {
+                annotations
+                    .rasterize(slide, &region, limit_bounds)
+                    .map(mask_to_array)
+            })
             .map_err(match_error)?;
-        let region: NdColor = NdImage(&region).into();
-        Ok(region.to_owned().into_pyarray(py))
+        Ok(mask.into_pyarray(py))
     }
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn openslide_py(py: Python, m: &PyModule) -> PyResult<()> {
+    // Glib and libtiff warnings reach the Python logging module through log, once
+    // forward_native_logs() is called
+    pyo3_log::init();
+
     m.add_class::<_OpenSlide>()?;
+    m.add_class::<_AnnotationSet>()?;
+    m.add_class::<_Cache>()?;
+    m.add_class::<_SlidePool>()?;
+    m.add_class::<_RegionBuffer>()?;
+    m.add_class::<_DeepZoom>()?;
+    m.add_class::<_PatchSampler>()?;
+    m.add_class::<_PatchLoader>()?;
+    m.add_function(wrap_pyfunction!(tissue_mask, m)?)?;
+    m.add_function(wrap_pyfunction!(otsu_threshold, m)?)?;
+    m.add_function(wrap_pyfunction!(mask_contours, m)?)?;
+    m.add_function(wrap_pyfunction!(set_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(get_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(forward_native_logs, m)?)?;
     m.add("OpenSlideError", py.get_type::<OpenSlideError>())?;
     m.add(
         "OpenSlideUnsupportedFormatError",
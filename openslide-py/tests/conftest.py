@@ -42,3 +42,8 @@ def unreadable_svs(assets):
 @pytest.fixture
 def boxes_tiff_slide(boxes_tiff):
     return OpenSlide(boxes_tiff)
+
+
+@pytest.fixture
+def annotations_geojson(assets):
+    return assets / "annotations.geojson"
@@ -0,0 +1,46 @@
+import pytest
+
+from openslide_py.annotation import ANNOTATED, AnnotationSet
+
+
+def test_open(annotations_geojson):
+    annotations = AnnotationSet.open(annotations_geojson)
+
+    # The point annotation has no area
+    assert len(annotations) == 2
+    assert annotations.classifications == ['Stroma', 'Tumor']
+    tumor = annotations.annotations[0]
+    assert tumor['name'] == 'Region 1'
+    assert tumor['classification'] == 'Tumor'
+    assert tumor['color'] == (200, 0, 0)
+    assert len(tumor['polygons'][0]['holes']) == 1
+    assert tumor['measurements'] == {'Area': 9600.0}
+    assert repr(annotations) == 'AnnotationSet(annotations=2)'
+
+    tumors = annotations.with_classifications(['Tumor'])
+    assert len(tumors) == 1
+
+
+def test_geojson_roundtrip(annotations_geojson, tmp_path):
+    annotations = AnnotationSet.open(annotations_geojson)
+
+    path = tmp_path / 'annotations.geojson'
+    annotations.save(path)
+    assert AnnotationSet.open(path).annotations == annotations.annotations
+    assert AnnotationSet.from_geojson(annotations.to_geojson()).annotations == annotations.annotations
+
+    with pytest.raises(ValueError):
+        AnnotationSet.from_geojson('{')
+
+
+def test_rasterize(boxes_tiff_slide, annotations_geojson):
+    annotations = AnnotationSet.open(annotations_geojson)
+
+    mask = annotations.rasterize(boxes_tiff_slide, (0, 0), 0, (300, 250))
+    assert mask.shape == (250, 300)
+    assert mask[20, 20] == ANNOTATED
+    assert mask[50, 50] == 0
+    assert (mask == ANNOTATED).sum() == 9600 + 3010 + 7000
+
+    with pytest.raises(IndexError):
+        annotations.rasterize(boxes_tiff_slide, (0, 0), 10, (50, 50))
@@ -16,8 +16,15 @@
 # along with this library; if not, write to the Free Software Foundation,
 # Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 #
+import asyncio
+import numpy as np
+import pickle
 import pytest
 
+from io import BytesIO
+from PIL import Image
+from xml.etree import ElementTree
+
 from openslide_py.deepzoom import DeepZoomGenerator
 
 
@@ -70,5 +77,162 @@ def test_get_tile_dimensions(boxes_tiff_dz):
     assert boxes_tiff_dz.get_tile_dimensions(9, (1,0)) == (47, 250)
 
 
+def test_get_tile_bytes(boxes_tiff_dz):
+    jpeg = Image.open(BytesIO(boxes_tiff_dz.get_tile_bytes(9, (1, 0))))
+    assert (jpeg.format, jpeg.size) == ('JPEG', (47, 250))
+
+    png = Image.open(BytesIO(boxes_tiff_dz.get_tile_bytes(9, (1, 0), 'png')))
+    assert png.format == 'PNG'
+    np.testing.assert_array_equal(np.asarray(png.convert('RGB')),
+                                  np.asarray(boxes_tiff_dz.get_tile(9, (1, 0))))
+
+    webp = Image.open(BytesIO(boxes_tiff_dz.get_tile_bytes(9, (1, 0), 'webp', quality=90)))
+    assert webp.format == 'WEBP'
+
+    with pytest.raises(ValueError):
+        boxes_tiff_dz.get_tile_bytes(9, (1, 0), 'gif')
+    with pytest.raises(ValueError):
+        boxes_tiff_dz.get_tile_bytes(9, (1, 0), quality=0)
+    with pytest.raises(ValueError):
+        boxes_tiff_dz.get_tile_bytes(9, (2, 0))
+
+
+def test_get_tile_bytes_python(boxes_tiff_slide):
+    python_dz = DeepZoomGenerator(SlideProxy(boxes_tiff_slide), 254, 1, limit_bounds=False)
+
+    png = Image.open(BytesIO(python_dz.get_tile_bytes(9, (1, 0), 'png')))
+    assert (png.format, png.size) == ('PNG', (47, 250))
+    with pytest.raises(ValueError):
+        python_dz.get_tile_bytes(9, (1, 0), quality=0)
+
+
+def test_get_tile_response(boxes_tiff_dz, boxes_tiff_slide):
+    body, content_type = boxes_tiff_dz.get_tile_response(9, 1, 0)
+    assert content_type == 'image/jpeg'
+    assert body == boxes_tiff_dz.get_tile_bytes(9, (1, 0))
+
+    body, content_type = boxes_tiff_dz.get_tile_response(9, 1, 0, 'png')
+    assert (Image.open(BytesIO(body)).format, content_type) == ('PNG', 'image/png')
+    assert boxes_tiff_dz.get_tile_response(9, 0, 0, 'webp', 90)[1] == 'image/webp'
+    with pytest.raises(ValueError):
+        boxes_tiff_dz.get_tile_response(9, 2, 0)
+
+    python_dz = DeepZoomGenerator(SlideProxy(boxes_tiff_slide), 254, 1, limit_bounds=False)
+    body, content_type = python_dz.get_tile_response(9, 1, 0, 'jpg')
+    assert (Image.open(BytesIO(body)).format, content_type) == ('JPEG', 'image/jpeg')
+    with pytest.raises(ValueError):
+        python_dz.get_tile_response(9, 1, 0, 'gif')
+
+
 def test_get_dzi(boxes_tiff_dz):
     assert 'http://schemas.microsoft.com/deepzoom/2008' in boxes_tiff_dz.get_dzi('jpeg')
+
+
+class BoundedSlide:
+    """A slide stub with vendor bounds properties."""
+
+    dimensions = (1000, 800)
+    level_dimensions = ((1000, 800), (500, 400))
+    level_downsamples = (1.0, 2.0)
+
+    def __init__(self, **bounds):
+        self.properties = {f'openslide.bounds-{name}': value
+                           for name, value in bounds.items()}
+
+    def get_best_level_for_downsample(self, downsample):
+        return 0 if downsample < 2 else 1
+
+
+def test_limit_bounds(boxes_tiff_slide):
+    dz = DeepZoomGenerator(boxes_tiff_slide, 254, 1, limit_bounds=True)
+    assert dz.l0_offset == (0, 0)
+    assert dz.l0_dimensions == (300, 250)
+
+    dz = DeepZoomGenerator(BoundedSlide(x='100', y='50', width='600', height='400'),
+                           254, 1, limit_bounds=True)
+    assert dz.l0_offset == (100, 50)
+    assert dz.l0_dimensions == (600, 400)
+    assert dz.level_dimensions[-1] == (600, 400)
+    assert dz.get_tile_coordinates(dz.level_count - 1, (0, 0)) == ((100, 50), 0, (255, 255))
+
+
+def test_limit_bounds_invalid():
+    dz = DeepZoomGenerator(BoundedSlide(x=' 100 ', y='abc', width='1.5', height='-400'),
+                           254, 1, limit_bounds=True)
+    assert dz.l0_offset == (100, 0)
+    assert dz.l0_dimensions == (900, 800)
+
+    dz = DeepZoomGenerator(BoundedSlide(x='900', y='800', width='600', height='10'),
+                           254, 1, limit_bounds=True)
+    assert dz.l0_offset == (900, 0)
+    assert dz.l0_dimensions == (100, 800)
+
+
+class SlideProxy:
+    """A slide object that is not an OpenSlide, tiled in pure Python."""
+
+    def __init__(self, osr):
+        self._osr = osr
+
+    def __getattr__(self, name):
+        return getattr(self._osr, name)
+
+
+def test_rust_generator_parity(boxes_tiff_slide, boxes_tiff_dz):
+    assert boxes_tiff_dz._dz is not None
+    python_dz = DeepZoomGenerator(SlideProxy(boxes_tiff_slide), 254, 1, limit_bounds=False)
+    assert python_dz._dz is None
+
+    assert boxes_tiff_dz.level_tiles == python_dz.level_tiles
+    assert boxes_tiff_dz.level_dimensions == python_dz.level_dimensions
+    assert isinstance(boxes_tiff_dz.level_tiles, tuple)
+    assert isinstance(boxes_tiff_dz.level_dimensions, tuple)
+    for format in ['jpeg', 'png']:
+        rust_dzi = ElementTree.fromstring(boxes_tiff_dz.get_dzi(format).encode())
+        python_dzi = ElementTree.fromstring(python_dz.get_dzi(format).encode())
+        assert rust_dzi.attrib == python_dzi.attrib
+        assert [e.attrib for e in rust_dzi] == [e.attrib for e in python_dzi]
+    for level in range(boxes_tiff_dz.level_count):
+        for col, row in [(0, 0), tuple(t - 1 for t in boxes_tiff_dz.level_tiles[level])]:
+            address = (col, row)
+            assert boxes_tiff_dz.get_tile_coordinates(level, address) == \
+                python_dz.get_tile_coordinates(level, address)
+            tile = boxes_tiff_dz.get_tile(level, address)
+            assert tile.mode == 'RGB'
+            assert tile.size == python_dz.get_tile(level, address).size
+
+
+def test_pickle(boxes_tiff_dz):
+    unpickled = pickle.loads(pickle.dumps(boxes_tiff_dz))
+
+    assert repr(unpickled) == repr(boxes_tiff_dz)
+    assert unpickled.level_tiles == boxes_tiff_dz.level_tiles
+    assert unpickled.get_tile(9, (1, 0)).tobytes() == boxes_tiff_dz.get_tile(9, (1, 0)).tobytes()
+
+
+def test_tiles(boxes_tiff_dz):
+    tiles = list(boxes_tiff_dz.tiles(9))
+
+    assert [address for address, _ in tiles] == [(0, 0), (1, 0)]
+    for address, tile in tiles:
+        np.testing.assert_array_equal(tile, np.asarray(boxes_tiff_dz.get_tile(9, address)))
+
+    hilbert = [address for address, _ in boxes_tiff_dz.tiles(9, order="hilbert")]
+    assert sorted(hilbert) == sorted(address for address, _ in tiles)
+
+    with pytest.raises(ValueError):
+        next(boxes_tiff_dz.tiles(10))
+
+    address, image = next(boxes_tiff_dz.tiles(9, as_pil=True))
+    assert image.mode == "RGB"
+    assert image.tobytes() == boxes_tiff_dz.get_tile(9, address).tobytes()
+
+
+def test_get_tile_async(boxes_tiff_dz):
+    tile = asyncio.run(boxes_tiff_dz.get_tile_async(9, (1, 0)))
+
+    assert tile.mode == "RGB"
+    assert tile.tobytes() == boxes_tiff_dz.get_tile(9, (1, 0)).tobytes()
+
+    with pytest.raises(ValueError):
+        asyncio.run(boxes_tiff_dz.get_tile_async(-1, (0, 0)))
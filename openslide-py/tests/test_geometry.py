@@ -0,0 +1,23 @@
+import numpy as np
+import pytest
+
+from openslide_py import Address, Region, Size
+
+
+def test_of():
+    assert Address.of((1, 2)) == Address(x=1, y=2)
+    assert Size.of(Size(3, 4)) == (3, 4)
+    assert Size.of(np.array([3, 4])) == Size(3, 4)
+    assert Region.of(((1, 2), 0, (3, 4))) == Region(Address(1, 2), 0, Size(3, 4))
+    assert type(Region.of(((1, 2), 0, (3, 4))).size) is Size
+
+
+def test_of_invalid():
+    with pytest.raises(TypeError, match=r"Address must be an \(x, y\) tuple"):
+        Address.of((1, 2, 3))
+    with pytest.raises(TypeError, match="of integers"):
+        Size.of((1.5, 2))
+    with pytest.raises(TypeError, match="Region must be"):
+        Region.of(((1, 2), 0))
+    with pytest.raises(TypeError, match="Region level"):
+        Region.of(((1, 2), "0", (3, 4)))
@@ -17,11 +17,24 @@
 # Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 #
 
+import asyncio
+import pickle
+import re
 import pytest
 
+from concurrent.futures import ThreadPoolExecutor
+
 import numpy as np
 
-from openslide_py import OpenSlide, OpenSlideError, OpenSlideUnsupportedFormatError
+from io import BytesIO
+from PIL import ImageCms
+
+from openslide_py import (PROPERTY_NAME_MPP_X, PROPERTY_NAME_MPP_Y,
+                          PROPERTY_NAME_OBJECTIVE_POWER, Address, Bounds, Cache, OpenSlide,
+                          OpenSlideError, OpenSlideUnsupportedFormatError, Region, Size,
+                          SlideInfo, forward_native_logs, get_num_threads, set_num_threads)
+from openslide_py.deepzoom import DeepZoomGenerator
+from openslide_py.open_slide import array_to_image
 
 
 def test_detect_format_missing(missing_file):
@@ -30,8 +43,7 @@ def test_detect_format_missing(missing_file):
 
 
 def test_detect_format_unsupported(unsupported_file):
-    with pytest.raises(OpenSlideUnsupportedFormatError):
-        OpenSlide.detect_format(unsupported_file)
+    assert OpenSlide.detect_format(unsupported_file) is None
 
 
 def test_detect_format(boxes_tiff):
@@ -62,19 +74,57 @@ def test_context_manager(boxes_tiff):
         slide.level_count
 
 
+def test_context_manager_as(boxes_tiff):
+    with OpenSlide(boxes_tiff) as slide:
+        properties = slide.properties
+        assert not slide.closed
+
+    assert slide.closed
+    with pytest.raises(OpenSlideError):
+        slide.read_region((0, 0), 0, (16, 16))
+    with pytest.raises(OpenSlideError):
+        properties["openslide.vendor"]
+    with pytest.raises(OpenSlideError):
+        slide.cache_size = 0
+
+    # Closing again is a no-op
+    slide.close()
+
+
 def test_repr(boxes_tiff):
     slide = OpenSlide(boxes_tiff)
-    assert repr(slide) == f"OpenSlide({boxes_tiff})"
+    assert repr(slide) == (
+        f"OpenSlide({str(boxes_tiff)!r}, dimensions=(300, 250), vendor='generic-tiff', open)")
+
+    slide.close()
+    assert repr(slide).endswith("closed)")
+
+
+def test_eq_hash(boxes_tiff, small_svs):
+    slide = OpenSlide(boxes_tiff)
+    same = OpenSlide(boxes_tiff)
+    other = OpenSlide(small_svs)
+
+    assert slide == same
+    assert slide != other
+    assert slide != boxes_tiff
+    assert len({slide, same, other}) == 2
+
+    hash_before = hash(slide)
+    slide.close()
+    assert hash(slide) == hash_before
+    assert slide == same
 
 
 def test_basic_metadata(boxes_tiff):
     slide = OpenSlide(boxes_tiff)
 
     assert slide.level_count == 4
-    assert slide.level_dimensions == [(300, 250), (150, 125), (75, 62), (37, 31)]
-    assert slide.dimensions == (300, 250)
+    assert slide.level_dimensions == ((300, 250), (150, 125), (75, 62), (37, 31))
+    assert slide.dimensions == Size(w=300, h=250)
+    assert slide.level_dimensions[1].h == 125
     assert len(slide.level_downsamples) == slide.level_count
-    assert slide.level_downsamples[0:2] == [1, 2]
+    assert slide.level_downsamples[0:2] == (1, 2)
     np.testing.assert_almost_equal(slide.level_downsamples[2], 4, decimal=1)
     np.testing.assert_almost_equal(slide.level_downsamples[3], 8, decimal=1)
     assert slide.get_best_level_for_downsample(0.5) == 0
@@ -91,6 +141,384 @@ def test_properties(boxes_tiff):
         slide.properties["__missing"]
 
 
+def test_shared_cache(boxes_tiff, small_svs):
+    cache = Cache(1024 * 1024)
+    assert repr(cache) == "OpenSlideCache(1048576)"
+
+    slide = OpenSlide(boxes_tiff)
+    other = OpenSlide(small_svs)
+    before = slide.read_region((0, 0), 0, (64, 64)).tobytes()
+    slide.set_cache(cache)
+    other.set_cache(cache)
+    assert slide.cache_size == 1024 * 1024
+    del cache
+
+    assert slide.read_region((0, 0), 0, (64, 64)).tobytes() == before
+    slide.set_cache_size(0)
+    assert slide.cache_size == 0
+    assert slide.read_region((0, 0), 0, (64, 64)).tobytes() == before
+
+
+def test_set_cache_tiled(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    dz = DeepZoomGenerator(slide)
+
+    with pytest.raises(ValueError):
+        slide.set_cache(Cache(1024))
+    del dz
+    slide.set_cache(Cache(1024))
+
+
+def test_pickle(boxes_tiff):
+    slide = OpenSlide(boxes_tiff, cache_size=1024)
+    unpickled = pickle.loads(pickle.dumps(slide))
+
+    assert unpickled == slide
+    assert unpickled.cache_size == 1024
+    assert unpickled.level_dimensions == slide.level_dimensions
+    assert not unpickled.attach_color_profile
+    assert pickle.loads(pickle.dumps(OpenSlide(boxes_tiff, attach_color_profile=True))) \
+        .attach_color_profile
+
+
+def test_color_profile(boxes_tiff, small_svs):
+    for path in [boxes_tiff, small_svs]:
+        slide = OpenSlide(path, attach_color_profile=True)
+        profile = slide.color_profile
+        region = slide.read_region((0, 0), 0, (10, 10))
+        thumbnail = slide.thumbnail((10, 10))
+        if profile is None:
+            assert 'icc_profile' not in region.info
+            continue
+        # ICC header signature
+        assert profile[36:40] == b'acsp'
+        ImageCms.ImageCmsProfile(BytesIO(profile))
+        assert region.info['icc_profile'] == profile
+        assert thumbnail.info['icc_profile'] == profile
+        assert DeepZoomGenerator(slide).get_tile(0, (0, 0)).info['icc_profile'] == profile
+
+    slide = OpenSlide(small_svs)
+    assert 'icc_profile' not in slide.read_region((0, 0), 0, (10, 10)).info
+
+
+def test_bounds_info(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    assert slide.bounds is None
+
+    info = slide.info
+    assert isinstance(info, SlideInfo)
+    assert info.vendor == "generic-tiff"
+    assert info.quickhash == slide.properties.get("openslide.quickhash-1")
+    assert (info.mpp, info.objective_power, info.bounds) == (None, None, None)
+    assert [level.dimensions for level in info.levels] == list(slide.level_dimensions)
+    assert [level.downsample for level in info.levels] == list(slide.level_downsamples)
+    tile_width = slide.properties.get("openslide.level[0].tile-width")
+    if tile_width is not None:
+        assert info.levels[0].tile_size.w == int(tile_width)
+
+    bounds = Bounds(10, 20, 30, 40)
+    assert (bounds.address, bounds.size) == (Address(10, 20), Size(30, 40))
+
+    slide.close()
+    with pytest.raises(OpenSlideError):
+        slide.info
+
+
+def test_read_region(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    region = slide.read_region((-10, -10), 1, (400, 400))
+    assert region.mode == "RGBA"
+    assert region.size == (400, 400)
+    assert region.getpixel((0, 0)) == (255, 255, 255, 255)
+    assert region.getpixel((399, 399)) == (255, 255, 255, 255)
+    level = slide.read_region((0, 0), 1, (150, 125))
+    assert region.crop((5, 5, 155, 130)).tobytes() == level.tobytes()
+
+
+def test_read_region_threads(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    locations = [(x, y) for x in range(0, 300, 50) for y in range(0, 250, 50)]
+
+    with ThreadPoolExecutor(max_workers=4) as executor:
+        regions = list(executor.map(
+            lambda location: slide.read_region(location, 0, (50, 50)), locations))
+
+    for location, region in zip(locations, regions):
+        assert region.tobytes() == slide.read_region(location, 0, (50, 50)).tobytes()
+
+
+def test_read_region_out(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    out = np.zeros((50, 100, 4), dtype=np.uint8)
+
+    assert slide.read_region((10, 20), 1, (100, 50), out=out) is out
+    np.testing.assert_array_equal(out, np.asarray(slide.read_region((10, 20), 1, (100, 50))))
+
+    slide.read_region((-10, 20), 1, (100, 50), out=out)
+    np.testing.assert_array_equal(out, np.asarray(slide.read_region((-10, 20), 1, (100, 50))))
+
+    with pytest.raises(ValueError):
+        slide.read_region((0, 0), 1, (50, 100), out=out)
+    with pytest.raises(ValueError):
+        slide.read_region((0, 0), 1, (50, 50), out=out[:, ::2])
+
+
+def test_read_region_rgb(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    rgba = np.asarray(slide.read_region((10, 20), 1, (100, 50)))
+
+    region = slide.read_region((10, 20), 1, (100, 50), mode="RGB")
+    assert region.mode == "RGB"
+    np.testing.assert_array_equal(np.asarray(region), rgba[..., :3])
+
+    out = np.zeros((50, 100, 3), dtype=np.uint8)
+    slide.read_region((10, 20), 1, (100, 50), out=out, mode="RGB")
+    np.testing.assert_array_equal(out, rgba[..., :3])
+
+    assert slide.read_region((-10, 20), 1, (100, 50), mode="RGB").mode == "RGB"
+
+    with pytest.raises(ValueError):
+        slide.read_region((0, 0), 1, (100, 50), mode="L")
+
+
+def test_read_regions(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    regions = [((x, 0), 0, (32, 16)) for x in range(0, 256, 32)] + [((0, 0), 1, (10, 20))]
+
+    arrays = slide.read_regions(regions)
+    assert len(arrays) == len(regions)
+    for region, arr in zip(regions, arrays):
+        np.testing.assert_array_equal(arr, np.asarray(slide.read_region(*region)))
+
+    stacked = slide.read_regions(regions[:-1], mode="RGB", stack=True)
+    assert stacked.shape == (8, 16, 32, 3)
+    np.testing.assert_array_equal(stacked[3], arrays[3][..., :3])
+
+    assert slide.read_regions([], stack=True).shape == (0, 0, 0, 4)
+    with pytest.raises(ValueError):
+        slide.read_regions(regions, stack=True)
+
+    images = slide.read_regions(regions, mode="RGB", as_pil=True)
+    assert [image.size for image in images] == [size for _, _, size in regions]
+    assert images[3].mode == "RGB"
+    np.testing.assert_array_equal(np.asarray(images[3]), arrays[3][..., :3])
+    with pytest.raises(ValueError):
+        slide.read_regions(regions[:-1], stack=True, as_pil=True)
+
+
+def test_read_patches(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    coords = np.array([[x, y] for y in range(0, 200, 50) for x in range(0, 250, 64)])
+
+    patches = slide.read_patches(coords, 0, (64, 32), mode="RGB")
+    assert patches.shape == (len(coords), 32, 64, 3)
+    for (x, y), patch in zip(coords, patches):
+        np.testing.assert_array_equal(
+            patch, np.asarray(slide.read_region((int(x), int(y)), 0, (64, 32)))[..., :3])
+
+    stacked = slide.read_regions([((x, y), 1, (16, 16)) for x, y in coords[:3]], stack=True)
+    np.testing.assert_array_equal(slide.read_patches(list(coords[:3]), 1, (16, 16)), stacked)
+    assert slide.read_patches([], 0, (8, 8)).shape == (0, 8, 8, 4)
+    assert slide.read_patches(np.zeros((0, 2), dtype=int), 0, (8, 8)).shape == (0, 8, 8, 4)
+
+    with pytest.raises(ValueError):
+        slide.read_patches([(-1, 0)], 0, (8, 8))
+    with pytest.raises(ValueError):
+        slide.read_patches([(0, 0)], 0, (8, 8), mode="CMYK")
+    with pytest.raises(TypeError):
+        slide.read_patches(np.zeros((1, 2)), 0, (8, 8))
+
+
+def test_export_level(boxes_tiff, tmp_path):
+    slide = OpenSlide(boxes_tiff)
+    w, h = slide.level_dimensions[1]
+    level = np.asarray(slide.read_region((0, 0), 1, (w, h)))[..., :3]
+
+    pixels = slide.export_level(1, tmp_path / "level.npy")
+    assert isinstance(pixels, np.memmap)
+    assert pixels.shape == (h, w, 3)
+    np.testing.assert_array_equal(pixels, level)
+    np.testing.assert_array_equal(np.load(tmp_path / "level.npy", mmap_mode="r"), level)
+    assert not pixels.flags.writeable
+
+    raw = slide.export_level(1, str(tmp_path / "level.raw"))
+    np.testing.assert_array_equal(raw, level)
+    assert (tmp_path / "level.raw").stat().st_size == w * h * 3
+
+    with pytest.raises(IndexError):
+        slide.export_level(slide.level_count, tmp_path / "missing.raw")
+
+
+def test_forward_native_logs(boxes_tiff):
+    forward_native_logs()
+    # Forwarding is installed once
+    forward_native_logs()
+    assert OpenSlide(boxes_tiff).read_region((0, 0), 0, (10, 10)).size == (10, 10)
+
+
+def test_num_threads(boxes_tiff, tmp_path):
+    slide = OpenSlide(boxes_tiff)
+    regions = [((x, 0), 0, (32, 16)) for x in range(0, 256, 32)]
+    default = get_num_threads()
+    assert default >= 1
+
+    try:
+        set_num_threads(2)
+        assert get_num_threads() == 2
+        stacked = slide.read_regions(regions, stack=True)
+        np.testing.assert_array_equal(slide.read_regions(regions, stack=True, threads=1),
+                                      stacked)
+        np.testing.assert_array_equal(
+            slide.read_patches([address for address, _, _ in regions], 0, (32, 16), threads=3),
+            stacked)
+        np.testing.assert_array_equal(slide.export_level(3, tmp_path / "level.npy", threads=1),
+                                      np.asarray(slide.read_region((0, 0), 3, (37, 31)))[..., :3])
+
+        with pytest.raises(ValueError):
+            set_num_threads(0)
+        with pytest.raises(ValueError):
+            slide.read_regions(regions, threads=0)
+        assert get_num_threads() == 2
+    finally:
+        set_num_threads(None)
+    assert get_num_threads() == default
+
+
+def test_array_to_image():
+    arr = np.arange(2 * 3 * 4, dtype=np.uint8).reshape(2, 3, 4)
+
+    image = array_to_image(arr)
+    assert (image.mode, image.size) == ("RGBA", (3, 2))
+    np.testing.assert_array_equal(np.asarray(image), arr)
+    assert array_to_image(arr[..., :3]).mode == "RGB"
+    np.testing.assert_array_equal(np.asarray(array_to_image(arr[..., :3])), arr[..., :3])
+    assert array_to_image(arr[..., 0]).mode == "L"
+
+    # Modifying the image leaves the array untouched
+    image.putpixel((0, 0), (255, 255, 255, 255))
+    assert arr[0, 0, 0] == 0
+
+
+def test_read_region_async(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    async def read():
+        return await asyncio.gather(
+            slide.read_region_async((10, 20), 1, (100, 50)),
+            slide.read_region_async((-10, 20), 1, (100, 50), mode="RGB"))
+
+    region, padded = asyncio.run(read())
+    assert region.tobytes() == slide.read_region((10, 20), 1, (100, 50)).tobytes()
+    assert padded.tobytes() == slide.read_region((-10, 20), 1, (100, 50), mode="RGB").tobytes()
+
+
+def test_read_region_size_zero(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    assert slide.read_region((0, 0), 1, (400, 0)).size == (400, 0)
+
+
+def test_read_region_bad_size(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    with pytest.raises(OpenSlideError, match=r"Size\(w=400, h=-5\)"):
+        slide.read_region((0, 0), 1, (400, -5))
+
+
+def test_mpp_magnification(boxes_tiff, small_svs):
+    slide = OpenSlide(boxes_tiff)
+    assert slide.mpp is None
+    assert slide.magnification is None
+
+    slide = OpenSlide(small_svs)
+    properties = slide.properties
+    assert slide.mpp == pytest.approx((float(properties[PROPERTY_NAME_MPP_X]),
+                                       float(properties[PROPERTY_NAME_MPP_Y])))
+    if PROPERTY_NAME_OBJECTIVE_POWER in properties:
+        assert slide.magnification == pytest.approx(
+            float(properties[PROPERTY_NAME_OBJECTIVE_POWER]))
+
+
+def test_level_slicing(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+    level = slide.level(1)
+
+    assert level.shape == (125, 150, 4)
+    assert len(level) == 125
+    arr = level[10:60, 20:120]
+    np.testing.assert_array_equal(arr, slide.read_region((40, 20), 1, (100, 50)))
+    # Slices are clamped to the level, negative indices count from its edges
+    np.testing.assert_array_equal(level[-25:, 100:1000],
+                                  slide.read_region((200, 200), 1, (50, 25)))
+    np.testing.assert_array_equal(level[10, 20:120], arr[0])
+    np.testing.assert_array_equal(level[10:60, 20:120, :3], arr[..., :3])
+    assert level[10:60, 20:120, 3].shape == (50, 100)
+    assert level[10:10, 20:120].shape == (0, 100, 4)
+    assert slide.level(1, mode="RGB")[:, :].shape == (125, 150, 3)
+
+    with pytest.raises(ValueError):
+        level[10:60:2, 20:120]
+    with pytest.raises(IndexError):
+        level[125, :]
+    with pytest.raises(IndexError):
+        slide.level(4)
+
+
+def test_read_region_raw(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    raw = slide.read_region_raw((10, 20), 1, (100, 50))
+    assert (raw.shape, raw.dtype) == ((50, 100), np.uint32)
+    region = np.asarray(slide.read_region((10, 20), 1, (100, 50)))
+    # Opaque pixels need no unpremultiplying
+    opaque = (raw >> 24) == 255
+    for shift, channel in [(16, 0), (8, 1), (0, 2)]:
+        np.testing.assert_array_equal(((raw >> shift) & 0xff)[opaque], region[..., channel][opaque])
+
+    with pytest.raises(ValueError):
+        slide.read_region_raw((-10, 20), 1, (100, 50))
+    with pytest.raises(OpenSlideError):
+        slide.read_region_raw((10, 20), 1, (-100, 50))
+
+
+def test_read_region_buffer(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    view = slide.read_region_buffer((10, 20), 1, (100, 50))
+    assert (view.format, view.shape, view.readonly) == ("B", (50, 100, 4), False)
+    region = slide.read_region((10, 20), 1, (100, 50))
+    assert view.tobytes() == region.tobytes()
+    arr = np.frombuffer(view, dtype=np.uint8).reshape(view.shape)
+    np.testing.assert_array_equal(arr, np.asarray(region))
+    # The array shares the memory of the buffer
+    arr[0, 0, 0] = 7
+    assert view[0, 0, 0] == 7
+
+    view = slide.read_region_buffer((10, 20), 1, (100, 50), mode="RGB")
+    assert view.shape == (50, 100, 3)
+    assert view.tobytes() == slide.read_region((10, 20), 1, (100, 50), mode="RGB").tobytes()
+
+    padded = slide.read_region_buffer((-10, 20), 1, (100, 50))
+    assert padded.shape == (50, 100, 4)
+    assert padded.tobytes() == slide.read_region((-10, 20), 1, (100, 50)).tobytes()
+    assert slide.read_region_buffer((0, 0), 0, (0, 10)).nbytes == 0
+
+
+def test_read_region_geometry(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    region = slide.read_region(Address(10, 20), 1, Size(100, 50))
+    assert region.tobytes() == slide.read_region((10, 20), 1, (100, 50)).tobytes()
+    arrays = slide.read_regions([Region(Address(10, 20), 1, Size(100, 50))])
+    np.testing.assert_array_equal(arrays[0], np.asarray(region))
+
+    with pytest.raises(TypeError):
+        slide.read_region((10,), 1, (100, 50))
+    with pytest.raises(TypeError):
+        slide.read_regions([((10, 20), 1)])
+
+
 @pytest.mark.skip
 def test_read_region_2gb(boxes_tiff):
     slide = OpenSlide(boxes_tiff)
@@ -103,6 +531,20 @@ def test_thumbnail(boxes_tiff):
     assert slide.get_thumbnail((100, 100)).size == (100, 83)
 
 
+def test_rust_thumbnail(boxes_tiff):
+    slide = OpenSlide(boxes_tiff)
+
+    thumbnail = slide.thumbnail((100, 100))
+    assert thumbnail.mode == "RGBA"
+    assert thumbnail.size == (100, 83)
+
+    arr = slide.thumbnail((100, 100), filter="nearest", mode="RGB", as_array=True)
+    assert arr.shape == (83, 100, 3)
+
+    with pytest.raises(ValueError):
+        slide.thumbnail((100, 100), filter="bicubic")
+
+
 def test_associated_images(small_svs):
     slide = OpenSlide(small_svs)
 
@@ -113,6 +555,28 @@ def test_associated_images(small_svs):
         slide.associated_images["__missing"]
 
 
+def test_associated_image_rgb(small_svs):
+    slide = OpenSlide(small_svs)
+
+    rgb = slide._osr.associated_image("thumbnail", mode="RGB")
+    assert rgb.shape == (16, 16, 3)
+    np.testing.assert_array_equal(rgb, slide.associated_images.arrays["thumbnail"][..., :3])
+
+
+def test_associated_images_cache(small_svs):
+    slide = OpenSlide(small_svs)
+
+    assert slide.associated_images is slide.associated_images
+    arr = slide.associated_images.arrays["thumbnail"]
+    assert arr.shape == (16, 16, 4)
+    assert arr.dtype == np.uint8
+    assert slide.associated_images.arrays["thumbnail"] is arr
+    np.testing.assert_array_equal(np.asarray(slide.associated_images["thumbnail"]), arr)
+
+    with pytest.raises(KeyError):
+        slide.associated_images.arrays["__missing"]
+
+
 def test_read_bad_region(unreadable_svs):
     slide = OpenSlide(unreadable_svs)
 
@@ -122,6 +586,39 @@ def test_read_bad_region(unreadable_svs):
         slide.read_region((0, 0), 0, (16, 16))
 
 
+def test_error_reopen(unreadable_svs):
+    slide = OpenSlide(unreadable_svs, cache_size=1024)
+    assert slide.error is None
+
+    with pytest.raises(OpenSlideError):
+        slide.read_region((0, 0), 0, (16, 16))
+    error = slide.error
+    assert error
+    # The handle stays in error until reopened
+    with pytest.raises(OpenSlideError, match=re.escape(error)):
+        slide.level_count
+
+    slide.reopen()
+    assert slide.error is None
+    assert slide.level_count > 0
+    assert slide.cache_size == 1024
+
+    slide.close()
+    assert slide.error is None
+    slide.reopen()
+    assert not slide.closed
+
+
+def test_reopen_shared_cache(boxes_tiff):
+    cache = Cache(2048)
+    slide = OpenSlide(boxes_tiff)
+    slide.set_cache(cache)
+
+    slide.reopen()
+    assert slide._cache is cache
+    assert slide.read_region((0, 0), 0, (10, 10)).size == (10, 10)
+
+
 def test_read_bad_associated_image(unreadable_svs):
     slide = OpenSlide(unreadable_svs)
 
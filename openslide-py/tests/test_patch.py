@@ -0,0 +1,94 @@
+import pickle
+
+import numpy as np
+import pytest
+
+from openslide_py.patch import Patch, PatchReader, PatchSampler
+
+
+def test_patch_sampler(boxes_tiff_slide):
+    sampler = PatchSampler(boxes_tiff_slide, 64)
+
+    assert sampler.patch_size == 64
+    # 300x250 holds 4x3 whole patches
+    assert len(sampler) == 12
+    assert sampler.patches[0] == Patch((0, 0), 0, (64, 64), sampler.patches[0].tissue_fraction)
+    assert repr(sampler) == f"PatchSampler({boxes_tiff_slide}, patch_size=64, patches=12)"
+
+
+def test_iter_pixels(boxes_tiff_slide):
+    sampler = PatchSampler(boxes_tiff_slide, 64, stride=128)
+
+    pairs = list(sampler)
+    assert [patch for patch, _ in pairs] == sampler.patches
+    for patch, pixels in pairs:
+        assert pixels.shape == (64, 64, 4)
+        region = boxes_tiff_slide.read_region(patch.location, patch.level, patch.size)
+        np.testing.assert_array_equal(pixels, np.asarray(region))
+
+    _, pixels = next(sampler.iter_pixels(mode="RGB"))
+    assert pixels.shape == (64, 64, 3)
+
+    _, image = next(sampler.iter_pixels(mode="RGB", as_pil=True))
+    assert (image.mode, image.size) == ("RGB", (64, 64))
+    np.testing.assert_array_equal(np.asarray(image), pixels)
+
+
+def test_read_patch_bad_index(boxes_tiff_slide):
+    sampler = PatchSampler(boxes_tiff_slide, 64)
+
+    with pytest.raises(IndexError):
+        sampler.read_patch(len(sampler))
+
+
+def test_patch_reader(boxes_tiff_slide):
+    reader = PatchReader(boxes_tiff_slide, 64, threads=2, capacity=2)
+    sampler = PatchSampler(boxes_tiff_slide, 64)
+
+    pairs = list(reader)
+    assert len(pairs) == len(reader) == len(sampler)
+    assert sorted(location for _, location in pairs) == \
+        sorted(patch.location for patch in sampler.patches)
+    for pixels, location in pairs:
+        assert pixels.shape == (64, 64, 3)
+        np.testing.assert_array_equal(
+            pixels, np.asarray(boxes_tiff_slide.read_region(location, 0, (64, 64)))[..., :3])
+
+    # Iterating again starts new reader threads
+    assert len(list(reader)) == len(sampler)
+    assert next(iter(PatchReader(boxes_tiff_slide, 64, mode="RGBA")))[0].shape == (64, 64, 4)
+
+
+def test_patch_reader_shards(boxes_tiff_slide):
+    reader = PatchReader(boxes_tiff_slide, 64, threads=1)
+    locations = [patch.location for patch in reader.sampler.patches]
+
+    # Out of a DataLoader worker, every patch is read
+    reader.worker_init()
+    assert reader.shard == (0, 1)
+
+    shards = []
+    for worker_id in range(5):
+        reader.worker_init(worker_id, 5)
+        shards.append([location for _, location in reader])
+        assert len(shards[-1]) == len(reader)
+    assert shards[1] == locations[1::5]
+    assert sorted(sum(shards, [])) == sorted(locations)
+
+    with pytest.raises(ValueError):
+        reader.worker_init(5, 5)
+    with pytest.raises(ValueError):
+        PatchReader(boxes_tiff_slide, 64, threads=0)
+
+
+def test_patch_reader_pickle(boxes_tiff_slide):
+    reader = PatchReader(boxes_tiff_slide, 64, stride=128, mode="RGBA", threads=2)
+    reader.worker_init(1, 2)
+    len(reader)
+
+    copy = pickle.loads(pickle.dumps(reader))
+    assert copy._sampler is None
+    assert (copy.mode, copy.threads, copy.capacity, copy.shard) == ("RGBA", 2, 64, (1, 2))
+    assert repr(copy) == f"PatchReader({boxes_tiff_slide}, patch_size=64, shard=1/2)"
+    assert sorted(location for _, location in copy) == \
+        sorted(location for _, location in reader)
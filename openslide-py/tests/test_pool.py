@@ -0,0 +1,48 @@
+import numpy as np
+import pytest
+
+from openslide_py import OpenSlide, OpenSlideError
+from openslide_py.pool import SlidePool
+
+
+def test_slide_pool(boxes_tiff, small_svs):
+    pool = SlidePool(max_open=1)
+    assert pool.max_open == 1
+    assert len(pool) == 0
+    assert repr(pool) == "SlidePool(max_open=1, open=0)"
+
+    region = pool.read_region(boxes_tiff, (10, 20), 0, (30, 40))
+    expected = OpenSlide(boxes_tiff).read_region((10, 20), 0, (30, 40))
+    np.testing.assert_array_equal(np.asarray(region), np.asarray(expected))
+    assert boxes_tiff in pool
+    assert str(boxes_tiff) in pool
+
+    slide = pool.get(boxes_tiff)
+    assert slide == OpenSlide(boxes_tiff)
+    assert slide.dimensions == (300, 250)
+
+    # The least recently used slide is closed, the slide still reads
+    pool.read_region(small_svs, (0, 0), 0, (16, 16))
+    assert len(pool) == 1
+    assert boxes_tiff not in pool
+    assert slide.read_region((0, 0), 0, (8, 8)).size == (8, 8)
+
+    pool.close(small_svs)
+    assert len(pool) == 0
+    pool.get(boxes_tiff)
+    pool.clear()
+    assert len(pool) == 0
+
+
+def test_slide_pool_errors(boxes_tiff, missing_file, unopenable_tiff):
+    pool = SlidePool()
+
+    with pytest.raises(FileNotFoundError):
+        pool.get(missing_file)
+    with pytest.raises(OpenSlideError):
+        pool.read_region(unopenable_tiff, (0, 0), 0, (8, 8))
+    assert len(pool) == 0
+
+    # Slides of the pool share its handles and their caches
+    with pytest.raises(ValueError):
+        pool.get(boxes_tiff).set_cache_size(1024)
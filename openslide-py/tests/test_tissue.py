@@ -0,0 +1,32 @@
+import numpy as np
+
+from openslide_py.tissue import BACKGROUND, TISSUE, mask_contours, otsu_threshold, tissue_mask
+
+
+def test_tissue_mask(boxes_tiff_slide):
+    # The thumbnail is never larger than the slide
+    mask = tissue_mask(boxes_tiff_slide, thumbnail_size=10_000, threshold=255)
+    assert mask.shape == (250, 300)
+    assert mask.dtype == np.uint8
+    assert (mask == TISSUE).all()
+
+    mask = tissue_mask(boxes_tiff_slide, thumbnail_size=100)
+    assert max(mask.shape) == 100
+    assert np.isin(mask, [TISSUE, BACKGROUND]).all()
+
+
+def test_otsu_threshold():
+    image = np.array([[10, 10, 200, 200]], dtype=np.uint8)
+    assert 10 <= otsu_threshold(image) < 200
+
+
+def test_mask_contours():
+    mask = np.zeros((100, 100), dtype=np.uint8)
+    mask[10:50, 20:60] = TISSUE
+
+    polygons = mask_contours(mask, (1000, 1000))
+    assert len(polygons) == 1
+    assert polygons[0]['holes'] == []
+    xs = [x for x, _ in polygons[0]['exterior']]
+    ys = [y for _, y in polygons[0]['exterior']]
+    assert (min(xs), max(xs), min(ys), max(ys)) == (200, 600, 100, 500)
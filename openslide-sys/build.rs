@@ -51,6 +51,7 @@ fn main() {
         .file("c-code/openslide-error.c")
         .file("c-code/openslide-grid.c")
         .file("c-code/openslide-hash.c")
+        .file("c-code/openslide-rs-log.c")
         .file("c-code/openslide-jdatasrc.c")
         .file("c-code/openslide-tables.c")
         .file("c-code/openslide-util.c")
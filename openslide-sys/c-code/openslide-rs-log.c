@@ -0,0 +1,28 @@
+/*
+ *  Log forwarding for the Rust bindings.
+ *
+ *  libtiff reports warnings through its own handler, printing them to
+ *  stderr by default; they are logged through glib instead, under the
+ *  "TIFF" domain, so that a single glib handler receives every message of
+ *  the library.
+ */
+
+#include <glib.h>
+#include <tiffio.h>
+
+#include "openslide-rs-log.h"
+
+static void tiff_warning(const char *module, const char *fmt, va_list ap) {
+  char *message = g_strdup_vprintf(fmt, ap);
+  if (module) {
+    g_log("TIFF", G_LOG_LEVEL_WARNING, "%s: %s", module, message);
+  } else {
+    g_log("TIFF", G_LOG_LEVEL_WARNING, "%s", message);
+  }
+  g_free(message);
+}
+
+void openslide_rs_set_log_handler(GLogFunc handler, gpointer user_data) {
+  TIFFSetWarningHandler(tiff_warning);
+  g_log_set_default_handler(handler, user_data);
+}
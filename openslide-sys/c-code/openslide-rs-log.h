@@ -0,0 +1,9 @@
+#ifndef OPENSLIDE_RS_LOG_H
+#define OPENSLIDE_RS_LOG_H
+
+#include <glib.h>
+
+// Forward glib messages, and libtiff warnings, to a log handler
+void openslide_rs_set_log_handler(GLogFunc handler, gpointer user_data);
+
+#endif
@@ -4,3 +4,30 @@
 #[allow(non_upper_case_globals)]
 mod bindings;
 pub use bindings::*;
+
+use std::os::raw::{c_char, c_uint, c_void};
+
+/// The `GLogLevelFlags` bits of a glib message.
+pub const G_LOG_LEVEL_ERROR: c_uint = 1 << 2;
+pub const G_LOG_LEVEL_CRITICAL: c_uint = 1 << 3;
+pub const G_LOG_LEVEL_WARNING: c_uint = 1 << 4;
+pub const G_LOG_LEVEL_MESSAGE: c_uint = 1 << 5;
+pub const G_LOG_LEVEL_INFO: c_uint = 1 << 6;
+pub const G_LOG_LEVEL_DEBUG: c_uint = 1 << 7;
+
+/// A glib log handler, called with the domain, may be null, the level flags and the
+/// message of each logged message.
+pub type GLogFunc = Option<
+    unsafe extern "C" fn(
+        log_domain: *const c_char,
+        log_level: c_uint,
+        message: *const c_char,
+        user_data: *mut c_void,
+    ),
+>;
+
+extern "C" {
+    /// Forward the glib messages of the library, and libtiff warnings under the
+    /// `TIFF` domain, to `handler`.
+    pub fn openslide_rs_set_log_handler(handler: GLogFunc, user_data: *mut c_void);
+}
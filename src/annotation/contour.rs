@@ -0,0 +1,291 @@
+//! Marching squares contour tracing.
+
+use std::collections::HashMap;
+
+use image::GrayImage;
+
+use crate::annotation::{Point, Polygon};
+use crate::openslide::Size;
+
+/// A point in half mask pixels, exact on the marching squares grid.
+type Key = (i64, i64);
+
+/// Trace the contours of the non-zero areas of a mask, such as a
+/// [tissue mask](tissue/fn.tissue_mask.html) or an annotation rasterization, into
+/// level 0 polygons.
+///
+/// Contours pass halfway between the pixel centers of the areas and their
+/// surroundings, diagonal pixels being separate areas, and areas enclosed in one
+/// another give holes. Contours are then simplified with the Douglas-Peucker
+/// algorithm.
+///
+/// # Arguments
+///
+/// * `mask` - a mask covering the whole slide.
+/// * `dimensions` - the level 0 dimensions of the slide.
+/// * `tolerance` - the maximum distance between a contour and its simplification,
+/// in level 0 pixels: 0 only removes aligned points.
+pub fn mask_contours(mask: &GrayImage, dimensions: Size, tolerance: f64) -> Vec<Polygon> {
+    let scale = (
+        f64::from(dimensions.w) / f64::from(mask.width()),
+        f64::from(dimensions.h) / f64::from(mask.height()),
+    );
+    let to_level0 = |(x, y): Key| Point {
+        x: x as f64 / 2. * scale.0,
+        y: y as f64 / 2. * scale.1,
+    };
+
+    let mut exteriors = Vec::new();
+    let mut holes = Vec::new();
+    for ring in trace_rings(mask) {
+        let ring: Vec<Point> = ring.into_iter().map(to_level0).collect();
+        let ring = simplify(&ring, tolerance);
+        if ring.len() < 3 {
+            continue;
+        }
+        // With y pointing down, exteriors run counterclockwise on screen
+        let area = signed_area(&ring);
+        if area < 0. {
+            exteriors.push((
+                area.abs(),
+                Polygon {
+                    exterior: ring,
+                    holes: Vec::new(),
+                },
+            ));
+        } else {
+            holes.push(ring);
+        }
+    }
+
+    for hole in holes {
+        // The smallest exterior around the hole holds it
+        let container = exteriors
+            .iter_mut()
+            .filter(|(_, polygon)| contains(&polygon.exterior, hole[0]))
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+        if let Some((_, polygon)) = container {
+            polygon.holes.push(hole);
+        }
+    }
+    exteriors.into_iter().map(|(_, polygon)| polygon).collect()
+}
+
+/// Trace the closed contours of the non-zero areas, foreground on the left.
+fn trace_rings(mask: &GrayImage) -> Vec<Vec<Key>> {
+    let (width, height) = (i64::from(mask.width()), i64::from(mask.height()));
+    let inside = |x: i64, y: i64| {
+        x >= 0 && y >= 0 && x < width && y < height && mask.get_pixel(x as u32, y as u32).0[0] != 0
+    };
+
+    // Each cell joins 4 pixel centers; cells around the mask close the contours
+    let mut segments: HashMap<Key, Key> = HashMap::new();
+    for y in -1..height {
+        for x in -1..width {
+            // Corners and edge midpoints, clockwise on screen from the top left
+            let corners = [
+                inside(x, y),
+                inside(x + 1, y),
+                inside(x + 1, y + 1),
+                inside(x, y + 1),
+            ];
+            let midpoints = [
+                (2 * x + 2, 2 * y + 1),
+                (2 * x + 3, 2 * y + 2),
+                (2 * x + 2, 2 * y + 3),
+                (2 * x + 1, 2 * y + 2),
+            ];
+            let crossings: Vec<usize> = (0..4)
+                .filter(|edge| corners[*edge] != corners[(edge + 1) % 4])
+                .collect();
+            for (index, edge) in crossings.iter().enumerate() {
+                // Entering the area, the contour leaves by the next crossing
+                if !corners[*edge] {
+                    let next = crossings[(index + 1) % crossings.len()];
+                    segments.insert(midpoints[*edge], midpoints[next]);
+                }
+            }
+        }
+    }
+
+    let mut rings = Vec::new();
+    loop {
+        let start = match segments.keys().next() {
+            Some(start) => *start,
+            None => break,
+        };
+        let mut ring = vec![start];
+        let mut point = segments.remove(&start).unwrap();
+        while point != start {
+            ring.push(point);
+            point = segments.remove(&point).unwrap();
+        }
+        rings.push(ring);
+    }
+    rings
+}
+
+/// Simplify a closed ring with the Douglas-Peucker algorithm.
+fn simplify(ring: &[Point], tolerance: f64) -> Vec<Point> {
+    if ring.len() < 3 {
+        return ring.to_vec();
+    }
+
+    // Split the ring at its first point and the point farthest from it
+    let distance = |a: Point, b: Point| (a.x - b.x).hypot(a.y - b.y);
+    let far = (1..ring.len())
+        .max_by(|a, b| {
+            distance(ring[0], ring[*a])
+                .partial_cmp(&distance(ring[0], ring[*b]))
+                .unwrap()
+        })
+        .unwrap();
+    let mut first: Vec<Point> = ring[..=far].to_vec();
+    let mut second: Vec<Point> = ring[far..].to_vec();
+    second.push(ring[0]);
+
+    first = simplify_line(&first, tolerance);
+    second = simplify_line(&second, tolerance);
+    first.pop();
+    second.pop();
+    first.extend(second);
+    first
+}
+
+/// Simplify an open line with the Douglas-Peucker algorithm, keeping its ends.
+fn simplify_line(line: &[Point], tolerance: f64) -> Vec<Point> {
+    let (start, end) = (line[0], line[line.len() - 1]);
+    let farthest = (1..line.len() - 1)
+        .map(|index| (index, segment_distance(line[index], start, end)))
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+    match farthest {
+        Some((index, distance)) if distance > tolerance => {
+            let mut simplified = simplify_line(&line[..=index], tolerance);
+            simplified.pop();
+            simplified.extend(simplify_line(&line[index..], tolerance));
+            simplified
+        }
+        _ => vec![start, end],
+    }
+}
+
+/// The distance from `point` to the segment from `start` to `end`.
+fn segment_distance(point: Point, start: Point, end: Point) -> f64 {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0. {
+        (((point.x - start.x) * dx + (point.y - start.y) * dy) / length).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (point.x - start.x - t * dx).hypot(point.y - start.y - t * dy)
+}
+
+/// The shoelace area of a ring, positive when clockwise on screen.
+fn signed_area(ring: &[Point]) -> f64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f64>()
+        / 2.
+}
+
+/// Return true if `point` lies inside `ring`, with the even-odd rule.
+fn contains(ring: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    for (index, start) in ring.iter().enumerate() {
+        let end = ring[(index + 1) % ring.len()];
+        if (start.y <= point.y) != (end.y <= point.y)
+            && point.x < start.x + (point.y - start.y) * (end.x - start.x) / (end.y - start.y)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn block(mask: &mut GrayImage, x: u32, y: u32, w: u32, h: u32) {
+        for j in y..y + h {
+            for i in x..x + w {
+                mask.put_pixel(i, j, Luma([255]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_pixel() {
+        let mut mask = GrayImage::new(3, 3);
+        block(&mut mask, 1, 1, 1, 1);
+
+        let polygons = mask_contours(&mask, Size { w: 3, h: 3 }, 0.);
+        assert_eq!(polygons.len(), 1);
+        // A diamond through the midpoints to the neighbouring pixel centers
+        let exterior = &polygons[0].exterior;
+        assert_eq!(exterior.len(), 4);
+        assert!((signed_area(exterior).abs() - 0.5).abs() < 1e-9);
+        assert!(exterior.contains(&Point { x: 1., y: 1.5 }));
+    }
+
+    #[test]
+    fn test_hole_and_areas() {
+        let mut mask = GrayImage::new(12, 8);
+        block(&mut mask, 1, 1, 5, 5);
+        mask.put_pixel(3, 3, Luma([0]));
+        // A separate area, and a diagonal pixel which does not join it
+        block(&mut mask, 8, 2, 3, 2);
+        mask.put_pixel(11, 4, Luma([255]));
+
+        let mut polygons = mask_contours(&mask, Size { w: 12, h: 8 }, 0.);
+        polygons.sort_by(|a, b| {
+            signed_area(&b.exterior)
+                .abs()
+                .partial_cmp(&signed_area(&a.exterior).abs())
+                .unwrap()
+        });
+        assert_eq!(polygons.len(), 3);
+        assert_eq!(polygons[0].holes.len(), 1);
+        // The square minus its cut corners
+        assert!((signed_area(&polygons[0].exterior).abs() - 24.5).abs() < 1e-9);
+        assert!(polygons[1].holes.is_empty());
+        assert!((signed_area(&polygons[2].exterior).abs() - 0.5).abs() < 1e-9);
+
+        // Contours are scaled to level 0
+        let scaled = mask_contours(&mask, Size { w: 120, h: 80 }, 0.);
+        let largest = scaled
+            .iter()
+            .map(|polygon| signed_area(&polygon.exterior).abs())
+            .fold(0., f64::max);
+        assert!((largest - 2450.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_simplify() {
+        let line = |bump: f64| -> Vec<Point> {
+            (0..=10)
+                .map(|x| Point {
+                    x: f64::from(x),
+                    y: if x == 5 { bump } else { 0. },
+                })
+                .collect()
+        };
+        assert_eq!(simplify_line(&line(0.), 0.).len(), 2);
+        assert_eq!(simplify_line(&line(0.4), 0.5).len(), 2);
+        assert_eq!(simplify_line(&line(0.4), 0.35).len(), 3);
+
+        // Rings keep at least their first and farthest points
+        let square: Vec<Point> = line(0.)
+            .into_iter()
+            .chain(line(0.).into_iter().map(|point| Point {
+                x: 10. - point.x,
+                y: 10.,
+            }))
+            .collect();
+        assert_eq!(simplify(&square, 0.).len(), 4);
+    }
+}
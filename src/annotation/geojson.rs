@@ -0,0 +1,267 @@
+//! GeoJSON annotations, as exported by QuPath.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::annotation::{Annotation, AnnotationSet, Point, Polygon};
+use crate::{OpenSlideError, Result};
+
+pub(crate) fn parse(text: &str) -> Result<AnnotationSet> {
+    let root: Value = serde_json::from_str(text)
+        .map_err(|e| OpenSlideError::InvalidArgument(format!("Invalid GeoJSON: {}", e)))?;
+
+    let features: Vec<&Value> = match &root {
+        // Older QuPath versions export a bare array of features
+        Value::Array(features) => features.iter().collect(),
+        Value::Object(object) => match object.get("type").and_then(Value::as_str) {
+            Some("FeatureCollection") => object
+                .get("features")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("FeatureCollection without features"))?
+                .iter()
+                .collect(),
+            Some("Feature") => vec![&root],
+            other => return Err(invalid(&format!("Unsupported GeoJSON type {:?}", other))),
+        },
+        _ => return Err(invalid("GeoJSON root must be an object or an array")),
+    };
+
+    let mut annotations = Vec::with_capacity(features.len());
+    for feature in features {
+        let annotation = parse_feature(feature)?;
+        if !annotation.polygons.is_empty() {
+            annotations.push(annotation);
+        }
+    }
+    Ok(AnnotationSet { annotations })
+}
+
+fn parse_feature(feature: &Value) -> Result<Annotation> {
+    if feature.get("type").and_then(Value::as_str) != Some("Feature") {
+        return Err(invalid("GeoJSON features must have the Feature type"));
+    }
+
+    let mut polygons = Vec::new();
+    if let Some(geometry) = feature
+        .get("geometry")
+        .filter(|geometry| !geometry.is_null())
+    {
+        parse_geometry(geometry, &mut polygons)?;
+    }
+
+    let empty = Map::new();
+    let properties = feature
+        .get("properties")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    let classification = properties.get("classification");
+
+    Ok(Annotation {
+        id: feature.get("id").and_then(|id| match id {
+            Value::String(id) => Some(id.clone()),
+            Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        }),
+        name: properties
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        classification: classification
+            .and_then(|classification| match classification {
+                Value::String(name) => Some(name.as_str()),
+                classification => classification.get("name").and_then(Value::as_str),
+            })
+            .map(str::to_string),
+        color: classification
+            .and_then(parse_color)
+            .or_else(|| properties.get("color").and_then(parse_color_value)),
+        polygons,
+        measurements: parse_measurements(properties.get("measurements")),
+    })
+}
+
+fn parse_geometry(geometry: &Value, polygons: &mut Vec<Polygon>) -> Result<()> {
+    let coordinates = || {
+        geometry
+            .get("coordinates")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("GeoJSON geometry without coordinates"))
+    };
+
+    match geometry.get("type").and_then(Value::as_str) {
+        Some("Polygon") => polygons.push(parse_polygon(coordinates()?)?),
+        Some("MultiPolygon") => {
+            for polygon in coordinates()? {
+                let rings = polygon
+                    .as_array()
+                    .ok_or_else(|| invalid("MultiPolygon coordinates must be polygons"))?;
+                polygons.push(parse_polygon(rings)?);
+            }
+        }
+        Some("GeometryCollection") => {
+            let geometries = geometry
+                .get("geometries")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("GeometryCollection without geometries"))?;
+            for geometry in geometries {
+                parse_geometry(geometry, polygons)?;
+            }
+        }
+        // Geometries without area
+        Some("Point") | Some("MultiPoint") | Some("LineString") | Some("MultiLineString") => {}
+        other => {
+            return Err(invalid(&format!(
+                "Unsupported GeoJSON geometry {:?}",
+                other
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn parse_polygon(rings: &[Value]) -> Result<Polygon> {
+    let mut rings = rings.iter().map(parse_ring);
+    let exterior = rings
+        .next()
+        .ok_or_else(|| invalid("Polygon without exterior ring"))??;
+    Ok(Polygon {
+        exterior,
+        holes: rings.collect::<Result<_>>()?,
+    })
+}
+
+fn parse_ring(ring: &Value) -> Result<Vec<Point>> {
+    let mut points = ring
+        .as_array()
+        .ok_or_else(|| invalid("Polygon rings must be arrays of positions"))?
+        .iter()
+        .map(|position| {
+            let coordinate = |index: usize| position.get(index).and_then(Value::as_f64);
+            match (coordinate(0), coordinate(1)) {
+                (Some(x), Some(y)) => Ok(Point { x, y }),
+                _ => Err(invalid(&format!("Invalid GeoJSON position {}", position))),
+            }
+        })
+        .collect::<Result<Vec<Point>>>()?;
+
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() < 3 {
+        return Err(invalid("Polygon rings must have at least 3 points"));
+    }
+    Ok(points)
+}
+
+/// The color of a QuPath classification: a `color` RGB array, or a `colorRGB`
+/// packed ARGB integer.
+fn parse_color(classification: &Value) -> Option<[u8; 3]> {
+    if let Some(color) = classification.get("color").and_then(parse_color_value) {
+        return Some(color);
+    }
+    classification
+        .get("colorRGB")
+        .and_then(Value::as_i64)
+        .map(|argb| {
+            let argb = argb as u32;
+            [(argb >> 16) as u8, (argb >> 8) as u8, argb as u8]
+        })
+}
+
+fn parse_color_value(color: &Value) -> Option<[u8; 3]> {
+    let color = color.as_array()?;
+    let channel = |index: usize| {
+        color
+            .get(index)
+            .and_then(Value::as_u64)
+            .filter(|channel| *channel <= 255)
+            .map(|channel| channel as u8)
+    };
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+/// Numeric measurements, either an object or a list of `name` and `value` pairs;
+/// missing values, exported as `null`, are skipped.
+fn parse_measurements(measurements: Option<&Value>) -> BTreeMap<String, f64> {
+    match measurements {
+        Some(Value::Object(measurements)) => measurements
+            .iter()
+            .filter_map(|(name, value)| value.as_f64().map(|value| (name.clone(), value)))
+            .collect(),
+        Some(Value::Array(measurements)) => measurements
+            .iter()
+            .filter_map(|measurement| {
+                let name = measurement.get("name")?.as_str()?;
+                let value = measurement.get("value")?.as_f64()?;
+                Some((name.to_string(), value))
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+pub(crate) fn write(set: &AnnotationSet) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": set.annotations.iter().map(write_feature).collect::<Vec<Value>>(),
+    })
+}
+
+fn write_feature(annotation: &Annotation) -> Value {
+    let mut properties = Map::new();
+    properties.insert("objectType".to_string(), json!("annotation"));
+    if let Some(name) = &annotation.name {
+        properties.insert("name".to_string(), json!(name));
+    }
+    if let Some(classification) = &annotation.classification {
+        let mut class = Map::new();
+        class.insert("name".to_string(), json!(classification));
+        if let Some(color) = annotation.color {
+            class.insert("color".to_string(), json!(color));
+        }
+        properties.insert("classification".to_string(), Value::Object(class));
+    } else if let Some(color) = annotation.color {
+        properties.insert("color".to_string(), json!(color));
+    }
+    if !annotation.measurements.is_empty() {
+        properties.insert("measurements".to_string(), json!(annotation.measurements));
+    }
+
+    let polygons: Vec<Value> = annotation.polygons.iter().map(write_polygon).collect();
+    let geometry = match polygons.len() {
+        0 => Value::Null,
+        1 => json!({ "type": "Polygon", "coordinates": polygons[0] }),
+        _ => json!({ "type": "MultiPolygon", "coordinates": polygons }),
+    };
+
+    let mut feature = Map::new();
+    feature.insert("type".to_string(), json!("Feature"));
+    if let Some(id) = &annotation.id {
+        feature.insert("id".to_string(), json!(id));
+    }
+    feature.insert("geometry".to_string(), geometry);
+    feature.insert("properties".to_string(), Value::Object(properties));
+    Value::Object(feature)
+}
+
+/// The rings of a polygon, closed as GeoJSON requires.
+fn write_polygon(polygon: &Polygon) -> Value {
+    Value::Array(
+        std::iter::once(&polygon.exterior)
+            .chain(&polygon.holes)
+            .map(|ring| {
+                Value::Array(
+                    ring.iter()
+                        .chain(ring.first())
+                        .map(|point| json!([point.x, point.y]))
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
+
+fn invalid(message: &str) -> OpenSlideError {
+    OpenSlideError::InvalidArgument(message.to_string())
+}
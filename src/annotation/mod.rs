@@ -0,0 +1,227 @@
+//! This module provides slide annotations: classified polygons in level 0
+//! coordinates, as drawn by pathologists in viewers such as QuPath.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+use crate::deepzoom::slide_bounds;
+use crate::openslide::{OpenSlide, Region};
+use crate::Result;
+
+mod contour;
+mod geojson;
+mod raster;
+mod transform;
+
+pub use contour::mask_contours;
+
+/// The mask value of annotated pixels.
+pub const ANNOTATED: u8 = 255;
+
+/// A point in level 0 pixel coordinates.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A polygon, possibly with holes.
+///
+/// Rings are not closed: the last point differs from the first one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Polygon {
+    /// The outer boundary
+    pub exterior: Vec<Point>,
+    /// The boundaries of the areas cut out of the polygon
+    pub holes: Vec<Vec<Point>>,
+}
+
+/// An annotated area of a slide.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// The identifier of the annotation, if any
+    pub id: Option<String>,
+    /// The name given to the annotation, if any
+    pub name: Option<String>,
+    /// The class of the annotated area, such as `Tumor`
+    pub classification: Option<String>,
+    /// The display color of the class, as RGB
+    pub color: Option<[u8; 3]>,
+    /// The polygons covering the annotated area
+    pub polygons: Vec<Polygon>,
+    /// The numeric measurements attached to the annotation
+    pub measurements: BTreeMap<String, f64>,
+}
+
+/// The annotations of a slide.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnnotationSet {
+    pub annotations: Vec<Annotation>,
+}
+
+impl From<Vec<Polygon>> for AnnotationSet {
+    /// A single unclassified annotation covering `polygons`.
+    fn from(polygons: Vec<Polygon>) -> Self {
+        AnnotationSet {
+            annotations: vec![Annotation {
+                id: None,
+                name: None,
+                classification: None,
+                color: None,
+                polygons,
+                measurements: BTreeMap::new(),
+            }],
+        }
+    }
+}
+
+impl AnnotationSet {
+    /// Parse GeoJSON annotations, as exported by QuPath: a `FeatureCollection`, a
+    /// single `Feature` or an array of features.
+    ///
+    /// `Polygon`, `MultiPolygon` and `GeometryCollection` geometries are read;
+    /// features without any area, such as points or lines, are skipped. The class is
+    /// read from the `classification` property, with its `color` or `colorRGB`, and
+    /// measurements from the `measurements` property, either an object or a list of
+    /// `name` and `value` pairs.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the GeoJSON is malformed.
+    pub fn from_geojson(text: &str) -> Result<AnnotationSet> {
+        geojson::parse(text)
+    }
+
+    /// Read and parse a GeoJSON annotation file.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be read.
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the GeoJSON is malformed.
+    pub fn open_geojson(path: &Path) -> Result<AnnotationSet> {
+        AnnotationSet::from_geojson(&fs::read_to_string(path)?)
+    }
+
+    /// Serialize the annotations as a GeoJSON `FeatureCollection` that QuPath can
+    /// import, the reverse of [`from_geojson()`](struct.AnnotationSet.html#method.from_geojson).
+    ///
+    /// Each annotation is a `Feature` with a `Polygon` geometry, or a `MultiPolygon`
+    /// one if it has several polygons, and `name`, `classification` and
+    /// `measurements` properties. Rings are closed; measurements that are not finite
+    /// are written as `null`.
+    pub fn to_geojson(&self) -> String {
+        serde_json::to_string_pretty(&geojson::write(self)).unwrap()
+    }
+
+    /// Write the annotations to a GeoJSON file, as
+    /// [`to_geojson()`](struct.AnnotationSet.html#method.to_geojson) does.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be written.
+    pub fn save_geojson(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_geojson())?;
+        Ok(())
+    }
+
+    /// The number of annotations.
+    pub fn len(&self) -> usize {
+        self.annotations.len()
+    }
+
+    /// Return true if there is no annotation.
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    /// The distinct classes of the annotations, sorted.
+    pub fn classifications(&self) -> Vec<&str> {
+        let mut classifications: Vec<&str> = self
+            .annotations
+            .iter()
+            .filter_map(|annotation| annotation.classification.as_deref())
+            .collect();
+        classifications.sort_unstable();
+        classifications.dedup();
+        classifications
+    }
+
+    /// The annotations of the given classes.
+    pub fn with_classifications(&self, classifications: &[&str]) -> AnnotationSet {
+        AnnotationSet {
+            annotations: self
+                .annotations
+                .iter()
+                .filter(|annotation| {
+                    annotation
+                        .classification
+                        .as_deref()
+                        .map_or(false, |class| classifications.contains(&class))
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Render the annotation polygons into a mask matching, pixel for pixel, the
+    /// image returned by [`OpenSlide::read_region()`](struct.OpenSlide.html#method.read_region)
+    /// for the same region.
+    ///
+    /// Pixels whose center lies inside an annotation are set to
+    /// [`ANNOTATED`](constant.ANNOTATED.html), the others to 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `slide` - the annotated slide, giving the downsample of the region level.
+    /// * `region` - the region to render.
+    /// * `limit_bounds` - true if annotation coordinates are relative to the
+    /// non-empty slide region given by the `openslide.bounds-*` properties, as
+    /// exported by QuPath, rather than to the whole slide.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): the region level is out of range.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn rasterize(
+        &self,
+        slide: &OpenSlide,
+        region: &Region,
+        limit_bounds: bool,
+    ) -> Result<GrayImage> {
+        let downsample = f64::from(slide.level_downsample(region.level as u32)?);
+        let mut origin = Point {
+            x: f64::from(region.address.x),
+            y: f64::from(region.address.y),
+        };
+        if limit_bounds {
+            let (offset, _) = slide_bounds(slide)?;
+            origin.x -= f64::from(offset.x);
+            origin.y -= f64::from(offset.y);
+        }
+
+        let mut mask = GrayImage::new(region.size.w, region.size.h);
+        self.fill(&mut mask, origin, downsample);
+        Ok(mask)
+    }
+
+    /// Fill the annotation polygons into a mask whose top left corner lies at
+    /// `origin`, in annotation coordinates, each mask pixel covering `downsample`
+    /// annotation pixels.
+    pub(crate) fn fill(&self, mask: &mut GrayImage, origin: Point, downsample: f64) {
+        let transform = |point: Point| Point {
+            x: (point.x - origin.x) / downsample,
+            y: (point.y - origin.y) / downsample,
+        };
+        for polygon in self
+            .annotations
+            .iter()
+            .flat_map(|annotation| &annotation.polygons)
+        {
+            raster::fill_polygon(mask, polygon, ANNOTATED, transform);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Polygon scanline rasterization.
+
+use image::{GrayImage, Luma};
+
+use crate::annotation::{Point, Polygon};
+
+/// Fill a polygon into a mask, with the even-odd rule.
+///
+/// A pixel is filled when its center lies inside the polygon, once `polygon`
+/// points are mapped to mask pixel coordinates by `transform`.
+pub(crate) fn fill_polygon<T>(mask: &mut GrayImage, polygon: &Polygon, value: u8, transform: T)
+where
+    T: Fn(Point) -> Point,
+{
+    let rings: Vec<Vec<Point>> = std::iter::once(&polygon.exterior)
+        .chain(&polygon.holes)
+        .map(|ring| ring.iter().map(|point| transform(*point)).collect())
+        .collect();
+
+    let (min_y, max_y) = rings
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), point| {
+            (min.min(point.y), max.max(point.y))
+        });
+    let (width, height) = mask.dimensions();
+    // Rows whose center lies within the vertical extent of the polygon
+    let first_row = (min_y - 0.5).ceil().max(0.) as u32;
+    let last_row = ((max_y - 0.5).floor() + 1.).clamp(0., f64::from(height)) as u32;
+
+    let mut crossings = Vec::new();
+    for row in first_row..last_row {
+        let y = f64::from(row) + 0.5;
+        crossings.clear();
+        for ring in &rings {
+            for (index, start) in ring.iter().enumerate() {
+                let end = ring[(index + 1) % ring.len()];
+                if (start.y <= y) != (end.y <= y) {
+                    crossings.push(start.x + (y - start.y) * (end.x - start.x) / (end.y - start.y));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for span in crossings.chunks_exact(2) {
+            // Columns whose center lies within the span
+            let first = (span[0] - 0.5).ceil().clamp(0., f64::from(width)) as u32;
+            let last = (span[1] - 0.5).ceil().clamp(0., f64::from(width)) as u32;
+            for column in first..last {
+                mask.put_pixel(column, row, Luma([value]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(points: &[(f64, f64)]) -> Vec<Point> {
+        points.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    fn filled(mask: &GrayImage) -> usize {
+        mask.pixels().filter(|pixel| pixel.0[0] == 255).count()
+    }
+
+    #[test]
+    fn test_fill_square() {
+        let square = Polygon {
+            exterior: ring(&[(2., 2.), (6., 2.), (6., 6.), (2., 6.)]),
+            holes: vec![],
+        };
+        let mut mask = GrayImage::new(10, 10);
+        fill_polygon(&mut mask, &square, 255, |point| point);
+
+        assert_eq!(filled(&mask), 16);
+        assert_eq!(mask.get_pixel(2, 2).0[0], 255);
+        assert_eq!(mask.get_pixel(5, 5).0[0], 255);
+        assert_eq!(mask.get_pixel(6, 6).0[0], 0);
+        assert_eq!(mask.get_pixel(1, 2).0[0], 0);
+    }
+
+    #[test]
+    fn test_fill_hole_and_clip() {
+        let polygon = Polygon {
+            exterior: ring(&[(-4., -4.), (8., -4.), (8., 8.), (-4., 8.)]),
+            holes: vec![ring(&[(2., 2.), (4., 2.), (4., 4.), (2., 4.)])],
+        };
+        let mut mask = GrayImage::new(6, 6);
+        fill_polygon(&mut mask, &polygon, 255, |point| point);
+        assert_eq!(filled(&mask), 36 - 4);
+        assert_eq!(mask.get_pixel(3, 3).0[0], 0);
+
+        // Transformed into a 4 times smaller mask
+        let square = Polygon {
+            exterior: ring(&[(0., 0.), (8., 0.), (8., 8.), (0., 8.)]),
+            holes: vec![],
+        };
+        let mut mask = GrayImage::new(6, 6);
+        fill_polygon(&mut mask, &square, 255, |point| Point {
+            x: point.x / 4.,
+            y: point.y / 4.,
+        });
+        assert_eq!(filled(&mask), 4);
+        assert_eq!(mask.get_pixel(1, 1).0[0], 255);
+        assert_eq!(mask.get_pixel(2, 2).0[0], 0);
+    }
+}
@@ -0,0 +1,231 @@
+//! Annotation coordinate transforms.
+
+use crate::annotation::{Annotation, AnnotationSet, Point, Polygon};
+use crate::deepzoom::slide_mpp_xy;
+use crate::openslide::{OpenSlide, Region};
+use crate::Result;
+
+impl AnnotationSet {
+    /// Apply `transform` to every point of the annotations.
+    pub fn map_points<F: Fn(Point) -> Point>(&self, transform: F) -> AnnotationSet {
+        let ring = |ring: &Vec<Point>| -> Vec<Point> {
+            ring.iter().map(|point| transform(*point)).collect()
+        };
+        AnnotationSet {
+            annotations: self
+                .annotations
+                .iter()
+                .map(|annotation| Annotation {
+                    polygons: annotation
+                        .polygons
+                        .iter()
+                        .map(|polygon| Polygon {
+                            exterior: ring(&polygon.exterior),
+                            holes: polygon.holes.iter().map(ring).collect(),
+                        })
+                        .collect(),
+                    ..annotation.clone()
+                })
+                .collect(),
+        }
+    }
+
+    /// Move the annotations by `dx` and `dy`.
+    pub fn translate(&self, dx: f64, dy: f64) -> AnnotationSet {
+        self.map_points(|point| Point {
+            x: point.x + dx,
+            y: point.y + dy,
+        })
+    }
+
+    /// Scale the annotations by `sx` and `sy` around the origin.
+    pub fn scale(&self, sx: f64, sy: f64) -> AnnotationSet {
+        self.map_points(|point| Point {
+            x: point.x * sx,
+            y: point.y * sy,
+        })
+    }
+
+    /// Convert level 0 coordinates into pixel coordinates of a slide level.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn level0_to_level(&self, slide: &OpenSlide, level: u32) -> Result<AnnotationSet> {
+        let downsample = f64::from(slide.level_downsample(level)?);
+        Ok(self.scale(1. / downsample, 1. / downsample))
+    }
+
+    /// Convert pixel coordinates of a slide level into level 0 coordinates.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn level_to_level0(&self, slide: &OpenSlide, level: u32) -> Result<AnnotationSet> {
+        let downsample = f64::from(slide.level_downsample(level)?);
+        Ok(self.scale(downsample, downsample))
+    }
+
+    /// Convert level 0 coordinates into micrometers, from the slide `openslide.mpp-x`
+    /// and `openslide.mpp-y` properties.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the slide has no valid MPP properties.
+    pub fn level0_to_microns(&self, slide: &OpenSlide) -> Result<AnnotationSet> {
+        let (mpp_x, mpp_y) = slide_mpp_xy(slide)?;
+        Ok(self.scale(f64::from(mpp_x), f64::from(mpp_y)))
+    }
+
+    /// Convert micrometers into level 0 coordinates, from the slide `openslide.mpp-x`
+    /// and `openslide.mpp-y` properties.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the slide has no valid MPP properties.
+    pub fn microns_to_level0(&self, slide: &OpenSlide) -> Result<AnnotationSet> {
+        let (mpp_x, mpp_y) = slide_mpp_xy(slide)?;
+        Ok(self.scale(1. / f64::from(mpp_x), 1. / f64::from(mpp_y)))
+    }
+
+    /// Clip the annotations to the level 0 area of a region, dropping those outside.
+    ///
+    /// Coordinates stay in level 0; chain with
+    /// [`translate()`](struct.AnnotationSet.html#method.translate) and
+    /// [`level0_to_level()`](struct.AnnotationSet.html#method.level0_to_level) for coordinates
+    /// relative to the region pixels.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): the region level is out of range.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn crop(&self, slide: &OpenSlide, region: &Region) -> Result<AnnotationSet> {
+        let downsample = f64::from(slide.level_downsample(region.level as u32)?);
+        let start = Point {
+            x: f64::from(region.address.x),
+            y: f64::from(region.address.y),
+        };
+        let end = Point {
+            x: start.x + f64::from(region.size.w) * downsample,
+            y: start.y + f64::from(region.size.h) * downsample,
+        };
+
+        let annotations = self
+            .annotations
+            .iter()
+            .filter_map(|annotation| {
+                let polygons: Vec<Polygon> = annotation
+                    .polygons
+                    .iter()
+                    .filter_map(|polygon| {
+                        let exterior = clip_ring(&polygon.exterior, start, end);
+                        if exterior.is_empty() {
+                            return None;
+                        }
+                        Some(Polygon {
+                            exterior,
+                            holes: polygon
+                                .holes
+                                .iter()
+                                .map(|hole| clip_ring(hole, start, end))
+                                .filter(|hole| !hole.is_empty())
+                                .collect(),
+                        })
+                    })
+                    .collect();
+                if polygons.is_empty() {
+                    None
+                } else {
+                    Some(Annotation {
+                        polygons,
+                        ..annotation.clone()
+                    })
+                }
+            })
+            .collect();
+        Ok(AnnotationSet { annotations })
+    }
+}
+
+/// Clip a ring to the rectangle from `start` to `end` with the Sutherland-Hodgman
+/// algorithm, returning an empty ring if less than 3 points remain.
+fn clip_ring(ring: &[Point], start: Point, end: Point) -> Vec<Point> {
+    let points = clip_side(ring, |p| p.x >= start.x, |a, b| at_x(a, b, start.x));
+    let points = clip_side(&points, |p| p.x <= end.x, |a, b| at_x(a, b, end.x));
+    let points = clip_side(&points, |p| p.y >= start.y, |a, b| at_y(a, b, start.y));
+    let points = clip_side(&points, |p| p.y <= end.y, |a, b| at_y(a, b, end.y));
+
+    if points.len() < 3 {
+        Vec::new()
+    } else {
+        points
+    }
+}
+
+/// Keep the part of a ring on the side of a line where `inside` holds, edges
+/// crossing the line at the point given by `cross`.
+fn clip_side<I, C>(ring: &[Point], inside: I, cross: C) -> Vec<Point>
+where
+    I: Fn(Point) -> bool,
+    C: Fn(Point, Point) -> Point,
+{
+    let mut points = Vec::with_capacity(ring.len());
+    for (index, current) in ring.iter().enumerate() {
+        let previous = ring[(index + ring.len() - 1) % ring.len()];
+        match (inside(previous), inside(*current)) {
+            (true, true) => points.push(*current),
+            (true, false) => points.push(cross(previous, *current)),
+            (false, true) => {
+                points.push(cross(previous, *current));
+                points.push(*current);
+            }
+            (false, false) => {}
+        }
+    }
+    points
+}
+
+/// The point of the segment from `a` to `b` at abscissa `x`.
+fn at_x(a: Point, b: Point, x: f64) -> Point {
+    Point {
+        x,
+        y: a.y + (x - a.x) * (b.y - a.y) / (b.x - a.x),
+    }
+}
+
+/// The point of the segment from `a` to `b` at ordinate `y`.
+fn at_y(a: Point, b: Point, y: f64) -> Point {
+    Point {
+        x: a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y),
+        y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(points: &[(f64, f64)]) -> Vec<Point> {
+        points.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    #[test]
+    fn test_clip_ring() {
+        let start = Point { x: 0., y: 0. };
+        let end = Point { x: 10., y: 10. };
+
+        let inside = ring(&[(1., 1.), (5., 1.), (5., 5.)]);
+        assert_eq!(clip_ring(&inside, start, end), inside);
+
+        let outside = ring(&[(11., 1.), (15., 1.), (15., 5.)]);
+        assert!(clip_ring(&outside, start, end).is_empty());
+
+        // A square overlapping the bottom right corner
+        let square = ring(&[(5., 5.), (15., 5.), (15., 15.), (5., 15.)]);
+        let mut clipped = clip_ring(&square, start, end);
+        clipped.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+        assert_eq!(clipped, ring(&[(5., 5.), (5., 10.), (10., 5.), (10., 10.)]));
+    }
+}
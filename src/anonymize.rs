@@ -0,0 +1,161 @@
+//! This module provides functionality for removing identifying information,
+//! such as slide labels, from whole slide images.
+//!
+//! Anonymization works on a copy of the slide: associated images are unlinked
+//! from the TIFF directory chain and their pixel data is zeroed, and identifying
+//! metadata fields are blanked. The slide levels are left untouched.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::openslide::OpenSlide;
+use crate::tiff::{Directory, TiffFile};
+use crate::{OpenSlideError, Result};
+
+const TAG_DOCUMENT_NAME: u16 = 269;
+const TAG_IMAGE_DESCRIPTION: u16 = 270;
+const TAG_DATE_TIME: u16 = 306;
+const TAG_ARTIST: u16 = 315;
+const TAG_HOST_COMPUTER: u16 = 316;
+const TAG_NDPI_SOURCE_LENS: u16 = 65421;
+const TAG_NDPI_REFERENCE: u16 = 65427;
+
+/// ASCII tags blanked in every directory.
+const IDENTIFYING_TAGS: &[u16] = &[
+    TAG_DOCUMENT_NAME,
+    TAG_DATE_TIME,
+    TAG_ARTIST,
+    TAG_HOST_COMPUTER,
+];
+
+/// Fields removed from Aperio image descriptions.
+const APERIO_IDENTIFYING_FIELDS: &[&str] =
+    &["Filename", "Date", "Time", "Time Zone", "User", "Barcode"];
+
+/// Vendors supported by [`anonymize`](fn.anonymize.html).
+pub const SUPPORTED_VENDORS: &[&str] = &["aperio", "hamamatsu", "generic-tiff"];
+
+/// Write an anonymized copy of a whole slide image.
+///
+/// The `label` and `macro` associated images are removed and identifying
+/// properties (file names, dates, operators, barcodes) are blanked.
+///
+/// # Arguments
+///
+/// * `src` - path to a valid whole slide image.
+/// * `dest` - path of the anonymized copy. Overwritten if it exists, and left
+/// untouched if anonymization fails.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::MissingFile`](enum.OpenSlideError.html#variant.MissingFile): the file does not exist
+/// * [`OpenSlideError::UnsupportedFile`](enum.OpenSlideError.html#variant.UnsupportedFile): the file is not a
+/// TIFF based slide from one of the [`SUPPORTED_VENDORS`](constant.SUPPORTED_VENDORS.html).
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the copy could not be written.
+pub fn anonymize(src: &Path, dest: &Path) -> Result<()> {
+    let vendor = OpenSlide::detect_vendor(src)?;
+    if !SUPPORTED_VENDORS.contains(&vendor.as_str()) {
+        return Err(OpenSlideError::UnsupportedFile(src.display().to_string()));
+    }
+
+    // Work on a temporary copy next to `dest`, renamed once complete, so that a
+    // failure never leaves a partly anonymized slide behind.
+    let mut file_name = dest.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".partial");
+    let partial = dest.with_file_name(file_name);
+
+    fs::copy(src, &partial)?;
+    let result = anonymize_copy(src, &partial, &vendor)
+        .and_then(|()| fs::rename(&partial, dest).map_err(OpenSlideError::from));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Anonymize, in place, the copy at `path` of the slide `src`.
+fn anonymize_copy(src: &Path, path: &Path, vendor: &str) -> Result<()> {
+    let mut tiff = match TiffFile::open(path, true)? {
+        Some(tiff) => tiff,
+        None => return Err(OpenSlideError::UnsupportedFile(src.display().to_string())),
+    };
+
+    // NDPI files larger than 4GB store the high bits of their offsets outside of
+    // the TIFF structure.
+    if vendor == "hamamatsu" && !tiff.big_tiff && tiff.len()? > u64::from(u32::MAX) {
+        return Err(OpenSlideError::UnsupportedFile(src.display().to_string()));
+    }
+
+    let mut pointer_position = tiff.first_directory_pointer();
+    for directory in tiff.directories()? {
+        if is_associated_image(&mut tiff, vendor, &directory)? {
+            tiff.blank_image_data(&directory)?;
+            // Unlink the directory from the chain.
+            tiff.write_offset(pointer_position, directory.next)?;
+        } else {
+            pointer_position = directory.next_position;
+        }
+        scrub_directory(&mut tiff, vendor, &directory)?;
+    }
+
+    tiff.file.flush()?;
+    Ok(())
+}
+
+/// Return true if the directory holds a `label` or `macro` associated image.
+fn is_associated_image(tiff: &mut TiffFile, vendor: &str, directory: &Directory) -> Result<bool> {
+    match vendor {
+        "aperio" => match directory.entry(TAG_IMAGE_DESCRIPTION) {
+            Some(entry) => {
+                let description = tiff.read_ascii(entry)?;
+                let description = String::from_utf8_lossy(&description);
+                Ok(description
+                    .lines()
+                    .nth(1)
+                    .map(|line| line.starts_with("label ") || line.starts_with("macro "))
+                    .unwrap_or(false))
+            }
+            None => Ok(false),
+        },
+        // The macro image has a source lens of -1.
+        "hamamatsu" => match directory.entry(TAG_NDPI_SOURCE_LENS) {
+            Some(entry) => Ok(tiff.read_float(entry)? == -1.0),
+            None => Ok(false),
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Blank the identifying metadata of a directory.
+fn scrub_directory(tiff: &mut TiffFile, vendor: &str, directory: &Directory) -> Result<()> {
+    for entry in directory.entries.iter() {
+        let identifying = IDENTIFYING_TAGS.contains(&entry.tag)
+            || (vendor == "hamamatsu" && entry.tag == TAG_NDPI_REFERENCE);
+
+        if identifying {
+            tiff.rewrite_ascii(entry, |_| Vec::new())?;
+        } else if vendor == "aperio" && entry.tag == TAG_IMAGE_DESCRIPTION {
+            tiff.rewrite_ascii(entry, scrub_aperio_description)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the identifying `key = value` fields of an Aperio image description.
+fn scrub_aperio_description(description: &[u8]) -> Vec<u8> {
+    let description = String::from_utf8_lossy(description);
+    description
+        .split('|')
+        .enumerate()
+        .filter(|(i, field)| {
+            *i == 0
+                || !APERIO_IDENTIFYING_FIELDS
+                    .iter()
+                    .any(|name| field.split(" = ").next().map(str::trim) == Some(*name))
+        })
+        .map(|(_, field)| field)
+        .collect::<Vec<&str>>()
+        .join("|")
+        .into_bytes()
+}
@@ -0,0 +1,284 @@
+//! This module provides a catalog of the slides of a directory tree: their vendor,
+//! key metadata and quickhash, kept up to date incrementally and persisted to JSON
+//! or, with the `sqlite` feature, to a SQLite database.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::deepzoom::slide_mpp;
+use crate::openslide::{OpenSlide, Size};
+use crate::{OpenSlideError, Result};
+
+/// A slide of the catalog.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlideRecord {
+    /// The path of the slide, relative to the scanned directory
+    pub path: PathBuf,
+    /// The slide format vendor
+    pub vendor: String,
+    /// The `openslide.quickhash-1` property, identifying the slide content
+    pub quickhash: Option<String>,
+    /// The size of the slide file in bytes
+    pub file_size: u64,
+    /// The last modification time of the slide file, in seconds since the Unix epoch
+    pub modified: u64,
+    /// The level 0 dimensions
+    pub dimensions: Size,
+    /// The number of levels
+    pub level_count: u32,
+    /// The level 0 resolution in micrometers per pixel, if known
+    pub mpp: Option<f32>,
+    /// The magnification of the scanner objective, if known
+    pub objective_power: Option<f32>,
+    /// The names of the associated images, such as `label` or `macro`
+    pub associated_images: Vec<String>,
+    /// The error met reading the slide metadata, in which case the fields above
+    /// hold their defaults from the point of failure on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Walk a directory tree and describe every slide in it, sorted by path.
+///
+/// Files which are not slides, such as the companion files of multi-file formats,
+/// are skipped, and symbolic links to directories are not followed. Slides are
+/// opened in parallel; a slide whose metadata cannot be read gets a record holding
+/// the error rather than failing the scan.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the directory could not be walked.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn scan(dir: &Path) -> Result<Vec<SlideRecord>> {
+    update(dir, &[])
+}
+
+/// Bring a catalog of a directory tree up to date, sorted by path.
+///
+/// The records of slides whose file size and modification time are unchanged are
+/// kept as is, without opening the slides again; other slides are described anew,
+/// and the records of removed slides dropped.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the directory could not be walked.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn update(dir: &Path, previous: &[SlideRecord]) -> Result<Vec<SlideRecord>> {
+    let previous: HashMap<&Path, &SlideRecord> = previous
+        .iter()
+        .map(|record| (record.path.as_path(), record))
+        .collect();
+
+    let mut files = Vec::new();
+    walk(dir, &mut files)?;
+    files.sort();
+
+    let records = files
+        .par_iter()
+        .map(|file| -> Result<Option<SlideRecord>> {
+            let metadata = fs::metadata(file)?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs());
+            let relative = file.strip_prefix(dir).unwrap_or(file);
+
+            match previous.get(relative) {
+                Some(record)
+                    if record.file_size == metadata.len() && record.modified == modified =>
+                {
+                    Ok(Some((*record).clone()))
+                }
+                _ => Ok(describe(file, relative, metadata.len(), modified)),
+            }
+        })
+        .collect::<Result<Vec<Option<SlideRecord>>>>()?;
+    Ok(records.into_iter().flatten().collect())
+}
+
+/// Write a catalog as pretty printed JSON.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be written.
+pub fn save_json(records: &[SlideRecord], path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(records)
+        .map_err(|e| OpenSlideError::InternalError(e.to_string()))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Read a catalog written by [`save_json()`](fn.save_json.html).
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be read.
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the file is not a valid catalog.
+pub fn load_json(path: &Path) -> Result<Vec<SlideRecord>> {
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| OpenSlideError::InvalidArgument(format!("Invalid catalog: {}", e)))
+}
+
+/// Write a catalog to the `slides` table of a SQLite database, replacing its
+/// content. The table is created if needed; associated image names are stored as
+/// a JSON array.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the database could not be written.
+#[cfg(feature = "sqlite")]
+pub fn save_sqlite(records: &[SlideRecord], path: &Path) -> Result<()> {
+    let mut connection = rusqlite::Connection::open(path)?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS slides (
+            path TEXT PRIMARY KEY,
+            vendor TEXT NOT NULL,
+            quickhash TEXT,
+            file_size INTEGER NOT NULL,
+            modified INTEGER NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            level_count INTEGER NOT NULL,
+            mpp REAL,
+            objective_power REAL,
+            associated_images TEXT NOT NULL,
+            error TEXT
+        );
+        CREATE INDEX IF NOT EXISTS slides_quickhash ON slides (quickhash);",
+    )?;
+
+    let transaction = connection.transaction()?;
+    transaction.execute("DELETE FROM slides", [])?;
+    for record in records {
+        transaction.execute(
+            "INSERT INTO slides VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            rusqlite::params![
+                record.path.to_string_lossy(),
+                record.vendor,
+                record.quickhash,
+                record.file_size as i64,
+                record.modified as i64,
+                record.dimensions.w,
+                record.dimensions.h,
+                record.level_count,
+                record.mpp,
+                record.objective_power,
+                serde_json::to_string(&record.associated_images)
+                    .map_err(|e| OpenSlideError::InternalError(e.to_string()))?,
+                record.error,
+            ],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Read a catalog written by [`save_sqlite()`](fn.save_sqlite.html), sorted by path.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the database could not be read.
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the database is not a valid catalog.
+#[cfg(feature = "sqlite")]
+pub fn load_sqlite(path: &Path) -> Result<Vec<SlideRecord>> {
+    let connection = rusqlite::Connection::open(path)?;
+    let mut statement = connection.prepare(
+        "SELECT path, vendor, quickhash, file_size, modified, width, height, level_count,
+            mpp, objective_power, associated_images, error
+        FROM slides ORDER BY path",
+    )?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((
+                SlideRecord {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    vendor: row.get(1)?,
+                    quickhash: row.get(2)?,
+                    file_size: row.get::<_, i64>(3)? as u64,
+                    modified: row.get::<_, i64>(4)? as u64,
+                    dimensions: Size {
+                        w: row.get(5)?,
+                        h: row.get(6)?,
+                    },
+                    level_count: row.get(7)?,
+                    mpp: row.get(8)?,
+                    objective_power: row.get(9)?,
+                    associated_images: Vec::new(),
+                    error: row.get(11)?,
+                },
+                row.get::<_, String>(10)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<(SlideRecord, String)>>>()?;
+
+    rows.into_iter()
+        .map(|(mut record, associated_images)| {
+            record.associated_images = serde_json::from_str(&associated_images)
+                .map_err(|e| OpenSlideError::InvalidArgument(format!("Invalid catalog: {}", e)))?;
+            Ok(record)
+        })
+        .collect()
+}
+
+/// Collect the files of a directory tree, without following symbolic links to
+/// directories, which could otherwise recurse forever.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            walk(&path, files)?;
+        } else if !path.is_dir() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Describe a file, or return `None` if it is not a slide.
+fn describe(file: &Path, relative: &Path, file_size: u64, modified: u64) -> Option<SlideRecord> {
+    let vendor = match OpenSlide::detect_vendor(file) {
+        Ok(vendor) => vendor,
+        Err(_) => return None,
+    };
+    // Files claimed by a vendor may still fail to open, such as damaged slides
+    let slide = match OpenSlide::open(file) {
+        Ok(slide) => slide,
+        Err(_) => return None,
+    };
+
+    let mut record = SlideRecord {
+        path: relative.to_path_buf(),
+        vendor,
+        quickhash: None,
+        file_size,
+        modified,
+        dimensions: Size { w: 0, h: 0 },
+        level_count: 0,
+        mpp: None,
+        objective_power: None,
+        associated_images: Vec::new(),
+        error: None,
+    };
+    if let Err(error) = read_metadata(&slide, &mut record) {
+        record.error = Some(error.to_string());
+    }
+    Some(record)
+}
+
+/// Fill the metadata fields of the record of a slide.
+fn read_metadata(slide: &OpenSlide, record: &mut SlideRecord) -> Result<()> {
+    record.quickhash = slide.property("openslide.quickhash-1")?;
+    record.dimensions = slide.dimensions()?;
+    record.level_count = slide.level_count()?;
+    record.mpp = slide_mpp(slide).ok();
+    record.objective_power = slide.magnification()?;
+    record.associated_images = slide.associated_image_names()?;
+    Ok(())
+}
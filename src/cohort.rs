@@ -0,0 +1,313 @@
+//! This module provides a sanity pass over a cohort of slides before training:
+//! resolution, magnification, level structure and color profile of each slide,
+//! flagging those that do not match the rest of the cohort.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::deepzoom::slide_mpp_xy;
+use crate::openslide::{OpenSlide, Size};
+use crate::pyramid::{parallel_map, Parallelism};
+use crate::Result;
+
+/// The tolerances slides must meet, relative to the expected values or, when these
+/// are not given, to the cohort.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CohortTolerances {
+    /// The expected level 0 resolution in micrometers per pixel, or `None` for the
+    /// cohort median
+    pub expected_mpp: Option<f32>,
+    /// The largest relative difference to the expected resolution, and between the
+    /// resolutions of the two axes
+    pub mpp_tolerance: f32,
+    /// The expected objective power, or `None` for the most common one of the cohort
+    pub expected_objective_power: Option<f32>,
+    /// The minimum number of levels
+    pub min_level_count: u32,
+    /// True if slides must embed an ICC profile
+    pub require_icc_profile: bool,
+}
+
+impl Default for CohortTolerances {
+    fn default() -> Self {
+        CohortTolerances {
+            expected_mpp: None,
+            mpp_tolerance: 0.1,
+            expected_objective_power: None,
+            min_level_count: 1,
+            require_icc_profile: false,
+        }
+    }
+}
+
+/// The properties of a single slide of the cohort.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlideSummary {
+    /// The path of the slide
+    pub path: PathBuf,
+    /// The slide format vendor, if the slide could be opened
+    pub vendor: Option<String>,
+    /// The level 0 dimensions
+    pub dimensions: Option<Size>,
+    /// The downsample of each level
+    pub level_downsamples: Vec<f32>,
+    /// The level 0 resolution along the x axis in micrometers per pixel, if known
+    pub mpp_x: Option<f32>,
+    /// The level 0 resolution along the y axis in micrometers per pixel, if known
+    pub mpp_y: Option<f32>,
+    /// The magnification of the scanner objective, if known
+    pub objective_power: Option<f32>,
+    /// True if the slide embeds an ICC profile
+    pub has_icc_profile: bool,
+    /// The tolerances the slide does not meet, or the error met opening it
+    pub issues: Vec<String>,
+}
+
+impl SlideSummary {
+    /// Return true if the slide meets every tolerance.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// The summary of a cohort of slides.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CohortReport {
+    /// The slides, in the order they were given
+    pub slides: Vec<SlideSummary>,
+    /// The resolution slides were compared to, if any slide has one
+    pub reference_mpp: Option<f32>,
+    /// The objective power slides were compared to, if any slide has one
+    pub reference_objective_power: Option<f32>,
+}
+
+impl CohortReport {
+    /// The slides that do not meet the tolerances.
+    pub fn flagged(&self) -> impl Iterator<Item = &SlideSummary> {
+        self.slides.iter().filter(|slide| !slide.is_ok())
+    }
+}
+
+/// Scan a cohort of slides and flag those outside the tolerances.
+///
+/// Slides are opened in parallel. A slide that cannot be opened is reported with
+/// the error as its only issue, rather than failing the scan.
+///
+/// # Arguments
+///
+/// * `paths` - the slides.
+/// * `tolerances` - the expected values and tolerances.
+/// * `parallelism` - how many threads open slides.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): the thread pool could not be created.
+pub fn scan_cohort<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    tolerances: CohortTolerances,
+    parallelism: Parallelism,
+) -> Result<CohortReport> {
+    let mut slides = parallel_map(
+        paths,
+        parallelism,
+        |_, _| {},
+        |path| {
+            let path: &Path = path.as_ref();
+            summarize(path).unwrap_or_else(|error| SlideSummary {
+                path: path.to_path_buf(),
+                vendor: None,
+                dimensions: None,
+                level_downsamples: Vec::new(),
+                mpp_x: None,
+                mpp_y: None,
+                objective_power: None,
+                has_icc_profile: false,
+                issues: vec![error.to_string()],
+            })
+        },
+    )?;
+
+    let reference_mpp = tolerances.expected_mpp.or_else(|| {
+        median(
+            slides
+                .iter()
+                .filter_map(|slide| Some((slide.mpp_x? + slide.mpp_y?) / 2.))
+                .collect(),
+        )
+    });
+    let reference_objective_power = tolerances.expected_objective_power.or_else(|| {
+        most_common(
+            slides
+                .iter()
+                .filter_map(|slide| slide.objective_power)
+                .collect(),
+        )
+    });
+
+    for slide in slides.iter_mut().filter(|slide| slide.dimensions.is_some()) {
+        let mut issues = check(slide, &tolerances, reference_mpp, reference_objective_power);
+        slide.issues.append(&mut issues);
+    }
+    Ok(CohortReport {
+        slides,
+        reference_mpp,
+        reference_objective_power,
+    })
+}
+
+/// Read the properties of a slide.
+fn summarize(path: &Path) -> Result<SlideSummary> {
+    let vendor = OpenSlide::detect_vendor(path)?;
+    let slide = OpenSlide::open(path)?;
+    let level_downsamples = (0..slide.level_count()?)
+        .map(|level| slide.level_downsample(level))
+        .collect::<Result<Vec<f32>>>()?;
+    let mpp = slide_mpp_xy(&slide).ok();
+    let objective_power = slide
+        .property("openslide.objective-power")?
+        .and_then(|power| power.parse::<f32>().ok())
+        .filter(|power| power.is_finite() && *power > 0.);
+
+    Ok(SlideSummary {
+        path: path.to_path_buf(),
+        vendor: Some(vendor),
+        dimensions: Some(slide.dimensions()?),
+        level_downsamples,
+        mpp_x: mpp.map(|(x, _)| x),
+        mpp_y: mpp.map(|(_, y)| y),
+        objective_power,
+        has_icc_profile: slide.icc_profile()?.is_some(),
+        issues: Vec::new(),
+    })
+}
+
+/// List the tolerances a slide does not meet.
+fn check(
+    slide: &SlideSummary,
+    tolerances: &CohortTolerances,
+    reference_mpp: Option<f32>,
+    reference_objective_power: Option<f32>,
+) -> Vec<String> {
+    let differs = |value: f32, reference: f32| {
+        (value - reference).abs() > tolerances.mpp_tolerance * reference
+    };
+
+    let mut issues = Vec::new();
+    match (slide.mpp_x, slide.mpp_y) {
+        (Some(mpp_x), Some(mpp_y)) => {
+            if differs(mpp_y, mpp_x) {
+                issues.push(format!("Anisotropic resolution: {} x {} MPP", mpp_x, mpp_y));
+            }
+            let mpp = (mpp_x + mpp_y) / 2.;
+            if let Some(reference) = reference_mpp {
+                if differs(mpp, reference) {
+                    issues.push(format!(
+                        "Resolution {} MPP differs from {} MPP",
+                        mpp, reference
+                    ));
+                }
+            }
+        }
+        _ => issues.push("No resolution".to_string()),
+    }
+    match (slide.objective_power, reference_objective_power) {
+        (None, _) => issues.push("No objective power".to_string()),
+        (Some(power), Some(reference)) if (power - reference).abs() > f32::EPSILON => issues.push(
+            format!("Objective power {} differs from {}", power, reference),
+        ),
+        _ => {}
+    }
+    if (slide.level_downsamples.len() as u32) < tolerances.min_level_count {
+        issues.push(format!(
+            "{} levels, fewer than {}",
+            slide.level_downsamples.len(),
+            tolerances.min_level_count
+        ));
+    }
+    if tolerances.require_icc_profile && !slide.has_icc_profile {
+        issues.push("No ICC profile".to_string());
+    }
+    issues
+}
+
+/// The median of values, the lower one for an even count.
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values.get((values.len().max(1) - 1) / 2).copied()
+}
+
+/// The most common of values, the smallest one on ties.
+fn most_common(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut best: Option<(f32, usize)> = None;
+    let mut start = 0;
+    while start < values.len() {
+        let run = values[start..]
+            .iter()
+            .take_while(|value| **value == values[start])
+            .count();
+        if best.map_or(true, |(_, count)| run > count) {
+            best = Some((values[start], run));
+        }
+        start += run;
+    }
+    best.map(|(value, _)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_values() {
+        assert_eq!(median(vec![0.5, 0.25, 1.]), Some(0.5));
+        assert_eq!(median(vec![0.5, 0.25]), Some(0.25));
+        assert_eq!(median(Vec::new()), None);
+
+        assert_eq!(most_common(vec![40., 20., 40., 20., 40.]), Some(40.));
+        assert_eq!(most_common(vec![40., 20.]), Some(20.));
+        assert_eq!(most_common(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let slide = SlideSummary {
+            path: PathBuf::from("slide.svs"),
+            vendor: Some("aperio".to_string()),
+            dimensions: Some(Size { w: 100, h: 100 }),
+            level_downsamples: vec![1.],
+            mpp_x: Some(0.5),
+            mpp_y: Some(0.5),
+            objective_power: Some(20.),
+            has_icc_profile: false,
+            issues: Vec::new(),
+        };
+        let tolerances = CohortTolerances::default();
+        assert!(check(&slide, &tolerances, Some(0.52), Some(20.)).is_empty());
+
+        let issues = check(&slide, &tolerances, Some(0.25), Some(40.));
+        assert_eq!(issues.len(), 2);
+        assert!(issues[0].starts_with("Resolution 0.5 MPP"));
+
+        let strict = CohortTolerances {
+            min_level_count: 3,
+            require_icc_profile: true,
+            ..CohortTolerances::default()
+        };
+        let unknown = SlideSummary {
+            mpp_x: None,
+            objective_power: None,
+            ..slide
+        };
+        assert_eq!(
+            check(&unknown, &strict, None, None),
+            vec![
+                "No resolution",
+                "No objective power",
+                "1 levels, fewer than 3",
+                "No ICC profile"
+            ]
+        );
+    }
+}
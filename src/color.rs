@@ -0,0 +1,52 @@
+//! This module provides the color management shared by the image readers: conversion
+//! of slide pixels from their ICC profile to sRGB.
+//!
+//! This module requires the `color` feature.
+
+use image::RgbaImage;
+use lcms2::{DisallowCache, Flags, GlobalContext, Intent, PixelFormat, Profile, Transform};
+
+use crate::{OpenSlideError, Result};
+
+/// A conversion from a slide ICC profile to sRGB, usable from several threads.
+pub(crate) struct SrgbTransform {
+    transform: Transform<[u8; 3], [u8; 3], GlobalContext, DisallowCache>,
+}
+
+impl SrgbTransform {
+    /// Build the conversion from the given ICC profile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the profile is invalid.
+    pub(crate) fn new(icc_profile: &[u8]) -> Result<SrgbTransform> {
+        let invalid = |e: lcms2::Error| {
+            OpenSlideError::InvalidArgument(format!("Invalid ICC profile: {}", e))
+        };
+        let profile = Profile::new_icc(icc_profile).map_err(invalid)?;
+        let transform = Transform::new_flags(
+            &profile,
+            PixelFormat::RGB_8,
+            &Profile::new_srgb(),
+            PixelFormat::RGB_8,
+            Intent::Perceptual,
+            Flags::NO_CACHE,
+        )
+        .map_err(invalid)?;
+
+        Ok(SrgbTransform { transform })
+    }
+
+    /// Convert an image in place. The alpha channel is left untouched.
+    pub(crate) fn apply(&self, image: &mut RgbaImage) {
+        let mut pixels: Vec<[u8; 3]> = image
+            .pixels()
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        self.transform.transform_in_place(&mut pixels);
+
+        for (pixel, rgb) in image.pixels_mut().zip(pixels) {
+            pixel.0[..3].copy_from_slice(&rgb);
+        }
+    }
+}
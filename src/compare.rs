@@ -0,0 +1,206 @@
+//! This module compares the pixels of two slides, to validate format conversions
+//! and regression test decoders.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::{OpenSlideError, Result};
+
+/// The width and height of the tiles compared at once.
+const TILE_SIZE: u32 = 512;
+
+/// A tile holding pixels that differ by more than the tolerance.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TileDiff {
+    /// The top left corner of the tile, in level pixels
+    pub address: Address,
+    /// The size of the tile, in level pixels
+    pub size: Size,
+    /// The largest channel difference in the tile
+    pub max_difference: u8,
+    /// The number of pixels of the tile differing by more than the tolerance
+    pub mismatched_pixels: u64,
+}
+
+/// The differences between two slides at a level.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiffReport {
+    /// The compared level
+    pub level: u32,
+    /// The dimensions of the level
+    pub dimensions: Size,
+    /// The largest difference of a red, green, blue or alpha channel
+    pub max_difference: u8,
+    /// The mean difference of the channels
+    pub mean_difference: f64,
+    /// The number of pixels with a channel differing by more than the tolerance
+    pub mismatched_pixels: u64,
+    /// The tiles holding mismatched pixels, in row-major order
+    pub mismatched_tiles: Vec<TileDiff>,
+}
+
+impl DiffReport {
+    /// Return true if no pixel differs by more than the tolerance.
+    pub fn is_match(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+}
+
+/// Compare the RGBA pixels of two slides at a level, such as a slide and its
+/// conversion to another format.
+///
+/// The level is compared in tiles read in parallel, so that memory stays bounded
+/// whatever its size.
+///
+/// # Arguments
+///
+/// * `slide_a`: the reference slide.
+/// * `slide_b`: the compared slide.
+/// * `level`: the level to compare, of the same dimensions in both slides.
+/// * `tolerance`: the largest channel difference of matching pixels.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range in either slide.
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the level dimensions differ.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn compare(
+    slide_a: &OpenSlide,
+    slide_b: &OpenSlide,
+    level: u32,
+    tolerance: u8,
+) -> Result<DiffReport> {
+    let dimensions = slide_a.level_dimensions(level)?;
+    let dimensions_b = slide_b.level_dimensions(level)?;
+    if dimensions != dimensions_b {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Level {} dimensions {}x{} and {}x{} differ",
+            level, dimensions.w, dimensions.h, dimensions_b.w, dimensions_b.h
+        )));
+    }
+    let downsample_a = f64::from(slide_a.level_downsample(level)?);
+    let downsample_b = f64::from(slide_b.level_downsample(level)?);
+
+    let tiles: Vec<Address> = (0..dimensions.h)
+        .step_by(TILE_SIZE as usize)
+        .flat_map(|y| {
+            (0..dimensions.w)
+                .step_by(TILE_SIZE as usize)
+                .map(move |x| Address { x, y })
+        })
+        .collect();
+    let diffs = tiles
+        .par_iter()
+        .map(|address| {
+            let size = Size {
+                w: TILE_SIZE.min(dimensions.w - address.x),
+                h: TILE_SIZE.min(dimensions.h - address.y),
+            };
+            let read = |slide: &OpenSlide, downsample: f64| {
+                slide.read_region(Region {
+                    address: Address {
+                        x: (f64::from(address.x) * downsample).round() as u32,
+                        y: (f64::from(address.y) * downsample).round() as u32,
+                    },
+                    level,
+                    size,
+                })
+            };
+            let a = read(slide_a, downsample_a)?;
+            let b = read(slide_b, downsample_b)?;
+            Ok((*address, size, diff_images(&a, &b, tolerance)))
+        })
+        .collect::<Result<Vec<(Address, Size, ImageDiff)>>>()?;
+
+    let channels = 4. * f64::from(dimensions.w) * f64::from(dimensions.h);
+    let total: u64 = diffs.iter().map(|(_, _, diff)| diff.total_difference).sum();
+    Ok(DiffReport {
+        level,
+        dimensions,
+        max_difference: diffs
+            .iter()
+            .map(|(_, _, diff)| diff.max_difference)
+            .max()
+            .unwrap_or(0),
+        mean_difference: if channels > 0. {
+            total as f64 / channels
+        } else {
+            0.
+        },
+        mismatched_pixels: diffs
+            .iter()
+            .map(|(_, _, diff)| diff.mismatched_pixels)
+            .sum(),
+        mismatched_tiles: diffs
+            .iter()
+            .filter(|(_, _, diff)| diff.mismatched_pixels > 0)
+            .map(|(address, size, diff)| TileDiff {
+                address: *address,
+                size: *size,
+                max_difference: diff.max_difference,
+                mismatched_pixels: diff.mismatched_pixels,
+            })
+            .collect(),
+    })
+}
+
+/// The differences between two images of the same size.
+#[derive(Debug, PartialEq)]
+struct ImageDiff {
+    max_difference: u8,
+    total_difference: u64,
+    mismatched_pixels: u64,
+}
+
+/// Compare two images of the same size channel by channel.
+fn diff_images(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> ImageDiff {
+    let mut diff = ImageDiff {
+        max_difference: 0,
+        total_difference: 0,
+        mismatched_pixels: 0,
+    };
+    for (pixel_a, pixel_b) in a.pixels().zip(b.pixels()) {
+        let mut pixel_max = 0;
+        for (channel_a, channel_b) in pixel_a.0.iter().zip(&pixel_b.0) {
+            let difference = if channel_a > channel_b {
+                channel_a - channel_b
+            } else {
+                channel_b - channel_a
+            };
+            pixel_max = pixel_max.max(difference);
+            diff.total_difference += u64::from(difference);
+        }
+        diff.max_difference = diff.max_difference.max(pixel_max);
+        if pixel_max > tolerance {
+            diff.mismatched_pixels += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_diff_images() {
+        let a = RgbaImage::from_pixel(2, 2, Rgba([100, 100, 100, 255]));
+        let mut b = a.clone();
+        b.put_pixel(0, 0, Rgba([103, 100, 100, 255]));
+        b.put_pixel(1, 1, Rgba([100, 90, 101, 255]));
+
+        assert_eq!(
+            diff_images(&a, &b, 3),
+            ImageDiff {
+                max_difference: 10,
+                total_difference: 14,
+                mismatched_pixels: 1,
+            }
+        );
+        assert_eq!(diff_images(&a, &b, 10).mismatched_pixels, 0);
+        assert_eq!(diff_images(&a, &a, 0).max_difference, 0);
+    }
+}
@@ -0,0 +1,141 @@
+//! HDF5 patch datasets.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+
+use hdf5_rust::types::{H5Type, VarLenUnicode};
+use hdf5_rust::File;
+use ndarray::{s, ArrayView3};
+
+use crate::dataset::rgb_pixels;
+use crate::deepzoom::slide_mpp;
+use crate::openslide::OpenSlide;
+use crate::patch::{Patch, PatchSampler};
+use crate::pyramid::{for_each_tile, Parallelism};
+use crate::{OpenSlideError, Result};
+
+/// Export the patches of a sampler into an HDF5 file.
+///
+/// The file holds, in sampler order:
+///
+/// * `patches` - the RGB `u8` pixels, with `(n, size, size, 3)` shape, chunked by patch
+/// and deflate compressed.
+/// * `coords` - the level 0 `(x, y)` coordinates of each patch, with `(n, 2)` shape.
+/// * `tissue_fraction` - the share of each patch covered by tissue.
+///
+/// The file attributes record the `slide_id`, the `patch_size`, the `l0_size` of a
+/// patch in level 0 pixels, the slide `level` read and, if the slide resolution is
+/// known, the `mpp` of the patches.
+///
+/// # Arguments
+///
+/// * `sampler` - the patches to export.
+/// * `slide_id` - the identifier of the slide.
+/// * `path` - the path of the HDF5 file, replaced if it exists.
+/// * `compression` - the deflate level, from 0 to 9.
+/// * `parallelism` - how many threads read patches; writes are serialized.
+/// * `progress` - called with the number of patches written so far and the total
+/// number of patches, after each patch.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `compression` is larger than 9.
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be written.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn export_patches_hdf5<S, F>(
+    sampler: &PatchSampler<S>,
+    slide_id: &str,
+    path: &Path,
+    compression: u8,
+    parallelism: Parallelism,
+    progress: F,
+) -> Result<()>
+where
+    S: Deref<Target = OpenSlide> + Sync,
+    F: Fn(usize, usize) + Sync,
+{
+    if compression > 9 {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Deflate level {} must be between 0 and 9",
+            compression
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let count = sampler.len();
+    let size = sampler.patch_size() as usize;
+    let file = File::create(path)?;
+
+    let patches = file
+        .new_dataset::<u8>()
+        .chunk((1, size, size, 3))
+        .deflate(compression)
+        .shape((count, size, size, 3))
+        .create("patches")?;
+    let coords: Vec<u32> = sampler
+        .patches()
+        .iter()
+        .flat_map(|patch| [patch.address.x, patch.address.y])
+        .collect();
+    file.new_dataset::<u32>()
+        .shape((count, 2))
+        .create("coords")?
+        .write_raw(&coords)?;
+    let fractions: Vec<f32> = sampler
+        .patches()
+        .iter()
+        .map(|patch| patch.tissue_fraction)
+        .collect();
+    file.new_dataset::<f32>()
+        .shape(count)
+        .create("tissue_fraction")?
+        .write_raw(&fractions)?;
+
+    let slide_id: VarLenUnicode = slide_id.parse().map_err(|_| {
+        OpenSlideError::InvalidArgument(format!("Invalid slide identifier {:?}", slide_id))
+    })?;
+    write_attribute(&file, "slide_id", &slide_id)?;
+    write_attribute(&file, "patch_size", &sampler.patch_size())?;
+    write_attribute(
+        &file,
+        "l0_size",
+        &((f64::from(sampler.patch_size()) * sampler.downsample()).round() as u32),
+    )?;
+    if let Some(patch) = sampler.patches().first() {
+        write_attribute(&file, "level", &(patch.level as u32))?;
+    }
+    if let Ok(mpp) = slide_mpp(sampler.slide()) {
+        write_attribute(
+            &file,
+            "mpp",
+            &((f64::from(mpp) * sampler.downsample()) as f32),
+        )?;
+    }
+
+    // The HDF5 library is not reentrant: patches are read in parallel, written in turn
+    let patches = Mutex::new(patches);
+    let indexed: Vec<(usize, &Patch)> = sampler.patches().iter().enumerate().collect();
+    for_each_tile(&indexed, parallelism, progress, |(index, patch)| {
+        let pixels = rgb_pixels(&sampler.read_patch(patch)?);
+        let view = ArrayView3::from_shape((size, size, 3), &pixels)
+            .map_err(|e| OpenSlideError::InternalError(e.to_string()))?;
+        patches
+            .lock()
+            .unwrap()
+            .write_slice(view, s![*index, .., .., ..])?;
+        Ok(())
+    })
+}
+
+/// Write a scalar attribute of the file.
+fn write_attribute<T: H5Type>(file: &File, name: &str, value: &T) -> Result<()> {
+    file.new_attr::<T>()
+        .shape(())
+        .create(name)?
+        .write_scalar(value)?;
+    Ok(())
+}
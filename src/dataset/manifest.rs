@@ -0,0 +1,118 @@
+//! Parquet patch manifests.
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_rs::array::{ArrayRef, Float32Array, StringArray, UInt32Array, UInt64Array};
+use arrow_rs::datatypes::{DataType, Field, Schema};
+use arrow_rs::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::dataset::PatchRecord;
+use crate::{OpenSlideError, Result};
+
+/// Write patch records as a Parquet manifest, to query extraction metadata with
+/// tools such as DuckDB or Spark.
+///
+/// The file holds a row per record, with the columns of the CSV manifest in the
+/// order of the [`PatchRecord`](struct.PatchRecord.html) fields: `slide_id` and
+/// `path` strings, `x`, `y`, `l0_size` and `size` `u32`, `level` `u64`, a nullable
+/// `mpp` `f32` and `tissue_fraction` `f32`. An optional nullable `label` string
+/// column follows, holding the class of each patch. The file is Snappy compressed.
+///
+/// # Arguments
+///
+/// * `records` - the records to write.
+/// * `labels` - the label of each record, if any.
+/// * `path` - the Parquet file, replaced if it exists.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): there is not a label per record.
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be written.
+pub fn write_manifest_parquet(
+    records: &[PatchRecord],
+    labels: Option<&[Option<String>]>,
+    path: &Path,
+) -> Result<()> {
+    if let Some(labels) = labels {
+        if labels.len() != records.len() {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "{} labels given for {} records",
+                labels.len(),
+                records.len()
+            )));
+        }
+    }
+
+    let strings = |field: fn(&PatchRecord) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from(
+            records.iter().map(field).collect::<Vec<&str>>(),
+        ))
+    };
+    let integers = |field: fn(&PatchRecord) -> u32| -> ArrayRef {
+        Arc::new(UInt32Array::from(
+            records.iter().map(field).collect::<Vec<u32>>(),
+        ))
+    };
+
+    let mut fields = vec![
+        Field::new("slide_id", DataType::Utf8, false),
+        Field::new("path", DataType::Utf8, false),
+        Field::new("x", DataType::UInt32, false),
+        Field::new("y", DataType::UInt32, false),
+        Field::new("l0_size", DataType::UInt32, false),
+        Field::new("size", DataType::UInt32, false),
+        Field::new("level", DataType::UInt64, false),
+        Field::new("mpp", DataType::Float32, true),
+        Field::new("tissue_fraction", DataType::Float32, false),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        strings(|record| record.slide_id.as_str()),
+        strings(|record| record.path.as_str()),
+        integers(|record| record.x),
+        integers(|record| record.y),
+        integers(|record| record.l0_size),
+        integers(|record| record.size),
+        Arc::new(UInt64Array::from(
+            records
+                .iter()
+                .map(|record| record.level as u64)
+                .collect::<Vec<u64>>(),
+        )),
+        Arc::new(Float32Array::from(
+            records
+                .iter()
+                .map(|record| record.mpp)
+                .collect::<Vec<Option<f32>>>(),
+        )),
+        Arc::new(Float32Array::from(
+            records
+                .iter()
+                .map(|record| record.tissue_fraction)
+                .collect::<Vec<f32>>(),
+        )),
+    ];
+    if let Some(labels) = labels {
+        fields.push(Field::new("label", DataType::Utf8, true));
+        columns.push(Arc::new(StringArray::from(
+            labels
+                .iter()
+                .map(|label| label.as_deref())
+                .collect::<Vec<Option<&str>>>(),
+        )));
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,249 @@
+//! This module provides functionality for exporting sampled patches as training
+//! datasets.
+
+use std::fmt::Write;
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::deepzoom::slide_mpp;
+use crate::encode::{encode, Format};
+use crate::openslide::OpenSlide;
+use crate::patch::PatchSampler;
+use crate::pyramid::{for_each_tile, Parallelism};
+use crate::{OpenSlideError, Result};
+
+#[cfg(feature = "hdf5")]
+mod h5;
+#[cfg(feature = "arrow")]
+mod manifest;
+mod session;
+mod zarr;
+
+#[cfg(feature = "hdf5")]
+pub use h5::export_patches_hdf5;
+#[cfg(feature = "arrow")]
+pub use manifest::write_manifest_parquet;
+pub use session::{export_patches_resumable, SessionStats};
+pub use zarr::ZarrPatchWriter;
+
+/// A manifest entry describing an exported patch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PatchRecord {
+    /// The identifier of the slide the patch comes from
+    pub slide_id: String,
+    /// The path of the patch image, relative to the export directory
+    pub path: String,
+    /// The level 0 abscissa of the top left corner
+    pub x: u32,
+    /// The level 0 ordinate of the top left corner
+    pub y: u32,
+    /// The side of the patch in level 0 pixels
+    pub l0_size: u32,
+    /// The side of the patch image in pixels
+    pub size: u32,
+    /// The slide level the patch was read from
+    pub level: usize,
+    /// The resolution of the patch image in micrometers per pixel, if the slide
+    /// resolution is known
+    pub mpp: Option<f32>,
+    /// The share of the patch covered by tissue, from 0 to 1
+    pub tissue_fraction: f32,
+}
+
+/// The columns of the CSV manifest, in the order of the
+/// [`PatchRecord`](struct.PatchRecord.html) fields.
+const CSV_HEADER: &str = "slide_id,path,x,y,l0_size,size,level,mpp,tissue_fraction";
+
+/// Export the patches of a sampler as image files, with a manifest.
+///
+/// Patches are written to `{dir}/{slide_id}/{x}_{y}.{extension}`, named after their
+/// level 0 coordinates, and described in sampler order by the
+/// `{dir}/{slide_id}_manifest.csv` and `{dir}/{slide_id}_manifest.json` manifests, so
+/// that the patches of several slides can share a directory. With the `arrow`
+/// feature, a `{dir}/{slide_id}_manifest.parquet` manifest is also written, as by
+/// [`write_manifest_parquet()`](fn.write_manifest_parquet.html).
+///
+/// # Arguments
+///
+/// * `sampler` - the patches to export.
+/// * `slide_id` - the identifier of the slide, used to name the files.
+/// * `dir` - the output directory.
+/// * `format` - the format of the patch images.
+/// * `parallelism` - how many threads read and encode patches.
+/// * `progress` - called with the number of patches written so far and the total
+/// number of patches, after each patch.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `slide_id` is empty or contains a path separator.
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the patches could not be written.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn export_patches<S, F>(
+    sampler: &PatchSampler<S>,
+    slide_id: &str,
+    dir: &Path,
+    format: Format,
+    parallelism: Parallelism,
+    progress: F,
+) -> Result<Vec<PatchRecord>>
+where
+    S: Deref<Target = OpenSlide> + Sync,
+    F: Fn(usize, usize) + Sync,
+{
+    check_slide_id(slide_id)?;
+    let records = patch_records(sampler, slide_id, format);
+    fs::create_dir_all(dir.join(slide_id))?;
+    for_each_tile(
+        &sampler.patches().iter().zip(&records).collect::<Vec<_>>(),
+        parallelism,
+        progress,
+        |(patch, record)| {
+            let pixels = sampler.read_patch(patch)?;
+            fs::write(dir.join(&record.path), encode(&pixels, format)?)?;
+            Ok(())
+        },
+    )?;
+
+    write_manifests(&records, slide_id, dir)?;
+    Ok(records)
+}
+
+/// Check that a slide identifier can name files.
+fn check_slide_id(slide_id: &str) -> Result<()> {
+    if slide_id.is_empty()
+        || slide_id.contains(|c| c == '/' || c == '\\')
+        || matches!(slide_id, "." | "..")
+    {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Invalid slide identifier {:?}",
+            slide_id
+        )));
+    }
+    Ok(())
+}
+
+/// Write the CSV, JSON and, with the `arrow` feature, Parquet manifests of a slide.
+fn write_manifests(records: &[PatchRecord], slide_id: &str, dir: &Path) -> Result<()> {
+    fs::write(
+        dir.join(format!("{}_manifest.csv", slide_id)),
+        manifest_csv(records),
+    )?;
+    fs::write(
+        dir.join(format!("{}_manifest.json", slide_id)),
+        serde_json::to_string_pretty(records)
+            .map_err(|e| OpenSlideError::InternalError(e.to_string()))?,
+    )?;
+    #[cfg(feature = "arrow")]
+    write_manifest_parquet(
+        records,
+        None,
+        &dir.join(format!("{}_manifest.parquet", slide_id)),
+    )?;
+    Ok(())
+}
+
+/// Describe the patches of a sampler, in sampler order.
+pub(crate) fn patch_records<S: Deref<Target = OpenSlide>>(
+    sampler: &PatchSampler<S>,
+    slide_id: &str,
+    format: Format,
+) -> Vec<PatchRecord> {
+    let mpp = slide_mpp(sampler.slide())
+        .ok()
+        .map(|mpp| (f64::from(mpp) * sampler.downsample()) as f32);
+    let l0_size = (f64::from(sampler.patch_size()) * sampler.downsample()).round() as u32;
+
+    sampler
+        .patches()
+        .iter()
+        .map(|patch| PatchRecord {
+            slide_id: slide_id.to_string(),
+            path: format!(
+                "{}/{}_{}.{}",
+                slide_id,
+                patch.address.x,
+                patch.address.y,
+                format.extension()
+            ),
+            x: patch.address.x,
+            y: patch.address.y,
+            l0_size,
+            size: sampler.patch_size(),
+            level: patch.level,
+            mpp,
+            tissue_fraction: patch.tissue_fraction,
+        })
+        .collect()
+}
+
+/// Return the RGB pixels of a patch in row-major, interleaved order.
+pub(crate) fn rgb_pixels(image: &RgbaImage) -> Vec<u8> {
+    image
+        .pixels()
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect()
+}
+
+/// Render records as CSV, with a header line.
+fn manifest_csv(records: &[PatchRecord]) -> String {
+    let mut csv = String::new();
+    writeln!(csv, "{}", CSV_HEADER).unwrap();
+    for record in records {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{}",
+            csv_field(&record.slide_id),
+            csv_field(&record.path),
+            record.x,
+            record.y,
+            record.l0_size,
+            record.size,
+            record.level,
+            record.mpp.map(|mpp| mpp.to_string()).unwrap_or_default(),
+            record.tissue_fraction
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// Quote a CSV field if needed.
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_csv() {
+        let record = PatchRecord {
+            slide_id: "a,\"b\"".to_string(),
+            path: "a/0_0.png".to_string(),
+            x: 0,
+            y: 10,
+            l0_size: 20,
+            size: 10,
+            level: 1,
+            mpp: None,
+            tissue_fraction: 0.5,
+        };
+
+        assert_eq!(
+            manifest_csv(&[record]),
+            format!(
+                "{}\n\"a,\"\"b\"\"\",a/0_0.png,0,10,20,10,1,,0.5\n",
+                CSV_HEADER
+            )
+        );
+    }
+}
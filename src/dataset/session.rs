@@ -0,0 +1,214 @@
+//! Resumable patch exports.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{check_slide_id, manifest_csv, patch_records, write_manifests, PatchRecord};
+use crate::encode::{encode, Format};
+use crate::openslide::OpenSlide;
+use crate::patch::PatchSampler;
+use crate::pyramid::{for_each_tile, Parallelism};
+use crate::{OpenSlideError, Result};
+
+/// Statistics of a resumable patch export.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SessionStats {
+    /// The number of patches of the sampler
+    pub patches: usize,
+    /// The number of patches written by a previous run and verified intact
+    pub resumed: usize,
+    /// The number of patches written by a previous run but missing or altered since,
+    /// written again
+    pub invalid: usize,
+    /// The number of patches written by this run
+    pub written: usize,
+}
+
+/// The first line of a session file, identifying the export.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SessionHeader {
+    slide_id: String,
+    format: String,
+    patches: usize,
+    fingerprint: u64,
+}
+
+/// A line of a session file, recording a written patch.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Checkpoint {
+    path: String,
+    size: u64,
+    checksum: u64,
+}
+
+/// Export the patches of a sampler as [`export_patches()`](fn.export_patches.html)
+/// does, recording its progress so that an interrupted export can resume where it
+/// stopped.
+///
+/// Each written patch is appended to the `{dir}/{slide_id}_session.jsonl` session
+/// file, with its size and checksum. Running the same export again verifies the
+/// patches recorded there and only writes those missing, altered or never written;
+/// the manifests are written once every patch is.
+///
+/// # Arguments
+///
+/// * `sampler` - the patches to export.
+/// * `slide_id` - the identifier of the slide, used to name the files.
+/// * `dir` - the output directory.
+/// * `format` - the format of the patch images.
+/// * `parallelism` - how many threads read and encode patches.
+/// * `progress` - called with the number of patches done so far, including resumed
+/// ones, and the total number of patches, after each patch.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `slide_id` is empty or contains a path separator, or the session file records an export of other patches or in another format.
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the patches or the session file could not be written.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn export_patches_resumable<S, F>(
+    sampler: &PatchSampler<S>,
+    slide_id: &str,
+    dir: &Path,
+    format: Format,
+    parallelism: Parallelism,
+    progress: F,
+) -> Result<(Vec<PatchRecord>, SessionStats)>
+where
+    S: Deref<Target = OpenSlide> + Sync,
+    F: Fn(usize, usize) + Sync,
+{
+    check_slide_id(slide_id)?;
+    let records = patch_records(sampler, slide_id, format);
+    fs::create_dir_all(dir.join(slide_id))?;
+
+    let header = SessionHeader {
+        slide_id: slide_id.to_string(),
+        format: format!("{:?}", format),
+        patches: records.len(),
+        fingerprint: checksum(manifest_csv(&records).as_bytes()),
+    };
+    let session_path = dir.join(format!("{}_session.jsonl", slide_id));
+    let checkpoints = if session_path.exists() {
+        read_session(&session_path, &header)?
+    } else {
+        fs::write(&session_path, format!("{}\n", to_json(&header)?))?;
+        HashMap::new()
+    };
+
+    let states: Vec<(bool, bool)> = records
+        .par_iter()
+        .map(|record| match checkpoints.get(&record.path) {
+            Some(checkpoint) => (true, is_intact(&dir.join(&record.path), checkpoint)),
+            None => (false, false),
+        })
+        .collect();
+    let resumed = states.iter().filter(|(_, intact)| *intact).count();
+    let invalid = states
+        .iter()
+        .filter(|(recorded, intact)| *recorded && !*intact)
+        .count();
+
+    let mut session = OpenOptions::new().append(true).open(&session_path)?;
+    // A line cut short by an interruption must not swallow the next checkpoint
+    if !fs::read(&session_path)?.ends_with(b"\n") {
+        session.write_all(b"\n")?;
+    }
+    let session = Mutex::new(session);
+
+    let pending: Vec<_> = sampler
+        .patches()
+        .iter()
+        .zip(&records)
+        .zip(&states)
+        .filter(|(_, (_, intact))| !*intact)
+        .map(|(patch, _)| patch)
+        .collect();
+    let total = records.len();
+    for_each_tile(
+        &pending,
+        parallelism,
+        |done, _| progress(resumed + done, total),
+        |(patch, record)| {
+            let data = encode(&sampler.read_patch(patch)?, format)?;
+            fs::write(dir.join(&record.path), &data)?;
+            let checkpoint = Checkpoint {
+                path: record.path.clone(),
+                size: data.len() as u64,
+                checksum: checksum(&data),
+            };
+            let line = format!("{}\n", to_json(&checkpoint)?);
+            session.lock().unwrap().write_all(line.as_bytes())?;
+            Ok(())
+        },
+    )?;
+
+    write_manifests(&records, slide_id, dir)?;
+    let stats = SessionStats {
+        patches: total,
+        resumed,
+        invalid,
+        written: pending.len(),
+    };
+    Ok((records, stats))
+}
+
+/// Read the checkpoints of a session file by patch path, checking that it records
+/// the same export.
+///
+/// Lines that cannot be parsed, such as one cut short by an interruption, are
+/// skipped: their patches are written again. The last checkpoint of a patch wins.
+fn read_session(path: &Path, header: &SessionHeader) -> Result<HashMap<String, Checkpoint>> {
+    let content = fs::read_to_string(path)?;
+    let mut lines = content.lines();
+    let recorded: Option<SessionHeader> = lines
+        .next()
+        .and_then(|line| serde_json::from_str(line).ok());
+    if recorded.as_ref() != Some(header) {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Session {} records another export",
+            path.display()
+        )));
+    }
+    Ok(lines
+        .filter_map(|line| serde_json::from_str::<Checkpoint>(line).ok())
+        .map(|checkpoint| (checkpoint.path.clone(), checkpoint))
+        .collect())
+}
+
+/// Return true if a patch file matches its checkpoint.
+fn is_intact(path: &Path, checkpoint: &Checkpoint) -> bool {
+    match fs::read(path) {
+        Ok(data) => data.len() as u64 == checkpoint.size && checksum(&data) == checkpoint.checksum,
+        Err(_) => false,
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| OpenSlideError::InternalError(e.to_string()))
+}
+
+/// The 64-bit FNV-1a hash of data, stable across platforms and releases.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(checksum(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(checksum(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
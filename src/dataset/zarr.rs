@@ -0,0 +1,243 @@
+//! Zarr patch datasets.
+
+use std::ops::{Deref, Range};
+
+use serde_json::{json, Value};
+
+use crate::dataset::rgb_pixels;
+use crate::deepzoom::slide_mpp;
+use crate::openslide::OpenSlide;
+use crate::patch::{Patch, PatchSampler};
+use crate::pyramid::{for_each_tile, Parallelism};
+use crate::zarr::ZarrStore;
+use crate::{OpenSlideError, Result};
+
+/// The number of rows of each chunk of the metadata arrays.
+const METADATA_CHUNK: usize = 65536;
+
+/// Writes sampled patches, from one or several slides, into a Zarr hierarchy.
+///
+/// The hierarchy holds, in append order:
+///
+/// * `patches` - the RGB `u8` pixels, with `(n, size, size, 3)` shape and one
+/// uncompressed chunk per patch.
+/// * `coords` - the level 0 `(x, y)` coordinates of each patch, as `u32`.
+/// * `tissue_fraction` - the share of each patch covered by tissue, as `f32`.
+/// * `slide_index` - the index of the slide of each patch in the `slides` attribute,
+/// as `u32`.
+///
+/// The `slides` attribute of the root group describes each appended slide: its `id`,
+/// the slide `level` read, the `l0_size` of a patch in level 0 pixels and, if known,
+/// the `mpp` of its patches.
+///
+/// The destination is any [`ZarrStore`](trait.ZarrStore.html), such as a local
+/// [`DirectoryStore`](struct.DirectoryStore.html) or an object storage bucket.
+pub struct ZarrPatchWriter<'a, Z: ZarrStore> {
+    store: &'a Z,
+    patch_size: u32,
+    coords: Vec<u32>,
+    tissue_fractions: Vec<f32>,
+    slide_indices: Vec<u32>,
+    slides: Vec<Value>,
+}
+
+impl<'a, Z: ZarrStore> ZarrPatchWriter<'a, Z> {
+    /// Create an empty patch dataset in `store`, replacing any previous one.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `patch_size` is 0.
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the store could not be written to.
+    pub fn create(store: &'a Z, patch_size: u32) -> Result<ZarrPatchWriter<'a, Z>> {
+        if patch_size == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "Patch size must be positive".to_string(),
+            ));
+        }
+
+        let writer = ZarrPatchWriter {
+            store,
+            patch_size,
+            coords: Vec::new(),
+            tissue_fractions: Vec::new(),
+            slide_indices: Vec::new(),
+            slides: Vec::new(),
+        };
+        store.set(
+            ".zgroup",
+            json!({ "zarr_format": 2 }).to_string().as_bytes(),
+        )?;
+        writer.write_metadata(0)?;
+        Ok(writer)
+    }
+
+    /// The width and height of the patches.
+    pub fn patch_size(&self) -> u32 {
+        self.patch_size
+    }
+
+    /// The number of patches written so far.
+    pub fn len(&self) -> usize {
+        self.tissue_fractions.len()
+    }
+
+    /// Return true if no patch was written yet.
+    pub fn is_empty(&self) -> bool {
+        self.tissue_fractions.is_empty()
+    }
+
+    /// Append the patches of a sampler, and return their indices in the dataset.
+    ///
+    /// Patch chunks are read and written in parallel; the metadata arrays and the
+    /// array shapes are updated once every patch is written, so that readers never
+    /// see a patch without its metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampler` - the patches to append.
+    /// * `slide_id` - the identifier of the slide.
+    /// * `parallelism` - how many threads read and write patches.
+    /// * `progress` - called with the number of patches written so far and the total
+    /// number of patches of the sampler, after each patch.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the sampler patch size differs from the dataset one.
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the store could not be written to.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn append<S, F>(
+        &mut self,
+        sampler: &PatchSampler<S>,
+        slide_id: &str,
+        parallelism: Parallelism,
+        progress: F,
+    ) -> Result<Range<usize>>
+    where
+        S: Deref<Target = OpenSlide> + Sync,
+        F: Fn(usize, usize) + Sync,
+    {
+        if sampler.patch_size() != self.patch_size {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Patch size {} differs from the dataset patch size {}",
+                sampler.patch_size(),
+                self.patch_size
+            )));
+        }
+
+        let start = self.len();
+        let indexed: Vec<(usize, &Patch)> = sampler
+            .patches()
+            .iter()
+            .enumerate()
+            .map(|(index, patch)| (start + index, patch))
+            .collect();
+        let store = self.store;
+        for_each_tile(&indexed, parallelism, progress, |(index, patch)| {
+            let pixels = rgb_pixels(&sampler.read_patch(patch)?);
+            store.set(&format!("patches/{}.0.0.0", index), &pixels)
+        })?;
+
+        let slide_index = self.slides.len() as u32;
+        let mut slide = json!({
+            "id": slide_id,
+            "level": sampler.patches().first().map(|patch| patch.level),
+            "l0_size": (f64::from(self.patch_size) * sampler.downsample()).round() as u32,
+        });
+        if let Ok(mpp) = slide_mpp(sampler.slide()) {
+            slide["mpp"] = json!((f64::from(mpp) * sampler.downsample()) as f32);
+        }
+        self.slides.push(slide);
+        for patch in sampler.patches() {
+            self.coords.extend([patch.address.x, patch.address.y]);
+            self.tissue_fractions.push(patch.tissue_fraction);
+            self.slide_indices.push(slide_index);
+        }
+
+        self.write_metadata(start)?;
+        Ok(start..self.len())
+    }
+
+    /// Write the array descriptors, the root attributes and the metadata chunks
+    /// holding rows from `first_row` on.
+    fn write_metadata(&self, first_row: usize) -> Result<()> {
+        let count = self.len();
+        let size = self.patch_size;
+        self.set_zarray(
+            "patches",
+            json!([count, size, size, 3]),
+            json!([1, size, size, 3]),
+            "|u1",
+        )?;
+        self.set_zarray(
+            "coords",
+            json!([count, 2]),
+            json!([METADATA_CHUNK, 2]),
+            "<u4",
+        )?;
+        self.set_zarray(
+            "tissue_fraction",
+            json!([count]),
+            json!([METADATA_CHUNK]),
+            "<f4",
+        )?;
+        self.set_zarray(
+            "slide_index",
+            json!([count]),
+            json!([METADATA_CHUNK]),
+            "<u4",
+        )?;
+
+        let first_chunk = first_row / METADATA_CHUNK;
+        let chunk_count = (count + METADATA_CHUNK - 1) / METADATA_CHUNK;
+        for chunk in first_chunk..chunk_count {
+            let rows = chunk * METADATA_CHUNK..((chunk + 1) * METADATA_CHUNK).min(count);
+            // Chunks are always complete: the last one is padded with the fill value
+            let padding = METADATA_CHUNK - rows.len();
+
+            let mut coords: Vec<u8> = self.coords[2 * rows.start..2 * rows.end]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            coords.resize(coords.len() + 2 * 4 * padding, 0);
+            self.store.set(&format!("coords/{}.0", chunk), &coords)?;
+
+            let mut fractions: Vec<u8> = self.tissue_fractions[rows.clone()]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            fractions.resize(fractions.len() + 4 * padding, 0);
+            self.store
+                .set(&format!("tissue_fraction/{}", chunk), &fractions)?;
+
+            let mut indices: Vec<u8> = self.slide_indices[rows]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            indices.resize(indices.len() + 4 * padding, 0);
+            self.store
+                .set(&format!("slide_index/{}", chunk), &indices)?;
+        }
+
+        self.store.set(
+            ".zattrs",
+            json!({ "patch_size": size, "slides": self.slides })
+                .to_string()
+                .as_bytes(),
+        )
+    }
+
+    fn set_zarray(&self, name: &str, shape: Value, chunks: Value, dtype: &str) -> Result<()> {
+        let zarray = json!({
+            "zarr_format": 2,
+            "shape": shape,
+            "chunks": chunks,
+            "dtype": dtype,
+            "compressor": null,
+            "fill_value": 0,
+            "order": "C",
+            "filters": null,
+        });
+        self.store
+            .set(&format!("{}/.zarray", name), zarray.to_string().as_bytes())
+    }
+}
@@ -1,78 +1,248 @@
 //! This module provides functionality for generating Deep Zoom images from
 //! OpenSlide slides.
 
+use std::convert::TryFrom;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "color")]
+use crate::color::SrgbTransform;
+use crate::encode::{encode, Format};
+use crate::grid::{TileGrid, TileOrder};
 use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::pyramid::BackgroundFilter;
+use crate::utils::composite_buffer;
 use crate::{OpenSlideError, Result};
-use image::imageops::{resize, FilterType};
-use image::RgbaImage;
+use image::imageops::{self, resize, FilterType};
+use image::{Rgb, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// A closure processing each tile before it is encoded, given the tile, its Deep Zoom
+/// level and its address.
+pub type TileHook = dyn Fn(&mut RgbaImage, usize, Address) + Send + Sync;
 
 /// Support for Deep Zoom images.
-pub struct DeepZoom<'a> {
+///
+/// The generator is generic over how it holds the slide: borrow it with
+/// `DeepZoom::new(&slide, ...)`, or share ownership with
+/// `DeepZoom::new(Arc::new(slide), ...)` to get a `'static` generator that can be
+/// stored in application state or moved into other threads.
+pub struct DeepZoom<S: Deref<Target = OpenSlide>> {
+    /// The number of Deep Zoom levels.
     pub level_count: usize,
+    /// The number of tiles in each axis, for each Deep Zoom level.
     pub level_tiles: Vec<Size>,
+    /// The size in pixels of each Deep Zoom level.
     pub level_dimensions: Vec<Size>,
 
-    slide: &'a OpenSlide,
+    level_grids: Vec<TileGrid>,
+
+    slide: S,
     tile_size: u32,
     overlap: u32,
+    scale_factor: f32,
+    base_downsample: f32,
 
     l0_offset: Address,
+    background_color: Rgb<u8>,
+    resize_filter: ResizeFilter,
+    background_filter: Option<BackgroundFilter>,
+    deduplicate: bool,
+    tile_order: TileOrder,
+    tile_hook: Option<Box<TileHook>>,
+    #[cfg(feature = "color")]
+    srgb_transform: Option<SrgbTransform>,
     slide_level_dimensions: Vec<Size>,
     slide_from_dz_level: Vec<usize>,
+    l0_z_downsamples: Vec<f32>,
     l0_l_downsamples: Vec<f32>,
     l_z_downsamples: Vec<f32>,
+    /// The number of prefetches started, so that running ones stop once superseded
+    prefetches: Arc<AtomicUsize>,
+}
+
+/// The filter used to scale read regions to the final tile size.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ResizeFilter {
+    /// Nearest neighbor
+    Nearest,
+    /// Linear
+    Triangle,
+    /// Lanczos with a window of 3
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
 }
 
-impl<'a> DeepZoom<'a> {
+/// The location of a tile in the slide.
+#[derive(Debug, PartialEq)]
+pub struct TileBounds {
+    /// The area covered by the tile, overlap included, in level 0 coordinates
+    pub l0_region: Region,
+    /// The slide level the tile is read from
+    pub slide_level: usize,
+    /// The final size of the tile
+    pub output_size: Size,
+}
+
+/// Description of a single Deep Zoom level.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelInfo {
+    /// The size of the level in pixels
+    pub dimensions: Size,
+    /// The number of tiles in each axis
+    pub tiles: Size,
+    /// The downsample factor relative to slide level 0
+    pub downsample: f32,
+    /// The slide level tiles are read from
+    pub slide_level: usize,
+}
+
+/// Serializable description of a whole Deep Zoom pyramid and of the slide it is
+/// generated from, enough to plan tiling jobs without opening the slide.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PyramidInfo {
+    /// The width and height of a single tile, without overlap
+    pub tile_size: u32,
+    /// The number of extra pixels added to each interior edge of a tile
+    pub overlap: u32,
+    /// The downsample factor between consecutive Deep Zoom levels
+    pub scale_factor: f32,
+    /// The level 0 position of the pyramid origin, not null with `limit_bounds`
+    pub l0_offset: Address,
+    /// The total number of tiles
+    pub tile_count: u64,
+    /// The Deep Zoom levels, from the smallest to the largest
+    pub levels: Vec<LevelInfo>,
+    /// The dimensions of each slide level
+    pub slide_level_dimensions: Vec<Size>,
+    /// The downsample factor of each slide level relative to level 0
+    pub slide_level_downsamples: Vec<f32>,
+    /// The `openslide.quickhash-1` property of the slide, if available
+    pub quickhash: Option<String>,
+}
+
+impl<S: Deref<Target = OpenSlide>> DeepZoom<S> {
     /// Create a DeepZoom wrapping an OpenSlide object.
     ///
     /// # Arguments
     ///
-    /// * `slide` - a slide
+    /// * `slide` - a slide, either borrowed or behind a smart pointer such as `Arc`.
     /// * `tile_size` - the width and height of a single tile.  For best viewer performance,
     /// tile_size + 2 * overlap should be a power of two.
     /// * `overlap` - the number of extra pixels to add to each interior edge of a tile.
     /// * `limit_bounds` - True to render only the non-empty slide region.
-    pub fn new(
-        slide: &'a OpenSlide,
+    pub fn new(slide: S, tile_size: u32, overlap: u32, limit_bounds: bool) -> Result<DeepZoom<S>> {
+        DeepZoom::with_scale_factor(slide, tile_size, overlap, limit_bounds, 2.0)
+    }
+
+    /// Create a DeepZoom whose consecutive levels differ by `scale_factor` instead of 2.
+    ///
+    /// Viewers reading `.dzi` descriptors assume a factor of 2: other factors are meant
+    /// for tiling conventions that address levels by their downsample.
+    ///
+    /// # Arguments
+    ///
+    /// * `slide` - a slide, either borrowed or behind a smart pointer such as `Arc`.
+    /// * `tile_size` - the width and height of a single tile.
+    /// * `overlap` - the number of extra pixels to add to each interior edge of a tile.
+    /// * `limit_bounds` - True to render only the non-empty slide region.
+    /// * `scale_factor` - the downsample between a level and the next larger one.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `scale_factor` is not greater than 1, or `tile_size` is 0.
+    pub fn with_scale_factor(
+        slide: S,
+        tile_size: u32,
+        overlap: u32,
+        limit_bounds: bool,
+        scale_factor: f32,
+    ) -> Result<DeepZoom<S>> {
+        if !scale_factor.is_finite() || scale_factor <= 1.0 {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Scale factor {} must be greater than 1",
+                scale_factor
+            )));
+        }
+        DeepZoom::build(slide, tile_size, overlap, limit_bounds, scale_factor, 1.0)
+    }
+
+    /// Create a DeepZoom whose largest level has a resolution of `target_mpp`
+    /// micrometers per pixel, whatever the resolution of the slide level 0.
+    ///
+    /// Tiles of slides from scanners with different base resolutions then cover the
+    /// same physical area. Targets finer than the slide resolution upsample level 0.
+    /// The whole slide is rendered, as without `limit_bounds`.
+    ///
+    /// # Arguments
+    ///
+    /// * `slide` - a slide, either borrowed or behind a smart pointer such as `Arc`.
+    /// * `target_mpp` - the resolution of the largest level, in micrometers per pixel.
+    /// * `tile_size` - the width and height of a single tile.
+    /// * `overlap` - the number of extra pixels to add to each interior edge of a tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `target_mpp` is not positive, the slide has no valid `openslide.mpp-x` and `openslide.mpp-y` properties, or `tile_size` is 0.
+    pub fn with_base_mpp(
+        slide: S,
+        target_mpp: f32,
+        tile_size: u32,
+        overlap: u32,
+    ) -> Result<DeepZoom<S>> {
+        if !target_mpp.is_finite() || target_mpp <= 0.0 {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Target MPP {} must be positive",
+                target_mpp
+            )));
+        }
+
+        let slide_mpp = slide_mpp(&slide)?;
+
+        DeepZoom::build(
+            slide,
+            tile_size,
+            overlap,
+            false,
+            2.0,
+            target_mpp / slide_mpp,
+        )
+    }
+
+    /// Build the generator, the largest level being level 0 downsampled by
+    /// `base_downsample`.
+    fn build(
+        slide: S,
         tile_size: u32,
         overlap: u32,
         limit_bounds: bool,
-    ) -> Result<DeepZoom<'a>> {
+        scale_factor: f32,
+        base_downsample: f32,
+    ) -> Result<DeepZoom<S>> {
         let mut slide_level_dimensions: Vec<Size> = Vec::new();
         let mut l0_offset = Address { x: 0, y: 0 };
 
         if limit_bounds {
-            let bounds_x: u32 = match slide.property("openslide.bounds-x")? {
-                Some(v) => v.parse::<u32>().unwrap(),
-                None => 0,
-            };
-
-            let bounds_y: u32 = match slide.property("openslide.bounds-y")? {
-                Some(v) => v.parse::<u32>().unwrap(),
-                None => 0,
-            };
+            let slide_dimensions = slide.dimensions()?;
+            let (offset, bounds) = slide_bounds(&slide)?;
 
             // Level 0 coordinate offset
-            l0_offset.x = bounds_x;
-            l0_offset.y = bounds_y;
+            l0_offset = offset;
 
             // Slide level dimensions scale factor in each axis
-            let slide_dimensions = slide.dimensions().unwrap();
-
-            let bounds_width: u32 = match slide.property("openslide.bounds-width")? {
-                Some(v) => v.parse::<u32>().unwrap(),
-                None => slide_dimensions.w as _,
-            };
-
-            let bounds_height: u32 = match slide.property("openslide.bounds-height")? {
-                Some(v) => v.parse::<u32>().unwrap(),
-                None => slide_dimensions.h as _,
-            };
-
             let size_scale = (
-                bounds_width as f32 / slide_dimensions.w as f32,
-                bounds_height as f32 / slide_dimensions.h as f32,
+                bounds.w as f32 / slide_dimensions.w as f32,
+                bounds.h as f32 / slide_dimensions.h as f32,
             );
 
             slide_level_dimensions.extend(
@@ -93,34 +263,43 @@ impl<'a> DeepZoom<'a> {
 
         // Deep Zooom levels
         let mut z_size = Size {
-            w: slide_level0_dimensions.w,
-            h: slide_level0_dimensions.h,
+            w: ((slide_level0_dimensions.w as f32 / base_downsample).ceil() as u32).max(1),
+            h: ((slide_level0_dimensions.h as f32 / base_downsample).ceil() as u32).max(1),
         };
         let mut level_dimensions = vec![z_size];
 
+        // Every level is strictly smaller than the next larger one: with scale factors
+        // below 2, rounding up alone would keep sides of 2 forever
+        let shrink = |side: u32| {
+            if side > 1 {
+                ((side as f32 / scale_factor).ceil() as u32)
+                    .min(side - 1)
+                    .max(1)
+            } else {
+                1
+            }
+        };
         while z_size.w > 1 || z_size.h > 1 {
-            z_size.w = ((z_size.w as f32 / 2.0).ceil() as u32).max(1) as _;
-            z_size.h = ((z_size.h as f32 / 2.0).ceil() as u32).max(1) as _;
+            z_size.w = shrink(z_size.w);
+            z_size.h = shrink(z_size.h);
 
             level_dimensions.push(z_size);
         }
         level_dimensions.reverse();
 
         // Tile
-        let level_tiles: Vec<Size> = level_dimensions
+        let level_grids = level_dimensions
             .iter()
-            .map(|Size { w, h }| Size {
-                w: (*w as f32 / tile_size as f32).ceil() as _,
-                h: (*h as f32 / tile_size as f32).ceil() as _,
-            })
-            .collect();
+            .map(|dimensions| TileGrid::new(*dimensions, tile_size, overlap))
+            .collect::<Result<Vec<TileGrid>>>()?;
+        let level_tiles: Vec<Size> = level_grids.iter().map(|grid| grid.tiles()).collect();
 
         // Deep Zoom level count
         let level_count = level_dimensions.len();
 
         // Total downsamples for each Deep Zoom level
         let l0_z_downsamples: Vec<f32> = (0..level_count)
-            .map(|level| 2_u32.pow((level_count - level - 1) as _) as f32)
+            .map(|level| base_downsample * scale_factor.powi((level_count - level - 1) as _))
             .collect();
 
         // Preferred slide levels for each Deep Zoom level
@@ -140,112 +319,376 @@ impl<'a> DeepZoom<'a> {
             })
             .collect();
 
+        let background_color = slide.background_color()?;
+
         Ok(DeepZoom {
             slide,
             tile_size,
             overlap,
+            scale_factor,
+            base_downsample,
             l0_offset,
+            background_color,
+            resize_filter: ResizeFilter::Lanczos3,
+            background_filter: None,
+            deduplicate: false,
+            tile_order: TileOrder::RowMajor,
+            tile_hook: None,
+            #[cfg(feature = "color")]
+            srgb_transform: None,
             level_dimensions,
             slide_level_dimensions,
+            level_grids,
             level_tiles,
             level_count,
             slide_from_dz_level,
+            l0_z_downsamples,
             l0_l_downsamples,
             l_z_downsamples,
+            prefetches: Arc::new(AtomicUsize::new(0)),
         })
     }
 
-    fn tile_info(&self, level: usize, address: Address) -> Result<(Region, Size)> {
-        if level >= self.level_count {
-            return Err(OpenSlideError::InternalError(format!(
-                "Level {} out of range",
-                level
-            )));
+    /// The number of Deep Zoom levels in the image.
+    pub fn level_count(&self) -> usize {
+        self.level_count
+    }
+
+    /// The number of tiles in each axis, for each Deep Zoom level.
+    pub fn level_tiles(&self) -> &[Size] {
+        &self.level_tiles
+    }
+
+    /// The size in pixels of each Deep Zoom level.
+    pub fn level_dimensions(&self) -> &[Size] {
+        &self.level_dimensions
+    }
+
+    /// The tile grid of a Deep Zoom level, or `None` if it is out of range.
+    pub fn level_grid(&self, level: usize) -> Option<TileGrid> {
+        self.level_grids.get(level).copied()
+    }
+
+    /// The total number of tiles in the image.
+    pub fn tile_count(&self) -> u64 {
+        self.level_tiles
+            .iter()
+            .map(|tiles| u64::from(tiles.w) * u64::from(tiles.h))
+            .sum()
+    }
+
+    /// The number of tiles in a Deep Zoom level.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range
+    pub fn tile_count_at(&self, level: usize) -> Result<u64> {
+        match self.level_tiles.get(level) {
+            Some(tiles) => Ok(u64::from(tiles.w) * u64::from(tiles.h)),
+            None => Err(OpenSlideError::IndexError(level.to_string())),
         }
+    }
 
-        let level_tiles = self.level_tiles[level];
-        let level_dimensions = self.level_dimensions[level];
+    /// Return true if `address` is a valid tile address in the Deep Zoom level.
+    pub fn contains(&self, level: usize, address: Address) -> bool {
+        match self.level_tiles.get(level) {
+            Some(tiles) => address.x < tiles.w && address.y < tiles.h,
+            None => false,
+        }
+    }
 
-        if address.x >= level_tiles.w || address.y > level_tiles.h {
-            return Err(OpenSlideError::InternalError(format!(
-                "Address {} out of range",
-                address
-            )));
+    /// The width and height of a single tile, without overlap.
+    pub fn tile_edge(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// The number of extra pixels added to each interior edge of a tile.
+    pub fn overlap(&self) -> u32 {
+        self.overlap
+    }
+
+    /// The downsample between a Deep Zoom level and the next larger one.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// The downsample of the largest level relative to slide level 0: 1 unless the
+    /// generator was created [from a target MPP](struct.DeepZoom.html#method.with_base_mpp).
+    pub fn base_downsample(&self) -> f32 {
+        self.base_downsample
+    }
+
+    /// The level 0 position of the rendered region: the slide bounds offset with
+    /// `limit_bounds`, the slide origin otherwise.
+    pub fn l0_offset(&self) -> Address {
+        self.l0_offset
+    }
+
+    /// The level 0 size of the rendered region: the slide bounds size with
+    /// `limit_bounds`, the slide dimensions otherwise.
+    pub fn l0_dimensions(&self) -> Size {
+        self.slide_level_dimensions[0]
+    }
+
+    /// The color transparent tile regions are composited over.
+    pub fn background_color(&self) -> Rgb<u8> {
+        self.background_color
+    }
+
+    /// Override the color transparent tile regions are composited over. Defaults to
+    /// the slide [`background_color()`](struct.OpenSlide.html#method.background_color).
+    pub fn set_background_color(&mut self, color: Rgb<u8>) {
+        self.background_color = color;
+    }
+
+    /// The filter used when a read region must be scaled to the tile size.
+    pub fn resize_filter(&self) -> ResizeFilter {
+        self.resize_filter
+    }
+
+    /// Set the filter used when a read region must be scaled to the tile size.
+    /// Defaults to [`ResizeFilter::Lanczos3`](enum.ResizeFilter.html#variant.Lanczos3).
+    pub fn set_resize_filter(&mut self, filter: ResizeFilter) {
+        self.resize_filter = filter;
+    }
+
+    /// The detection of background tiles applied by pyramid exports, if any.
+    pub fn background_filter(&self) -> Option<BackgroundFilter> {
+        self.background_filter
+    }
+
+    /// Detect near-uniform background tiles during pyramid exports, and skip or share
+    /// them as configured by the filter. Disabled by default.
+    pub fn set_background_filter(&mut self, filter: Option<BackgroundFilter>) {
+        self.background_filter = filter;
+    }
+
+    /// True if pyramid exports deduplicate identical tiles.
+    pub fn deduplicate(&self) -> bool {
+        self.deduplicate
+    }
+
+    /// Write each distinct tile only once during pyramid exports, which saves space on
+    /// slides with large empty areas. Disabled by default.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    /// The order in which pyramid exports generate the tiles of each level.
+    pub fn tile_order(&self) -> TileOrder {
+        self.tile_order
+    }
+
+    /// Set the order in which pyramid exports generate the tiles of each level.
+    /// Defaults to row-major; the Hilbert curve order improves the libopenslide cache
+    /// hit rate.
+    pub fn set_tile_order(&mut self, order: TileOrder) {
+        self.tile_order = order;
+    }
+
+    /// Iterate over the addresses of the tiles of a Deep Zoom level, in the given order.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range
+    pub fn tile_addresses(
+        &self,
+        level: usize,
+        order: TileOrder,
+    ) -> Result<impl Iterator<Item = Address>> {
+        match self.level_grids.get(level) {
+            Some(grid) => Ok(grid.addresses(order)),
+            None => Err(OpenSlideError::IndexError(level.to_string())),
         }
+    }
 
-        // Get preferred slide level
-        let slide_level = self.slide_from_dz_level[level];
-        let slide_level_dimensions = self.slide_level_dimensions[slide_level];
+    /// Run `hook` on every tile read by the generator, before it is encoded or stitched,
+    /// replacing any previous hook. This is where tiles can be masked, normalized or
+    /// have annotations burnt in.
+    ///
+    /// The hook may be called concurrently from several threads during pyramid exports.
+    pub fn set_tile_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut RgbaImage, usize, Address) + Send + Sync + 'static,
+    {
+        self.tile_hook = Some(Box::new(hook));
+    }
 
-        // Calculate top/left and bottom/right overlap
-        let z_overlap_topleft = Address {
-            x: if address.x != 0 { self.overlap } else { 0 },
-            y: if address.y != 0 { self.overlap } else { 0 },
-        };
+    /// Remove the tile hook, if any.
+    pub fn clear_tile_hook(&mut self) {
+        self.tile_hook = None;
+    }
 
-        // Calculate top/left and bottom/right overlap
-        let z_overlap_bottomright = Address {
-            x: if address.x != (level_tiles.w - 1) {
-                self.overlap
-            } else {
-                0
-            },
-            y: if address.y != (level_tiles.h - 1) {
-                self.overlap
-            } else {
-                0
-            },
-        };
+    /// True if a tile hook is set.
+    pub fn has_tile_hook(&self) -> bool {
+        self.tile_hook.is_some()
+    }
 
-        // Get final size of the tile
-        let z_size = Size {
-            w: self
-                .tile_size
-                .min(level_dimensions.w - self.tile_size * address.x)
-                + z_overlap_topleft.x
-                + z_overlap_bottomright.x,
-            h: self
-                .tile_size
-                .min(level_dimensions.h - self.tile_size * address.y)
-                + z_overlap_topleft.y
-                + z_overlap_bottomright.y,
-        };
+    /// True if tiles are converted to sRGB.
+    #[cfg(feature = "color")]
+    pub fn srgb_conversion(&self) -> bool {
+        self.srgb_transform.is_some()
+    }
 
-        // Obtain the region coordinates
-        let z_location = Address {
-            x: address.x * self.tile_size,
-            y: address.y * self.tile_size,
+    /// Convert tiles from the slide [ICC profile](struct.OpenSlide.html#method.icc_profile)
+    /// to sRGB before they are processed by the tile hook and encoded, so that slides
+    /// from different scanners display consistent colors. Disabled by default.
+    ///
+    /// This method requires the `color` feature.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the slide has no ICC profile, or its profile is invalid.
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the slide file could not be read.
+    #[cfg(feature = "color")]
+    pub fn set_srgb_conversion(&mut self, enabled: bool) -> Result<()> {
+        self.srgb_transform = if enabled {
+            let profile = self.slide.icc_profile()?.ok_or_else(|| {
+                OpenSlideError::InvalidArgument("Slide has no ICC profile".to_string())
+            })?;
+            Some(SrgbTransform::new(&profile)?)
+        } else {
+            None
         };
+        Ok(())
+    }
 
-        let l_location = Address {
-            x: (self.l_z_downsamples[level] * (z_location.x - z_overlap_topleft.x) as f32).ceil()
-                as _,
-            y: (self.l_z_downsamples[level] * (z_location.y - z_overlap_topleft.y) as f32).ceil()
-                as _,
-        };
+    /// Describe a Deep Zoom level, or return `None` if it is out of range.
+    pub fn level_info(&self, level: usize) -> Option<LevelInfo> {
+        if level >= self.level_count {
+            return None;
+        }
 
-        // Round location down and size up, and add offset of active area
-        let l0_location = Address {
-            x: (self.l0_l_downsamples[slide_level] * l_location.x as f32 + self.l0_offset.x as f32)
-                as _,
-            y: (self.l0_l_downsamples[slide_level] * l_location.y as f32 + self.l0_offset.y as f32)
-                as _,
-        };
+        Some(LevelInfo {
+            dimensions: self.level_dimensions[level],
+            tiles: self.level_tiles[level],
+            downsample: self.l0_z_downsamples[level],
+            slide_level: self.slide_from_dz_level[level],
+        })
+    }
 
-        let l_size = Size {
-            w: (slide_level_dimensions.w - l_location.x)
-                .min((self.l_z_downsamples[level] * z_size.w as f32).ceil() as _),
-            h: (slide_level_dimensions.h - l_location.y)
-                .min((self.l_z_downsamples[level] * z_size.h as f32).ceil() as _),
+    /// Describe every Deep Zoom level, from the smallest to the largest.
+    pub fn levels(&self) -> Vec<LevelInfo> {
+        (0..self.level_count)
+            .filter_map(|level| self.level_info(level))
+            .collect()
+    }
+
+    /// Describe the whole pyramid, in a form that can be serialized and sent to
+    /// workers.
+    pub fn describe(&self) -> PyramidInfo {
+        PyramidInfo {
+            tile_size: self.tile_size,
+            overlap: self.overlap,
+            scale_factor: self.scale_factor,
+            l0_offset: self.l0_offset,
+            tile_count: self.tile_count(),
+            levels: self.levels(),
+            slide_level_dimensions: self.slide_level_dimensions.clone(),
+            slide_level_downsamples: self.l0_l_downsamples.clone(),
+            quickhash: self.slide.property("openslide.quickhash-1").ok().flatten(),
+        }
+    }
+
+    /// Return the XML metadata for the `.dzi` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - the format of the individual tiles.
+    pub fn dzi(&self, format: Format) -> String {
+        let dimensions = self.level_dimensions[self.level_count - 1];
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" "#,
+                r#"Format="{}" Overlap="{}" TileSize="{}">"#,
+                r#"<Size Height="{}" Width="{}"/></Image>"#
+            ),
+            format.extension(),
+            self.overlap,
+            self.tile_size,
+            dimensions.h,
+            dimensions.w
+        )
+    }
+
+    fn tile_info(&self, level: usize, address: Address) -> Result<(Region, Size)> {
+        if level >= self.level_count {
+            return Err(OpenSlideError::IndexError(level.to_string()));
+        }
+
+        let slide_level = self.slide_from_dz_level[level];
+        tile_region_for(
+            &self.level_grids[level],
+            address,
+            slide_level,
+            self.slide_level_dimensions[slide_level],
+            self.l_z_downsamples[level],
+            self.l0_l_downsamples[slide_level],
+            self.l0_offset,
+        )
+    }
+
+    /// Return the addresses of the tiles of a Deep Zoom level covering a viewport,
+    /// extended by `radius` tiles in every direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - the Deep Zoom level.
+    /// * `viewport` - the visible region, as it would be given to
+    /// [`read_region()`](struct.OpenSlide.html#method.read_region).
+    /// * `radius` - the number of extra tiles around the viewport.
+    pub fn viewport_tiles(
+        &self,
+        level: usize,
+        viewport: &Region,
+        radius: u32,
+    ) -> Result<Vec<Address>> {
+        if level >= self.level_count {
+            return Err(OpenSlideError::IndexError(level.to_string()));
+        }
+        let viewport_downsample = match self.l0_l_downsamples.get(viewport.level) {
+            Some(downsample) => *downsample,
+            None => return Err(OpenSlideError::IndexError(viewport.level.to_string())),
         };
 
-        let region = Region {
-            address: l0_location,
-            level: slide_level,
-            size: l_size,
+        // Viewport corners in the Deep Zoom level pixel coordinates
+        let l0_z_downsample = self.l0_z_downsamples[level];
+        let to_z = |l0: f32, offset: u32| ((l0 - offset as f32) / l0_z_downsample).max(0.0);
+        let z_topleft = (
+            to_z(viewport.address.x as f32, self.l0_offset.x),
+            to_z(viewport.address.y as f32, self.l0_offset.y),
+        );
+        let z_bottomright = (
+            to_z(
+                viewport.address.x as f32 + viewport.size.w as f32 * viewport_downsample,
+                self.l0_offset.x,
+            ),
+            to_z(
+                viewport.address.y as f32 + viewport.size.h as f32 * viewport_downsample,
+                self.l0_offset.y,
+            ),
+        );
+
+        let level_tiles = self.level_tiles[level];
+        let tile_range = |topleft: f32, bottomright: f32, count: u32| {
+            let first = (topleft / self.tile_size as f32) as u32;
+            let last = ((bottomright.ceil() as u32).max(1) - 1) / self.tile_size;
+            (
+                first.saturating_sub(radius).min(count - 1),
+                last.saturating_add(radius).min(count - 1),
+            )
         };
+        let (first_x, last_x) = tile_range(z_topleft.0, z_bottomright.0, level_tiles.w);
+        let (first_y, last_y) = tile_range(z_topleft.1, z_bottomright.1, level_tiles.h);
 
-        Ok((region, z_size))
+        let mut addresses = Vec::new();
+        for y in first_y..=last_y {
+            for x in first_x..=last_x {
+                addresses.push(Address { x, y });
+            }
+        }
+        Ok(addresses)
     }
 
     /// Return the `openslide::Openslide::read_region` arguments for the specified tile.
@@ -254,20 +697,470 @@ impl<'a> DeepZoom<'a> {
         Ok(region)
     }
 
+    /// Return the location of the specified tile in level 0 coordinates, the slide level it
+    /// is read from and its final size.
+    pub fn tile_bounds(&self, level: usize, address: Address) -> Result<TileBounds> {
+        let (region, output_size) = self.tile_info(level, address)?;
+        let downsample = self.l0_l_downsamples[region.level];
+
+        Ok(TileBounds {
+            l0_region: Region {
+                address: region.address,
+                level: 0,
+                size: Size {
+                    w: (region.size.w as f32 * downsample).ceil() as _,
+                    h: (region.size.h as f32 * downsample).ceil() as _,
+                },
+            },
+            slide_level: region.level,
+            output_size,
+        })
+    }
+
     /// Return the tile final size for the specified tile
-    pub fn tile_size(&self, level: usize, address: Address) -> Result<Size> {
+    pub fn tile_dimensions(&self, level: usize, address: Address) -> Result<Size> {
         let (_, size) = self.tile_info(level, address)?;
         Ok(size)
     }
 
-    /// Return a RGB tile
+    /// Return the tile final size for the specified tile
+    #[deprecated(note = "use `tile_dimensions` instead")]
+    pub fn tile_size(&self, level: usize, address: Address) -> Result<Size> {
+        self.tile_dimensions(level, address)
+    }
+
+    /// Return a RGB tile, converted to sRGB if [enabled](struct.DeepZoom.html#method.set_srgb_conversion)
+    /// and processed by the [tile hook](struct.DeepZoom.html#method.set_tile_hook) if one
+    /// is set.
     pub fn read_tile(&self, level: usize, address: Address) -> Result<RgbaImage> {
         let (region, size) = self.tile_info(level, address)?;
-        let mut tile = self.slide.read_region(region)?;
+        let region_size = region.size;
+        let buffer = self.slide.read_region_raw(region)?;
+
+        // Apply on solid background
+        let mut tile =
+            composite_buffer(&buffer, region_size.w, region_size.h, self.background_color);
 
         if tile.dimensions() != (size.w, size.h) {
-            tile = resize(&tile, size.w, size.h, FilterType::Lanczos3);
+            tile = resize(&tile, size.w, size.h, self.resize_filter.into());
+        }
+        #[cfg(feature = "color")]
+        if let Some(transform) = &self.srgb_transform {
+            transform.apply(&mut tile);
+        }
+        if let Some(hook) = &self.tile_hook {
+            hook(&mut tile, level, address);
         }
         Ok(tile)
     }
+
+    /// Return a tile encoded into the given format, ready to be served.
+    pub fn tile_bytes(&self, level: usize, address: Address, format: Format) -> Result<Vec<u8>> {
+        encode(&self.read_tile(level, address)?, format)
+    }
+
+    /// Assemble an arbitrary region of a Deep Zoom level from its tiles.
+    ///
+    /// The overlap of each tile is cropped before the tiles are stitched, so the result
+    /// matches a direct read of the region at the Deep Zoom level resolution. Parts of
+    /// the region outside of the image are filled with the background color.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - the Deep Zoom level.
+    /// * `l0_region` - the region to assemble, in level 0 coordinates: `level` must be 0.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the region is not in level 0 coordinates or is empty.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn read_mosaic(&self, level: usize, l0_region: &Region) -> Result<RgbaImage> {
+        if level >= self.level_count {
+            return Err(OpenSlideError::IndexError(level.to_string()));
+        }
+        if l0_region.level != 0 {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Mosaic region must be in level 0 coordinates, got level {}",
+                l0_region.level
+            )));
+        }
+        if l0_region.size.w == 0 || l0_region.size.h == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "Mosaic region is empty".to_string(),
+            ));
+        }
+
+        // Region in the Deep Zoom level pixel coordinates
+        let downsample = self.l0_z_downsamples[level] as f64;
+        let z_x =
+            ((l0_region.address.x as f64 - self.l0_offset.x as f64) / downsample).floor() as i64;
+        let z_y =
+            ((l0_region.address.y as f64 - self.l0_offset.y as f64) / downsample).floor() as i64;
+        let z_w = ((l0_region.size.w as f64 / downsample).ceil() as u32).max(1);
+        let z_h = ((l0_region.size.h as f64 / downsample).ceil() as u32).max(1);
+
+        let [r, g, b] = self.background_color.0;
+        let mut mosaic = RgbaImage::from_pixel(z_w, z_h, Rgba([r, g, b, 255]));
+
+        let level_dimensions = self.level_dimensions[level];
+        let level_tiles = self.level_tiles[level];
+        let tile_size = i64::from(self.tile_size);
+        let tile_range = |start: i64, length: u32, dimension: u32, count: u32| {
+            let end = (start + i64::from(length)).min(i64::from(dimension));
+            let start = start.max(0);
+            if start >= end {
+                return None;
+            }
+            let first = (start / tile_size) as u32;
+            let last = (((end - 1) / tile_size) as u32).min(count - 1);
+            Some(first..=last)
+        };
+        let (columns, rows) = match (
+            tile_range(z_x, z_w, level_dimensions.w, level_tiles.w),
+            tile_range(z_y, z_h, level_dimensions.h, level_tiles.h),
+        ) {
+            (Some(columns), Some(rows)) => (columns, rows),
+            _ => return Ok(mosaic),
+        };
+
+        for y in rows {
+            for x in columns.clone() {
+                let tile = self.read_tile(level, Address { x, y })?;
+
+                // Drop the top/left overlap: the tile content starts at its grid position
+                let overlap_x = if x != 0 { self.overlap } else { 0 };
+                let overlap_y = if y != 0 { self.overlap } else { 0 };
+                let tile_x = i64::from(x) * tile_size;
+                let tile_y = i64::from(y) * tile_size;
+                let inner_w = (i64::from(level_dimensions.w) - tile_x).min(tile_size);
+                let inner_h = (i64::from(level_dimensions.h) - tile_y).min(tile_size);
+
+                // Intersection of the tile content with the mosaic
+                let left = tile_x.max(z_x);
+                let top = tile_y.max(z_y);
+                let right = (tile_x + inner_w).min(z_x + i64::from(z_w));
+                let bottom = (tile_y + inner_h).min(z_y + i64::from(z_h));
+                if left >= right || top >= bottom {
+                    continue;
+                }
+
+                let part = imageops::crop_imm(
+                    &tile,
+                    (left - tile_x) as u32 + overlap_x,
+                    (top - tile_y) as u32 + overlap_y,
+                    (right - left) as u32,
+                    (bottom - top) as u32,
+                )
+                .to_image();
+                imageops::replace(&mut mosaic, &part, left - z_x, top - z_y);
+            }
+        }
+
+        Ok(mosaic)
+    }
+}
+
+/// Return the level 0 resolution of the slide in micrometers per pixel, averaged
+/// over both axes.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the slide has no valid `openslide.mpp-x` and `openslide.mpp-y` properties.
+pub(crate) fn slide_mpp(slide: &OpenSlide) -> Result<f32> {
+    let (mpp_x, mpp_y) = slide_mpp_xy(slide)?;
+    Ok((mpp_x + mpp_y) / 2.0)
+}
+
+/// Return the level 0 resolution of the slide in micrometers per pixel along the x
+/// and y axes.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the slide has no valid `openslide.mpp-x` and `openslide.mpp-y` properties.
+pub(crate) fn slide_mpp_xy(slide: &OpenSlide) -> Result<(f32, f32)> {
+    let mpp = |name: &str| -> Result<f32> {
+        slide.positive_property(name)?.ok_or_else(|| {
+            OpenSlideError::InvalidArgument(format!("Slide has no valid {} property", name))
+        })
+    };
+    Ok((mpp("openslide.mpp-x")?, mpp("openslide.mpp-y")?))
+}
+
+/// Return the level 0 offset and dimensions of the non-empty slide region, the whole
+/// slide if it has no valid `openslide.bounds-*` properties.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub(crate) fn slide_bounds(slide: &OpenSlide) -> Result<(Address, Size)> {
+    Ok(effective_bounds(
+        slide.dimensions()?,
+        slide.property("openslide.bounds-x")?.as_deref(),
+        slide.property("openslide.bounds-y")?.as_deref(),
+        slide.property("openslide.bounds-width")?.as_deref(),
+        slide.property("openslide.bounds-height")?.as_deref(),
+    ))
+}
+
+/// Return the level 0 offset and dimensions of the non-empty slide region, from the
+/// `openslide.bounds-*` property values.
+///
+/// Vendors fill these properties, so invalid values are ignored rather than trusted:
+/// an axis whose offset or size cannot be parsed, is zero or lies outside the slide,
+/// falls back to the whole slide, and sizes are clamped to the slide edge.
+fn effective_bounds(
+    slide_dimensions: Size,
+    x: Option<&str>,
+    y: Option<&str>,
+    width: Option<&str>,
+    height: Option<&str>,
+) -> (Address, Size) {
+    let parse = |value: Option<&str>| value.and_then(|value| value.trim().parse::<u32>().ok());
+    let axis = |offset: Option<&str>, size: Option<&str>, limit: u32| {
+        let offset = parse(offset).unwrap_or(0);
+        if offset >= limit {
+            return (0, limit);
+        }
+        match parse(size) {
+            Some(size) if size > 0 => (offset, size.min(limit - offset)),
+            Some(_) => (0, limit),
+            None => (offset, limit - offset),
+        }
+    };
+
+    let (x, w) = axis(x, width, slide_dimensions.w);
+    let (y, h) = axis(y, height, slide_dimensions.h);
+    (Address { x, y }, Size { w, h })
+}
+
+/// Compute the slide region read for a tile and the final tile size.
+///
+/// Positions are computed on `i64` so that edge tiles, overlaps larger than the tile
+/// size and rounding past the level edge cannot underflow; the results are converted
+/// back to `u32` with checked conversions.
+fn tile_region_for(
+    grid: &TileGrid,
+    address: Address,
+    slide_level: usize,
+    slide_level_dimensions: Size,
+    l_z_downsample: f32,
+    l0_l_downsample: f32,
+    l0_offset: Address,
+) -> Result<(Region, Size)> {
+    // Calculate top/left overlap
+    let (z_overlap_topleft, _) = grid.tile_overlaps(address)?;
+
+    // Get final size of the tile
+    let z_size = grid.tile_dimensions(address)?;
+
+    // Obtain the region coordinates, overlap included
+    let z_location = grid.tile_origin(address)?;
+    let z_start = |location: u32, overlap: u32| (i64::from(location) - i64::from(overlap)).max(0);
+
+    let l_location = (
+        (l_z_downsample * z_start(z_location.x, z_overlap_topleft.x) as f32).ceil() as i64,
+        (l_z_downsample * z_start(z_location.y, z_overlap_topleft.y) as f32).ceil() as i64,
+    );
+
+    // Round location down and size up, and add offset of active area
+    let l0_location = Address {
+        x: checked_u32(
+            (l0_l_downsample * l_location.0 as f32) as i64 + i64::from(l0_offset.x),
+            address,
+        )?,
+        y: checked_u32(
+            (l0_l_downsample * l_location.1 as f32) as i64 + i64::from(l0_offset.y),
+            address,
+        )?,
+    };
+
+    // Never read past the slide level, but always read at least one pixel
+    let l_size = |dimension: u32, location: i64, size: u32| {
+        let remaining = i64::from(dimension) - location;
+        let scaled = (l_z_downsample * size as f32).ceil() as i64;
+        checked_u32(remaining.min(scaled).max(1), address)
+    };
+    let l_size = Size {
+        w: l_size(slide_level_dimensions.w, l_location.0, z_size.w)?,
+        h: l_size(slide_level_dimensions.h, l_location.1, z_size.h)?,
+    };
+
+    let region = Region {
+        address: l0_location,
+        level: slide_level,
+        size: l_size,
+    };
+
+    Ok((region, z_size))
+}
+
+/// Convert a tile coordinate back to `u32`, failing for the tile at `address` if it
+/// does not fit.
+fn checked_u32(value: i64, address: Address) -> Result<u32> {
+    u32::try_from(value).map_err(|_| OpenSlideError::OutOfBounds(address.to_string()))
+}
+
+impl<S> DeepZoom<S>
+where
+    S: Deref<Target = OpenSlide> + Clone + Send + 'static,
+{
+    /// Warm the slide cache for the tiles around a viewport.
+    ///
+    /// The regions of the tiles returned by
+    /// [`viewport_tiles()`](struct.DeepZoom.html#method.viewport_tiles) are read on the
+    /// global rayon thread pool, so that later [`read_tile()`](struct.DeepZoom.html#method.read_tile)
+    /// calls hit the libopenslide cache. Read errors are ignored; they will surface when
+    /// the tile is actually requested.
+    ///
+    /// Each call supersedes the previous ones, as a viewer panning to a new viewport
+    /// does: their remaining tiles are not read.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - the Deep Zoom level.
+    /// * `viewport` - the visible region, as it would be given to
+    /// [`read_region()`](struct.OpenSlide.html#method.read_region).
+    /// * `radius` - the number of extra tiles around the viewport.
+    pub fn prefetch(&self, level: usize, viewport: Region, radius: u32) -> Result<()> {
+        let regions = self
+            .viewport_tiles(level, &viewport, radius)?
+            .into_iter()
+            .map(|address| self.tile_region(level, address))
+            .collect::<Result<Vec<Region>>>()?;
+
+        let slide = self.slide.clone();
+        let prefetches = self.prefetches.clone();
+        let prefetch = prefetches.fetch_add(1, Ordering::SeqCst) + 1;
+        rayon::spawn(move || {
+            for region in regions {
+                if prefetches.load(Ordering::SeqCst) != prefetch {
+                    return;
+                }
+                let _ = slide.read_region_raw(region);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Slide level downsamples as found in real slides: close to, but not always
+    /// exactly, powers of two.
+    fn downsamples() -> impl Strategy<Value = f32> {
+        prop_oneof![
+            Just(1.0f32),
+            Just(2.0f32),
+            Just(4.0f32),
+            Just(4.000_345f32),
+            Just(16.001_95f32),
+            1.0f32..64.0,
+        ]
+    }
+
+    #[test]
+    fn test_large_overlap() {
+        // The overlap is larger than the tile: the region is clamped to the level origin
+        let grid = TileGrid::new(Size { w: 100, h: 100 }, 4, 10).unwrap();
+        let (region, size) = tile_region_for(
+            &grid,
+            Address { x: 1, y: 1 },
+            0,
+            Size { w: 100, h: 100 },
+            1.0,
+            1.0,
+            Address { x: 0, y: 0 },
+        )
+        .unwrap();
+
+        assert_eq!(region.address, Address { x: 0, y: 0 });
+        assert_eq!(size, Size { w: 24, h: 24 });
+    }
+
+    #[test]
+    fn test_effective_bounds() {
+        let slide = Size { w: 1000, h: 800 };
+        let bounds = |x, y, w, h| effective_bounds(slide, x, y, w, h);
+
+        assert_eq!(
+            bounds(Some("100"), Some("50"), Some("600"), Some("400")),
+            (Address { x: 100, y: 50 }, Size { w: 600, h: 400 })
+        );
+        // Missing properties: the whole slide
+        assert_eq!(
+            bounds(None, None, None, None),
+            (Address { x: 0, y: 0 }, slide)
+        );
+        // Whitespace is tolerated, garbage is ignored
+        assert_eq!(
+            bounds(Some(" 100 "), Some("abc"), Some("600\n"), Some("-400")),
+            (Address { x: 100, y: 0 }, Size { w: 600, h: 800 })
+        );
+        // Sizes are clamped to the slide edge, a missing size extends to it
+        assert_eq!(
+            bounds(Some("900"), Some("700"), Some("600"), None),
+            (Address { x: 900, y: 700 }, Size { w: 100, h: 100 })
+        );
+        // Offsets outside the slide and empty sizes are ignored
+        assert_eq!(
+            bounds(Some("1000"), Some("10"), Some("10"), Some("0")),
+            (Address { x: 0, y: 0 }, slide)
+        );
+        assert_eq!(
+            bounds(Some("4294967296"), None, Some("1.5"), None),
+            (Address { x: 0, y: 0 }, slide)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_tile_region_in_bounds(
+            w in 1u32..2_000_000,
+            h in 1u32..2_000_000,
+            tile_size in 1u32..2048,
+            overlap in 0u32..64,
+            x in 0u32..4096,
+            y in 0u32..4096,
+            l0_l_downsample in downsamples(),
+            level_downsample in prop_oneof![Just(1.0f32), 1.0f32..2.0],
+            offset_x in 0u32..100_000,
+            offset_y in 0u32..100_000,
+        ) {
+            let grid = TileGrid::new(Size { w, h }, tile_size, overlap).unwrap();
+            let tiles = grid.tiles();
+            // Also exercise addresses past the grid
+            let address = Address { x: x % (tiles.w + 2), y: y % (tiles.h + 2) };
+            let slide_level_dimensions = Size {
+                w: ((w as f32 * level_downsample) as u32).max(1),
+                h: ((h as f32 * level_downsample) as u32).max(1),
+            };
+
+            let result = tile_region_for(
+                &grid,
+                address,
+                0,
+                slide_level_dimensions,
+                level_downsample,
+                l0_l_downsample,
+                Address { x: offset_x, y: offset_y },
+            );
+
+            if !grid.contains(address) {
+                prop_assert_eq!(result, Err(OpenSlideError::OutOfBounds(address.to_string())));
+                return Ok(());
+            }
+
+            let (region, size) = result.unwrap();
+            prop_assert!(region.size.w >= 1 && region.size.h >= 1);
+            prop_assert!(region.address.x >= offset_x && region.address.y >= offset_y);
+            prop_assert!(size.w >= 1 && size.w <= tile_size + 2 * overlap);
+            prop_assert!(size.h >= 1 && size.h <= tile_size + 2 * overlap);
+            prop_assert!(region.size.w <= slide_level_dimensions.w);
+            prop_assert!(region.size.h <= slide_level_dimensions.h);
+        }
+    }
 }
@@ -0,0 +1,265 @@
+//! Streaming whole slide downscaling.
+
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::deepzoom::ResizeFilter;
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::{OpenSlideError, Result};
+
+/// The maximum width and height of the level regions read at once.
+const MAX_SOURCE_SIZE: f64 = 2048.;
+
+/// The source pixels contributing to an output pixel: the first one, and the
+/// normalized weights of it and the following ones.
+type Contributions = (u32, Vec<f32>);
+
+impl OpenSlide {
+    /// Downscale the whole slide to an image of exactly `width` x `height` pixels,
+    /// such as a large overview.
+    ///
+    /// Unlike [`thumbnail()`](struct.OpenSlide.html#method.thumbnail), the level
+    /// best suited to the requested size is never read whole: it is streamed in
+    /// tiles, resampled in parallel, so that memory stays bounded by the output
+    /// size. Every output pixel is computed from the same source pixels whatever
+    /// the tiling, so that the result shows no seams. The axes are scaled
+    /// independently: keep the slide aspect ratio to avoid distortion.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: the output width.
+    /// * `height`: the output height.
+    /// * `filter`: the resampling filter.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `width` or `height` is 0.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn downscale_to(&self, width: u32, height: u32, filter: ResizeFilter) -> Result<RgbaImage> {
+        if width == 0 || height == 0 {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Output size {}x{} must be positive",
+                width, height
+            )));
+        }
+
+        // The finest axis decides the level, so that neither axis is upsampled more
+        // than needed
+        let dimensions = self.dimensions()?;
+        let downsample = (f64::from(dimensions.w) / f64::from(width))
+            .min(f64::from(dimensions.h) / f64::from(height));
+        let level = self.best_level_for_downsample(downsample as f32)?;
+        let level_dimensions = self.level_dimensions(level)?;
+        let level_downsample = f64::from(self.level_downsample(level)?);
+
+        let columns = contributions(width, level_dimensions.w, filter);
+        let rows = contributions(height, level_dimensions.h, filter);
+        let tile_size = |output: u32, source: u32| {
+            let scale = f64::from(source) / f64::from(output);
+            (MAX_SOURCE_SIZE / scale.max(1.)).max(16.) as u32
+        };
+        let tile_size = Size {
+            w: tile_size(width, level_dimensions.w),
+            h: tile_size(height, level_dimensions.h),
+        };
+
+        let tiles: Vec<Address> = (0..height)
+            .step_by(tile_size.h as usize)
+            .flat_map(|y| {
+                (0..width)
+                    .step_by(tile_size.w as usize)
+                    .map(move |x| Address { x, y })
+            })
+            .collect();
+        let resampled = tiles
+            .par_iter()
+            .map(|origin| {
+                let end = Address {
+                    x: (origin.x + tile_size.w).min(width),
+                    y: (origin.y + tile_size.h).min(height),
+                };
+                let (x_start, x_end) = span(&columns[origin.x as usize..end.x as usize]);
+                let (y_start, y_end) = span(&rows[origin.y as usize..end.y as usize]);
+                let source = self.read_region(Region {
+                    address: Address {
+                        x: (f64::from(x_start) * level_downsample).round() as u32,
+                        y: (f64::from(y_start) * level_downsample).round() as u32,
+                    },
+                    level: level as _,
+                    size: Size {
+                        w: x_end - x_start,
+                        h: y_end - y_start,
+                    },
+                })?;
+                Ok(resample(
+                    &source,
+                    Address {
+                        x: x_start,
+                        y: y_start,
+                    },
+                    &columns[origin.x as usize..end.x as usize],
+                    &rows[origin.y as usize..end.y as usize],
+                ))
+            })
+            .collect::<Result<Vec<RgbaImage>>>()?;
+
+        let mut output = RgbaImage::new(width, height);
+        for (origin, tile) in tiles.iter().zip(resampled) {
+            for (x, y, pixel) in tile.enumerate_pixels() {
+                output.put_pixel(origin.x + x, origin.y + y, *pixel);
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Compute the source pixels contributing to each of the `output` pixels of an axis
+/// resampled from `source` pixels, with the filter stretched when downscaling.
+fn contributions(output: u32, source: u32, filter: ResizeFilter) -> Vec<Contributions> {
+    let scale = f64::from(source) / f64::from(output);
+    let filter_scale = scale.max(1.);
+    let support = match filter {
+        ResizeFilter::Nearest => 0.,
+        ResizeFilter::Triangle => 1.,
+        ResizeFilter::Lanczos3 => 3.,
+    } * filter_scale;
+
+    (0..output)
+        .map(|index| {
+            let center = (f64::from(index) + 0.5) * scale;
+            let nearest = ((center.floor() as u32).min(source - 1), vec![1.]);
+            if filter == ResizeFilter::Nearest {
+                return nearest;
+            }
+
+            let start = (center - support).floor().max(0.) as u32;
+            let end = ((center + support).ceil() as u32).min(source);
+            let weights: Vec<f64> = (start..end)
+                .map(|pixel| kernel(filter, (f64::from(pixel) + 0.5 - center) / filter_scale))
+                .collect();
+            let total: f64 = weights.iter().sum();
+            if total == 0. {
+                return nearest;
+            }
+            (
+                start,
+                weights
+                    .iter()
+                    .map(|weight| (weight / total) as f32)
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// The value of a filter kernel at `x` source pixels from the center.
+fn kernel(filter: ResizeFilter, x: f64) -> f64 {
+    let sinc = |x: f64| {
+        if x == 0. {
+            1.
+        } else {
+            let x = x * std::f64::consts::PI;
+            x.sin() / x
+        }
+    };
+    match filter {
+        ResizeFilter::Nearest => f64::from(u8::from(x.abs() <= 0.5)),
+        ResizeFilter::Triangle => (1. - x.abs()).max(0.),
+        ResizeFilter::Lanczos3 if x.abs() < 3. => sinc(x) * sinc(x / 3.),
+        ResizeFilter::Lanczos3 => 0.,
+    }
+}
+
+/// The range of source pixels contributing to consecutive output pixels.
+fn span(contributions: &[Contributions]) -> (u32, u32) {
+    let start = contributions.iter().map(|(start, _)| *start).min().unwrap();
+    let end = contributions
+        .iter()
+        .map(|(start, weights)| start + weights.len() as u32)
+        .max()
+        .unwrap();
+    (start, end)
+}
+
+/// Resample a source tile whose top left pixel is `origin` in the level.
+fn resample(
+    source: &RgbaImage,
+    origin: Address,
+    columns: &[Contributions],
+    rows: &[Contributions],
+) -> RgbaImage {
+    // Resample the rows first, then the columns of the result
+    let horizontal: Vec<Vec<[f32; 4]>> = (0..source.height())
+        .map(|y| {
+            columns
+                .iter()
+                .map(|(start, weights)| {
+                    let mut value = [0.; 4];
+                    for (offset, weight) in weights.iter().enumerate() {
+                        let pixel = source.get_pixel(start - origin.x + offset as u32, y);
+                        for (channel, sum) in value.iter_mut().enumerate() {
+                            *sum += f32::from(pixel[channel]) * weight;
+                        }
+                    }
+                    value
+                })
+                .collect()
+        })
+        .collect();
+
+    RgbaImage::from_fn(columns.len() as u32, rows.len() as u32, |x, y| {
+        let (start, weights) = &rows[y as usize];
+        let mut value = [0.; 4];
+        for (offset, weight) in weights.iter().enumerate() {
+            let pixel = horizontal[(start - origin.y) as usize + offset][x as usize];
+            for (sum, channel) in value.iter_mut().zip(pixel.iter()) {
+                *sum += channel * weight;
+            }
+        }
+        let channel = |value: f32| value.round().clamp(0., 255.) as u8;
+        Rgba([
+            channel(value[0]),
+            channel(value[1]),
+            channel(value[2]),
+            channel(value[3]),
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contributions() {
+        for filter in [
+            ResizeFilter::Nearest,
+            ResizeFilter::Triangle,
+            ResizeFilter::Lanczos3,
+        ] {
+            for (output, source) in [(10, 10), (7, 100), (100, 7), (1, 1), (3, 1000)] {
+                let contributions = contributions(output, source, filter);
+                assert_eq!(contributions.len(), output as usize);
+                for (start, weights) in &contributions {
+                    assert!(start + weights.len() as u32 <= source);
+                    let total: f32 = weights.iter().sum();
+                    assert!((total - 1.).abs() < 1e-5, "{:?}", weights);
+                }
+            }
+
+            // Same size is the identity
+            for (index, (start, weights)) in contributions(10, 10, filter).iter().enumerate() {
+                let pixel = weights
+                    .iter()
+                    .position(|weight| (weight - 1.).abs() < 1e-6)
+                    .unwrap();
+                assert_eq!(*start as usize + pixel, index);
+            }
+        }
+
+        // Halving averages pixel pairs
+        let halved = contributions(5, 10, ResizeFilter::Triangle);
+        assert_eq!(halved[2].0, 3);
+        assert_eq!(halved[2].1, vec![0.125, 0.375, 0.375, 0.125]);
+    }
+}
@@ -0,0 +1,271 @@
+//! This module provides a parser for Deep Zoom `.dzi` descriptors, in their XML and
+//! JSON forms, so that pyramids generated elsewhere can be read back.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::grid::TileGrid;
+use crate::openslide::{Address, Region, Size};
+use crate::{OpenSlideError, Result};
+
+/// The contents of a Deep Zoom descriptor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DziDescriptor {
+    /// The file extension of the tiles, such as `jpg`
+    pub format: String,
+    /// The number of extra pixels added to each interior edge of a tile
+    pub overlap: u32,
+    /// The width and height of a single tile, without overlap
+    pub tile_size: u32,
+    /// The size of the largest level in pixels
+    pub size: Size,
+    /// The base URL of the tiles, found in some JSON descriptors
+    pub url: Option<String>,
+}
+
+impl DziDescriptor {
+    /// Parse a descriptor, either XML or JSON.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the descriptor is malformed.
+    pub fn parse(text: &str) -> Result<DziDescriptor> {
+        if text.trim_start().starts_with('{') {
+            DziDescriptor::from_json(text)
+        } else {
+            DziDescriptor::from_xml(text)
+        }
+    }
+
+    /// Read and parse a descriptor file.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be read.
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the descriptor is malformed.
+    pub fn open(path: &Path) -> Result<DziDescriptor> {
+        DziDescriptor::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse an XML descriptor, as written by
+    /// [`DeepZoom::dzi()`](struct.DeepZoom.html#method.dzi).
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the descriptor is malformed.
+    pub fn from_xml(text: &str) -> Result<DziDescriptor> {
+        let image = xml_attributes(text, "Image")?;
+        let size = xml_attributes(text, "Size")?;
+        let attribute = |attributes: &HashMap<String, String>, name: &str| {
+            attributes.get(name).cloned().ok_or_else(|| {
+                OpenSlideError::InvalidArgument(format!("Missing DZI attribute {}", name))
+            })
+        };
+
+        Ok(DziDescriptor {
+            format: attribute(&image, "Format")?,
+            overlap: parse_number(&attribute(&image, "Overlap")?, "Overlap")?,
+            tile_size: parse_number(&attribute(&image, "TileSize")?, "TileSize")?,
+            size: Size {
+                w: parse_number(&attribute(&size, "Width")?, "Width")?,
+                h: parse_number(&attribute(&size, "Height")?, "Height")?,
+            },
+            url: image.get("Url").cloned(),
+        })
+    }
+
+    /// Parse a JSON descriptor, as used by OpenSeadragon: an `Image` object holding
+    /// the XML attributes, numbers being given either as strings or as numbers.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the descriptor is malformed.
+    pub fn from_json(text: &str) -> Result<DziDescriptor> {
+        let root: Value = serde_json::from_str(text)
+            .map_err(|e| OpenSlideError::InvalidArgument(format!("Invalid DZI JSON: {}", e)))?;
+        let image = root
+            .get("Image")
+            .ok_or_else(|| OpenSlideError::InvalidArgument("Missing DZI Image".to_string()))?;
+        let field = |value: &Value, name: &str| -> Result<Value> {
+            value.get(name).cloned().ok_or_else(|| {
+                OpenSlideError::InvalidArgument(format!("Missing DZI attribute {}", name))
+            })
+        };
+        let number = |value: &Value, name: &str| -> Result<u32> {
+            match field(value, name)? {
+                Value::String(string) => parse_number(&string, name),
+                Value::Number(number) => number
+                    .as_u64()
+                    .and_then(|number| u32::try_from(number).ok())
+                    .ok_or_else(|| invalid_number(name, &number.to_string())),
+                other => Err(invalid_number(name, &other.to_string())),
+            }
+        };
+        let size = field(image, "Size")?;
+
+        Ok(DziDescriptor {
+            format: match field(image, "Format")? {
+                Value::String(format) => format,
+                other => return Err(invalid_number("Format", &other.to_string())),
+            },
+            overlap: number(image, "Overlap")?,
+            tile_size: number(image, "TileSize")?,
+            size: Size {
+                w: number(&size, "Width")?,
+                h: number(&size, "Height")?,
+            },
+            url: image
+                .get("Url")
+                .and_then(|url| url.as_str())
+                .map(|url| url.to_string()),
+        })
+    }
+
+    /// The number of Deep Zoom levels.
+    pub fn level_count(&self) -> usize {
+        self.level_dimensions().len()
+    }
+
+    /// The size in pixels of each Deep Zoom level, from the smallest to the largest.
+    pub fn level_dimensions(&self) -> Vec<Size> {
+        let mut z_size = self.size;
+        let mut level_dimensions = vec![z_size];
+        while z_size.w > 1 || z_size.h > 1 {
+            z_size = Size {
+                w: (z_size.w / 2 + z_size.w % 2).max(1),
+                h: (z_size.h / 2 + z_size.h % 2).max(1),
+            };
+            level_dimensions.push(z_size);
+        }
+        level_dimensions.reverse();
+        level_dimensions
+    }
+
+    /// The tile grid of a Deep Zoom level.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the tile size is 0.
+    pub fn level_grid(&self, level: usize) -> Result<TileGrid> {
+        match self.level_dimensions().get(level) {
+            Some(dimensions) => TileGrid::new(*dimensions, self.tile_size, self.overlap),
+            None => Err(OpenSlideError::IndexError(level.to_string())),
+        }
+    }
+
+    /// Return the path of a tile relative to the descriptor, such as
+    /// `slide_files/9/1_0.jpg` for a descriptor named `slide.dzi`.
+    pub fn tile_path(&self, name: &str, level: usize, address: Address) -> String {
+        format!(
+            "{}_files/{}/{}_{}.{}",
+            name, level, address.x, address.y, self.format
+        )
+    }
+
+    /// Return the area covered by a tile, overlap included, in the coordinates of the
+    /// largest level.
+    ///
+    /// The largest level is the slide level 0 for pyramids generated without
+    /// `limit_bounds`; otherwise the bounds offset of the slide must be added.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): address out of range
+    pub fn tile_region(&self, level: usize, address: Address) -> Result<Region> {
+        let grid = self.level_grid(level)?;
+        let origin = grid.tile_origin(address)?;
+        let (topleft, _) = grid.tile_overlaps(address)?;
+        let size = grid.tile_dimensions(address)?;
+
+        // Levels are halved and rounded up, so scaling back may pass the image edge
+        let shift = (self.level_count() - 1 - level) as u32;
+        let scale =
+            |value: u32, limit: u32| (u64::from(value) << shift).min(u64::from(limit)) as u32;
+        let x = scale(origin.x - topleft.x, self.size.w);
+        let y = scale(origin.y - topleft.y, self.size.h);
+        Ok(Region {
+            address: Address { x, y },
+            level: 0,
+            size: Size {
+                w: scale(origin.x - topleft.x + size.w, self.size.w) - x,
+                h: scale(origin.y - topleft.y + size.h, self.size.h) - y,
+            },
+        })
+    }
+}
+
+impl FromStr for DziDescriptor {
+    type Err = OpenSlideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        DziDescriptor::parse(s)
+    }
+}
+
+/// Return the attributes of the first `tag` element of an XML document.
+fn xml_attributes(text: &str, tag: &str) -> Result<HashMap<String, String>> {
+    let open = format!("<{}", tag);
+    let start = text
+        .match_indices(&open)
+        .map(|(index, _)| index + open.len())
+        .find(|index| {
+            text[*index..]
+                .chars()
+                .next()
+                .map_or(false, |c| c.is_whitespace() || c == '/' || c == '>')
+        })
+        .ok_or_else(|| OpenSlideError::InvalidArgument(format!("Missing DZI element {}", tag)))?;
+    let end = text[start..]
+        .find('>')
+        .ok_or_else(|| OpenSlideError::InvalidArgument(format!("Unclosed DZI element {}", tag)))?;
+
+    let mut attributes = HashMap::new();
+    let mut rest = text[start..start + end].trim_end_matches('/');
+    while let Some(equal) = rest.find('=') {
+        let name = rest[..equal].trim();
+        let value = rest[equal + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => {
+                return Err(OpenSlideError::InvalidArgument(format!(
+                    "Unquoted DZI attribute {}",
+                    name
+                )))
+            }
+        };
+        let length = value[1..].find(quote).ok_or_else(|| {
+            OpenSlideError::InvalidArgument(format!("Unterminated DZI attribute {}", name))
+        })?;
+        attributes.insert(name.to_string(), unescape(&value[1..1 + length]));
+        rest = &value[length + 2..];
+    }
+    Ok(attributes)
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn parse_number(value: &str, name: &str) -> Result<u32> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid_number(name, value))
+}
+
+fn invalid_number(name: &str, value: &str) -> OpenSlideError {
+    OpenSlideError::InvalidArgument(format!("Invalid DZI attribute {}: {}", name, value))
+}
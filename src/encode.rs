@@ -0,0 +1,95 @@
+//! This module provides the image encoders shared by region reads and Deep Zoom
+//! tiles.
+
+use image::buffer::ConvertBuffer;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::{ColorType, ImageEncoder, RgbImage, RgbaImage};
+
+use crate::{OpenSlideError, Result};
+
+/// An output image format.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    /// Lossy JPEG. `quality` goes from 1 (worst) to 100 (best).
+    Jpeg { quality: u8 },
+    /// Lossless PNG.
+    Png,
+    /// Lossy WebP. `quality` goes from 1 (worst) to 100 (best).
+    Webp { quality: u8 },
+}
+
+impl Format {
+    /// The file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Jpeg { .. } => "jpg",
+            Format::Png => "png",
+            Format::Webp { .. } => "webp",
+        }
+    }
+
+    /// The media type of the format, as sent in `Content-Type` headers.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Format::Jpeg { .. } => "image/jpeg",
+            Format::Png => "image/png",
+            Format::Webp { .. } => "image/webp",
+        }
+    }
+}
+
+/// Encode an image into the given format.
+///
+/// JPEG does not support transparency: the alpha channel is dropped.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the quality is not in the 1-100 range.
+/// * [`OpenSlideError::ImageError`](enum.OpenSlideError.html#variant.ImageError): the image could not be encoded.
+pub(crate) fn encode(image: &RgbaImage, format: Format) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+
+    match format {
+        Format::Jpeg { quality } => {
+            check_quality(quality)?;
+            let rgb: RgbImage = image.convert();
+            JpegEncoder::new_with_quality(&mut buffer, quality).write_image(
+                &rgb,
+                rgb.width(),
+                rgb.height(),
+                ColorType::Rgb8,
+            )?;
+        }
+        Format::Png => {
+            PngEncoder::new(&mut buffer).write_image(
+                image,
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        Format::Webp { quality } => {
+            check_quality(quality)?;
+            WebPEncoder::new_with_quality(&mut buffer, WebPQuality::lossy(quality)).write_image(
+                image,
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+    }
+
+    Ok(buffer)
+}
+
+fn check_quality(quality: u8) -> Result<()> {
+    if quality == 0 || quality > 100 {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Quality {} is not in the 1-100 range",
+            quality
+        )));
+    }
+    Ok(())
+}
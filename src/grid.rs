@@ -0,0 +1,453 @@
+//! This module provides the tiling math shared by the pyramid generators.
+
+use crate::openslide::{Address, Size};
+use crate::{OpenSlideError, Result};
+
+/// The order in which the tiles of a grid are visited.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileOrder {
+    /// Row by row, left to right.
+    RowMajor,
+    /// Column by column, top to bottom.
+    ColumnMajor,
+    /// Along a Hilbert curve, so that consecutive tiles are neighbors and nearby tiles
+    /// are visited close in time, which helps the libopenslide cache.
+    Hilbert,
+}
+
+impl Default for TileOrder {
+    fn default() -> Self {
+        TileOrder::RowMajor
+    }
+}
+
+/// The grid of tiles covering a single image level.
+///
+/// Tiles are `tile_size` x `tile_size` pixels, except on the last column and row
+/// which hold the remainder of the level. Each tile is extended by `overlap` pixels
+/// on every interior edge.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileGrid {
+    dimensions: Size,
+    tile_size: u32,
+    overlap: u32,
+}
+
+impl TileGrid {
+    /// Create the grid of a level.
+    ///
+    /// # Arguments
+    ///
+    /// * `dimensions` - the size of the level in pixels.
+    /// * `tile_size` - the width and height of a single tile, without overlap.
+    /// * `overlap` - the number of extra pixels added to each interior edge of a tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `tile_size` is 0.
+    pub fn new(dimensions: Size, tile_size: u32, overlap: u32) -> Result<TileGrid> {
+        if tile_size == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "Tile size must be positive".to_string(),
+            ));
+        }
+
+        Ok(TileGrid {
+            dimensions,
+            tile_size,
+            overlap,
+        })
+    }
+
+    /// The size of the level in pixels.
+    pub fn dimensions(&self) -> Size {
+        self.dimensions
+    }
+
+    /// The width and height of a single tile, without overlap.
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// The number of extra pixels added to each interior edge of a tile.
+    pub fn overlap(&self) -> u32 {
+        self.overlap
+    }
+
+    /// The number of tiles in each axis.
+    pub fn tiles(&self) -> Size {
+        Size {
+            w: div_ceil(self.dimensions.w, self.tile_size),
+            h: div_ceil(self.dimensions.h, self.tile_size),
+        }
+    }
+
+    /// The total number of tiles.
+    pub fn tile_count(&self) -> u64 {
+        let tiles = self.tiles();
+        u64::from(tiles.w) * u64::from(tiles.h)
+    }
+
+    /// Return true if `address` is a tile of the grid.
+    pub fn contains(&self, address: Address) -> bool {
+        let tiles = self.tiles();
+        address.x < tiles.w && address.y < tiles.h
+    }
+
+    /// Return the overlap added on the top/left and on the bottom/right edges of a tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): address out of range
+    pub fn tile_overlaps(&self, address: Address) -> Result<(Address, Address)> {
+        self.check(address)?;
+
+        let tiles = self.tiles();
+        let overlap = |interior: bool| if interior { self.overlap } else { 0 };
+        Ok((
+            Address {
+                x: overlap(address.x != 0),
+                y: overlap(address.y != 0),
+            },
+            Address {
+                x: overlap(address.x != tiles.w - 1),
+                y: overlap(address.y != tiles.h - 1),
+            },
+        ))
+    }
+
+    /// Return the position of a tile in the level, without overlap.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): address out of range
+    pub fn tile_origin(&self, address: Address) -> Result<Address> {
+        self.check(address)?;
+
+        // Cannot overflow: the origin of a tile is inside the level
+        Ok(Address {
+            x: address.x * self.tile_size,
+            y: address.y * self.tile_size,
+        })
+    }
+
+    /// Return the size of a tile, overlap included.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): address out of range
+    pub fn tile_dimensions(&self, address: Address) -> Result<Size> {
+        let origin = self.tile_origin(address)?;
+        let (topleft, bottomright) = self.tile_overlaps(address)?;
+
+        // The origin is inside the level, but the overlap can push the size past u32
+        let size = |content: u32, topleft: u32, bottomright: u32| {
+            content
+                .checked_add(topleft)
+                .and_then(|size| size.checked_add(bottomright))
+                .ok_or_else(|| {
+                    OpenSlideError::InvalidArgument(format!(
+                        "Tile {} size overflows with an overlap of {}",
+                        address, self.overlap
+                    ))
+                })
+        };
+
+        Ok(Size {
+            w: size(
+                self.tile_size.min(self.dimensions.w - origin.x),
+                topleft.x,
+                bottomright.x,
+            )?,
+            h: size(
+                self.tile_size.min(self.dimensions.h - origin.y),
+                topleft.y,
+                bottomright.y,
+            )?,
+        })
+    }
+
+    /// Iterate over the addresses of every tile, in the given order.
+    pub fn addresses(&self, order: TileOrder) -> impl Iterator<Item = Address> {
+        let tiles = self.tiles();
+        let mut addresses: Vec<Address> = match order {
+            TileOrder::ColumnMajor => (0..tiles.w)
+                .flat_map(|x| (0..tiles.h).map(move |y| Address { x, y }))
+                .collect(),
+            _ => (0..tiles.h)
+                .flat_map(|y| (0..tiles.w).map(move |x| Address { x, y }))
+                .collect(),
+        };
+        if order == TileOrder::Hilbert {
+            let side = u64::from(tiles.w.max(tiles.h)).next_power_of_two();
+            addresses.sort_by_key(|address| hilbert_index(side, *address));
+        }
+        addresses.into_iter()
+    }
+
+    fn check(&self, address: Address) -> Result<()> {
+        if !self.contains(address) {
+            return Err(OpenSlideError::OutOfBounds(address.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Return the starts of the windows sliding along an axis of `length` pixels.
+///
+/// Windows start every `stride` pixels and stay inside the axis: if the stride does
+/// not end exactly on the edge, a last window is aligned to it, so that the edge is
+/// reached. An axis shorter than the window gives a single window at 0, which
+/// extends past the edge.
+pub(crate) fn window_starts(length: u32, window: u32, stride: u32) -> Vec<u32> {
+    if length == 0 {
+        return Vec::new();
+    }
+    if length <= window {
+        return vec![0];
+    }
+
+    let last = length - window;
+    let mut starts: Vec<u32> = (0..=last).step_by(stride as usize).collect();
+    if starts.last() != Some(&last) {
+        starts.push(last);
+    }
+    starts
+}
+
+/// Divide and round up, without going through floats which lose precision past 2^24.
+fn div_ceil(value: u32, divisor: u32) -> u32 {
+    value / divisor + u32::from(value % divisor != 0)
+}
+
+/// Return the position of `address` along the Hilbert curve filling a `side` x `side`
+/// square, `side` being a power of two.
+fn hilbert_index(side: u64, address: Address) -> u64 {
+    let (mut x, mut y) = (u64::from(address.x), u64::from(address.y));
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = u64::from(x & s != 0);
+        let ry = u64::from(y & s != 0);
+        index += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so that the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(w: u32, h: u32, tile_size: u32, overlap: u32) -> TileGrid {
+        TileGrid::new(Size { w, h }, tile_size, overlap).unwrap()
+    }
+
+    #[test]
+    fn test_tiles_non_square() {
+        assert_eq!(grid(1000, 10, 100, 0).tiles(), Size { w: 10, h: 1 });
+        assert_eq!(grid(10, 1000, 100, 0).tiles(), Size { w: 1, h: 10 });
+        assert_eq!(grid(300, 250, 254, 1).tiles(), Size { w: 2, h: 1 });
+        assert_eq!(grid(254, 255, 254, 1).tiles(), Size { w: 1, h: 2 });
+    }
+
+    #[test]
+    fn test_tiles_edge_sizes() {
+        assert_eq!(grid(0, 0, 254, 1).tiles(), Size { w: 0, h: 0 });
+        assert_eq!(grid(0, 0, 254, 1).tile_count(), 0);
+        assert_eq!(grid(1, 1, 254, 1).tiles(), Size { w: 1, h: 1 });
+        assert_eq!(grid(1, 1, 1, 0).tiles(), Size { w: 1, h: 1 });
+        assert_eq!(
+            grid(u32::MAX, u32::MAX, 1, 0).tiles(),
+            Size {
+                w: u32::MAX,
+                h: u32::MAX
+            }
+        );
+        assert_eq!(grid(u32::MAX, 1, u32::MAX, 0).tiles(), Size { w: 1, h: 1 });
+        // Not representable exactly as f32
+        assert_eq!(grid(16_777_217, 1, 1, 0).tiles().w, 16_777_217);
+        assert_eq!(grid(16_777_217, 1, 16_777_216, 0).tiles().w, 2);
+    }
+
+    #[test]
+    fn test_tile_dimensions_exhaustive() {
+        for tile_size in 1..=6 {
+            for overlap in 0..=3 {
+                for w in 1..=20 {
+                    for h in 1..=20 {
+                        let grid = grid(w, h, tile_size, overlap);
+                        let tiles = grid.tiles();
+
+                        assert_eq!(tiles.w, (w + tile_size - 1) / tile_size);
+                        assert_eq!(tiles.h, (h + tile_size - 1) / tile_size);
+                        assert_eq!(grid.tile_count(), u64::from(tiles.w * tiles.h));
+
+                        // Tile contents exactly cover the level
+                        let mut covered = Size { w: 0, h: 0 };
+                        for x in 0..tiles.w {
+                            let address = Address { x, y: 0 };
+                            let (topleft, bottomright) = grid.tile_overlaps(address).unwrap();
+                            let size = grid.tile_dimensions(address).unwrap();
+                            assert_eq!(grid.tile_origin(address).unwrap().x, covered.w);
+                            assert_eq!(topleft.x, if x == 0 { 0 } else { overlap });
+                            assert_eq!(bottomright.x, if x == tiles.w - 1 { 0 } else { overlap });
+                            covered.w += size.w - topleft.x - bottomright.x;
+                        }
+                        for y in 0..tiles.h {
+                            let address = Address { x: 0, y };
+                            let (topleft, bottomright) = grid.tile_overlaps(address).unwrap();
+                            let size = grid.tile_dimensions(address).unwrap();
+                            assert_eq!(grid.tile_origin(address).unwrap().y, covered.h);
+                            covered.h += size.h - topleft.y - bottomright.y;
+                        }
+                        assert_eq!(covered, Size { w, h });
+
+                        assert!(grid.contains(Address {
+                            x: tiles.w - 1,
+                            y: tiles.h - 1
+                        }));
+                        assert!(!grid.contains(Address { x: tiles.w, y: 0 }));
+                        assert!(!grid.contains(Address { x: 0, y: tiles.h }));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let grid = grid(300, 250, 254, 1);
+
+        assert_eq!(
+            grid.tile_dimensions(Address { x: 2, y: 0 }),
+            Err(OpenSlideError::OutOfBounds("(2, 0)".to_string()))
+        );
+        assert_eq!(
+            grid.tile_origin(Address { x: 0, y: 1 }),
+            Err(OpenSlideError::OutOfBounds("(0, 1)".to_string()))
+        );
+        assert_eq!(
+            grid.tile_overlaps(Address {
+                x: u32::MAX,
+                y: u32::MAX
+            }),
+            Err(OpenSlideError::OutOfBounds(
+                "(4294967295, 4294967295)".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_overlap_overflow() {
+        let grid = grid(u32::MAX, 1, u32::MAX / 2, u32::MAX / 2);
+
+        assert!(matches!(
+            grid.tile_dimensions(Address { x: 1, y: 0 }),
+            Err(OpenSlideError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_zero_tile_size() {
+        assert!(matches!(
+            TileGrid::new(Size { w: 10, h: 10 }, 0, 0),
+            Err(OpenSlideError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_addresses_order() {
+        let grid = grid(30, 20, 10, 0);
+
+        let row_major: Vec<Address> = grid.addresses(TileOrder::RowMajor).collect();
+        assert_eq!(
+            &row_major[..4],
+            &[
+                Address { x: 0, y: 0 },
+                Address { x: 1, y: 0 },
+                Address { x: 2, y: 0 },
+                Address { x: 0, y: 1 }
+            ]
+        );
+        let column_major: Vec<Address> = grid.addresses(TileOrder::ColumnMajor).collect();
+        assert_eq!(
+            &column_major[..3],
+            &[
+                Address { x: 0, y: 0 },
+                Address { x: 0, y: 1 },
+                Address { x: 1, y: 0 }
+            ]
+        );
+        assert_eq!(grid.addresses(TileOrder::default()).count(), 6);
+    }
+
+    #[test]
+    fn test_window_starts() {
+        assert_eq!(window_starts(10, 4, 2), vec![0, 2, 4, 6]);
+        // The last window is aligned to the edge
+        assert_eq!(window_starts(10, 4, 4), vec![0, 4, 6]);
+        assert_eq!(window_starts(10, 4, 100), vec![0, 6]);
+        assert_eq!(window_starts(10, 10, 3), vec![0]);
+        assert_eq!(window_starts(3, 10, 3), vec![0]);
+        assert!(window_starts(0, 10, 3).is_empty());
+
+        for length in 1..=30 {
+            for window in 1..=8 {
+                for stride in 1..=8 {
+                    let starts = window_starts(length, window, stride);
+                    let mut covered = vec![false; length as usize];
+                    for start in &starts {
+                        for pixel in *start..(start + window).min(length) {
+                            covered[pixel as usize] = true;
+                        }
+                    }
+                    // Both edges are reached, and pixels are skipped only by larger strides
+                    assert!(covered[0] && covered[length as usize - 1]);
+                    if stride <= window {
+                        assert!(covered.iter().all(|covered| *covered));
+                    }
+                    assert!(starts.windows(2).all(|pair| pair[0] < pair[1]));
+                    assert!(starts
+                        .iter()
+                        .all(|start| start + window <= length.max(window)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_addresses_hilbert() {
+        for (w, h) in [(1, 1), (8, 8), (5, 3), (1, 7), (16, 9)] {
+            let grid = grid(w, h, 1, 0);
+            let hilbert: Vec<Address> = grid.addresses(TileOrder::Hilbert).collect();
+
+            // Every tile, once
+            let mut sorted = hilbert.clone();
+            sorted.sort_by_key(|address| (address.y, address.x));
+            assert_eq!(
+                sorted,
+                grid.addresses(TileOrder::RowMajor)
+                    .collect::<Vec<Address>>()
+            );
+            assert_eq!(hilbert[0], Address { x: 0, y: 0 });
+        }
+
+        // On full power of two squares, consecutive tiles are neighbors
+        let hilbert: Vec<Address> = grid(8, 8, 1, 0).addresses(TileOrder::Hilbert).collect();
+        for pair in hilbert.windows(2) {
+            let distance = (pair[0].x as i64 - pair[1].x as i64).abs()
+                + (pair[0].y as i64 - pair[1].y as i64).abs();
+            assert_eq!(distance, 1, "{:?}", pair);
+        }
+    }
+}
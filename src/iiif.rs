@@ -0,0 +1,609 @@
+//! This module implements the [IIIF Image API 3.0](https://iiif.io/api/image/3.0/)
+//! on top of OpenSlide slides.
+//!
+//! An image request URL ends with `{region}/{size}/{rotation}/{quality}.{format}`.
+//! [`ImageRequest::parse`](struct.ImageRequest.html#method.parse) parses that part
+//! of the URL, and [`render`](fn.render.html) answers the request by reading the
+//! slide level best matching the requested size, within the
+//! [`SizeLimits`](struct.SizeLimits.html) advertised by [`info_json`](fn.info_json.html).
+
+use std::str::FromStr;
+
+use image::imageops::{self, resize, FilterType};
+use image::{Rgba, RgbaImage};
+use serde_json::json;
+
+use crate::encode::{encode, Format};
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::utils::composite_buffer;
+use crate::{OpenSlideError, Result};
+
+/// The JPEG quality used for `.jpg` requests.
+pub const DEFAULT_JPEG_QUALITY: u8 = 75;
+
+/// The WebP quality used for `.webp` requests.
+pub const DEFAULT_WEBP_QUALITY: u8 = 75;
+
+/// The largest images served, advertised as `maxWidth` and `maxArea` in `info.json`.
+///
+/// `max` sizes are reduced to fit in the limits, and requests for larger images are
+/// rejected, so that a single request cannot allocate a whole level 0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SizeLimits {
+    /// The largest width and height of a returned image, in pixels
+    pub max_width: u32,
+    /// The largest number of pixels of a returned image
+    pub max_area: u64,
+    /// The largest number of pixels read from the slide level for a request: slides
+    /// missing intermediate levels need more pixels than they return
+    pub max_read_area: u64,
+}
+
+impl Default for SizeLimits {
+    /// Images of at most 8192 pixels wide and high and 16 megapixels, read from at
+    /// most 256 megapixels.
+    fn default() -> Self {
+        SizeLimits {
+            max_width: 8192,
+            max_area: 4096 * 4096,
+            max_read_area: 16 * 4096 * 4096,
+        }
+    }
+}
+
+impl SizeLimits {
+    /// Scale `size` to the largest size fitting in the limits, preserving its aspect
+    /// ratio, enlarging it only if `upscale` is set.
+    fn fit(&self, size: Size, upscale: bool) -> Size {
+        let (w, h) = (f64::from(size.w), f64::from(size.h));
+        let mut scale = (f64::from(self.max_width) / w)
+            .min(f64::from(self.max_width) / h)
+            .min((self.max_area as f64 / (w * h)).sqrt());
+        if !upscale {
+            scale = scale.min(1.);
+        }
+        Size {
+            w: ((w * scale).floor() as u32).max(1),
+            h: ((h * scale).floor() as u32).max(1),
+        }
+    }
+
+    /// Check that an image of `size` is within the limits.
+    fn check(&self, size: Size) -> Result<()> {
+        if size.w > self.max_width
+            || size.h > self.max_width
+            || u64::from(size.w) * u64::from(size.h) > self.max_area
+        {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "IIIF size {}x{} exceeds the maximum width of {} or area of {} pixels",
+                size.w, size.h, self.max_width, self.max_area
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The `{region}` parameter: the part of the image to return.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RegionRequest {
+    /// The complete image
+    Full,
+    /// The largest square centered in the image
+    Square,
+    /// A region in level 0 pixels: `x,y,w,h`
+    Pixels { x: u32, y: u32, w: u32, h: u32 },
+    /// A region in percents of the full image: `pct:x,y,w,h`
+    Percent { x: f32, y: f32, w: f32, h: f32 },
+}
+
+/// The `{size}` parameter: the dimensions of the returned image. `upscale` is set by
+/// the `^` prefix and allows sizes larger than the extracted region.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SizeRequest {
+    /// The region at its full resolution, reduced to fit in the size limits, or
+    /// scaled to the limits with `^`: `max`
+    Max { upscale: bool },
+    /// Scale to a width, preserving the aspect ratio: `w,`
+    Width { w: u32, upscale: bool },
+    /// Scale to a height, preserving the aspect ratio: `,h`
+    Height { h: u32, upscale: bool },
+    /// Scale by a percentage of the region size: `pct:n`
+    Percent { pct: f32, upscale: bool },
+    /// Scale to exact dimensions, distorting the aspect ratio if needed: `w,h`
+    Exact { w: u32, h: u32, upscale: bool },
+    /// Scale to the largest size fitting in `w` x `h`, preserving the aspect ratio:
+    /// `!w,h`
+    BestFit { w: u32, h: u32, upscale: bool },
+}
+
+/// The `{rotation}` parameter. Only multiples of 90 degrees are supported.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rotation {
+    /// Mirror the image horizontally before rotating it, set by the `!` prefix
+    pub mirror: bool,
+    /// Clockwise rotation in degrees: 0, 90, 180 or 270
+    pub degrees: u32,
+}
+
+/// The `{quality}` parameter: the color space of the returned image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Quality {
+    /// The default quality of the server: color
+    Default,
+    /// Full color
+    Color,
+    /// Grayscale
+    Gray,
+    /// Black and white
+    Bitonal,
+}
+
+/// A parsed IIIF image request.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImageRequest {
+    /// The part of the image to return
+    pub region: RegionRequest,
+    /// The dimensions of the returned image
+    pub size: SizeRequest,
+    /// The rotation applied to the returned image
+    pub rotation: Rotation,
+    /// The color space of the returned image
+    pub quality: Quality,
+    /// The encoding of the returned image
+    pub format: Format,
+}
+
+impl FromStr for RegionRequest {
+    type Err = OpenSlideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(RegionRequest::Full),
+            "square" => Ok(RegionRequest::Square),
+            _ => {
+                if let Some(values) = s.strip_prefix("pct:") {
+                    let [x, y, w, h] = parse_quad::<f32>(values, s)?;
+                    Ok(RegionRequest::Percent { x, y, w, h })
+                } else {
+                    let [x, y, w, h] = parse_quad::<u32>(s, s)?;
+                    Ok(RegionRequest::Pixels { x, y, w, h })
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for SizeRequest {
+    type Err = OpenSlideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (upscale, value) = match s.strip_prefix('^') {
+            Some(value) => (true, value),
+            None => (false, s),
+        };
+        let invalid = || OpenSlideError::InvalidArgument(format!("Invalid IIIF size: {}", s));
+
+        if value == "max" {
+            return Ok(SizeRequest::Max { upscale });
+        }
+        if let Some(pct) = value.strip_prefix("pct:") {
+            let pct = pct.parse::<f32>().map_err(|_| invalid())?;
+            return Ok(SizeRequest::Percent { pct, upscale });
+        }
+
+        let (best_fit, value) = match value.strip_prefix('!') {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let (w, h) = value.split_once(',').ok_or_else(invalid)?;
+        let parse = |v: &str| v.parse::<u32>().map_err(|_| invalid());
+
+        match (w.is_empty(), h.is_empty()) {
+            (false, true) if !best_fit => Ok(SizeRequest::Width {
+                w: parse(w)?,
+                upscale,
+            }),
+            (true, false) if !best_fit => Ok(SizeRequest::Height {
+                h: parse(h)?,
+                upscale,
+            }),
+            (false, false) if best_fit => Ok(SizeRequest::BestFit {
+                w: parse(w)?,
+                h: parse(h)?,
+                upscale,
+            }),
+            (false, false) => Ok(SizeRequest::Exact {
+                w: parse(w)?,
+                h: parse(h)?,
+                upscale,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = OpenSlideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (mirror, value) = match s.strip_prefix('!') {
+            Some(value) => (true, value),
+            None => (false, s),
+        };
+        let invalid = || OpenSlideError::InvalidArgument(format!("Invalid IIIF rotation: {}", s));
+
+        // Accept "90.0" and the like, but only for multiples of 90 degrees
+        let degrees = value.parse::<f32>().map_err(|_| invalid())?;
+        if degrees.fract() != 0. || !(0. ..360.).contains(&degrees) || degrees as u32 % 90 != 0 {
+            return Err(invalid());
+        }
+
+        Ok(Rotation {
+            mirror,
+            degrees: degrees as u32,
+        })
+    }
+}
+
+impl FromStr for Quality {
+    type Err = OpenSlideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Quality::Default),
+            "color" => Ok(Quality::Color),
+            "gray" => Ok(Quality::Gray),
+            "bitonal" => Ok(Quality::Bitonal),
+            _ => Err(OpenSlideError::InvalidArgument(format!(
+                "Invalid IIIF quality: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl ImageRequest {
+    /// Parse the `{region}/{size}/{rotation}/{quality}.{format}` part of an image
+    /// request URL. Supported formats are `jpg`, `png` and `webp`.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the request is malformed or uses an unsupported feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use openslide_rs::iiif::{ImageRequest, Quality, RegionRequest, SizeRequest};
+    ///
+    /// let request = ImageRequest::parse("full/max/0/default.jpg").unwrap();
+    /// assert_eq!(request.region, RegionRequest::Full);
+    /// assert_eq!(request.size, SizeRequest::Max { upscale: false });
+    /// assert_eq!(request.quality, Quality::Default);
+    /// ```
+    pub fn parse(path: &str) -> Result<ImageRequest> {
+        let invalid =
+            || OpenSlideError::InvalidArgument(format!("Invalid IIIF image request: {}", path));
+
+        let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        if parts.len() != 4 {
+            return Err(invalid());
+        }
+        let (quality, format) = parts[3].rsplit_once('.').ok_or_else(invalid)?;
+
+        let format = match format {
+            "jpg" => Format::Jpeg {
+                quality: DEFAULT_JPEG_QUALITY,
+            },
+            "png" => Format::Png,
+            "webp" => Format::Webp {
+                quality: DEFAULT_WEBP_QUALITY,
+            },
+            _ => {
+                return Err(OpenSlideError::InvalidArgument(format!(
+                    "Unsupported IIIF format: {}",
+                    format
+                )))
+            }
+        };
+
+        Ok(ImageRequest {
+            region: parts[0].parse()?,
+            size: parts[1].parse()?,
+            rotation: parts[2].parse()?,
+            quality: quality.parse()?,
+            format,
+        })
+    }
+}
+
+impl RegionRequest {
+    /// Resolve the region against the full image dimensions, returning the region in
+    /// level 0 pixels, clipped to the image.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the region is empty.
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): the region is outside of the image.
+    pub fn resolve(&self, dimensions: Size) -> Result<(Address, Size)> {
+        let (x, y, w, h) = match *self {
+            RegionRequest::Full => (0, 0, dimensions.w, dimensions.h),
+            RegionRequest::Square => {
+                let side = dimensions.w.min(dimensions.h);
+                (
+                    (dimensions.w - side) / 2,
+                    (dimensions.h - side) / 2,
+                    side,
+                    side,
+                )
+            }
+            RegionRequest::Pixels { x, y, w, h } => (x, y, w, h),
+            RegionRequest::Percent { x, y, w, h } => {
+                let scale = |pct: f32, full: u32| (pct / 100. * full as f32).round() as u32;
+                (
+                    scale(x, dimensions.w),
+                    scale(y, dimensions.h),
+                    scale(w, dimensions.w),
+                    scale(h, dimensions.h),
+                )
+            }
+        };
+
+        if w == 0 || h == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "IIIF region is empty".to_string(),
+            ));
+        }
+        if x >= dimensions.w || y >= dimensions.h {
+            return Err(OpenSlideError::OutOfBounds(Address { x, y }.to_string()));
+        }
+
+        Ok((
+            Address { x, y },
+            Size {
+                w: w.min(dimensions.w - x),
+                h: h.min(dimensions.h - y),
+            },
+        ))
+    }
+}
+
+impl SizeRequest {
+    /// Resolve the requested size against the size of the extracted region and the
+    /// size limits.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the size is empty, larger than the region without the `^` prefix, or larger than the limits.
+    pub fn resolve(&self, region: Size, limits: SizeLimits) -> Result<Size> {
+        let scale = |value: u32, from: u32, to: u32| {
+            (value as f64 * to as f64 / from as f64).round().max(1.) as u32
+        };
+
+        let (size, upscale) = match *self {
+            SizeRequest::Max { upscale } => (limits.fit(region, upscale), upscale),
+            SizeRequest::Width { w, upscale } => (
+                Size {
+                    w,
+                    h: scale(region.h, region.w, w),
+                },
+                upscale,
+            ),
+            SizeRequest::Height { h, upscale } => (
+                Size {
+                    w: scale(region.w, region.h, h),
+                    h,
+                },
+                upscale,
+            ),
+            SizeRequest::Percent { pct, upscale } => (
+                Size {
+                    w: (region.w as f32 * pct / 100.).round() as u32,
+                    h: (region.h as f32 * pct / 100.).round() as u32,
+                },
+                upscale,
+            ),
+            SizeRequest::Exact { w, h, upscale } => (Size { w, h }, upscale),
+            SizeRequest::BestFit { w, h, upscale } => {
+                let fit_width =
+                    u64::from(w) * u64::from(region.h) <= u64::from(h) * u64::from(region.w);
+                let size = if fit_width {
+                    Size {
+                        w,
+                        h: scale(region.h, region.w, w),
+                    }
+                } else {
+                    Size {
+                        w: scale(region.w, region.h, h),
+                        h,
+                    }
+                };
+                (size, upscale)
+            }
+        };
+
+        if size.w == 0 || size.h == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "IIIF size is empty".to_string(),
+            ));
+        }
+        if !upscale && (size.w > region.w || size.h > region.h) {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "IIIF size {}x{} is larger than the {}x{} region",
+                size.w, size.h, region.w, region.h
+            )));
+        }
+        limits.check(size)?;
+
+        Ok(size)
+    }
+}
+
+/// Answer an image request, compositing transparent areas over the slide
+/// [`background_color()`](struct.OpenSlide.html#method.background_color).
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the region or size is invalid, or the image or the pixels read exceed `limits`.
+/// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): the region is outside of the image.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn render(slide: &OpenSlide, request: &ImageRequest, limits: SizeLimits) -> Result<RgbaImage> {
+    let (address, region_size) = request.region.resolve(slide.dimensions()?)?;
+    let size = request.size.resolve(region_size, limits)?;
+
+    // Read from the smallest level that still has enough pixels
+    let downsample = (region_size.w as f32 / size.w as f32)
+        .min(region_size.h as f32 / size.h as f32)
+        .max(1.);
+    let level = slide.best_level_for_downsample(downsample)?;
+    let level_downsample = slide.level_downsample(level)?;
+    let read_size = Size {
+        w: ((region_size.w as f32 / level_downsample).ceil() as u32).max(1),
+        h: ((region_size.h as f32 / level_downsample).ceil() as u32).max(1),
+    };
+    if u64::from(read_size.w) * u64::from(read_size.h) > limits.max_read_area {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "IIIF request reads {}x{} pixels, more than the {} pixels limit",
+            read_size.w, read_size.h, limits.max_read_area
+        )));
+    }
+
+    let buffer = slide.read_region_raw(Region {
+        address,
+        level: level as _,
+        size: read_size,
+    })?;
+    let mut image = composite_buffer(&buffer, read_size.w, read_size.h, slide.background_color()?);
+    if image.dimensions() != (size.w, size.h) {
+        image = resize(&image, size.w, size.h, FilterType::Lanczos3);
+    }
+
+    if request.rotation.mirror {
+        imageops::flip_horizontal_in_place(&mut image);
+    }
+    image = match request.rotation.degrees {
+        90 => imageops::rotate90(&image),
+        180 => imageops::rotate180(&image),
+        270 => imageops::rotate270(&image),
+        _ => image,
+    };
+
+    match request.quality {
+        Quality::Default | Quality::Color => {}
+        Quality::Gray | Quality::Bitonal => {
+            let bitonal = request.quality == Quality::Bitonal;
+            for pixel in image.pixels_mut() {
+                let [r, g, b, a] = pixel.0;
+                let luma =
+                    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8;
+                let value = match (bitonal, luma) {
+                    (false, luma) => luma,
+                    (true, luma) if luma < 128 => 0,
+                    (true, _) => 255,
+                };
+                *pixel = Rgba([value, value, value, a]);
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// Answer an image request with an image encoded in the requested format.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the region or size is invalid, or the image or the pixels read exceed `limits`.
+/// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): the region is outside of the image.
+/// * [`OpenSlideError::ImageError`](enum.OpenSlideError.html#variant.ImageError): the image could not be encoded.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn render_encoded(
+    slide: &OpenSlide,
+    request: &ImageRequest,
+    limits: SizeLimits,
+) -> Result<Vec<u8>> {
+    encode(&render(slide, request, limits)?, request.format)
+}
+
+/// Return the `info.json` document describing the slide.
+///
+/// # Arguments
+///
+/// * `slide` - a slide
+/// * `id` - the base URI of the image, without trailing slash.
+/// * `tile_size` - the tile width and height advertised to viewers.
+/// * `limits` - the size limits advertised to viewers, as given to
+/// [`render()`](fn.render.html).
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn info_json(
+    slide: &OpenSlide,
+    id: &str,
+    tile_size: u32,
+    limits: SizeLimits,
+) -> Result<String> {
+    let dimensions = slide.dimensions()?;
+
+    // Halve the resolution until the whole image fits in a single tile
+    let mut scale_factors = vec![1u32];
+    let largest = dimensions.w.max(dimensions.h);
+    while tile_size > 0 && largest / scale_factors[scale_factors.len() - 1] > tile_size {
+        scale_factors.push(scale_factors[scale_factors.len() - 1] * 2);
+    }
+
+    // Only advertise the level sizes that can be requested
+    let mut sizes = Vec::new();
+    for level in (0..slide.level_count()?).rev() {
+        let size = slide.level_dimensions(level)?;
+        if limits.check(size).is_ok() {
+            sizes.push(json!({ "width": size.w, "height": size.h }));
+        }
+    }
+
+    Ok(json!({
+        "@context": "http://iiif.io/api/image/3/context.json",
+        "id": id,
+        "type": "ImageService3",
+        "protocol": "http://iiif.io/api/image",
+        "profile": "level1",
+        "width": dimensions.w,
+        "height": dimensions.h,
+        "maxWidth": limits.max_width,
+        "maxArea": limits.max_area,
+        "sizes": sizes,
+        "tiles": [{ "width": tile_size, "scaleFactors": scale_factors }],
+        "extraFormats": ["png", "webp"],
+        "extraQualities": ["color", "gray", "bitonal"],
+        "extraFeatures": [
+            "mirroring",
+            "regionByPct",
+            "regionSquare",
+            "rotationBy90s",
+            "sizeByConfinedWh",
+            "sizeByPct",
+            "sizeByWh",
+            "sizeUpscaling",
+        ],
+    })
+    .to_string())
+}
+
+/// Parse a `a,b,c,d` list of four values.
+fn parse_quad<T: FromStr>(values: &str, source: &str) -> Result<[T; 4]> {
+    let invalid = || OpenSlideError::InvalidArgument(format!("Invalid IIIF region: {}", source));
+
+    let mut parsed = values
+        .split(',')
+        .map(|v| v.parse::<T>().map_err(|_| invalid()));
+    let quad = [
+        parsed.next().ok_or_else(invalid)??,
+        parsed.next().ok_or_else(invalid)??,
+        parsed.next().ok_or_else(invalid)??,
+        parsed.next().ok_or_else(invalid)??,
+    ];
+    if parsed.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(quad)
+}
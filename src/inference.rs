@@ -0,0 +1,296 @@
+//! This module runs ONNX models over slide tiles, turning a slide into a grid of
+//! model outputs such as class probabilities, aligned to the tiles of a level.
+
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{imageops, Rgba, RgbaImage};
+use ndarray::{Array2, Array3, Array4, CowArray};
+use ort::tensor::OrtOwnedTensor;
+use ort::{Environment, Session, SessionBuilder, Value};
+use rayon::prelude::*;
+
+use crate::deepzoom::DeepZoom;
+use crate::openslide::{Address, OpenSlide, Region};
+use crate::{OpenSlideError, Result};
+
+/// Parameters of [`TileModel`](struct.TileModel.html) runs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InferenceParams {
+    /// The number of tiles given to the model at once
+    pub batch_size: usize,
+    /// The mean of the red, green and blue channels, from 0 to 1, subtracted from
+    /// the inputs
+    pub mean: [f32; 3],
+    /// The standard deviation of the red, green and blue channels, from 0 to 1, the
+    /// inputs are divided by
+    pub std: [f32; 3],
+}
+
+impl Default for InferenceParams {
+    /// Batches of 16 tiles, normalized with the ImageNet statistics.
+    fn default() -> Self {
+        InferenceParams {
+            batch_size: 16,
+            mean: [0.485, 0.456, 0.406],
+            std: [0.229, 0.224, 0.225],
+        }
+    }
+}
+
+/// An ONNX model taking batches of tiles.
+///
+/// The model has a single input, a `(n, 3, h, w)` `f32` tensor of normalized RGB
+/// tiles, and its first output holds the outputs of each tile along the first axis,
+/// such as `(n, classes)` logits.
+pub struct TileModel {
+    session: Session,
+    params: InferenceParams,
+}
+
+impl TileModel {
+    /// Load an ONNX model.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `batch_size` is 0 or a deviation is not positive.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): the model could not be loaded.
+    pub fn open(path: &Path, params: InferenceParams) -> Result<TileModel> {
+        if params.batch_size == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "Batch size must be positive".to_string(),
+            ));
+        }
+        if params.std.iter().any(|std| *std <= 0.) {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Deviations {:?} must be positive",
+                params.std
+            )));
+        }
+
+        let environment = Arc::new(Environment::builder().with_name("openslide-rs").build()?);
+        let session = SessionBuilder::new(&environment)?.with_model_from_file(path)?;
+        Ok(TileModel { session, params })
+    }
+
+    /// The parameters of the runs.
+    pub fn params(&self) -> InferenceParams {
+        self.params
+    }
+
+    /// Run the model on tiles of the same size, returning the flattened outputs of
+    /// each tile as a `(n, outputs)` array.
+    ///
+    /// Transparent pixels are composited over white.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the tiles do not have the same size.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): the model failed.
+    pub fn predict(&self, tiles: &[RgbaImage]) -> Result<Array2<f32>> {
+        let (width, height) = match tiles.first() {
+            Some(tile) => tile.dimensions(),
+            None => return Ok(Array2::zeros((0, 0))),
+        };
+        if tiles
+            .iter()
+            .any(|tile| tile.dimensions() != (width, height))
+        {
+            return Err(OpenSlideError::InvalidArgument(
+                "Tiles must have the same size".to_string(),
+            ));
+        }
+
+        let mut input = Array4::zeros((tiles.len(), 3, height as usize, width as usize));
+        for (index, tile) in tiles.iter().enumerate() {
+            for (x, y, pixel) in tile.enumerate_pixels() {
+                let opacity = f32::from(pixel[3]) / 255.;
+                let statistics = self.params.mean.iter().zip(&self.params.std);
+                for (channel, (mean, std)) in statistics.enumerate() {
+                    let value =
+                        (f32::from(pixel[channel]) * opacity + 255. * (1. - opacity)) / 255.;
+                    input[[index, channel, y as usize, x as usize]] = (value - mean) / std;
+                }
+            }
+        }
+
+        let input = CowArray::from(input.into_dyn());
+        let outputs = self
+            .session
+            .run(vec![Value::from_array(self.session.allocator(), &input)?])?;
+        let output: OrtOwnedTensor<f32, _> = outputs
+            .first()
+            .ok_or_else(|| OpenSlideError::InternalError("The model has no output".to_string()))?
+            .try_extract()?;
+        let output = output.view();
+        let values: Vec<f32> = output.iter().copied().collect();
+        let count = values.len() / tiles.len();
+        Array2::from_shape_vec((tiles.len(), count), values)
+            .map_err(|e| OpenSlideError::InternalError(e.to_string()))
+    }
+
+    /// Run the model on a stream of tiles of the same size, in batches, returning
+    /// the flattened outputs of each tile as a `(n, outputs)` array.
+    ///
+    /// # Errors
+    ///
+    /// The first error of `tiles`, or those of
+    /// [`predict()`](struct.TileModel.html#method.predict).
+    pub fn predict_tiles<I>(&self, tiles: I) -> Result<Array2<f32>>
+    where
+        I: IntoIterator<Item = Result<RgbaImage>>,
+    {
+        let mut outputs: Vec<Array2<f32>> = Vec::new();
+        let mut batch = Vec::with_capacity(self.params.batch_size);
+        for tile in tiles {
+            batch.push(tile?);
+            if batch.len() == self.params.batch_size {
+                outputs.push(self.predict(&batch)?);
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            outputs.push(self.predict(&batch)?);
+        }
+        stack(&outputs)
+    }
+}
+
+/// Run a model over the windows of a level, as given by
+/// [`OpenSlide::windows()`](struct.OpenSlide.html#method.windows).
+///
+/// The windows of each batch are read in parallel. The result has a
+/// `(rows, columns, outputs)` shape: the cell at `(row, column)` holds the outputs of
+/// the window of the `row`-th distinct ordinate and `column`-th distinct abscissa.
+///
+/// # Errors
+///
+/// The errors of [`OpenSlide::windows()`](struct.OpenSlide.html#method.windows),
+/// of reading the windows and of [`TileModel::predict()`](struct.TileModel.html#method.predict).
+pub fn predict_windows(
+    model: &TileModel,
+    slide: &OpenSlide,
+    level: u32,
+    window_size: u32,
+    stride: u32,
+) -> Result<Array3<f32>> {
+    let windows: Vec<_> = slide.windows(level, window_size, stride)?.collect();
+    let columns = windows
+        .iter()
+        .take_while(|(address, _)| address.y == 0)
+        .count();
+    let rows = if columns == 0 {
+        0
+    } else {
+        windows.len() / columns
+    };
+
+    let mut outputs = Vec::new();
+    for batch in windows.chunks(model.params.batch_size) {
+        let tiles = batch
+            .par_iter()
+            .map(|(_, region)| {
+                slide.read_region(Region {
+                    address: region.address,
+                    level: region.level,
+                    size: region.size,
+                })
+            })
+            .collect::<Result<Vec<RgbaImage>>>()?;
+        outputs.push(model.predict(&tiles)?);
+    }
+    grid(stack(&outputs)?, rows, columns)
+}
+
+/// Run a model over the tiles of a Deep Zoom level.
+///
+/// Tiles are padded with the Deep Zoom background color to the size of interior
+/// tiles, `tile_size + 2 * overlap`, each tile content being placed at the same
+/// offset, so that edge tiles can be batched with the others. The result has a
+/// `(rows, columns, outputs)` shape, aligned to the level tiles.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range.
+///
+/// The errors of reading the tiles and of
+/// [`TileModel::predict()`](struct.TileModel.html#method.predict).
+pub fn predict_deepzoom<S>(
+    model: &TileModel,
+    deepzoom: &DeepZoom<S>,
+    level: usize,
+) -> Result<Array3<f32>>
+where
+    S: Deref<Target = OpenSlide> + Sync,
+{
+    let grid_tiles = deepzoom
+        .level_grid(level)
+        .ok_or_else(|| OpenSlideError::IndexError(level.to_string()))?;
+    let tiles = grid_tiles.tiles();
+    let size = deepzoom.tile_edge() + 2 * deepzoom.overlap();
+    let background = deepzoom.background_color();
+    let background = Rgba([background[0], background[1], background[2], 255]);
+
+    let addresses: Vec<Address> = (0..tiles.h)
+        .flat_map(|y| (0..tiles.w).map(move |x| Address { x, y }))
+        .collect();
+    let mut outputs = Vec::new();
+    for batch in addresses.chunks(model.params.batch_size) {
+        let padded = batch
+            .par_iter()
+            .map(|address| {
+                let tile = deepzoom.read_tile(level, *address)?;
+                let (topleft, _) = grid_tiles.tile_overlaps(*address)?;
+                let mut padded = RgbaImage::from_pixel(size, size, background);
+                imageops::replace(
+                    &mut padded,
+                    &tile,
+                    i64::from(deepzoom.overlap() - topleft.x),
+                    i64::from(deepzoom.overlap() - topleft.y),
+                );
+                Ok(padded)
+            })
+            .collect::<Result<Vec<RgbaImage>>>()?;
+        outputs.push(model.predict(&padded)?);
+    }
+    grid(stack(&outputs)?, tiles.h as usize, tiles.w as usize)
+}
+
+/// Concatenate batch outputs along the tile axis.
+fn stack(outputs: &[Array2<f32>]) -> Result<Array2<f32>> {
+    let views: Vec<_> = outputs.iter().map(|output| output.view()).collect();
+    if views.is_empty() {
+        return Ok(Array2::zeros((0, 0)));
+    }
+    ndarray::concatenate(ndarray::Axis(0), &views).map_err(|_| {
+        OpenSlideError::InternalError("The model outputs differ between batches".to_string())
+    })
+}
+
+/// Lay out row-major tile outputs on their grid.
+fn grid(outputs: Array2<f32>, rows: usize, columns: usize) -> Result<Array3<f32>> {
+    let count = outputs.ncols();
+    outputs
+        .into_shape((rows, columns, count))
+        .map_err(|e| OpenSlideError::InternalError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_stack_and_grid() {
+        let outputs = stack(&[array![[0., 1.], [2., 3.]], array![[4., 5.]]]).unwrap();
+        assert_eq!(outputs.dim(), (3, 2));
+
+        let grid = grid(stack(&[outputs.clone(), array![[6., 7.]]]).unwrap(), 2, 2).unwrap();
+        assert_eq!(grid.dim(), (2, 2, 2));
+        assert_eq!(grid[[1, 0, 1]], 5.);
+
+        assert!(stack(&[array![[0.]], array![[0., 1.]]]).is_err());
+        assert_eq!(stack(&[]).unwrap().dim(), (0, 0));
+    }
+}
@@ -0,0 +1,427 @@
+//! This module provides a batch extraction engine: it reads a list of regions, each
+//! from its own slide and at its own resolution, and writes them as images, with
+//! retries and a summary report.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::imageops::{resize, FilterType};
+use image::RgbaImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::deepzoom::slide_mpp;
+use crate::encode::{encode, Format};
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::pyramid::{parallel_map, Parallelism};
+use crate::{OpenSlideError, Result};
+
+/// The columns of CSV job lists, in the order of the
+/// [`RegionJob`](struct.RegionJob.html) fields.
+const CSV_HEADER: &str = "slide,level,mpp,x,y,w,h,output";
+
+/// A region to extract.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegionJob {
+    /// The slide to read
+    pub slide: PathBuf,
+    /// The level to read at; the resolution is given by either `level` or `mpp`,
+    /// level 0 being read if neither is
+    pub level: Option<u32>,
+    /// The resolution to read at, in micrometers per pixel; the region is read at the
+    /// best level and resized
+    pub mpp: Option<f32>,
+    /// The level 0 abscissa of the top left corner
+    pub x: u32,
+    /// The level 0 ordinate of the top left corner
+    pub y: u32,
+    /// The width of the output image, in pixels
+    pub w: u32,
+    /// The height of the output image, in pixels
+    pub h: u32,
+    /// The output image; its extension, `png`, `jpg`, `jpeg` or `webp`, gives its
+    /// format
+    pub output: PathBuf,
+}
+
+/// Options of [`run_jobs()`](fn.run_jobs.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JobOptions {
+    /// How many threads run jobs
+    pub parallelism: Parallelism,
+    /// How many times a job failing with an I/O or internal error is retried
+    pub retries: u32,
+    /// The quality of JPEG and WebP outputs, from 1 (worst) to 100 (best)
+    pub quality: u8,
+}
+
+impl Default for JobOptions {
+    /// Jobs run on the global thread pool, retried twice, with a 90 quality.
+    fn default() -> Self {
+        JobOptions {
+            parallelism: Parallelism::Auto,
+            retries: 2,
+            quality: 90,
+        }
+    }
+}
+
+/// The outcome of a single job.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobOutcome {
+    /// The output image of the job
+    pub output: PathBuf,
+    /// How many times the job was run
+    pub attempts: u32,
+    /// The number of bytes written
+    pub bytes_written: u64,
+    /// The error of the last attempt, if the job failed
+    pub error: Option<String>,
+}
+
+impl JobOutcome {
+    /// Return true if the job succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The summary of a batch of jobs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobReport {
+    /// The outcome of each job, in the order they were given
+    pub outcomes: Vec<JobOutcome>,
+}
+
+impl JobReport {
+    /// The number of jobs that succeeded.
+    pub fn succeeded(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.is_ok())
+            .count()
+    }
+
+    /// The jobs that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &JobOutcome> {
+        self.outcomes.iter().filter(|outcome| !outcome.is_ok())
+    }
+
+    /// The total number of bytes written.
+    pub fn bytes_written(&self) -> u64 {
+        self.outcomes
+            .iter()
+            .map(|outcome| outcome.bytes_written)
+            .sum()
+    }
+}
+
+/// Read a job list: a JSON array of [`RegionJob`](struct.RegionJob.html) objects if
+/// the file extension is `json`, or else CSV with a
+/// `slide,level,mpp,x,y,w,h,output` header line, `level` and `mpp` being empty when
+/// not given.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be read.
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the file is not a valid job list.
+pub fn load_jobs(path: &Path) -> Result<Vec<RegionJob>> {
+    let content = fs::read_to_string(path)?;
+    let is_json = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("json"));
+    if is_json {
+        serde_json::from_str(&content)
+            .map_err(|e| OpenSlideError::InvalidArgument(format!("Invalid job list: {}", e)))
+    } else {
+        parse_jobs_csv(&content)
+    }
+}
+
+/// Run jobs in parallel, writing each region to its output.
+///
+/// Each slide is opened once and shared by its jobs, and output directories are
+/// created as needed. A failing job is reported with its error rather than failing
+/// the batch; jobs failing with an
+/// [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError) or
+/// [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError)
+/// are retried.
+///
+/// # Arguments
+///
+/// * `jobs` - the regions to extract.
+/// * `options` - the parallelism, retries and output quality.
+/// * `progress` - called with the number of finished jobs and the total after each job.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): the thread pool could not be created.
+pub fn run_jobs<F>(jobs: &[RegionJob], options: JobOptions, progress: F) -> Result<JobReport>
+where
+    F: Fn(usize, usize) + Sync,
+{
+    let mut paths: Vec<&Path> = jobs.iter().map(|job| job.slide.as_path()).collect();
+    paths.sort();
+    paths.dedup();
+    let slides: HashMap<&Path, (Result<OpenSlide>, u32)> = paths
+        .par_iter()
+        .map(|path| (*path, retry(options.retries, || OpenSlide::open(path))))
+        .collect();
+
+    let outcomes = parallel_map(jobs, options.parallelism, progress, |job| {
+        let (result, attempts) = match &slides[job.slide.as_path()] {
+            (Ok(slide), _) => retry(options.retries, || run_job(slide, job, options.quality)),
+            (Err(error), attempts) => (Err(error.clone()), *attempts),
+        };
+        JobOutcome {
+            output: job.output.clone(),
+            attempts,
+            bytes_written: *result.as_ref().unwrap_or(&0),
+            error: result.err().map(|error| error.to_string()),
+        }
+    })?;
+
+    Ok(JobReport { outcomes })
+}
+
+/// Read the region of a job and write it, returning the number of bytes written.
+fn run_job(slide: &OpenSlide, job: &RegionJob, quality: u8) -> Result<u64> {
+    if job.w == 0 || job.h == 0 {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Region size {}x{} must be positive",
+            job.w, job.h
+        )));
+    }
+    let format = output_format(&job.output, quality)?;
+    let address = Address { x: job.x, y: job.y };
+    let size = Size { w: job.w, h: job.h };
+
+    let image = match (job.level, job.mpp) {
+        (Some(_), Some(_)) => {
+            return Err(OpenSlideError::InvalidArgument(
+                "Give either a level or a resolution".to_string(),
+            ))
+        }
+        (level, None) => slide.read_region(Region {
+            address,
+            level: level.unwrap_or(0),
+            size,
+        })?,
+        (None, Some(mpp)) => {
+            if !mpp.is_finite() || mpp <= 0. {
+                return Err(OpenSlideError::InvalidArgument(format!(
+                    "MPP {} must be positive",
+                    mpp
+                )));
+            }
+            read_at_mpp(slide, address, size, mpp)?
+        }
+    };
+
+    let bytes = encode(&image, format)?;
+    if let Some(parent) = job.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&job.output, &bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+/// Read a region at a resolution, from the best level, resized to `size`.
+fn read_at_mpp(slide: &OpenSlide, address: Address, size: Size, mpp: f32) -> Result<RgbaImage> {
+    let downsample = f64::from(mpp) / f64::from(slide_mpp(slide)?);
+    let level = slide.best_level_for_downsample(downsample as f32)?;
+    let scale = downsample / f64::from(slide.level_downsample(level)?);
+    let scaled = |length: u32| (f64::from(length) * scale).ceil().max(1.) as u32;
+
+    let region = slide.read_region(Region {
+        address,
+        level,
+        size: Size {
+            w: scaled(size.w),
+            h: scaled(size.h),
+        },
+    })?;
+    if region.dimensions() == (size.w, size.h) {
+        Ok(region)
+    } else {
+        Ok(resize(&region, size.w, size.h, FilterType::Lanczos3))
+    }
+}
+
+/// The format of an output image, given by its extension.
+fn output_format(output: &Path, quality: u8) -> Result<Format> {
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => Ok(Format::Png),
+        Some("jpg") | Some("jpeg") => Ok(Format::Jpeg { quality }),
+        Some("webp") => Ok(Format::Webp { quality }),
+        _ => Err(OpenSlideError::InvalidArgument(format!(
+            "Unsupported output format: {}",
+            output.display()
+        ))),
+    }
+}
+
+/// Run `job` until it succeeds, fails with an error other than an I/O or internal
+/// one, or has been retried `retries` times, returning its last result and the
+/// number of attempts.
+fn retry<T, F>(retries: u32, job: F) -> (Result<T>, u32)
+where
+    F: Fn() -> Result<T>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = job();
+        match result {
+            Err(OpenSlideError::IoError(_)) | Err(OpenSlideError::InternalError(_))
+                if attempts <= retries => {}
+            _ => return (result, attempts),
+        }
+    }
+}
+
+/// Parse a CSV job list.
+fn parse_jobs_csv(content: &str) -> Result<Vec<RegionJob>> {
+    let invalid = |line: usize, message: String| {
+        OpenSlideError::InvalidArgument(format!("Invalid job list line {}: {}", line, message))
+    };
+
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    match lines.next() {
+        Some((_, header)) if header.trim() == CSV_HEADER => {}
+        _ => {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Job lists must start with a {} header",
+                CSV_HEADER
+            )))
+        }
+    }
+
+    lines
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let fields = csv_fields(line);
+            if fields.len() != 8 {
+                return Err(invalid(
+                    line_number,
+                    format!("{} fields instead of 8", fields.len()),
+                ));
+            }
+            let number = |field: &str| {
+                field
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|e| invalid(line_number, format!("{}: {}", field, e)))
+            };
+            let (level, mpp) = (fields[1].trim(), fields[2].trim());
+
+            Ok(RegionJob {
+                slide: PathBuf::from(&fields[0]),
+                level: if level.is_empty() {
+                    None
+                } else {
+                    Some(number(level)?)
+                },
+                mpp: if mpp.is_empty() {
+                    None
+                } else {
+                    Some(
+                        mpp.parse::<f32>()
+                            .map_err(|e| invalid(line_number, format!("{}: {}", mpp, e)))?,
+                    )
+                },
+                x: number(&fields[3])?,
+                y: number(&fields[4])?,
+                w: number(&fields[5])?,
+                h: number(&fields[6])?,
+                output: PathBuf::from(&fields[7]),
+            })
+        })
+        .collect()
+}
+
+/// Split a CSV line into fields, unquoting quoted ones.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jobs_csv() {
+        let jobs = parse_jobs_csv(&format!(
+            "{}\nslide.svs,1,,0,10,256,128,out/a.png\n\n\"my, slide.svs\",,0.5,5,6,7,8,b.jpg\n",
+            CSV_HEADER
+        ))
+        .unwrap();
+        assert_eq!(
+            jobs,
+            vec![
+                RegionJob {
+                    slide: PathBuf::from("slide.svs"),
+                    level: Some(1),
+                    mpp: None,
+                    x: 0,
+                    y: 10,
+                    w: 256,
+                    h: 128,
+                    output: PathBuf::from("out/a.png"),
+                },
+                RegionJob {
+                    slide: PathBuf::from("my, slide.svs"),
+                    level: None,
+                    mpp: Some(0.5),
+                    x: 5,
+                    y: 6,
+                    w: 7,
+                    h: 8,
+                    output: PathBuf::from("b.jpg"),
+                },
+            ]
+        );
+
+        assert!(parse_jobs_csv("slide.svs,0,,0,0,1,1,a.png").is_err());
+        assert!(parse_jobs_csv(&format!("{}\nslide.svs,0,,0,0,1,a.png", CSV_HEADER)).is_err());
+        assert!(parse_jobs_csv(&format!("{}\nslide.svs,x,,0,0,1,1,a.png", CSV_HEADER)).is_err());
+    }
+
+    #[test]
+    fn test_retry() {
+        let attempts = std::cell::Cell::new(0);
+        let (result, count) = retry(2, || -> Result<()> {
+            attempts.set(attempts.get() + 1);
+            Err(OpenSlideError::IoError("busy".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!((count, attempts.get()), (3, 3));
+
+        let (result, count) = retry(2, || -> Result<()> {
+            Err(OpenSlideError::InvalidArgument("bad".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(count, 1);
+    }
+}
@@ -5,12 +5,64 @@
 use std::error::Error;
 use std::fmt;
 
+mod annotation;
+pub mod anonymize;
+pub mod catalog;
+pub mod cohort;
+#[cfg(feature = "color")]
+mod color;
+pub mod compare;
+mod dataset;
 mod deepzoom;
+mod downscale;
+mod dzi;
+mod encode;
+mod grid;
+pub mod iiif;
+#[cfg(feature = "inference")]
+pub mod inference;
+pub mod jobs;
+mod loader;
+mod logging;
+mod memmap;
 mod openslide;
+pub mod overlay;
+pub mod overview;
+mod patch;
+mod pool;
+mod pyramid;
+pub mod qc;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod stats;
+mod tiff;
+pub mod tiling;
+pub mod tissue;
 mod utils;
+mod zarr;
+mod zoomify;
 
-pub use deepzoom::DeepZoom;
-pub use openslide::{Address, OpenSlide, Region, Size};
+pub use annotation::{mask_contours, Annotation, AnnotationSet, Point, Polygon, ANNOTATED};
+#[cfg(feature = "hdf5")]
+pub use dataset::export_patches_hdf5;
+#[cfg(feature = "arrow")]
+pub use dataset::write_manifest_parquet;
+pub use dataset::{
+    export_patches, export_patches_resumable, PatchRecord, SessionStats, ZarrPatchWriter,
+};
+pub use deepzoom::{DeepZoom, LevelInfo, PyramidInfo, ResizeFilter, TileBounds, TileHook};
+pub use dzi::DziDescriptor;
+pub use encode::Format;
+pub use grid::{TileGrid, TileOrder};
+pub use loader::PatchLoader;
+pub use logging::forward_native_logs;
+pub use memmap::{export_level_memmap, LevelMemmap};
+pub use openslide::{Address, Cache, LevelProperties, OpenSlide, Region, Size, SlideProperties};
+pub use patch::{read_context_patches, Patch, PatchSampler};
+pub use pool::SlidePool;
+pub use pyramid::{BackgroundFilter, BackgroundTiles, ExportStats, Parallelism};
+pub use zarr::{write_ome_zarr, DirectoryStore, ZarrStore};
+pub use zoomify::Zoomify;
 
 type Result<T> = std::result::Result<T, OpenSlideError>;
 
@@ -19,6 +71,10 @@ pub enum OpenSlideError {
     MissingFile(String),
     UnsupportedFile(String),
     IndexError(String),
+    OutOfBounds(String),
+    InvalidArgument(String),
+    IoError(String),
+    ImageError(String),
     InternalError(String),
 }
 
@@ -28,6 +84,10 @@ impl OpenSlideError {
             Self::MissingFile(m) => format!("File {} does not exist", m),
             Self::UnsupportedFile(m) => format!("Unsupported format: {}", m),
             Self::IndexError(m) => format!("Level {} out of range", m),
+            Self::OutOfBounds(m) => format!("Address {} out of range", m),
+            Self::InvalidArgument(m) => m.to_string(),
+            Self::IoError(m) => format!("IO error: {}", m),
+            Self::ImageError(m) => format!("Image error: {}", m),
             Self::InternalError(m) => m.to_string(),
         }
     }
@@ -35,6 +95,59 @@ impl OpenSlideError {
 
 impl Error for OpenSlideError {}
 
+impl From<std::io::Error> for OpenSlideError {
+    fn from(error: std::io::Error) -> Self {
+        Self::IoError(error.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for OpenSlideError {
+    fn from(error: zip::result::ZipError) -> Self {
+        Self::IoError(error.to_string())
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl From<hdf5_rust::Error> for OpenSlideError {
+    fn from(error: hdf5_rust::Error) -> Self {
+        Self::IoError(error.to_string())
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl From<arrow_rs::error::ArrowError> for OpenSlideError {
+    fn from(error: arrow_rs::error::ArrowError) -> Self {
+        Self::IoError(error.to_string())
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl From<parquet::errors::ParquetError> for OpenSlideError {
+    fn from(error: parquet::errors::ParquetError) -> Self {
+        Self::IoError(error.to_string())
+    }
+}
+
+#[cfg(feature = "inference")]
+impl From<ort::OrtError> for OpenSlideError {
+    fn from(error: ort::OrtError) -> Self {
+        Self::InternalError(error.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for OpenSlideError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::IoError(error.to_string())
+    }
+}
+
+impl From<image::ImageError> for OpenSlideError {
+    fn from(error: image::ImageError) -> Self {
+        Self::ImageError(error.to_string())
+    }
+}
+
 impl fmt::Debug for OpenSlideError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.error_message())
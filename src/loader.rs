@@ -0,0 +1,126 @@
+//! This module provides a patch loader reading and decoding patches on background
+//! threads, so that consumers such as training loops do not wait on slide IO.
+
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use image::RgbaImage;
+
+use crate::openslide::OpenSlide;
+use crate::patch::{read_patch, Patch, PatchSampler};
+use crate::{OpenSlideError, Result};
+
+/// An iterator over patches and their pixels, read by a pool of background threads.
+///
+/// At most `capacity` decoded patches wait in the queue: reader threads block when
+/// the consumer falls behind. Patches are yielded in completion order, which differs
+/// from the sampler order with several threads, and read errors are yielded in place
+/// of the failed patch.
+///
+/// Dropping the loader, or calling [`shutdown()`](struct.PatchLoader.html#method.shutdown),
+/// stops the reader threads and waits for them to exit.
+pub struct PatchLoader {
+    receiver: Option<Receiver<Result<(Patch, RgbaImage)>>>,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl PatchLoader {
+    /// Start reading the patches of a sampler, each thread using its own slide handle.
+    ///
+    /// Handles may all point to one shared slide, or to several slides opened from
+    /// the same file so that threads do not contend on a single libopenslide cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampler` - the patches to read.
+    /// * `handles` - one slide handle per reader thread.
+    /// * `capacity` - the maximum number of decoded patches waiting to be consumed.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): there is no handle.
+    pub fn with_handles<S, H>(
+        sampler: &PatchSampler<S>,
+        handles: Vec<H>,
+        capacity: usize,
+    ) -> Result<PatchLoader>
+    where
+        S: Deref<Target = OpenSlide>,
+        H: Deref<Target = OpenSlide> + Send + 'static,
+    {
+        if handles.is_empty() {
+            return Err(OpenSlideError::InvalidArgument(
+                "Patch loader needs at least one thread".to_string(),
+            ));
+        }
+
+        let patches: Arc<Vec<Patch>> = Arc::new(sampler.patches().to_vec());
+        let patch_size = sampler.patch_size();
+        let next = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = sync_channel(capacity);
+
+        let workers = handles
+            .into_iter()
+            .map(|slide| {
+                let patches = patches.clone();
+                let next = next.clone();
+                let stop = stop.clone();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let patch = match patches.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            Some(patch) => *patch,
+                            None => break,
+                        };
+                        let result =
+                            read_patch(&slide, &patch, patch_size).map(|pixels| (patch, pixels));
+                        // The receiver is gone: the loader was shut down
+                        if sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Ok(PatchLoader {
+            receiver: Some(receiver),
+            stop,
+            workers,
+        })
+    }
+
+    /// Stop the reader threads and wait for them to exit. Patches still queued are
+    /// discarded.
+    pub fn shutdown(mut self) {
+        self.stop_workers();
+    }
+
+    fn stop_workers(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Unblock the threads waiting for room in the queue
+        self.receiver = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Iterator for PatchLoader {
+    type Item = Result<(Patch, RgbaImage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for PatchLoader {
+    fn drop(&mut self) {
+        self.stop_workers();
+    }
+}
@@ -0,0 +1,96 @@
+//! Forwarding of the C library messages to the `log` crate.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::panic;
+use std::sync::Once;
+
+use log::Level;
+use openslide_sys::{
+    G_LOG_LEVEL_CRITICAL, G_LOG_LEVEL_DEBUG, G_LOG_LEVEL_ERROR, G_LOG_LEVEL_INFO,
+    G_LOG_LEVEL_MESSAGE, G_LOG_LEVEL_WARNING,
+};
+
+static FORWARD: Once = Once::new();
+
+/// Forward the messages of the C library to the [`log`](https://docs.rs/log) crate,
+/// instead of printing them to the standard error.
+///
+/// This covers the glib warnings of OpenSlide, such as inconsistent slide metadata
+/// or performance warnings, and the warnings of libtiff. They are logged under the
+/// `openslide` target, libtiff ones under `openslide::tiff`. Calling this function
+/// again does nothing.
+///
+/// The glib default log handler and the libtiff warning handler are process-wide:
+/// they also capture the messages of every other glib or libtiff user in the
+/// process. The logger is called from the threads reading slides, possibly while
+/// OpenSlide holds its internal locks: it must not block on a lock held by a thread
+/// calling into OpenSlide, such as the Python GIL.
+pub fn forward_native_logs() {
+    FORWARD.call_once(|| unsafe {
+        openslide_sys::openslide_rs_set_log_handler(Some(log_handler), std::ptr::null_mut());
+    });
+}
+
+unsafe extern "C" fn log_handler(
+    domain: *const c_char,
+    flags: c_uint,
+    message: *const c_char,
+    _user_data: *mut c_void,
+) {
+    let domain = if domain.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(domain).to_string_lossy())
+    };
+    let message = if message.is_null() {
+        "".into()
+    } else {
+        CStr::from_ptr(message).to_string_lossy()
+    };
+    let target = log_target(domain.as_deref());
+    // A panicking logger must not unwind into C
+    let _ = panic::catch_unwind(|| {
+        log::log!(target: target.as_str(), log_level(flags), "{}", message);
+    });
+}
+
+/// The log level of glib level flags, the most severe one if several are set.
+fn log_level(flags: c_uint) -> Level {
+    if flags & (G_LOG_LEVEL_ERROR | G_LOG_LEVEL_CRITICAL) != 0 {
+        Level::Error
+    } else if flags & G_LOG_LEVEL_WARNING != 0 {
+        Level::Warn
+    } else if flags & (G_LOG_LEVEL_MESSAGE | G_LOG_LEVEL_INFO) != 0 {
+        Level::Info
+    } else if flags & G_LOG_LEVEL_DEBUG != 0 {
+        Level::Debug
+    } else {
+        Level::Trace
+    }
+}
+
+/// The log target of a glib domain.
+fn log_target(domain: Option<&str>) -> String {
+    match domain {
+        None | Some("") => "openslide".to_string(),
+        Some(domain) => format!("openslide::{}", domain.to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level(G_LOG_LEVEL_WARNING), Level::Warn);
+        // glib sets the fatal and recursion bits above the levels
+        assert_eq!(log_level(G_LOG_LEVEL_CRITICAL | 1 << 1), Level::Error);
+        assert_eq!(log_level(G_LOG_LEVEL_MESSAGE), Level::Info);
+        assert_eq!(log_level(G_LOG_LEVEL_DEBUG), Level::Debug);
+
+        assert_eq!(log_target(None), "openslide");
+        assert_eq!(log_target(Some("TIFF")), "openslide::tiff");
+    }
+}
@@ -0,0 +1,192 @@
+//! This module provides the export of a whole slide level to a flat, memory-mapped
+//! array file, for tools that want the pixels as a single array without holding
+//! them in memory.
+
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+
+use memmap2::{Mmap, MmapMut};
+use ndarray::ArrayView3;
+
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::pyramid::{for_each_tile, Parallelism};
+use crate::Result;
+
+/// The width and height of the regions read at once.
+const TILE_SIZE: u32 = 512;
+
+/// The NPY headers are padded to a multiple of this size, so that the array data
+/// is aligned.
+const NPY_ALIGNMENT: usize = 64;
+
+/// A slide level exported to a memory-mapped file.
+pub struct LevelMemmap {
+    mmap: Mmap,
+    offset: usize,
+    dimensions: Size,
+}
+
+impl LevelMemmap {
+    /// The size of the level in pixels.
+    pub fn dimensions(&self) -> Size {
+        self.dimensions
+    }
+
+    /// The offset of the pixels in the file, after the NPY header if any.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The pixels as a `(y, x, c)` array of RGB values.
+    pub fn view(&self) -> ArrayView3<u8> {
+        let shape = (
+            self.dimensions.h as usize,
+            self.dimensions.w as usize,
+            3_usize,
+        );
+        // The file was sized for the whole level
+        ArrayView3::from_shape(shape, &self.mmap[self.offset..]).unwrap()
+    }
+
+    /// The memory map of the whole file, header included.
+    pub fn into_mmap(self) -> Mmap {
+        self.mmap
+    }
+}
+
+/// Export a whole slide level to a memory-mapped array file.
+///
+/// The level is written as a `(y, x, c)` array of RGB `u8` pixels, in C order: a
+/// NPY file, readable with `numpy.load(path, mmap_mode="r")`, if `path` has the
+/// `npy` extension, and raw pixels otherwise. The level is read tile by tile, bands
+/// of tiles being written in parallel, so that only a few tiles are held in
+/// memory whatever the level size.
+///
+/// # Arguments
+///
+/// * `slide` - the slide.
+/// * `level` - the level to export.
+/// * `path` - the destination file, replaced if it exists.
+/// * `parallelism` - how many threads read the tiles.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range.
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the file could not be created or mapped.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn export_level_memmap(
+    slide: &OpenSlide,
+    level: u32,
+    path: &Path,
+    parallelism: Parallelism,
+) -> Result<LevelMemmap> {
+    let dimensions = slide.level_dimensions(level)?;
+    let downsample = f64::from(slide.level_downsample(level)?);
+
+    let header = if path
+        .extension()
+        .map_or(false, |extension| extension == "npy")
+    {
+        npy_header(dimensions)
+    } else {
+        Vec::new()
+    };
+    let row_bytes = dimensions.w as usize * 3;
+    let length = header.len() + row_bytes * dimensions.h as usize;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.set_len(length as u64)?;
+    // Safety: the file was just created by us, and is not resized while mapped
+    let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    mmap[..header.len()].copy_from_slice(&header);
+
+    if row_bytes > 0 {
+        // Bands of tile rows are disjoint, so that they can be written concurrently
+        let bands: Vec<(u32, Mutex<&mut [u8]>)> = mmap[header.len()..]
+            .chunks_mut(row_bytes * TILE_SIZE as usize)
+            .enumerate()
+            .map(|(index, band)| (index as u32 * TILE_SIZE, Mutex::new(band)))
+            .collect();
+
+        for_each_tile(
+            &bands,
+            parallelism,
+            |_, _| {},
+            |(y, band)| {
+                let mut band = band.lock().unwrap();
+                let height = (band.len() / row_bytes) as u32;
+                for x in (0..dimensions.w).step_by(TILE_SIZE as usize) {
+                    let width = TILE_SIZE.min(dimensions.w - x);
+                    let tile = slide.read_region(Region {
+                        address: Address {
+                            x: (f64::from(x) * downsample).round() as u32,
+                            y: (f64::from(*y) * downsample).round() as u32,
+                        },
+                        level: level as _,
+                        size: Size {
+                            w: width,
+                            h: height,
+                        },
+                    })?;
+                    for (tile_x, tile_y, pixel) in tile.enumerate_pixels() {
+                        let offset = tile_y as usize * row_bytes + (x + tile_x) as usize * 3;
+                        band[offset..offset + 3].copy_from_slice(&pixel.0[..3]);
+                    }
+                }
+                Ok(())
+            },
+        )?;
+    }
+
+    mmap.flush()?;
+    Ok(LevelMemmap {
+        mmap: mmap.make_read_only()?,
+        offset: header.len(),
+        dimensions,
+    })
+}
+
+/// Build the header of a version 1.0 NPY file holding a `(y, x, c)` array of `u8`.
+fn npy_header(dimensions: Size) -> Vec<u8> {
+    let mut dictionary = format!(
+        "{{'descr': '|u1', 'fortran_order': False, 'shape': ({}, {}, 3), }}",
+        dimensions.h, dimensions.w
+    );
+    // Magic string, version and header length come first; the header ends with a
+    // newline
+    let unpadded = 10 + dictionary.len() + 1;
+    let padding = (NPY_ALIGNMENT - unpadded % NPY_ALIGNMENT) % NPY_ALIGNMENT;
+    dictionary.push_str(&" ".repeat(padding));
+    dictionary.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dictionary.len() as u16).to_le_bytes());
+    header.extend_from_slice(dictionary.as_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy_header() {
+        let header = npy_header(Size { w: 300, h: 250 });
+        assert_eq!(header.len() % NPY_ALIGNMENT, 0);
+        assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!(
+            usize::from(u16::from_le_bytes([header[8], header[9]])),
+            header.len() - 10
+        );
+        let dictionary = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(dictionary
+            .starts_with("{'descr': '|u1', 'fortran_order': False, 'shape': (250, 300, 3), }"));
+        assert!(dictionary.ends_with(" \n"));
+    }
+}
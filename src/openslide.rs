@@ -2,19 +2,28 @@ use std::cmp::Ordering;
 
 use std::ffi::{CStr, CString};
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str;
 
-use image::imageops::{resize, FilterType};
-use image::RgbaImage;
+use image::imageops::resize;
+use image::{GrayImage, Luma, Rgb, RgbaImage};
 use openslide_sys as sys;
+use serde::{Deserialize, Serialize};
 use std::ptr::null_mut;
 
-use crate::utils::{decode_buffer, parse_null_terminated_array, resize_dimensions};
+use crate::deepzoom::{slide_bounds, ResizeFilter};
+use crate::encode::{encode, Format};
+use crate::grid::window_starts;
+use crate::tiff::{TiffFile, TAG_ICC_PROFILE};
+use crate::tissue::{mask_bounds, tissue_mask, TissueParams};
+use crate::utils::{
+    composite_buffer, decode_buffer, decode_buffer_into, parse_null_terminated_array,
+    resize_dimensions,
+};
 use crate::{OpenSlideError, Result};
 
 /// A basic x/y type
-#[derive(Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Address {
     /// x coordinate
     pub x: u32,
@@ -41,7 +50,7 @@ where
 }
 
 /// A basic width/height type.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Size {
     /// Height
     pub h: u32,
@@ -72,13 +81,46 @@ pub struct Region {
     pub size: Size,
 }
 
+/// The standard properties of a whole slide image, parsed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlideProperties {
+    /// The slide format vendor
+    pub vendor: Option<String>,
+    /// The `openslide.quickhash-1` property, identifying the slide content
+    pub quickhash: Option<String>,
+    /// The level 0 resolution in micrometers per pixel along the x and y axes
+    pub mpp: Option<(f32, f32)>,
+    /// The magnification of the scanner objective
+    pub objective_power: Option<f32>,
+    /// The level 0 offset and dimensions of the non-empty slide region
+    pub bounds: Option<(Address, Size)>,
+    /// The slide levels, from the largest to the smallest
+    pub levels: Vec<LevelProperties>,
+}
+
+/// The properties of a whole slide image level.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelProperties {
+    /// The size of the level in pixels
+    pub dimensions: Size,
+    /// The downsample factor relative to level 0
+    pub downsample: f32,
+    /// The size of the tiles the level is stored in, if tiled
+    pub tile_size: Option<Size>,
+}
+
 /// The main OpenSlide type.
 pub struct OpenSlide {
     data: *mut sys::_openslide,
+    path: PathBuf,
 }
 
 unsafe impl Send for OpenSlide {}
 
+// libopenslide handles are thread-safe: concurrent reads on the same handle are
+// synchronized by the C library.
+unsafe impl Sync for OpenSlide {}
+
 impl Drop for OpenSlide {
     fn drop(&mut self) {
         unsafe {
@@ -88,6 +130,51 @@ impl Drop for OpenSlide {
     }
 }
 
+/// A tile cache, shared by the slides it is attached to with
+/// [`set_cache()`](struct.OpenSlide.html#method.set_cache), so that memory stays
+/// bounded however many slides are open.
+///
+/// The cache is freed once it is dropped and every slide using it is closed.
+pub struct Cache {
+    data: *mut sys::openslide_cache_t,
+    capacity: usize,
+}
+
+// libopenslide caches are synchronized by the C library.
+unsafe impl Send for Cache {}
+
+unsafe impl Sync for Cache {}
+
+impl Cache {
+    /// Create a cache of `capacity` bytes.
+    pub fn new(capacity: usize) -> Cache {
+        let data = unsafe { sys::openslide_cache_create(capacity as _) };
+        Cache { data, capacity }
+    }
+
+    /// The capacity of the cache, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        unsafe {
+            sys::openslide_cache_release(self.data);
+        }
+        self.data = null_mut();
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
 /// # Examples
 ///
 /// ```
@@ -159,11 +246,39 @@ impl OpenSlide {
         }
         get_error(slide_ptr)?;
 
-        let slide = OpenSlide { data: slide_ptr };
+        let slide = OpenSlide {
+            data: slide_ptr,
+            path: path.to_path_buf(),
+        };
 
         Ok(slide)
     }
 
+    /// Open the file of the slide again, as a new handle.
+    ///
+    /// The C library errors are sticky: once an operation fails, every later
+    /// operation on the handle fails with the same error. A new handle recovers from
+    /// such an [`error()`](struct.OpenSlide.html#method.error); its cache is a new one
+    /// of the default size.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::MissingFile`](enum.OpenSlideError.html#variant.MissingFile): the file no longer exists
+    /// * [`OpenSlideError::UnsupportedFile`](enum.OpenSlideError.html#variant.UnsupportedFile): the file is no longer a valid whole slide image.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn reopen(&self) -> Result<OpenSlide> {
+        OpenSlide::open(&self.path)
+    }
+
+    /// Get the error the handle is stuck on, if any: once set, every operation on
+    /// the handle fails with it until the slide is [reopened](struct.OpenSlide.html#method.reopen).
+    pub fn error(&self) -> Option<String> {
+        match get_error(self.data) {
+            Err(OpenSlideError::InternalError(message)) => Some(message),
+            _ => None,
+        }
+    }
+
     /// Set the cache size of the whole slide image
     ///
     /// # Arguments
@@ -174,9 +289,23 @@ impl OpenSlide {
     ///
     /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
     pub fn set_cache_size(&mut self, cache_size: u32) -> Result<()> {
+        // The slide keeps its own reference to the cache
+        self.set_cache(&Cache::new(cache_size as _))
+    }
+
+    /// Attach a cache to the whole slide image, replacing its current cache. A cache
+    /// can be shared by many slides.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache`: the cache.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn set_cache(&mut self, cache: &Cache) -> Result<()> {
         unsafe {
-            let cache = sys::openslide_cache_create(cache_size as _);
-            sys::openslide_set_cache(self.data, cache);
+            sys::openslide_set_cache(self.data, cache.data);
         }
         get_error(self.data)
     }
@@ -311,13 +440,91 @@ impl OpenSlide {
     /// ```
     ///
     pub fn read_region(&self, region: Region) -> Result<RgbaImage> {
+        let size = region.size;
+        let dest = self.read_region_raw(region)?;
+
+        Ok(decode_buffer(&dest, size.w, size.h))
+    }
+
+    /// Read a region into a caller-provided buffer, as RGBA pixels in row-major
+    /// order, so that repeated reads can reuse the same memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `region`: the coordinates of the region to read.
+    /// * `dest`: the buffer, of exactly 4 bytes per pixel of the region.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `dest` is not of the size of the region.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn read_region_into(&self, region: Region, dest: &mut [u8]) -> Result<()> {
+        let size = region.size;
+        let expected = 4 * size.w as usize * size.h as usize;
+        if dest.len() != expected {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Buffer of {} bytes cannot hold a {}x{} region of {} bytes",
+                dest.len(),
+                size.w,
+                size.h,
+                expected
+            )));
+        }
+        let buffer = self.read_region_raw(region)?;
+
+        decode_buffer_into(&buffer, dest);
+        Ok(())
+    }
+
+    /// Read a region along with the mask of its pixels holding slide data.
+    ///
+    /// Formats such as MIRAX leave large areas of the slide without any data, which
+    /// the C library returns as transparent pixels. The pixels are composited over
+    /// the slide [background color](struct.OpenSlide.html#method.background_color),
+    /// so that these areas look like glass, while the mask tells them apart from
+    /// actual white tissue: pixels with data are 255, pixels without 0, from the
+    /// alpha channel before compositing.
+    ///
+    /// # Arguments
+    ///
+    /// * `region`: the coordinates of the region to read.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn read_region_with_coverage(&self, region: Region) -> Result<(RgbaImage, GrayImage)> {
+        let size = region.size;
+        let buffer = self.read_region_raw(region)?;
+
+        let mut coverage = GrayImage::new(size.w, size.h);
+        for (value, pixel) in buffer.iter().zip(coverage.pixels_mut()) {
+            *pixel = Luma([if value >> 24 == 0 { 0 } else { 255 }]);
+        }
+        let pixels = composite_buffer(&buffer, size.w, size.h, self.background_color()?);
+        Ok((pixels, coverage))
+    }
+
+    /// Read a region as returned by the C library, without decoding it: one
+    /// premultiplied `0xAARRGGBB` pixel per value, in row-major order.
+    ///
+    /// This skips the per-pixel conversion of [`read_region()`](struct.OpenSlide.html#method.read_region),
+    /// for callers converting the pixels themselves, such as on a GPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `region`: the coordinates of the region to read.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn read_region_raw(&self, region: Region) -> Result<Vec<u32>> {
         let Region {
             address,
             level,
             size,
         } = region;
 
-        let mut dest = vec![0u32; (size.w * size.h) as _];
+        let mut dest = vec![0u32; size.w as usize * size.h as usize];
 
         unsafe {
             openslide_sys::openslide_read_region(
@@ -332,7 +539,48 @@ impl OpenSlide {
         }
         get_error(self.data)?;
 
-        Ok(decode_buffer(&dest, size.w, size.h))
+        Ok(dest)
+    }
+
+    /// Read a region of a whole slide image and encode it into the given format.
+    ///
+    /// # Arguments
+    ///
+    /// * `region`: the coordinates of the region to read.
+    /// * `format`: the output image format.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the format quality is not in the 1-100 range.
+    /// * [`OpenSlideError::ImageError`](enum.OpenSlideError.html#variant.ImageError): the region could not be encoded.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use openslide_rs::{OpenSlide, OpenSlideError, Region, Address, Size, Format};
+    ///
+    /// fn main() -> Result<(), OpenSlideError> {
+    ///     let path = Path::new("tests/assets/default.svs");
+    ///     let slide = OpenSlide::open(&path)?;
+    ///
+    ///     let jpeg = slide.read_region_encoded(
+    ///         Region {
+    ///             address: Address { x: 512, y: 512 },
+    ///             level: 0,
+    ///             size: Size { w: 512, h: 512 },
+    ///         },
+    ///         Format::Jpeg { quality: 90 },
+    ///     )?;
+    ///     std::fs::write("tests/artifacts/example_read_region.jpg", jpeg).unwrap();
+    ///
+    ///     Ok(())
+    ///  }
+    /// ```
+    ///
+    pub fn read_region_encoded(&self, region: Region, format: Format) -> Result<Vec<u8>> {
+        encode(&self.read_region(region)?, format)
     }
 
     /// Get the property names vector.Address
@@ -388,6 +636,136 @@ impl OpenSlide {
         Ok(value)
     }
 
+    /// Get the slide background color.
+    ///
+    /// This is the color of the `openslide.background-color` property, or white if the
+    /// property is missing or invalid.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn background_color(&self) -> Result<Rgb<u8>> {
+        let color = self
+            .property("openslide.background-color")?
+            .filter(|value| value.len() == 6)
+            .and_then(|value| u32::from_str_radix(&value, 16).ok())
+            .map(|value| Rgb([(value >> 16) as u8, (value >> 8) as u8, value as u8]))
+            .unwrap_or(Rgb([255, 255, 255]));
+
+        Ok(color)
+    }
+
+    /// Get the level 0 resolution of the slide in micrometers per pixel along the x
+    /// and y axes.
+    ///
+    /// This is the resolution of the `openslide.mpp-x` and `openslide.mpp-y`
+    /// properties, or `None` if either is missing or not a positive number.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn mpp(&self) -> Result<Option<(f32, f32)>> {
+        let mpp_x = self.positive_property("openslide.mpp-x")?;
+        let mpp_y = self.positive_property("openslide.mpp-y")?;
+        Ok(mpp_x.zip(mpp_y))
+    }
+
+    /// Get the magnification of the objective the slide was scanned with.
+    ///
+    /// This is the `openslide.objective-power` property, or `None` if it is missing or
+    /// not a positive number.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn magnification(&self) -> Result<Option<f32>> {
+        self.positive_property("openslide.objective-power")
+    }
+
+    /// Get a property parsed as a finite positive number, `None` if it is missing or
+    /// invalid.
+    pub(crate) fn positive_property(&self, name: &str) -> Result<Option<f32>> {
+        Ok(self
+            .property(name)?
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|value| value.is_finite() && *value > 0.0))
+    }
+
+    /// Get the level 0 offset and dimensions of the non-empty slide region.
+    ///
+    /// This is the region of the `openslide.bounds-*` properties, or `None` if the
+    /// slide has none of them. Invalid values are ignored, an axis falling back to
+    /// the whole slide.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn bounds(&self) -> Result<Option<(Address, Size)>> {
+        let names = self.property_names()?;
+        if !names
+            .iter()
+            .any(|name| name.starts_with("openslide.bounds-"))
+        {
+            return Ok(None);
+        }
+        slide_bounds(self).map(Some)
+    }
+
+    /// Get the standard properties of the slide, parsed.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn slide_properties(&self) -> Result<SlideProperties> {
+        let levels = (0..self.level_count()?)
+            .map(|level| {
+                let tile_width =
+                    self.positive_property(&format!("openslide.level[{}].tile-width", level))?;
+                let tile_height =
+                    self.positive_property(&format!("openslide.level[{}].tile-height", level))?;
+                Ok(LevelProperties {
+                    dimensions: self.level_dimensions(level)?,
+                    downsample: self.level_downsample(level)?,
+                    tile_size: tile_width.zip(tile_height).map(|(w, h)| Size {
+                        w: w as u32,
+                        h: h as u32,
+                    }),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(SlideProperties {
+            vendor: self.property("openslide.vendor")?,
+            quickhash: self.property("openslide.quickhash-1")?,
+            mpp: self.mpp()?,
+            objective_power: self.magnification()?,
+            bounds: self.bounds()?,
+            levels,
+        })
+    }
+
+    /// Get the ICC color profile of the slide levels, if any.
+    ///
+    /// The profile is read from the first image directory of TIFF based slides. Other
+    /// formats, and slides without profile, return `None`.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the slide file could not be read.
+    pub fn icc_profile(&self) -> Result<Option<Vec<u8>>> {
+        let mut tiff = match TiffFile::open(&self.path, false)? {
+            Some(tiff) => tiff,
+            None => return Ok(None),
+        };
+        let directories = tiff.directories()?;
+        match directories
+            .first()
+            .and_then(|directory| directory.entry(TAG_ICC_PROFILE))
+        {
+            Some(entry) => Ok(Some(tiff.read_raw(entry)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Get the associated image names vector.
     ///
     /// Certain vendor-specific associated images may exist within a whole slide image. They are
@@ -406,17 +784,17 @@ impl OpenSlide {
         }
     }
 
-    /// Reads and decompresses an associated image associated with a whole slide image.
+    /// Get the dimensions of an associated image, without decoding it.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the desired associated image. Must be a valid name
-    /// as given by [`associated_image_names()`](struct.OpenSlide.html#method.associated_image_names).
+    /// * `name` - The name of the desired associated image, as given by
+    /// [`associated_image_names()`](struct.OpenSlide.html#method.associated_image_names).
     ///
     /// # Errors
     ///
     /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
-    pub fn associated_image(&self, name: &str) -> Result<Option<RgbaImage>> {
+    pub fn associated_image_dimensions(&self, name: &str) -> Result<Option<Size>> {
         if !self.associated_image_names()?.iter().any(|n| n == name) {
             return Ok(None);
         };
@@ -433,20 +811,67 @@ impl OpenSlide {
                 &mut h,
             );
         }
-
         get_error(self.data)?;
 
-        let mut dest = vec![0u32; (w * h) as _];
+        Ok(Some(Size {
+            w: w as _,
+            h: h as _,
+        }))
+    }
+
+    /// Reads and decompresses an associated image associated with a whole slide image.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the desired associated image. Must be a valid name
+    /// as given by [`associated_image_names()`](struct.OpenSlide.html#method.associated_image_names).
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn associated_image(&self, name: &str) -> Result<Option<RgbaImage>> {
+        let Size { w, h } = match self.associated_image_dimensions(name)? {
+            Some(dimensions) => dimensions,
+            None => return Ok(None),
+        };
+        let cstr = CString::new(name).unwrap();
+
+        let mut dest = vec![0u32; w as usize * h as usize];
 
         unsafe {
             sys::openslide_read_associated_image(self.data, cstr.as_ptr(), dest.as_mut_ptr());
         }
         get_error(self.data)?;
 
-        Ok(Some(decode_buffer(&dest, w as _, h as _)))
+        Ok(Some(decode_buffer(&dest, w, h)))
     }
 
+    /// Get a thumbnail of the slide fitting in `size`, preserving its aspect ratio,
+    /// resized with a Lanczos filter.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
     pub fn thumbnail(&self, size: Size) -> Result<RgbaImage> {
+        self.thumbnail_with_filter(size, ResizeFilter::Lanczos3)
+    }
+
+    /// Get a thumbnail of the slide fitting in `size`, preserving its aspect ratio,
+    /// resized with the given filter.
+    ///
+    /// The thumbnail is resized from the whole level best matching its
+    /// downsample: [`Nearest`](enum.ResizeFilter.html#variant.Nearest) trades
+    /// quality for speed on large levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `size`: the largest width and height of the thumbnail.
+    /// * `filter`: the resize filter.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn thumbnail_with_filter(&self, size: Size, filter: ResizeFilter) -> Result<RgbaImage> {
         let dimensions = self.dimensions()?;
         let downsample_w = dimensions.w as f32 / size.w as f32;
         let downsample_h = dimensions.h as f32 / size.h as f32;
@@ -467,7 +892,104 @@ impl OpenSlide {
 
         let (new_width, new_height) =
             resize_dimensions(tile.width(), tile.height(), size.w, size.h, false);
-        Ok(resize(&tile, new_width, new_height, FilterType::Lanczos3))
+        Ok(resize(&tile, new_width, new_height, filter.into()))
+    }
+
+    /// Get the tight level 0 bounding box of the tissue, as detected by
+    /// [`tissue_mask()`](tissue/fn.tissue_mask.html), to crop exports to the
+    /// non-empty part of the slide.
+    ///
+    /// The box is rounded outwards to whole mask pixels. A slide without tissue
+    /// gives an empty region at the origin.
+    ///
+    /// # Arguments
+    ///
+    /// * `params`: the tissue detection parameters.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `thumbnail_size` is 0.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn content_bounds(&self, params: TissueParams) -> Result<Region> {
+        let mask = tissue_mask(self, params)?;
+        Ok(mask_bounds(&mask, self.dimensions()?))
+    }
+
+    /// Slide a window over a level, row by row, for inference sweeps.
+    ///
+    /// Each window is given by its top left corner in level pixels, for stitching the
+    /// outputs back together, and by the region to read with
+    /// [`read_region()`](struct.OpenSlide.html#method.read_region).
+    ///
+    /// Windows start every `stride` pixels; when the stride does not end exactly on
+    /// the level edge, a last column and row of windows is aligned to it, so that
+    /// the edges are reached without any window extending past the level. Only a
+    /// level smaller than the window gives windows extending past its edge, which
+    /// read as transparent pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `level`: the level to sweep.
+    /// * `window_size`: the width and height of the windows, in level pixels.
+    /// * `stride`: the distance between consecutive windows, in level pixels.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `window_size` or `stride` is 0.
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn windows(
+        &self,
+        level: u32,
+        window_size: u32,
+        stride: u32,
+    ) -> Result<impl Iterator<Item = (Address, Region)>> {
+        if window_size == 0 || stride == 0 {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Window size {} and stride {} must be positive",
+                window_size, stride
+            )));
+        }
+
+        let dimensions = self.level_dimensions(level)?;
+        let downsample = f64::from(self.level_downsample(level)?);
+        let xs = window_starts(dimensions.w, window_size, stride);
+        let ys = window_starts(dimensions.h, window_size, stride);
+
+        Ok(ys.into_iter().flat_map(move |y| {
+            xs.clone().into_iter().map(move |x| {
+                let region = Region {
+                    address: Address {
+                        x: (f64::from(x) * downsample).round() as u32,
+                        y: (f64::from(y) * downsample).round() as u32,
+                    },
+                    level: level as _,
+                    size: Size {
+                        w: window_size,
+                        h: window_size,
+                    },
+                };
+                (Address { x, y }, region)
+            })
+        }))
+    }
+
+    /// Slide a window over a level like [`windows()`](struct.OpenSlide.html#method.windows),
+    /// reading the pixels of each window as it is reached.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`windows()`](struct.OpenSlide.html#method.windows); reading a
+    /// window yields the errors of [`read_region()`](struct.OpenSlide.html#method.read_region).
+    pub fn read_windows(
+        &self,
+        level: u32,
+        window_size: u32,
+        stride: u32,
+    ) -> Result<impl Iterator<Item = Result<(Address, RgbaImage)>> + '_> {
+        Ok(self
+            .windows(level, window_size, stride)?
+            .map(move |(address, region)| Ok((address, self.read_region(region)?))))
     }
 }
 
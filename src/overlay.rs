@@ -0,0 +1,197 @@
+//! This module provides heatmap overlays: model outputs over a slide, upsampled,
+//! colored and blended onto the slide pixels for visual inspection.
+
+use image::{Rgb, Rgba, RgbaImage};
+use ndarray::ArrayView2;
+use serde::{Deserialize, Serialize};
+
+use crate::openslide::{OpenSlide, Region};
+use crate::{OpenSlideError, Result};
+
+/// The viridis colormap, sampled at regular intervals.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+/// A mapping from heatmap values to colors.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Colormap {
+    /// Perceptually uniform dark purple to yellow
+    Viridis,
+    /// Blue to red through cyan, green and yellow
+    Jet,
+    /// Black to white
+    Gray,
+}
+
+impl Colormap {
+    /// The color of `value`, clamped between 0 and 1.
+    pub fn color(&self, value: f32) -> Rgb<u8> {
+        let value = value.clamp(0., 1.);
+        match self {
+            Colormap::Viridis => {
+                let position = value * (VIRIDIS.len() - 1) as f32;
+                let index = (position.floor() as usize).min(VIRIDIS.len() - 2);
+                let t = position - index as f32;
+                let (start, end) = (VIRIDIS[index], VIRIDIS[index + 1]);
+                let channel = |c: usize| {
+                    (f32::from(start[c]) * (1. - t) + f32::from(end[c]) * t).round() as u8
+                };
+                Rgb([channel(0), channel(1), channel(2)])
+            }
+            Colormap::Jet => {
+                let channel = |center: f32| {
+                    ((1.5 - (4. * value - center).abs()).clamp(0., 1.) * 255.).round() as u8
+                };
+                Rgb([channel(3.), channel(2.), channel(1.)])
+            }
+            Colormap::Gray => {
+                let gray = (value * 255.).round() as u8;
+                Rgb([gray, gray, gray])
+            }
+        }
+    }
+}
+
+/// Read a slide region and blend a heatmap onto it.
+///
+/// The heatmap covers the whole slide, its rows spanning the slide height and its
+/// columns the slide width, each cell holding a value from 0 to 1 such as a model
+/// prediction for the patch under it. It is bilinearly upsampled to the region
+/// pixels; pixels near a `NaN` cell, meaning no value, are left as is.
+///
+/// The slide pixels are composited over white, so that the result is opaque.
+///
+/// # Arguments
+///
+/// * `slide` - the slide.
+/// * `region` - the region to render.
+/// * `heatmap` - the values over the whole slide.
+/// * `colormap` - the colors of the values.
+/// * `alpha` - the opacity of the heatmap, from 0 to 1.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the heatmap is empty, or `alpha` is not between 0 and 1.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn render(
+    slide: &OpenSlide,
+    region: Region,
+    heatmap: ArrayView2<f32>,
+    colormap: Colormap,
+    alpha: f32,
+) -> Result<RgbaImage> {
+    if heatmap.is_empty() {
+        return Err(OpenSlideError::InvalidArgument(
+            "Heatmap is empty".to_string(),
+        ));
+    }
+    if !(0.0..=1.0).contains(&alpha) {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Alpha {} must be between 0 and 1",
+            alpha
+        )));
+    }
+
+    let dimensions = slide.dimensions()?;
+    let downsample = f64::from(slide.level_downsample(region.level as u32)?);
+    let address = region.address;
+    let mut image = slide.read_region(region)?;
+
+    let (rows, columns) = heatmap.dim();
+    let scale = (
+        columns as f64 / f64::from(dimensions.w),
+        rows as f64 / f64::from(dimensions.h),
+    );
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        // The level 0 position of the pixel center, in heatmap cells
+        let column = (f64::from(address.x) + (f64::from(x) + 0.5) * downsample) * scale.0;
+        let row = (f64::from(address.y) + (f64::from(y) + 0.5) * downsample) * scale.1;
+
+        let [r, g, b, a] = pixel.0;
+        let opacity = f32::from(a) / 255.;
+        let over_white = |channel: u8| f32::from(channel) * opacity + 255. * (1. - opacity);
+        let mut rgb = [over_white(r), over_white(g), over_white(b)];
+
+        let value = sample(&heatmap, row - 0.5, column - 0.5);
+        if !value.is_nan() {
+            let color = colormap.color(value).0;
+            for (channel, color) in rgb.iter_mut().zip(color.iter()) {
+                *channel = *channel * (1. - alpha) + f32::from(*color) * alpha;
+            }
+        }
+        *pixel = Rgba([
+            rgb[0].round() as u8,
+            rgb[1].round() as u8,
+            rgb[2].round() as u8,
+            255,
+        ]);
+    }
+    Ok(image)
+}
+
+/// Bilinearly interpolate the heatmap at fractional cell coordinates, clamped to
+/// the heatmap edges. The result is `NaN` if a cell with a non-zero weight is.
+fn sample(heatmap: &ArrayView2<f32>, row: f64, column: f64) -> f32 {
+    let (rows, columns) = heatmap.dim();
+    let axis = |position: f64, limit: usize| {
+        let position = position.clamp(0., (limit - 1) as f64);
+        let start = position.floor() as usize;
+        let end = (start + 1).min(limit - 1);
+        let t = (position - start as f64) as f32;
+        [(start, 1. - t), (end, t)]
+    };
+
+    let mut value = 0.;
+    for (row, row_weight) in axis(row, rows) {
+        for (column, column_weight) in axis(column, columns) {
+            let weight = row_weight * column_weight;
+            if weight > 0. {
+                value += heatmap[[row, column]] * weight;
+            }
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_colormap() {
+        assert_eq!(Colormap::Viridis.color(0.), Rgb([68, 1, 84]));
+        assert_eq!(Colormap::Viridis.color(1.), Rgb([253, 231, 37]));
+        assert_eq!(Colormap::Viridis.color(0.5), Rgb([33, 144, 141]));
+        assert_eq!(Colormap::Jet.color(0.), Rgb([0, 0, 128]));
+        assert_eq!(Colormap::Jet.color(0.5), Rgb([128, 255, 128]));
+        assert_eq!(Colormap::Jet.color(1.), Rgb([128, 0, 0]));
+        assert_eq!(Colormap::Gray.color(2.), Rgb([255, 255, 255]));
+        assert_eq!(Colormap::Gray.color(-1.), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_sample() {
+        let heatmap = array![[0., 1.], [1., 2.]];
+        let view = heatmap.view();
+        assert_eq!(sample(&view, 0., 0.), 0.);
+        assert_eq!(sample(&view, 0., 0.5), 0.5);
+        assert_eq!(sample(&view, 0.5, 0.5), 1.);
+        // Clamped to the edges
+        assert_eq!(sample(&view, -3., -3.), 0.);
+        assert_eq!(sample(&view, 5., 5.), 2.);
+
+        let missing = array![[0., f32::NAN]];
+        assert!(sample(&missing.view(), 0., 0.5).is_nan());
+        assert_eq!(sample(&missing.view(), 0., 0.), 0.);
+    }
+}
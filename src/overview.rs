@@ -0,0 +1,174 @@
+//! This module provides slide overviews: small renderings of the whole slide with
+//! the detected tissue outlined, for dataset QC galleries and viewer minimaps.
+
+use std::ops::Deref;
+
+use image::{Rgba, RgbaImage};
+
+use crate::deepzoom::DeepZoom;
+use crate::openslide::{OpenSlide, Region, Size};
+use crate::tissue::TissueParams;
+use crate::{OpenSlideError, Result};
+
+/// The color of the tissue bounding box.
+pub const BOUNDS_COLOR: Rgba<u8> = Rgba([220, 20, 60, 255]);
+
+/// The color of the Deep Zoom tile grid.
+pub const GRID_COLOR: Rgba<u8> = Rgba([30, 144, 255, 255]);
+
+/// The thickness of the tissue bounding box, in overview pixels.
+const BOUNDS_THICKNESS: u32 = 2;
+
+/// A whole slide overview.
+#[derive(Debug, PartialEq)]
+pub struct OverviewImage {
+    /// The rendering
+    pub image: RgbaImage,
+    /// The overview pixels per level 0 pixel, along the x and y axes
+    pub scale: (f64, f64),
+    /// The level 0 bounding box of the tissue, as given by
+    /// [`OpenSlide::content_bounds()`](struct.OpenSlide.html#method.content_bounds)
+    pub tissue_bounds: Region,
+}
+
+impl OverviewImage {
+    /// Draw the tile grid of a Deep Zoom level, as one pixel wide lines.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): level out of range.
+    pub fn draw_grid<S: Deref<Target = OpenSlide>>(
+        &mut self,
+        deepzoom: &DeepZoom<S>,
+        level: usize,
+    ) -> Result<()> {
+        let dimensions = *deepzoom
+            .level_dimensions()
+            .get(level)
+            .ok_or_else(|| OpenSlideError::IndexError(level.to_string()))?;
+        let offset = deepzoom.l0_offset();
+        let l0_dimensions = deepzoom.l0_dimensions();
+        let tile_size = f64::from(deepzoom.tile_edge());
+
+        // Tile edges along an axis, in overview pixels, the last one inside the image
+        let edges = |size: u32, l0_size: u32, l0_offset: u32, scale: f64, limit: u32| {
+            let downsample = f64::from(l0_size) / f64::from(size);
+            let tiles = (f64::from(size) / tile_size).ceil() as u32;
+            (0..=tiles)
+                .map(|tile| {
+                    let l0 = f64::from(l0_offset)
+                        + (f64::from(tile) * tile_size * downsample).min(f64::from(l0_size));
+                    ((l0 * scale).round() as u32).min(limit.saturating_sub(1))
+                })
+                .collect::<Vec<u32>>()
+        };
+        let (width, height) = self.image.dimensions();
+        let xs = edges(dimensions.w, l0_dimensions.w, offset.x, self.scale.0, width);
+        let ys = edges(
+            dimensions.h,
+            l0_dimensions.h,
+            offset.y,
+            self.scale.1,
+            height,
+        );
+        let (left, right) = (xs[0], *xs.last().unwrap());
+        let (top, bottom) = (ys[0], *ys.last().unwrap());
+
+        for x in &xs {
+            fill(&mut self.image, *x, top, *x + 1, bottom + 1, GRID_COLOR);
+        }
+        for y in &ys {
+            fill(&mut self.image, left, *y, right + 1, *y + 1, GRID_COLOR);
+        }
+        Ok(())
+    }
+}
+
+/// Render an overview of a slide, at most `max_size` pixels wide and high, with the
+/// tissue bounding box drawn on it.
+///
+/// Draw a Deep Zoom tile grid on top with
+/// [`OverviewImage::draw_grid()`](struct.OverviewImage.html#method.draw_grid).
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `max_size` is 0.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn overview(slide: &OpenSlide, max_size: u32) -> Result<OverviewImage> {
+    if max_size == 0 {
+        return Err(OpenSlideError::InvalidArgument(
+            "Overview size must be positive".to_string(),
+        ));
+    }
+
+    let mut image = slide.thumbnail(Size {
+        w: max_size,
+        h: max_size,
+    })?;
+    let dimensions = slide.dimensions()?;
+    let scale = (
+        f64::from(image.width()) / f64::from(dimensions.w),
+        f64::from(image.height()) / f64::from(dimensions.h),
+    );
+
+    let tissue_bounds = slide.content_bounds(TissueParams::default())?;
+    if tissue_bounds.size.w > 0 && tissue_bounds.size.h > 0 {
+        let to_overview = |l0: u32, scale: f64| (f64::from(l0) * scale).round() as u32;
+        let left = to_overview(tissue_bounds.address.x, scale.0);
+        let top = to_overview(tissue_bounds.address.y, scale.1);
+        let right = to_overview(tissue_bounds.address.x + tissue_bounds.size.w, scale.0);
+        let bottom = to_overview(tissue_bounds.address.y + tissue_bounds.size.h, scale.1);
+        let t = BOUNDS_THICKNESS;
+
+        fill(&mut image, left, top, right, top + t, BOUNDS_COLOR);
+        fill(
+            &mut image,
+            left,
+            bottom.saturating_sub(t),
+            right,
+            bottom,
+            BOUNDS_COLOR,
+        );
+        fill(&mut image, left, top, left + t, bottom, BOUNDS_COLOR);
+        fill(
+            &mut image,
+            right.saturating_sub(t),
+            top,
+            right,
+            bottom,
+            BOUNDS_COLOR,
+        );
+    }
+
+    Ok(OverviewImage {
+        image,
+        scale,
+        tissue_bounds,
+    })
+}
+
+/// Fill the rectangle from `(left, top)` included to `(right, bottom)` excluded,
+/// clipped to the image.
+fn fill(image: &mut RgbaImage, left: u32, top: u32, right: u32, bottom: u32, color: Rgba<u8>) {
+    for y in top..bottom.min(image.height()) {
+        for x in left..right.min(image.width()) {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        fill(&mut image, 1, 2, 10, 3, GRID_COLOR);
+
+        assert_eq!(*image.get_pixel(0, 2), Rgba([0, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(1, 2), GRID_COLOR);
+        assert_eq!(*image.get_pixel(3, 2), GRID_COLOR);
+        assert_eq!(*image.get_pixel(1, 3), Rgba([0, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,519 @@
+//! This module provides patch sampling: the coordinates of fixed size patches at a
+//! target resolution, restricted to tissue, as used to build training datasets.
+
+use std::ops::Deref;
+
+use image::imageops::{self, resize, FilterType};
+use image::{GrayImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::annotation::{AnnotationSet, Point};
+use crate::deepzoom::{slide_bounds, slide_mpp};
+use crate::loader::PatchLoader;
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::pyramid::{parallel_map, Parallelism};
+use crate::tissue::{tissue_mask, TissueParams, TISSUE};
+use crate::{OpenSlideError, Result};
+
+/// A patch selected by a [`PatchSampler`](struct.PatchSampler.html).
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Patch {
+    /// The level 0 position of the top left corner
+    pub address: Address,
+    /// The slide level the patch is read from
+    pub level: usize,
+    /// The size of the region read from that level, before scaling to the patch size
+    pub size: Size,
+    /// The share of the patch covered by tissue, from 0 to 1
+    pub tissue_fraction: f32,
+    /// The share of the patch covered by annotations, from 0 to 1, once restricted
+    /// with [`PatchSampler::restrict_to_annotations()`](struct.PatchSampler.html#method.restrict_to_annotations)
+    #[serde(default)]
+    pub annotation_fraction: Option<f32>,
+}
+
+impl Patch {
+    /// The slide region to read.
+    pub fn region(&self) -> Region {
+        Region {
+            address: self.address,
+            level: self.level,
+            size: self.size,
+        }
+    }
+}
+
+/// Patches of a slide, at a target resolution and restricted to tissue.
+///
+/// The sampler is generic over how it holds the slide, as
+/// [`DeepZoom`](struct.DeepZoom.html) is.
+#[derive(Clone)]
+pub struct PatchSampler<S: Deref<Target = OpenSlide>> {
+    slide: S,
+    patch_size: u32,
+    downsample: f64,
+    patches: Vec<Patch>,
+}
+
+impl<S: Deref<Target = OpenSlide>> PatchSampler<S> {
+    /// Sample patches on a regular grid, keeping those covered enough by the
+    /// [tissue mask](tissue/fn.tissue_mask.html) computed with the default
+    /// parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `slide` - a slide, either borrowed or behind a smart pointer such as `Arc`.
+    /// * `patch_size` - the width and height of a patch, in pixels at `target_mpp`.
+    /// * `stride` - the distance between two patches, in pixels at `target_mpp`.
+    /// * `target_mpp` - the resolution of the patches in micrometers per pixel, or
+    /// `None` for the slide level 0 resolution.
+    /// * `min_tissue_fraction` - the share of a patch that must be tissue, from 0 to
+    /// 1: 0 keeps every patch.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `patch_size` or `stride` is 0, `target_mpp` is not positive or the slide has no valid MPP properties, or `min_tissue_fraction` is not between 0 and 1.
+    pub fn grid(
+        slide: S,
+        patch_size: u32,
+        stride: u32,
+        target_mpp: Option<f32>,
+        min_tissue_fraction: f32,
+    ) -> Result<PatchSampler<S>> {
+        let mask = tissue_mask(&slide, TissueParams::default())?;
+        PatchSampler::grid_with_mask(
+            slide,
+            &mask,
+            patch_size,
+            stride,
+            target_mpp,
+            min_tissue_fraction,
+        )
+    }
+
+    /// Sample patches on a regular grid, keeping those covered enough by `mask`.
+    ///
+    /// The mask covers the whole slide at any resolution, tissue pixels being
+    /// [`TISSUE`](tissue/constant.TISSUE.html): a tissue mask computed with custom
+    /// parameters, or any other region of interest.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): the mask is empty, `patch_size` or `stride` is 0, `target_mpp` is not positive or the slide has no valid MPP properties, or `min_tissue_fraction` is not between 0 and 1.
+    pub fn grid_with_mask(
+        slide: S,
+        mask: &GrayImage,
+        patch_size: u32,
+        stride: u32,
+        target_mpp: Option<f32>,
+        min_tissue_fraction: f32,
+    ) -> Result<PatchSampler<S>> {
+        if patch_size == 0 || stride == 0 {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Patch size {} and stride {} must be positive",
+                patch_size, stride
+            )));
+        }
+        if !(0.0..=1.0).contains(&min_tissue_fraction) {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Tissue fraction {} must be between 0 and 1",
+                min_tissue_fraction
+            )));
+        }
+        if mask.width() == 0 || mask.height() == 0 {
+            return Err(OpenSlideError::InvalidArgument(
+                "Tissue mask is empty".to_string(),
+            ));
+        }
+
+        let downsample = match target_mpp {
+            Some(target_mpp) if !target_mpp.is_finite() || target_mpp <= 0.0 => {
+                return Err(OpenSlideError::InvalidArgument(format!(
+                    "Target MPP {} must be positive",
+                    target_mpp
+                )))
+            }
+            Some(target_mpp) => f64::from(target_mpp) / f64::from(slide_mpp(&slide)?),
+            None => 1.0,
+        };
+
+        let level = slide.best_level_for_downsample(downsample as f32)?;
+        let level_downsample = f64::from(slide.level_downsample(level)?);
+        let side = (f64::from(patch_size) * downsample / level_downsample)
+            .ceil()
+            .max(1.0) as u32;
+
+        let dimensions = slide.dimensions()?;
+        let coverage = MaskCoverage::new(mask, dimensions);
+        let extent = f64::from(patch_size) * downsample;
+        let step = f64::from(stride) * downsample;
+        let positions = |limit: u32| {
+            let count = if f64::from(limit) < extent {
+                0
+            } else {
+                ((f64::from(limit) - extent) / step).floor() as u32 + 1
+            };
+            (0..count).map(move |i| (f64::from(i) * step).round() as u32)
+        };
+
+        let mut patches = Vec::new();
+        for y in positions(dimensions.h) {
+            for x in positions(dimensions.w) {
+                let address = Address { x, y };
+                let tissue_fraction = coverage.fraction(address, extent);
+                if tissue_fraction >= min_tissue_fraction {
+                    patches.push(Patch {
+                        address,
+                        level: level as _,
+                        size: Size { w: side, h: side },
+                        tissue_fraction,
+                        annotation_fraction: None,
+                    });
+                }
+            }
+        }
+
+        Ok(PatchSampler {
+            slide,
+            patch_size,
+            downsample,
+            patches,
+        })
+    }
+
+    /// Keep only the patches covered enough by annotations, recording their
+    /// [`annotation_fraction`](struct.Patch.html#structfield.annotation_fraction).
+    ///
+    /// Select classes beforehand with
+    /// [`AnnotationSet::with_classifications()`](struct.AnnotationSet.html#method.with_classifications),
+    /// or restrict to arbitrary polygons with `AnnotationSet::from(polygons)`. The
+    /// overlap is measured on a rasterization of the annotations at a sixteenth of
+    /// the patch size.
+    ///
+    /// # Arguments
+    ///
+    /// * `annotations` - the annotations, in level 0 coordinates.
+    /// * `min_overlap` - the share of a patch that must be annotated, from 0 to 1: 0
+    /// only records the overlap.
+    /// * `limit_bounds` - true if annotation coordinates are relative to the
+    /// non-empty slide region, as exported by QuPath. See
+    /// [`AnnotationSet::rasterize()`](struct.AnnotationSet.html#method.rasterize).
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `min_overlap` is not between 0 and 1.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn restrict_to_annotations(
+        mut self,
+        annotations: &AnnotationSet,
+        min_overlap: f32,
+        limit_bounds: bool,
+    ) -> Result<PatchSampler<S>> {
+        if !(0.0..=1.0).contains(&min_overlap) {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Annotation overlap {} must be between 0 and 1",
+                min_overlap
+            )));
+        }
+
+        let dimensions = self.slide.dimensions()?;
+        let extent = f64::from(self.patch_size) * self.downsample;
+        let mask_downsample = (extent / 16.)
+            .max(f64::from(dimensions.w.max(dimensions.h)) / f64::from(MAX_MASK_SIZE))
+            .max(1.);
+        let mut mask = GrayImage::new(
+            (f64::from(dimensions.w) / mask_downsample).ceil() as u32,
+            (f64::from(dimensions.h) / mask_downsample).ceil() as u32,
+        );
+        let mut origin = Point { x: 0., y: 0. };
+        if limit_bounds {
+            let (offset, _) = slide_bounds(&self.slide)?;
+            origin.x -= f64::from(offset.x);
+            origin.y -= f64::from(offset.y);
+        }
+        annotations.fill(&mut mask, origin, mask_downsample);
+
+        let coverage = MaskCoverage::with_downsample(&mask, mask_downsample);
+        for patch in &mut self.patches {
+            patch.annotation_fraction = Some(coverage.fraction(patch.address, extent));
+        }
+        self.patches.retain(|patch| {
+            patch
+                .annotation_fraction
+                .map_or(false, |overlap| overlap >= min_overlap)
+        });
+        Ok(self)
+    }
+
+    /// Keep every `count`-th patch, starting from the `index`-th, so that `count`
+    /// workers can each read their own share of the patches.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `index` is not below `count`.
+    pub fn shard(mut self, index: usize, count: usize) -> Result<PatchSampler<S>> {
+        if index >= count {
+            return Err(OpenSlideError::InvalidArgument(format!(
+                "Shard {} is not below the shard count {}",
+                index, count
+            )));
+        }
+        self.patches = self
+            .patches
+            .into_iter()
+            .skip(index)
+            .step_by(count)
+            .collect();
+        Ok(self)
+    }
+
+    /// The slide the patches are read from.
+    pub fn slide(&self) -> &OpenSlide {
+        &self.slide
+    }
+
+    /// The width and height of a patch, in pixels at the target resolution.
+    pub fn patch_size(&self) -> u32 {
+        self.patch_size
+    }
+
+    /// The downsample of the target resolution relative to slide level 0.
+    pub fn downsample(&self) -> f64 {
+        self.downsample
+    }
+
+    /// The selected patches, in row-major order.
+    pub fn patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    /// The number of selected patches.
+    pub fn len(&self) -> usize {
+        self.patches.len()
+    }
+
+    /// Return true if no patch was selected.
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Read the pixels of a patch, scaled to the patch size.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): the region could not be read.
+    pub fn read_patch(&self, patch: &Patch) -> Result<RgbaImage> {
+        read_patch(&self.slide, patch, self.patch_size)
+    }
+
+    /// Iterate over the selected patches and their pixels.
+    pub fn iter_pixels(&self) -> impl Iterator<Item = Result<(Patch, RgbaImage)>> + '_ {
+        self.patches
+            .iter()
+            .map(move |patch| Ok((*patch, self.read_patch(patch)?)))
+    }
+}
+
+impl<S> PatchSampler<S>
+where
+    S: Deref<Target = OpenSlide> + Clone + Send + 'static,
+{
+    /// Start reading the patches on `threads` background threads sharing the slide,
+    /// at most `capacity` decoded patches waiting to be consumed. See
+    /// [`PatchLoader`](struct.PatchLoader.html).
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `threads` is 0.
+    pub fn loader(&self, threads: usize, capacity: usize) -> Result<PatchLoader> {
+        let handles = (0..threads).map(|_| self.slide.clone()).collect();
+        PatchLoader::with_handles(self, handles, capacity)
+    }
+}
+
+/// Read the pixels of a patch, scaled to `patch_size`.
+pub(crate) fn read_patch(slide: &OpenSlide, patch: &Patch, patch_size: u32) -> Result<RgbaImage> {
+    let region = slide.read_region(patch.region())?;
+    if region.dimensions() == (patch_size, patch_size) {
+        Ok(region)
+    } else {
+        Ok(resize(
+            &region,
+            patch_size,
+            patch_size,
+            FilterType::Lanczos3,
+        ))
+    }
+}
+
+/// Read concentric patches around a point at several resolutions, as used by
+/// multi-scale models.
+///
+/// Each patch is centered on `center` and scaled to its size; the parts of the
+/// wider patches reaching outside the slide are transparent.
+///
+/// # Arguments
+///
+/// * `slide` - the slide.
+/// * `center` - the level 0 position of the common center.
+/// * `sizes_and_mpps` - the width and height in pixels and the resolution in
+/// micrometers per pixel of each patch.
+/// * `parallelism` - how many threads read the patches.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): a size is 0, a resolution is not positive, or the slide has no valid MPP properties.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn read_context_patches(
+    slide: &OpenSlide,
+    center: Address,
+    sizes_and_mpps: &[(u32, f32)],
+    parallelism: Parallelism,
+) -> Result<Vec<RgbaImage>> {
+    if let Some((size, mpp)) = sizes_and_mpps
+        .iter()
+        .find(|(size, mpp)| *size == 0 || !mpp.is_finite() || *mpp <= 0.0)
+    {
+        return Err(OpenSlideError::InvalidArgument(format!(
+            "Context patch size {} and MPP {} must be positive",
+            size, mpp
+        )));
+    }
+    if sizes_and_mpps.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let base_mpp = f64::from(slide_mpp(slide)?);
+    parallel_map(
+        sizes_and_mpps,
+        parallelism,
+        |_, _| {},
+        |(size, mpp)| {
+            let downsample = f64::from(*mpp) / base_mpp;
+            read_centered(slide, center, *size, downsample)
+        },
+    )?
+    .into_iter()
+    .collect()
+}
+
+/// Read the square patch of side `size` at `downsample` centered on `center`.
+fn read_centered(
+    slide: &OpenSlide,
+    center: Address,
+    size: u32,
+    downsample: f64,
+) -> Result<RgbaImage> {
+    let level = slide.best_level_for_downsample(downsample as f32)?;
+    let level_downsample = f64::from(slide.level_downsample(level)?);
+    let side = (f64::from(size) * downsample / level_downsample)
+        .ceil()
+        .max(1.0) as u32;
+
+    // Level 0 regions cannot start before the slide: read the part from the slide
+    // edge on, in whole level pixels
+    let extent = f64::from(side) * level_downsample;
+    let start = |center: u32| {
+        let start = f64::from(center) - extent / 2.;
+        let skipped = if start < 0. {
+            ((-start / level_downsample).ceil() as u32).min(side)
+        } else {
+            0
+        };
+        let position = (start + f64::from(skipped) * level_downsample)
+            .round()
+            .max(0.);
+        (position as u32, skipped)
+    };
+    let (x, skipped_x) = start(center.x);
+    let (y, skipped_y) = start(center.y);
+
+    let mut patch = RgbaImage::new(side, side);
+    if skipped_x < side && skipped_y < side {
+        let part = slide.read_region(Region {
+            address: Address { x, y },
+            level: level as _,
+            size: Size {
+                w: side - skipped_x,
+                h: side - skipped_y,
+            },
+        })?;
+        imageops::replace(&mut patch, &part, skipped_x.into(), skipped_y.into());
+    }
+
+    if side == size {
+        Ok(patch)
+    } else {
+        Ok(resize(&patch, size, size, FilterType::Lanczos3))
+    }
+}
+
+/// The maximum width and height of the masks rasterizing annotations.
+const MAX_MASK_SIZE: u32 = 8192;
+
+/// The share of level 0 rectangles covered by a mask.
+struct MaskCoverage<'a> {
+    mask: &'a GrayImage,
+    scale: (f64, f64),
+}
+
+impl<'a> MaskCoverage<'a> {
+    fn new(mask: &'a GrayImage, dimensions: Size) -> Self {
+        MaskCoverage {
+            mask,
+            scale: (
+                f64::from(mask.width()) / f64::from(dimensions.w),
+                f64::from(mask.height()) / f64::from(dimensions.h),
+            ),
+        }
+    }
+
+    /// A mask whose pixels each cover `downsample` level 0 pixels.
+    fn with_downsample(mask: &'a GrayImage, downsample: f64) -> Self {
+        MaskCoverage {
+            mask,
+            scale: (1. / downsample, 1. / downsample),
+        }
+    }
+
+    /// The share of the mask pixels overlapping the square of side `extent` at
+    /// `address` which are tissue.
+    fn fraction(&self, address: Address, extent: f64) -> f32 {
+        let range = |position: u32, scale: f64, limit: u32| {
+            let start = ((f64::from(position) * scale).floor() as u32).min(limit - 1);
+            let end = ((f64::from(position) + extent) * scale).ceil() as u32;
+            start..end.max(start + 1).min(limit)
+        };
+        let columns = range(address.x, self.scale.0, self.mask.width());
+        let rows = range(address.y, self.scale.1, self.mask.height());
+
+        let total = columns.len() * rows.len();
+        let tissue = rows
+            .flat_map(|y| columns.clone().map(move |x| (x, y)))
+            .filter(|(x, y)| self.mask.get_pixel(*x, *y).0[0] == TISSUE)
+            .count();
+        tissue as f32 / total as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn test_mask_coverage() {
+        // The left half of a 100x50 slide is tissue, at a tenth of its resolution
+        let mask = GrayImage::from_fn(10, 5, |x, _| Luma([if x < 5 { TISSUE } else { 0 }]));
+        let coverage = MaskCoverage::new(&mask, Size { w: 100, h: 50 });
+
+        assert_eq!(coverage.fraction(Address { x: 0, y: 0 }, 20.), 1.);
+        assert_eq!(coverage.fraction(Address { x: 60, y: 10 }, 20.), 0.);
+        assert_eq!(coverage.fraction(Address { x: 40, y: 0 }, 20.), 0.5);
+        // Partially covered mask pixels count, and patches smaller than a mask pixel
+        // still see one
+        assert_eq!(coverage.fraction(Address { x: 45, y: 0 }, 10.), 0.5);
+        assert_eq!(coverage.fraction(Address { x: 1, y: 1 }, 2.), 1.);
+        assert_eq!(coverage.fraction(Address { x: 99, y: 49 }, 20.), 0.);
+    }
+}
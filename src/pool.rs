@@ -0,0 +1,123 @@
+//! This module provides a pool of slides opened on demand, for processes reading
+//! from many more slides than they can keep open.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::openslide::OpenSlide;
+use crate::Result;
+
+/// The most recently used open slides, the most recent last.
+struct OpenSlides {
+    slides: HashMap<PathBuf, Arc<OpenSlide>>,
+    order: VecDeque<PathBuf>,
+}
+
+impl OpenSlides {
+    fn touch(&mut self, path: &Path) {
+        if let Some(position) = self.order.iter().position(|other| other == path) {
+            let path = self.order.remove(position).unwrap();
+            self.order.push_back(path);
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.slides.remove(path);
+        self.order.retain(|other| other != path);
+    }
+}
+
+/// Slides opened on first access by path, at most `capacity` of them kept open.
+///
+/// When the limit is reached, the least recently used slide is closed, as soon as
+/// the handles returned for it are dropped. Paths are compared as given: open a
+/// slide through different paths, such as a relative and an absolute one, and it is
+/// opened twice.
+pub struct SlidePool {
+    capacity: usize,
+    open: Mutex<OpenSlides>,
+}
+
+impl SlidePool {
+    /// Create an empty pool keeping at most `capacity` slides open.
+    pub fn new(capacity: usize) -> SlidePool {
+        SlidePool {
+            capacity: capacity.max(1),
+            open: Mutex::new(OpenSlides {
+                slides: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// The maximum number of slides kept open.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of slides currently open.
+    pub fn len(&self) -> usize {
+        self.open.lock().unwrap().slides.len()
+    }
+
+    /// Return true if no slide is open.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return true if the slide at `path` is open.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.open.lock().unwrap().slides.contains_key(path)
+    }
+
+    /// Get the slide at `path`, opening it if it is not open, and closing the least
+    /// recently used slide if the pool is full.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::MissingFile`](enum.OpenSlideError.html#variant.MissingFile): the file does not exist
+    /// * [`OpenSlideError::UnsupportedFile`](enum.OpenSlideError.html#variant.UnsupportedFile): the file is not a valid whole slide image.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn get(&self, path: &Path) -> Result<Arc<OpenSlide>> {
+        {
+            let mut open = self.open.lock().unwrap();
+            if let Some(slide) = open.slides.get(path).cloned() {
+                open.touch(path);
+                return Ok(slide);
+            }
+        }
+
+        // Open outside of the lock: opening a slide can take a while
+        let slide = Arc::new(OpenSlide::open(path)?);
+
+        let mut open = self.open.lock().unwrap();
+        if let Some(slide) = open.slides.get(path).cloned() {
+            open.touch(path);
+            return Ok(slide);
+        }
+        while open.slides.len() >= self.capacity {
+            match open.order.pop_front() {
+                Some(oldest) => {
+                    open.slides.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        open.slides.insert(path.to_path_buf(), slide.clone());
+        open.order.push_back(path.to_path_buf());
+        Ok(slide)
+    }
+
+    /// Close the slide at `path`, if open, so that it is opened again on next access.
+    pub fn remove(&self, path: &Path) {
+        self.open.lock().unwrap().remove(path);
+    }
+
+    /// Close every slide.
+    pub fn clear(&self) {
+        let mut open = self.open.lock().unwrap();
+        open.slides.clear();
+        open.order.clear();
+    }
+}
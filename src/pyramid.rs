@@ -0,0 +1,474 @@
+//! This module provides functionality for exporting complete Deep Zoom pyramids
+//! to a directory tree or a single ZIP archive.
+
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::deepzoom::DeepZoom;
+use crate::encode::{encode, Format};
+use crate::openslide::OpenSlide;
+use crate::{OpenSlideError, Result};
+
+/// How many threads generate tiles during an export.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Parallelism {
+    /// Generate tiles on the calling thread.
+    Sequential,
+    /// Generate tiles on the global rayon thread pool.
+    Auto,
+    /// Generate tiles on a dedicated pool with the given number of threads.
+    Threads(usize),
+}
+
+/// What pyramid exports do with background tiles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BackgroundTiles {
+    /// Do not write background tiles at all.
+    Skip,
+    /// Write a single `{name}_files/blank_{width}x{height}.{extension}` tile for each
+    /// size of background tile, and map the `"{level}/{col}_{row}"` background tiles
+    /// to the name of the blank tile replacing them in the
+    /// `{name}_files/blank_tiles.json` object.
+    Shared,
+}
+
+/// Detection of near-uniform background tiles, such as empty glass.
+///
+/// A tile is background when its mean saturation and the standard deviation of its
+/// luminance are both below the thresholds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BackgroundFilter {
+    /// The maximum mean HSV saturation, from 0 to 1
+    pub max_saturation: f32,
+    /// The maximum standard deviation of the luminance, from 0 to 255
+    pub max_std_dev: f32,
+    /// What to do with background tiles
+    pub mode: BackgroundTiles,
+}
+
+impl Default for BackgroundFilter {
+    fn default() -> Self {
+        BackgroundFilter {
+            max_saturation: 0.05,
+            max_std_dev: 4.0,
+            mode: BackgroundTiles::Skip,
+        }
+    }
+}
+
+impl BackgroundFilter {
+    /// Return true if `tile` is a background tile.
+    pub fn is_background(&self, tile: &RgbaImage) -> bool {
+        let count = (tile.width() * tile.height()) as f64;
+        if count == 0. {
+            return true;
+        }
+
+        let mut saturation = 0.;
+        let mut luma = 0.;
+        let mut luma_squares = 0.;
+        for pixel in tile.pixels() {
+            let [r, g, b, _] = pixel.0;
+            let max = r.max(g).max(b);
+            let min = r.min(g).min(b);
+            if max != 0 {
+                saturation += f64::from(max - min) / f64::from(max);
+            }
+
+            let y = 0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b);
+            luma += y;
+            luma_squares += y * y;
+        }
+
+        let mean = luma / count;
+        let variance = (luma_squares / count - mean * mean).max(0.);
+        saturation / count <= f64::from(self.max_saturation)
+            && variance.sqrt() <= f64::from(self.max_std_dev)
+    }
+}
+
+/// Statistics of a pyramid export.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ExportStats {
+    /// The number of tiles in the pyramid
+    pub tiles: usize,
+    /// The number of background tiles skipped or shared
+    pub background: usize,
+    /// The number of tiles identical to a previously written tile
+    pub duplicates: usize,
+    /// The number of bytes of tile data written
+    pub bytes_written: u64,
+    /// The number of bytes of tile data not written thanks to deduplication
+    pub bytes_saved: u64,
+}
+
+/// A destination for the files of an exported pyramid.
+pub(crate) trait PyramidSink: Sync {
+    /// Write a file at `name`, a `/` separated path relative to the pyramid root.
+    fn write(&self, name: &str, data: &[u8]) -> Result<()>;
+
+    /// Make `name` an alias of the already written `target` file. Return false if the
+    /// sink does not support aliases.
+    fn link(&self, _name: &str, _target: &str) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Writes the pyramid files in a directory.
+pub(crate) struct DirectorySink {
+    pub(crate) root: PathBuf,
+}
+
+impl PyramidSink for DirectorySink {
+    fn write(&self, name: &str, data: &[u8]) -> Result<()> {
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    fn link(&self, name: &str, target: &str) -> Result<bool> {
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        // Hard links are not available on every filesystem: fall back to the manifest
+        Ok(fs::hard_link(self.root.join(target), path).is_ok())
+    }
+}
+
+/// Streams the pyramid files into a ZIP archive as they are generated.
+struct ZipSink {
+    writer: Mutex<ZipWriter<File>>,
+}
+
+impl PyramidSink for ZipSink {
+    fn write(&self, name: &str, data: &[u8]) -> Result<()> {
+        // Tiles are already compressed by their image format: only the descriptors
+        // are worth deflating.
+        let compression = if name.ends_with(".dzi") || name.ends_with(".json") {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        let options = FileOptions::default().compression_method(compression);
+
+        let mut writer = self.writer.lock().unwrap();
+        writer.start_file(name, options)?;
+        writer.write_all(data)?;
+        Ok(())
+    }
+}
+
+impl<S: Deref<Target = OpenSlide> + Sync> DeepZoom<S> {
+    /// Write the complete pyramid: the `{path}.dzi` descriptor and every tile in
+    /// `{path}_files/{level}/{col}_{row}.{extension}`.
+    ///
+    /// Background tiles are skipped or shared if a
+    /// [`BackgroundFilter`](struct.BackgroundFilter.html) is
+    /// [set](struct.DeepZoom.html#method.set_background_filter). With
+    /// [deduplication](struct.DeepZoom.html#method.set_deduplicate), tiles identical to
+    /// an already written tile are hard links to it, or are listed as
+    /// `"{level}/{col}_{row}"` keys of the `{path}_files/duplicates.json` object,
+    /// mapped to the written tile, where hard links are not available.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the output path, without extension.
+    /// * `format` - the format of the individual tiles.
+    /// * `parallelism` - how many threads generate tiles.
+    /// * `progress` - called with the number of tiles written so far and the total
+    /// number of tiles, after each tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the pyramid could not be written.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn write_pyramid<F>(
+        &self,
+        path: &Path,
+        format: Format,
+        parallelism: Parallelism,
+        progress: F,
+    ) -> Result<ExportStats>
+    where
+        F: Fn(usize, usize) + Sync,
+    {
+        let sink = DirectorySink {
+            root: path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+        };
+        self.export(&sink, &pyramid_name(path), format, parallelism, progress)
+    }
+
+    /// Write the complete pyramid into a single ZIP archive at `path`.
+    ///
+    /// The archive holds the same tree as
+    /// [`write_pyramid`](struct.DeepZoom.html#method.write_pyramid), named after
+    /// the archive file stem. Tiles are streamed into the archive as they are
+    /// generated.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path of the archive.
+    /// * `format` - the format of the individual tiles.
+    /// * `parallelism` - how many threads generate tiles.
+    /// * `progress` - called with the number of tiles written so far and the total
+    /// number of tiles, after each tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the archive could not be written.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn write_pyramid_zip<F>(
+        &self,
+        path: &Path,
+        format: Format,
+        parallelism: Parallelism,
+        progress: F,
+    ) -> Result<ExportStats>
+    where
+        F: Fn(usize, usize) + Sync,
+    {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let sink = ZipSink {
+            writer: Mutex::new(ZipWriter::new(File::create(path)?)),
+        };
+        let stats = self.export(&sink, &pyramid_name(path), format, parallelism, progress)?;
+
+        sink.writer.into_inner().unwrap().finish()?;
+        Ok(stats)
+    }
+
+    fn export<P, F>(
+        &self,
+        sink: &P,
+        name: &str,
+        format: Format,
+        parallelism: Parallelism,
+        progress: F,
+    ) -> Result<ExportStats>
+    where
+        P: PyramidSink,
+        F: Fn(usize, usize) + Sync,
+    {
+        sink.write(&format!("{}.dzi", name), self.dzi(format).as_bytes())?;
+
+        let mut tiles = Vec::new();
+        for level in 0..self.level_count() {
+            tiles.extend(
+                self.tile_addresses(level, self.tile_order())?
+                    .map(|address| (level, address)),
+            );
+        }
+
+        let blank_tiles = Mutex::new(BTreeMap::new());
+        let written = Mutex::new(HashMap::new());
+        let links = Mutex::new(Vec::new());
+        let background = AtomicUsize::new(0);
+        let duplicate_count = AtomicUsize::new(0);
+        let bytes_written = AtomicU64::new(0);
+        let bytes_saved = AtomicU64::new(0);
+
+        for_each_tile(&tiles, parallelism, progress, |(level, address)| {
+            let tile = self.read_tile(*level, *address)?;
+            let tile_name = format!("{}/{}_{}", level, address.x, address.y);
+            if let Some(filter) = self.background_filter() {
+                if filter.is_background(&tile) {
+                    background.fetch_add(1, Ordering::Relaxed);
+                    if filter.mode == BackgroundTiles::Shared {
+                        blank_tiles
+                            .lock()
+                            .unwrap()
+                            .insert(tile_name, tile.dimensions());
+                    }
+                    return Ok(());
+                }
+            }
+
+            let bytes = encode(&tile, format)?;
+            let path = format!("{}_files/{}.{}", name, tile_name, format.extension());
+            if !self.deduplicate() {
+                bytes_written.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                return sink.write(&path, &bytes);
+            }
+
+            // Only claim the contents under the lock: the first tile with given contents
+            // is written without holding it, and duplicates are linked once every tile
+            // is written, so that they never link to a file that does not exist yet
+            let mut hasher = DefaultHasher::new();
+            bytes.hash(&mut hasher);
+            let key = (hasher.finish(), bytes.len());
+            let original = match written.lock().unwrap().entry(key) {
+                Entry::Occupied(entry) => Some(entry.get().clone()),
+                Entry::Vacant(entry) => {
+                    entry.insert(tile_name.clone());
+                    None
+                }
+            };
+
+            match original {
+                None => {
+                    bytes_written.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                    sink.write(&path, &bytes)?;
+                }
+                Some(original) => {
+                    duplicate_count.fetch_add(1, Ordering::Relaxed);
+                    bytes_saved.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                    links.lock().unwrap().push((tile_name, original));
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut duplicates = BTreeMap::new();
+        for (tile_name, original) in links.into_inner().unwrap() {
+            let path = format!("{}_files/{}.{}", name, tile_name, format.extension());
+            let target = format!("{}_files/{}.{}", name, original, format.extension());
+            if !sink.link(&path, &target)? {
+                duplicates.insert(tile_name, original);
+            }
+        }
+        if !duplicates.is_empty() {
+            sink.write(
+                &format!("{}_files/duplicates.json", name),
+                serde_json::to_string(&duplicates)
+                    .map_err(|e| OpenSlideError::InternalError(e.to_string()))?
+                    .as_bytes(),
+            )?;
+        }
+
+        if let Some(BackgroundFilter {
+            mode: BackgroundTiles::Shared,
+            ..
+        }) = self.background_filter()
+        {
+            // Edge tiles and tiles with less overlap are smaller than the others
+            let [r, g, b] = self.background_color().0;
+            let blank_name =
+                |(w, h): (u32, u32)| format!("blank_{}x{}.{}", w, h, format.extension());
+            let blank_tiles = blank_tiles.into_inner().unwrap();
+            for &(w, h) in blank_tiles.values().collect::<BTreeSet<_>>() {
+                let blank = RgbaImage::from_pixel(w, h, Rgba([r, g, b, 255]));
+                sink.write(
+                    &format!("{}_files/{}", name, blank_name((w, h))),
+                    &encode(&blank, format)?,
+                )?;
+            }
+
+            let blank_tiles: BTreeMap<&String, String> = blank_tiles
+                .iter()
+                .map(|(tile, &size)| (tile, blank_name(size)))
+                .collect();
+            sink.write(
+                &format!("{}_files/blank_tiles.json", name),
+                serde_json::to_string(&blank_tiles)
+                    .map_err(|e| OpenSlideError::InternalError(e.to_string()))?
+                    .as_bytes(),
+            )?;
+        }
+
+        Ok(ExportStats {
+            tiles: tiles.len(),
+            background: background.into_inner(),
+            duplicates: duplicate_count.into_inner(),
+            bytes_written: bytes_written.into_inner(),
+            bytes_saved: bytes_saved.into_inner(),
+        })
+    }
+}
+
+/// Run `write` on every tile with the requested parallelism, reporting progress after
+/// each tile.
+pub(crate) fn for_each_tile<T, W, F>(
+    tiles: &[T],
+    parallelism: Parallelism,
+    progress: F,
+    write: W,
+) -> Result<()>
+where
+    T: Sync,
+    W: Fn(&T) -> Result<()> + Sync,
+    F: Fn(usize, usize) + Sync,
+{
+    let total = tiles.len();
+    let done = AtomicUsize::new(0);
+    let write_tile = |tile: &T| -> Result<()> {
+        write(tile)?;
+        progress(done.fetch_add(1, Ordering::SeqCst) + 1, total);
+        Ok(())
+    };
+
+    match parallelism {
+        Parallelism::Sequential => tiles.iter().try_for_each(write_tile),
+        Parallelism::Auto => tiles.par_iter().try_for_each(write_tile),
+        Parallelism::Threads(threads) => {
+            thread_pool(threads)?.install(|| tiles.par_iter().try_for_each(write_tile))
+        }
+    }
+}
+
+/// Run `map` on every item with the requested parallelism, reporting progress after
+/// each item, and return the results in the order of the items.
+pub(crate) fn parallel_map<T, R, M, F>(
+    items: &[T],
+    parallelism: Parallelism,
+    progress: F,
+    map: M,
+) -> Result<Vec<R>>
+where
+    T: Sync,
+    R: Send,
+    M: Fn(&T) -> R + Sync,
+    F: Fn(usize, usize) + Sync,
+{
+    let total = items.len();
+    let done = AtomicUsize::new(0);
+    let map_item = |item: &T| -> R {
+        let result = map(item);
+        progress(done.fetch_add(1, Ordering::SeqCst) + 1, total);
+        result
+    };
+
+    match parallelism {
+        Parallelism::Sequential => Ok(items.iter().map(map_item).collect()),
+        Parallelism::Auto => Ok(items.par_iter().map(map_item).collect()),
+        Parallelism::Threads(threads) => {
+            Ok(thread_pool(threads)?.install(|| items.par_iter().map(map_item).collect()))
+        }
+    }
+}
+
+/// Build a dedicated pool of `threads` threads.
+fn thread_pool(threads: usize) -> Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| OpenSlideError::InternalError(e.to_string()))
+}
+
+/// Return the name of the pyramid written at `path`: its file stem.
+fn pyramid_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,216 @@
+//! This module provides quality control metrics of tiles and patches: blur,
+//! brightness, saturation and pen marks, to filter out unusable images before
+//! training or serving.
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::openslide::{OpenSlide, Region};
+use crate::Result;
+
+/// Quality control metrics of an image.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QcReport {
+    /// The variance of the Laplacian of the luminance: low values denote blurry or
+    /// empty images
+    pub sharpness: f32,
+    /// The mean luminance, from 0 to 1
+    pub brightness: f32,
+    /// The mean HSV saturation, from 0 to 1
+    pub saturation: f32,
+    /// The share of pixels looking like pen ink, from 0 to 1
+    pub pen_fraction: f32,
+}
+
+/// Thresholds an image must meet to pass quality control.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QcThresholds {
+    /// The minimum [`sharpness`](struct.QcReport.html#structfield.sharpness)
+    pub min_sharpness: f32,
+    /// The maximum [`brightness`](struct.QcReport.html#structfield.brightness), above
+    /// which the image is mostly glass
+    pub max_brightness: f32,
+    /// The minimum [`saturation`](struct.QcReport.html#structfield.saturation), below
+    /// which the image holds no stained tissue
+    pub min_saturation: f32,
+    /// The maximum [`pen_fraction`](struct.QcReport.html#structfield.pen_fraction)
+    pub max_pen_fraction: f32,
+}
+
+impl Default for QcThresholds {
+    fn default() -> Self {
+        QcThresholds {
+            min_sharpness: 50.,
+            max_brightness: 0.9,
+            min_saturation: 0.05,
+            max_pen_fraction: 0.05,
+        }
+    }
+}
+
+impl QcThresholds {
+    /// Return true if `report` meets every threshold.
+    pub fn passes(&self, report: &QcReport) -> bool {
+        report.sharpness >= self.min_sharpness
+            && report.brightness <= self.max_brightness
+            && report.saturation >= self.min_saturation
+            && report.pen_fraction <= self.max_pen_fraction
+    }
+}
+
+/// Compute the quality control metrics of an image, transparent pixels counting as
+/// white glass.
+pub fn analyze(image: &RgbaImage) -> QcReport {
+    let (width, height) = image.dimensions();
+    let count = f64::from(width) * f64::from(height);
+    if count == 0. {
+        return QcReport {
+            sharpness: 0.,
+            brightness: 1.,
+            saturation: 0.,
+            pen_fraction: 0.,
+        };
+    }
+
+    let mut luma = Vec::with_capacity(count as usize);
+    let mut saturation = 0.;
+    let mut pen = 0usize;
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        let alpha = f32::from(a) / 255.;
+        let over_white = |channel: u8| f32::from(channel) * alpha + 255. * (1. - alpha);
+        let (r, g, b) = (over_white(r), over_white(g), over_white(b));
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let pixel_saturation = if max > 0. { (max - min) / max } else { 0. };
+        saturation += f64::from(pixel_saturation);
+        luma.push(0.299 * r + 0.587 * g + 0.114 * b);
+        if is_pen(r, g, b, pixel_saturation) {
+            pen += 1;
+        }
+    }
+
+    QcReport {
+        sharpness: laplacian_variance(&luma, width as usize, height as usize) as f32,
+        brightness: (luma.iter().map(|y| f64::from(*y)).sum::<f64>() / count / 255.) as f32,
+        saturation: (saturation / count) as f32,
+        pen_fraction: (pen as f64 / count) as f32,
+    }
+}
+
+/// Read a slide region and compute its quality control metrics.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): the region could not be read.
+pub fn analyze_region(slide: &OpenSlide, region: Region) -> Result<QcReport> {
+    Ok(analyze(&slide.read_region(region)?))
+}
+
+/// Return true if a pixel looks like pen ink: saturated green to blue hues, which
+/// hematoxylin and eosin do not produce, or near black.
+fn is_pen(r: f32, g: f32, b: f32, saturation: f32) -> bool {
+    let max = r.max(g).max(b);
+    if max < 40. {
+        return true;
+    }
+    if saturation < 0.3 {
+        return false;
+    }
+
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if max == r {
+        60. * ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / delta + 2.)
+    } else {
+        60. * ((r - g) / delta + 4.)
+    };
+    (70. ..=250.).contains(&hue)
+}
+
+/// The variance of the 4-neighbour Laplacian of a luminance image, over the pixels
+/// not on its border.
+fn laplacian_variance(luma: &[f32], width: usize, height: usize) -> f64 {
+    if width < 3 || height < 3 {
+        return 0.;
+    }
+
+    let mut sum = 0.;
+    let mut squares = 0.;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let at = |x: usize, y: usize| f64::from(luma[y * width + x]);
+            let laplacian =
+                at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4. * at(x, y);
+            sum += laplacian;
+            squares += laplacian * laplacian;
+        }
+    }
+
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    (squares / count - mean * mean).max(0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_blank() {
+        let report = analyze(&RgbaImage::from_pixel(16, 16, Rgba([255, 255, 255, 255])));
+        assert_eq!(report.sharpness, 0.);
+        assert!((report.brightness - 1.).abs() < 1e-6);
+        assert_eq!(report.saturation, 0.);
+        assert_eq!(report.pen_fraction, 0.);
+        assert!(!QcThresholds::default().passes(&report));
+
+        // Transparent pixels are glass
+        let transparent = analyze(&RgbaImage::new(16, 16));
+        assert_eq!(transparent, report);
+    }
+
+    #[test]
+    fn test_sharpness() {
+        let checkerboard = RgbaImage::from_fn(16, 16, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([200, 100, 150, 255])
+            } else {
+                Rgba([120, 40, 90, 255])
+            }
+        });
+        let gradient = RgbaImage::from_fn(16, 16, |x, _| {
+            let value = 100 + 4 * x as u8;
+            Rgba([value, 60, value, 255])
+        });
+
+        let sharp = analyze(&checkerboard);
+        let smooth = analyze(&gradient);
+        assert!(sharp.sharpness > 1000., "{}", sharp.sharpness);
+        assert!(smooth.sharpness < 1., "{}", smooth.sharpness);
+        assert!(QcThresholds::default().passes(&sharp));
+        assert!(!QcThresholds::default().passes(&smooth));
+    }
+
+    #[test]
+    fn test_pen() {
+        // Half of the image is covered by blue ink, the other half by eosin pink
+        let image = RgbaImage::from_fn(10, 10, |x, _| {
+            if x < 5 {
+                Rgba([30, 60, 200, 255])
+            } else {
+                Rgba([230, 120, 180, 255])
+            }
+        });
+        let report = analyze(&image);
+        assert_eq!(report.pen_fraction, 0.5);
+
+        assert!(is_pen(10., 10., 10., 0.));
+        assert!(is_pen(40., 180., 60., 0.78));
+        assert!(!is_pen(150., 60., 160., 0.63));
+    }
+}
@@ -0,0 +1,130 @@
+//! Tile server metrics, rendered in the Prometheus text exposition format.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// How a tile request was answered.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Outcome {
+    /// The tile was generated and sent
+    Served,
+    /// The client copy was still valid: `304 Not Modified`
+    NotModified,
+    /// The request failed
+    Error,
+}
+
+/// Counters updated by the tile handler.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    served: AtomicU64,
+    not_modified: AtomicU64,
+    errors: AtomicU64,
+    /// Non cumulative bucket counts, the last one holding slower requests
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    latency_sum_micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Record a tile request.
+    pub(crate) fn record(&self, outcome: Outcome, latency: Duration) {
+        let counter = match outcome {
+            Outcome::Served => &self.served,
+            Outcome::NotModified => &self.not_modified,
+            Outcome::Error => &self.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub(crate) fn render(&self) -> String {
+        let served = self.served.load(Ordering::Relaxed);
+        let not_modified = self.not_modified.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+
+        let mut text = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} counter", name).unwrap();
+            writeln!(text, "{} {}", name, value).unwrap();
+        };
+        counter(
+            "openslide_tiles_served_total",
+            "Tiles generated and sent.",
+            served,
+        );
+        counter(
+            "openslide_tiles_not_modified_total",
+            "Tile requests answered with 304 Not Modified.",
+            not_modified,
+        );
+        counter(
+            "openslide_tile_errors_total",
+            "Tile requests that failed.",
+            errors,
+        );
+
+        // Hits are requests the client cache could answer
+        let answered = served + not_modified;
+        let hit_rate = if answered == 0 {
+            0.
+        } else {
+            not_modified as f64 / answered as f64
+        };
+        writeln!(
+            text,
+            "# HELP openslide_tile_cache_hit_ratio Share of tile requests answered from the client cache."
+        )
+        .unwrap();
+        writeln!(text, "# TYPE openslide_tile_cache_hit_ratio gauge").unwrap();
+        writeln!(text, "openslide_tile_cache_hit_ratio {}", hit_rate).unwrap();
+
+        writeln!(
+            text,
+            "# HELP openslide_tile_duration_seconds Tile request latency."
+        )
+        .unwrap();
+        writeln!(text, "# TYPE openslide_tile_duration_seconds histogram").unwrap();
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            writeln!(
+                text,
+                "openslide_tile_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            )
+            .unwrap();
+        }
+        cumulative += self.latency_buckets[LATENCY_BUCKETS.len()].load(Ordering::Relaxed);
+        writeln!(
+            text,
+            "openslide_tile_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            cumulative
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "openslide_tile_duration_seconds_sum {}",
+            self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1e6
+        )
+        .unwrap();
+        writeln!(text, "openslide_tile_duration_seconds_count {}", cumulative).unwrap();
+
+        text
+    }
+}
@@ -0,0 +1,550 @@
+//! This module provides an HTTP tile server for Deep Zoom viewers such as
+//! [OpenSeadragon](https://openseadragon.github.io/), built on
+//! [axum](https://docs.rs/axum).
+//!
+//! The [`router`](fn.router.html) function returns an axum `Router` serving, for each
+//! slide id:
+//!
+//! * `/slide/{id}.dzi`: the Deep Zoom descriptor,
+//! * `/slide/{id}_files/{level}/{col}_{row}.{extension}`: the tiles,
+//! * `/metrics`: request metrics in the Prometheus text format.
+//!
+//! Tile requests may override the configured encoding with the `format` (`jpg`, `png`
+//! or `webp`) and `quality` (1 to 100) query parameters, as in
+//! `/slide/{id}_files/{level}/{col}_{row}.jpg?format=webp&quality=50`.
+//!
+//! Tiles are sent with an `ETag` derived from the slide quickhash and the tile
+//! coordinates, and a `Cache-Control` header: revalidation requests for an unchanged
+//! tile are answered with `304 Not Modified` without reading the slide. The Deep Zoom
+//! generator and quickhash of each slide are computed on its first request and kept
+//! while the slide source returns the same handle.
+//!
+//! Access to each slide can be restricted with an [`Authorizer`](trait.Authorizer.html)
+//! given to [`router_with_authorizer`](fn.router_with_authorizer.html): descriptor and
+//! tile requests are checked before the slide is opened.
+//!
+//! The router can be served directly or mounted into a larger application with
+//! `Router::nest` or `Router::merge`. Slides come from a [`SlideSource`](trait.SlideSource.html),
+//! such as a [`SlideStore`](struct.SlideStore.html) serving a directory of slides.
+//!
+//! This module requires the `server` feature.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::extract::{Extension, Path, Query};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+
+use crate::deepzoom::DeepZoom;
+use crate::encode::Format;
+use crate::openslide::{Address, OpenSlide};
+use crate::{OpenSlideError, Result};
+
+mod metrics;
+mod store;
+
+use metrics::{Metrics, Outcome};
+pub use store::SlideStore;
+
+/// A collection of slides served by the tile server, looked up by id.
+///
+/// Implement this trait to serve slides from a custom location, such as a database
+/// of slide paths.
+pub trait SlideSource: Send + Sync + 'static {
+    /// Return the slide with the given id, or `None` if there is no such slide.
+    fn get(&self, id: &str) -> Result<Option<Arc<OpenSlide>>>;
+}
+
+impl SlideSource for HashMap<String, Arc<OpenSlide>> {
+    fn get(&self, id: &str) -> Result<Option<Arc<OpenSlide>>> {
+        Ok(HashMap::get(self, id).cloned())
+    }
+}
+
+impl<T: SlideSource> SlideSource for Arc<T> {
+    fn get(&self, id: &str) -> Result<Option<Arc<OpenSlide>>> {
+        (**self).get(id)
+    }
+}
+
+/// The outcome of an authorization check.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Decision {
+    /// Serve the request
+    Allow,
+    /// The client is not authenticated: `401 Unauthorized`
+    Unauthenticated,
+    /// The client may not access the slide: `403 Forbidden`
+    Deny,
+}
+
+/// Decides which requests may access which slides.
+///
+/// The request is given without its body, which is empty for the served routes.
+/// Closures `Fn(&Request<()>, &str) -> Decision` implement this trait.
+///
+/// # Examples
+///
+/// ```
+/// use axum::http::{header, Request};
+/// use openslide_rs::server::{Authorizer, Decision};
+///
+/// let authorizer = |request: &Request<()>, slide_id: &str| {
+///     if slide_id.starts_with("public-") {
+///         return Decision::Allow;
+///     }
+///     match request.headers().get(header::AUTHORIZATION) {
+///         None => Decision::Unauthenticated,
+///         Some(token) if token.as_bytes() == b"Bearer secret" => Decision::Allow,
+///         Some(_) => Decision::Deny,
+///     }
+/// };
+/// let request = Request::builder().body(()).unwrap();
+/// assert_eq!(authorizer.authorize(&request, "case-1"), Decision::Unauthenticated);
+/// ```
+pub trait Authorizer: Send + Sync + 'static {
+    /// Decide if `request` may access the slide with the given id.
+    fn authorize(&self, request: &Request<()>, slide_id: &str) -> Decision;
+}
+
+impl<F> Authorizer for F
+where
+    F: Fn(&Request<()>, &str) -> Decision + Send + Sync + 'static,
+{
+    fn authorize(&self, request: &Request<()>, slide_id: &str) -> Decision {
+        self(request, slide_id)
+    }
+}
+
+/// The quality used when a request switches to a lossy format without giving one.
+const DEFAULT_QUALITY: u8 = 75;
+
+/// The Deep Zoom parameters of the tile server.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    /// The width and height of a single tile
+    pub tile_size: u32,
+    /// The number of extra pixels added to each interior edge of a tile
+    pub overlap: u32,
+    /// True to render only the non-empty slide region
+    pub limit_bounds: bool,
+    /// The format of the served tiles, unless overridden by the request
+    pub format: Format,
+    /// How long clients may cache tiles without revalidating them, in seconds
+    pub cache_max_age: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            tile_size: 254,
+            overlap: 1,
+            limit_bounds: true,
+            format: Format::Jpeg { quality: 75 },
+            cache_max_age: 86400,
+        }
+    }
+}
+
+struct ServerState<S> {
+    slides: S,
+    config: ServerConfig,
+    authorizer: Box<dyn Authorizer>,
+    metrics: Metrics,
+    generators: Mutex<HashMap<String, Arc<ServedSlide>>>,
+}
+
+/// A slide handle with its Deep Zoom generator, built once per handle.
+struct ServedSlide {
+    slide: Arc<OpenSlide>,
+    deepzoom: DeepZoom<Arc<OpenSlide>>,
+    /// The slide quickhash, or its dimensions if it has none, identifying its tiles
+    hash: String,
+}
+
+/// The encoding query parameters of a tile request.
+#[derive(Debug, Default, Deserialize)]
+struct TileQuery {
+    format: Option<String>,
+    quality: Option<u8>,
+}
+
+impl TileQuery {
+    /// Return the tile format, falling back to parts of the configured format.
+    fn format(&self, default: Format) -> Result<Format> {
+        let default_quality = match default {
+            Format::Jpeg { quality } | Format::Webp { quality } => quality,
+            Format::Png => DEFAULT_QUALITY,
+        };
+        let quality = match self.quality {
+            Some(quality) if !(1..=100).contains(&quality) => {
+                return Err(OpenSlideError::InvalidArgument(format!(
+                    "Quality {} is not in the 1-100 range",
+                    quality
+                )))
+            }
+            Some(quality) => quality,
+            None => default_quality,
+        };
+
+        match self
+            .format
+            .as_deref()
+            .unwrap_or_else(|| default.extension())
+        {
+            "jpg" | "jpeg" => Ok(Format::Jpeg { quality }),
+            "png" => Ok(Format::Png),
+            "webp" => Ok(Format::Webp { quality }),
+            other => Err(OpenSlideError::InvalidArgument(format!(
+                "Unsupported tile format: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A tile, or the confirmation that the client copy is still valid.
+enum TileBody {
+    Bytes(Vec<u8>),
+    NotModified,
+}
+
+impl<S: SlideSource> ServerState<S> {
+    /// Check that the request described by `method`, `uri` and `headers` may access the
+    /// slide `id`.
+    fn authorize(
+        &self,
+        id: &str,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+    ) -> std::result::Result<(), ServerError> {
+        let mut request = Request::new(());
+        *request.method_mut() = method;
+        *request.uri_mut() = uri;
+        *request.headers_mut() = headers;
+
+        match self.authorizer.authorize(&request, id) {
+            Decision::Allow => Ok(()),
+            Decision::Unauthenticated => Err(ServerError::Unauthorized),
+            Decision::Deny => Err(ServerError::Forbidden),
+        }
+    }
+
+    fn slide(&self, id: &str) -> Result<Arc<OpenSlide>> {
+        self.slides
+            .get(id)?
+            .ok_or_else(|| OpenSlideError::MissingFile(id.to_string()))
+    }
+
+    /// Return the generator of the slide `id`, built on its first request and rebuilt
+    /// when the source returns another handle, such as for a replaced file.
+    fn served(&self, id: &str) -> Result<Arc<ServedSlide>> {
+        let slide = self.slide(id)?;
+        if let Some(served) = self.generators.lock().unwrap().get(id) {
+            if Arc::ptr_eq(&served.slide, &slide) {
+                return Ok(served.clone());
+            }
+        }
+
+        // Build outside of the lock: building queries every level of the slide
+        let served = Arc::new(ServedSlide {
+            deepzoom: DeepZoom::new(
+                slide.clone(),
+                self.config.tile_size,
+                self.config.overlap,
+                self.config.limit_bounds,
+            )?,
+            hash: slide_hash(&slide)?,
+            slide,
+        });
+
+        let mut cached = self.generators.lock().unwrap();
+        // Handles only held by their entry and its generator were closed by the source,
+        // and are not kept open
+        cached.retain(|_, served| Arc::strong_count(&served.slide) > 2);
+        cached.insert(id.to_string(), served.clone());
+        Ok(served)
+    }
+
+    /// Return the tile ETag and, unless it matches `if_none_match`, the tile itself.
+    fn tile(
+        &self,
+        id: &str,
+        level: usize,
+        address: Address,
+        format: Format,
+        if_none_match: Option<&str>,
+    ) -> Result<(String, TileBody)> {
+        let served = self.served(id)?;
+        let etag = self.etag(&served.hash, level, address, format);
+        if let Some(if_none_match) = if_none_match {
+            if if_none_match == "*" || if_none_match.split(',').any(|tag| tag.trim() == etag) {
+                return Ok((etag, TileBody::NotModified));
+            }
+        }
+
+        if !served.deepzoom.contains(level, address) {
+            return Err(OpenSlideError::OutOfBounds(address.to_string()));
+        }
+        let bytes = served.deepzoom.tile_bytes(level, address, format)?;
+        Ok((etag, TileBody::Bytes(bytes)))
+    }
+
+    /// Build the tile ETag from the slide hash, the Deep Zoom parameters, the tile
+    /// format and the tile coordinates.
+    fn etag(&self, hash: &str, level: usize, address: Address, format: Format) -> String {
+        let quality = match format {
+            Format::Jpeg { quality } | Format::Webp { quality } => quality,
+            Format::Png => 0,
+        };
+
+        format!(
+            "\"{}-{}-{}-{}-{}{}-{}-{}-{}\"",
+            hash,
+            self.config.tile_size,
+            self.config.overlap,
+            self.config.limit_bounds as u8,
+            format.extension(),
+            quality,
+            level,
+            address.x,
+            address.y
+        )
+    }
+}
+
+/// Identify a slide by its quickhash, falling back to its dimensions for slides
+/// without one.
+fn slide_hash(slide: &OpenSlide) -> Result<String> {
+    match slide.property("openslide.quickhash-1")? {
+        Some(hash) => Ok(hash),
+        None => {
+            let dimensions = slide.dimensions()?;
+            Ok(format!("{}x{}", dimensions.w, dimensions.h))
+        }
+    }
+}
+
+/// Build a router serving the Deep Zoom descriptors and tiles of `slides`.
+///
+/// # Arguments
+///
+/// * `slides` - the served slides, for example a `HashMap<String, Arc<OpenSlide>>`.
+/// * `config` - the Deep Zoom parameters.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::collections::HashMap;
+/// use std::path::Path;
+/// use std::sync::Arc;
+/// use openslide_rs::server::{router, ServerConfig};
+/// use openslide_rs::OpenSlide;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut slides = HashMap::new();
+///     let slide = OpenSlide::open(Path::new("tests/assets/default.svs")).unwrap();
+///     slides.insert("default".to_string(), Arc::new(slide));
+///
+///     axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
+///         .serve(router(slides, ServerConfig::default()).into_make_service())
+///         .await
+///         .unwrap();
+/// }
+/// ```
+pub fn router<S: SlideSource>(slides: S, config: ServerConfig) -> Router {
+    router_with_authorizer(slides, config, |_: &Request<()>, _: &str| Decision::Allow)
+}
+
+/// Build a router serving the Deep Zoom descriptors and tiles of `slides`, to the
+/// requests allowed by `authorizer`.
+///
+/// The metrics route is not checked: restrict it in front of the router if needed.
+///
+/// # Arguments
+///
+/// * `slides` - the served slides, for example a `HashMap<String, Arc<OpenSlide>>`.
+/// * `config` - the Deep Zoom parameters.
+/// * `authorizer` - the per-slide access control.
+pub fn router_with_authorizer<S, A>(slides: S, config: ServerConfig, authorizer: A) -> Router
+where
+    S: SlideSource,
+    A: Authorizer,
+{
+    let state = Arc::new(ServerState {
+        slides,
+        config,
+        authorizer: Box::new(authorizer),
+        metrics: Metrics::default(),
+        generators: Mutex::new(HashMap::new()),
+    });
+
+    Router::new()
+        .route("/slide/:file", get(dzi_handler::<S>))
+        .route("/slide/:files/:level/:tile", get(tile_handler::<S>))
+        .route("/metrics", get(metrics_handler::<S>))
+        .layer(Extension(state))
+}
+
+async fn dzi_handler<S: SlideSource>(
+    Extension(state): Extension<Arc<ServerState<S>>>,
+    Path(file): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> std::result::Result<Response, ServerError> {
+    let id = file
+        .strip_suffix(".dzi")
+        .ok_or(ServerError::NotFound)?
+        .to_string();
+    state.authorize(&id, method, uri, headers)?;
+    let dzi = blocking(move || {
+        let served = state.served(&id)?;
+        Ok(served.deepzoom.dzi(state.config.format))
+    })
+    .await?;
+
+    Ok(([(header::CONTENT_TYPE, "application/xml")], dzi).into_response())
+}
+
+async fn tile_handler<S: SlideSource>(
+    Extension(state): Extension<Arc<ServerState<S>>>,
+    Path((files, level, tile)): Path<(String, usize, String)>,
+    Query(query): Query<TileQuery>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> std::result::Result<Response, ServerError> {
+    let start = Instant::now();
+    let response = match files.strip_suffix("_files") {
+        Some(id) => match state.authorize(id, method, uri, headers.clone()) {
+            Ok(()) => tile_response(state.clone(), files, level, tile, query, headers).await,
+            Err(error) => Err(error),
+        },
+        None => Err(ServerError::NotFound),
+    };
+
+    let outcome = match &response {
+        Ok(response) if response.status() == StatusCode::NOT_MODIFIED => Outcome::NotModified,
+        Ok(_) => Outcome::Served,
+        Err(_) => Outcome::Error,
+    };
+    state.metrics.record(outcome, start.elapsed());
+    response
+}
+
+async fn tile_response<S: SlideSource>(
+    state: Arc<ServerState<S>>,
+    files: String,
+    level: usize,
+    tile: String,
+    query: TileQuery,
+    headers: HeaderMap,
+) -> std::result::Result<Response, ServerError> {
+    let id = files
+        .strip_suffix("_files")
+        .ok_or(ServerError::NotFound)?
+        .to_string();
+    let format = query
+        .format(state.config.format)
+        .map_err(ServerError::Slide)?;
+    // Viewers keep the descriptor extension when adding query parameters
+    let address = parse_tile_name(&tile, state.config.format)
+        .or_else(|| parse_tile_name(&tile, format))
+        .ok_or(ServerError::NotFound)?;
+    let if_none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    let cache_control = format!("public, max-age={}", state.config.cache_max_age);
+    let (etag, body) =
+        blocking(move || state.tile(&id, level, address, format, if_none_match.as_deref())).await?;
+
+    let response = match body {
+        TileBody::NotModified => (
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, etag), (header::CACHE_CONTROL, cache_control)],
+        )
+            .into_response(),
+        TileBody::Bytes(bytes) => (
+            [
+                (header::CONTENT_TYPE, format.mime_type().to_string()),
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, cache_control),
+            ],
+            bytes,
+        )
+            .into_response(),
+    };
+    Ok(response)
+}
+
+async fn metrics_handler<S: SlideSource>(
+    Extension(state): Extension<Arc<ServerState<S>>>,
+) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
+
+/// Parse a `{col}_{row}.{extension}` tile name.
+fn parse_tile_name(name: &str, format: Format) -> Option<Address> {
+    let (address, extension) = name.rsplit_once('.')?;
+    if extension != format.extension() {
+        return None;
+    }
+    let (x, y) = address.split_once('_')?;
+    Some(Address {
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+    })
+}
+
+/// Run slide reads out of the async executor.
+async fn blocking<T, F>(f: F) -> std::result::Result<T, ServerError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| ServerError::Slide(OpenSlideError::InternalError(e.to_string())))?
+        .map_err(ServerError::Slide)
+}
+
+enum ServerError {
+    NotFound,
+    Unauthorized,
+    Forbidden,
+    Slide(OpenSlideError),
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        match self {
+            ServerError::NotFound => StatusCode::NOT_FOUND.into_response(),
+            ServerError::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
+            ServerError::Forbidden => StatusCode::FORBIDDEN.into_response(),
+            ServerError::Slide(error) => {
+                let status = match error {
+                    OpenSlideError::MissingFile(_)
+                    | OpenSlideError::IndexError(_)
+                    | OpenSlideError::OutOfBounds(_) => StatusCode::NOT_FOUND,
+                    OpenSlideError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                (status, error.to_string()).into_response()
+            }
+        }
+    }
+}
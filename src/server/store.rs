@@ -0,0 +1,179 @@
+//! A collection of slide files, opened on demand.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use super::SlideSource;
+use crate::openslide::OpenSlide;
+use crate::pool::SlidePool;
+use crate::{OpenSlideError, Result};
+
+/// A slide file known to the store.
+#[derive(Clone, Debug, PartialEq)]
+struct SlideFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// A [`SlideSource`](trait.SlideSource.html) mapping slide ids to files.
+///
+/// Slides are opened on first access and at most `capacity` slides are kept open:
+/// the least recently used slide is closed when the limit is reached.
+///
+/// A store created with [`from_directory`](struct.SlideStore.html#method.from_directory)
+/// serves every slide of a directory, using the file stems as ids. Files added,
+/// replaced or removed afterwards are picked up by
+/// [`refresh`](struct.SlideStore.html#method.refresh), or periodically with
+/// [`watch`](struct.SlideStore.html#method.watch): requests for unknown ids do not
+/// rescan the directory, so that clients cannot force a scan per request.
+pub struct SlideStore {
+    directory: Option<PathBuf>,
+    files: RwLock<HashMap<String, SlideFile>>,
+    pool: SlidePool,
+}
+
+impl SlideStore {
+    /// Create an empty store keeping at most `capacity` slides open.
+    pub fn new(capacity: usize) -> SlideStore {
+        SlideStore {
+            directory: None,
+            files: RwLock::new(HashMap::new()),
+            pool: SlidePool::new(capacity),
+        }
+    }
+
+    /// Create a store serving the slides of `directory`, keeping at most `capacity`
+    /// slides open.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the directory could not be read.
+    pub fn from_directory(directory: &Path, capacity: usize) -> Result<SlideStore> {
+        let mut store = SlideStore::new(capacity);
+        store.directory = Some(directory.to_path_buf());
+        store.refresh()?;
+        Ok(store)
+    }
+
+    /// Serve the slide at `path` under `id`, replacing any previous slide with that id.
+    pub fn insert(&self, id: &str, path: &Path) {
+        let file = SlideFile {
+            path: path.to_path_buf(),
+            modified: modified(path),
+        };
+        // The file may have been replaced since it was opened
+        self.pool.remove(path);
+        if let Some(previous) = self.files.write().unwrap().insert(id.to_string(), file) {
+            self.pool.remove(&previous.path);
+        }
+    }
+
+    /// Stop serving the slide with the given id.
+    pub fn remove(&self, id: &str) {
+        if let Some(file) = self.files.write().unwrap().remove(id) {
+            self.pool.remove(&file.path);
+        }
+    }
+
+    /// The ids of the served slides, sorted.
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.files.read().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// The path of the slide with the given id.
+    pub fn path(&self, id: &str) -> Option<PathBuf> {
+        self.files
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|file| file.path.clone())
+    }
+
+    /// The number of slides currently open.
+    pub fn open_count(&self) -> usize {
+        self.pool.len()
+    }
+
+    /// Rescan the watched directory: serve new slide files, reopen replaced ones and
+    /// stop serving deleted ones. Does nothing for stores without a directory.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the directory could not be read.
+    pub fn refresh(&self) -> Result<()> {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return Ok(()),
+        };
+
+        let mut found = HashMap::new();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if !path.is_file() || OpenSlide::detect_vendor(&path).is_err() {
+                continue;
+            }
+            if let Some(stem) = path.file_stem() {
+                let file = SlideFile {
+                    modified: modified(&path),
+                    path: path.clone(),
+                };
+                found.insert(stem.to_string_lossy().into_owned(), file);
+            }
+        }
+
+        let mut files = self.files.write().unwrap();
+        for (id, file) in files.iter() {
+            if found.get(id) != Some(file) {
+                self.pool.remove(&file.path);
+            }
+        }
+        *files = found;
+        Ok(())
+    }
+
+    /// Call [`refresh`](struct.SlideStore.html#method.refresh) every `interval` on a
+    /// background thread, until the store is dropped.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let store: Weak<SlideStore> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match store.upgrade() {
+                // A directory that is temporarily unreadable is retried on the next tick
+                Some(store) => {
+                    let _ = store.refresh();
+                }
+                None => return,
+            }
+        })
+    }
+
+    fn open_slide(&self, id: &str) -> Result<Option<Arc<OpenSlide>>> {
+        match self.path(id) {
+            Some(path) => self.pool.get(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl SlideSource for SlideStore {
+    fn get(&self, id: &str) -> Result<Option<Arc<OpenSlide>>> {
+        match self.open_slide(id) {
+            // The file was deleted since the last refresh
+            Err(OpenSlideError::MissingFile(_)) => {
+                self.remove(id);
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,183 @@
+//! This module provides color statistics of tiles, such as the per-channel mean and
+//! standard deviation used to normalize training inputs, computed in a single
+//! streaming pass.
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::Result;
+
+/// Color statistics of a set of tiles, over their non-transparent pixels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelStats {
+    /// The number of pixels
+    pub count: u64,
+    /// The mean of the red, green and blue channels, from 0 to 1
+    pub mean: [f64; 3],
+    /// The standard deviation of the red, green and blue channels, from 0 to 1
+    pub std: [f64; 3],
+    /// The 256 bins histograms of the red, green and blue channels, if requested
+    pub histograms: Option<Vec<Vec<u64>>>,
+}
+
+/// Accumulates color statistics tile after tile, in constant memory.
+///
+/// Accumulators of disjoint tile sets, such as the slides of a cohort or the
+/// batches of several threads, can be merged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsAccumulator {
+    count: u64,
+    // Integer sums are exact, whatever the order tiles are added and merged in
+    sum: [u64; 3],
+    squares: [u64; 3],
+    histograms: Option<Vec<[u64; 256]>>,
+}
+
+impl StatsAccumulator {
+    /// Create an empty accumulator, also counting channel histograms if
+    /// `histograms` is true.
+    pub fn new(histograms: bool) -> StatsAccumulator {
+        StatsAccumulator {
+            count: 0,
+            sum: [0; 3],
+            squares: [0; 3],
+            histograms: if histograms {
+                Some(vec![[0; 256]; 3])
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Add the pixels of a tile; transparent pixels, outside the slide, are skipped.
+    pub fn add(&mut self, tile: &RgbaImage) {
+        for pixel in tile.pixels() {
+            if pixel.0[3] == 0 {
+                continue;
+            }
+            self.count += 1;
+            for (channel, value) in pixel.0[..3].iter().enumerate() {
+                self.sum[channel] += u64::from(*value);
+                self.squares[channel] += u64::from(*value) * u64::from(*value);
+                if let Some(histograms) = &mut self.histograms {
+                    histograms[channel][*value as usize] += 1;
+                }
+            }
+        }
+    }
+
+    /// Add the statistics of another accumulator.
+    pub fn merge(&mut self, other: &StatsAccumulator) {
+        self.count += other.count;
+        for (sum, other) in self.sum.iter_mut().zip(&other.sum) {
+            *sum += other;
+        }
+        for (squares, other) in self.squares.iter_mut().zip(&other.squares) {
+            *squares += other;
+        }
+        self.histograms = match (self.histograms.take(), &other.histograms) {
+            (Some(mut histograms), Some(others)) => {
+                for (histogram, other) in histograms.iter_mut().zip(others) {
+                    for (bin, count) in histogram.iter_mut().zip(other.iter()) {
+                        *bin += count;
+                    }
+                }
+                Some(histograms)
+            }
+            // Histograms are only complete if both sides counted them
+            _ => None,
+        };
+    }
+
+    /// The number of pixels added so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The statistics of the pixels added so far: zero means and deviations if there
+    /// were none.
+    pub fn stats(&self) -> ChannelStats {
+        let count = self.count.max(1) as f64;
+        let moments = |channel: usize| {
+            let mean = self.sum[channel] as f64 / count;
+            let variance = (self.squares[channel] as f64 / count - mean * mean).max(0.);
+            (mean / 255., variance.sqrt() / 255.)
+        };
+        let channels = [moments(0), moments(1), moments(2)];
+
+        ChannelStats {
+            count: self.count,
+            mean: [channels[0].0, channels[1].0, channels[2].0],
+            std: [channels[0].1, channels[1].1, channels[2].1],
+            histograms: self.histograms.as_ref().map(|histograms| {
+                histograms
+                    .iter()
+                    .map(|histogram| histogram.to_vec())
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Compute the color statistics of tiles, such as the patches of a
+/// [`PatchSampler`](struct.PatchSampler.html) or a
+/// [`PatchLoader`](struct.PatchLoader.html), reading them one at a time.
+///
+/// # Arguments
+///
+/// * `tiles` - the tiles, or the errors met reading them.
+/// * `histograms` - true to also count the channel histograms.
+///
+/// # Errors
+///
+/// The first error of `tiles`.
+pub fn tile_stats<I>(tiles: I, histograms: bool) -> Result<ChannelStats>
+where
+    I: IntoIterator<Item = Result<RgbaImage>>,
+{
+    let mut accumulator = StatsAccumulator::new(histograms);
+    for tile in tiles {
+        accumulator.add(&tile?);
+    }
+    Ok(accumulator.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_stats() {
+        // Half black, half white red channel; transparent pixels are ignored
+        let tile = RgbaImage::from_fn(4, 4, |x, y| match (x, y) {
+            (_, 3) => Rgba([10, 10, 10, 0]),
+            (0, _) | (1, _) => Rgba([0, 51, 255, 255]),
+            _ => Rgba([255, 51, 255, 255]),
+        });
+
+        let stats = tile_stats(vec![Ok(tile.clone())], true).unwrap();
+        assert_eq!(stats.count, 12);
+        assert_eq!(stats.mean, [0.5, 0.2, 1.]);
+        assert_eq!(stats.std, [0.5, 0., 0.]);
+        let histograms = stats.histograms.unwrap();
+        assert_eq!(histograms[0][0], 6);
+        assert_eq!(histograms[0][255], 6);
+        assert_eq!(histograms[1][51], 12);
+
+        // Merging gives the statistics of the union
+        let mut first = StatsAccumulator::new(false);
+        first.add(&tile);
+        let mut second = StatsAccumulator::new(false);
+        second.add(&RgbaImage::from_pixel(2, 6, Rgba([0, 51, 255, 255])));
+        first.merge(&second);
+        let merged = first.stats();
+        assert_eq!(merged.count, 24);
+        assert_eq!(merged.mean[0], 0.25);
+        assert!(merged.histograms.is_none());
+
+        let empty = StatsAccumulator::new(true).stats();
+        assert_eq!(empty.count, 0);
+        assert_eq!(empty.mean, [0.; 3]);
+    }
+}
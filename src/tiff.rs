@@ -0,0 +1,437 @@
+//! This module provides a minimal reader and in-place editor of classic and BigTIFF
+//! file structures, for the TIFF based slide formats.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::{OpenSlideError, Result};
+
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_TILE_OFFSETS: u16 = 324;
+const TAG_TILE_BYTE_COUNTS: u16 = 325;
+/// The embedded ICC color profile.
+pub(crate) const TAG_ICC_PROFILE: u16 = 34675;
+
+/// A TIFF directory entry.
+pub(crate) struct Entry {
+    pub(crate) tag: u16,
+    pub(crate) field_type: u16,
+    pub(crate) count: u64,
+    /// File position of the value or offset field.
+    pub(crate) position: u64,
+}
+
+/// A TIFF image file directory.
+pub(crate) struct Directory {
+    pub(crate) entries: Vec<Entry>,
+    /// File position of the pointer to the next directory.
+    pub(crate) next_position: u64,
+    /// Offset of the next directory, 0 for the last one.
+    pub(crate) next: u64,
+}
+
+impl Directory {
+    pub(crate) fn entry(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+/// A classic or BigTIFF file, opened for reading or for in-place modification.
+pub(crate) struct TiffFile {
+    pub(crate) file: File,
+    pub(crate) little_endian: bool,
+    pub(crate) big_tiff: bool,
+}
+
+impl TiffFile {
+    /// Open a TIFF file, for modification if `writable`. Returns `None` if the file is
+    /// not a TIFF.
+    pub(crate) fn open(path: &Path, writable: bool) -> Result<Option<TiffFile>> {
+        let file = OpenOptions::new().read(true).write(writable).open(path)?;
+        let mut tiff = TiffFile {
+            file,
+            little_endian: true,
+            big_tiff: false,
+        };
+
+        let mut header = [0u8; 4];
+        if tiff.file.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        tiff.little_endian = match &header[..2] {
+            b"II" => true,
+            b"MM" => false,
+            _ => return Ok(None),
+        };
+        tiff.big_tiff = match tiff.read_u16(2)? {
+            42 => false,
+            43 => true,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(tiff))
+    }
+
+    pub(crate) fn len(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub(crate) fn first_directory_pointer(&self) -> u64 {
+        if self.big_tiff {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Walk the directory chain.
+    pub(crate) fn directories(&mut self) -> Result<Vec<Directory>> {
+        let mut directories = Vec::new();
+        let mut visited = HashSet::new();
+        let mut offset = self.read_offset(self.first_directory_pointer())?;
+
+        while offset != 0 && visited.insert(offset) {
+            let (count, entries_position, entry_size) = if self.big_tiff {
+                (self.read_u64(offset)?, offset + 8, 20)
+            } else {
+                (u64::from(self.read_u16(offset)?), offset + 2, 12)
+            };
+
+            // Check the directory fits in the file before trusting its entry count.
+            let size = count
+                .checked_mul(entry_size)
+                .and_then(|size| size.checked_add(if self.big_tiff { 8 } else { 4 }));
+            self.check_span(entries_position, size)?;
+
+            let mut entries = Vec::with_capacity(count as _);
+            for i in 0..count {
+                let position = entries_position + i * entry_size;
+                let count = if self.big_tiff {
+                    self.read_u64(position + 4)?
+                } else {
+                    u64::from(self.read_u32(position + 4)?)
+                };
+                entries.push(Entry {
+                    tag: self.read_u16(position)?,
+                    field_type: self.read_u16(position + 2)?,
+                    count,
+                    position: position + if self.big_tiff { 12 } else { 8 },
+                });
+            }
+
+            let next_position = entries_position + count * entry_size;
+            let next = self.read_offset(next_position)?;
+            directories.push(Directory {
+                entries,
+                next_position,
+                next,
+            });
+            offset = next;
+        }
+
+        Ok(directories)
+    }
+
+    /// Zero the strips or tiles of a directory.
+    pub(crate) fn blank_image_data(&mut self, directory: &Directory) -> Result<()> {
+        let len = self.len()?;
+        for (offsets_tag, counts_tag) in [
+            (TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS),
+            (TAG_TILE_OFFSETS, TAG_TILE_BYTE_COUNTS),
+        ] {
+            if let (Some(offsets), Some(counts)) =
+                (directory.entry(offsets_tag), directory.entry(counts_tag))
+            {
+                let offsets = self.read_integers(offsets)?;
+                let counts = self.read_integers(counts)?;
+                for (offset, count) in offsets.into_iter().zip(counts) {
+                    if offset.checked_add(count).map_or(true, |end| end > len) {
+                        return Err(past_end(offset, count));
+                    }
+                    self.write_bytes(offset, &vec![0u8; count as _])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the value of an ASCII entry, padding with NUL bytes so that the
+    /// entry keeps its size.
+    pub(crate) fn rewrite_ascii<F>(&mut self, entry: &Entry, rewrite: F) -> Result<()>
+    where
+        F: Fn(&[u8]) -> Vec<u8>,
+    {
+        if entry.field_type != 2 {
+            return Ok(());
+        }
+
+        let value = self.read_ascii(entry)?;
+        let (position, size) = self.value_span(entry)?;
+        let mut new_value = rewrite(&value);
+        new_value.resize(size as _, 0);
+
+        self.write_bytes(position, &new_value)
+    }
+
+    /// Read the value of an ASCII entry, without its trailing NUL bytes.
+    pub(crate) fn read_ascii(&mut self, entry: &Entry) -> Result<Vec<u8>> {
+        let (position, size) = self.value_span(entry)?;
+        let mut value = vec![0u8; size as _];
+        self.file.seek(SeekFrom::Start(position))?;
+        self.file.read_exact(&mut value)?;
+
+        while value.last() == Some(&0) {
+            value.pop();
+        }
+        Ok(value)
+    }
+
+    /// Read the raw bytes of the values of an entry.
+    pub(crate) fn read_raw(&mut self, entry: &Entry) -> Result<Vec<u8>> {
+        let (position, size) = self.value_span(entry)?;
+        let mut value = vec![0u8; size as _];
+        self.file.seek(SeekFrom::Start(position))?;
+        self.file.read_exact(&mut value)?;
+        Ok(value)
+    }
+
+    /// Read the values of an unsigned integer entry.
+    pub(crate) fn read_integers(&mut self, entry: &Entry) -> Result<Vec<u64>> {
+        let (position, _) = self.value_span(entry)?;
+        let size = type_size(entry.field_type);
+
+        (0..entry.count)
+            .map(|i| {
+                let position = position + i * size;
+                match size {
+                    2 => self.read_u16(position).map(u64::from),
+                    4 => self.read_u32(position).map(u64::from),
+                    _ => self.read_u64(position),
+                }
+            })
+            .collect()
+    }
+
+    /// Read the first value of a numeric entry as a float.
+    pub(crate) fn read_float(&mut self, entry: &Entry) -> Result<f64> {
+        let (position, _) = self.value_span(entry)?;
+        Ok(match entry.field_type {
+            8 => f64::from(self.read_u16(position)? as i16),
+            9 => f64::from(self.read_u32(position)? as i32),
+            11 => f64::from(f32::from_bits(self.read_u32(position)?)),
+            12 => f64::from_bits(self.read_u64(position)?),
+            _ => self.read_integers(entry)?.first().copied().unwrap_or(0) as f64,
+        })
+    }
+
+    /// Return the file position and size in bytes of the values of an entry, checking
+    /// that they lie within the file, as entry counts are not to be trusted.
+    fn value_span(&mut self, entry: &Entry) -> Result<(u64, u64)> {
+        let size = entry.count.checked_mul(type_size(entry.field_type));
+        let inline_size = if self.big_tiff { 8 } else { 4 };
+        let position = match size {
+            Some(size) if size <= inline_size => entry.position,
+            _ => self.read_offset(entry.position)?,
+        };
+        let size = self.check_span(position, size)?;
+        Ok((position, size))
+    }
+
+    /// Check that `size` bytes from `position` lie within the file, `None` standing
+    /// for an overflowing size, and return the size.
+    fn check_span(&self, position: u64, size: Option<u64>) -> Result<u64> {
+        let len = self.len()?;
+        match size {
+            Some(size) if position.checked_add(size).map_or(false, |end| end <= len) => Ok(size),
+            _ => Err(past_end(position, size.unwrap_or(u64::MAX))),
+        }
+    }
+
+    fn read_offset(&mut self, position: u64) -> Result<u64> {
+        if self.big_tiff {
+            self.read_u64(position)
+        } else {
+            self.read_u32(position).map(u64::from)
+        }
+    }
+
+    pub(crate) fn write_offset(&mut self, position: u64, offset: u64) -> Result<()> {
+        if self.big_tiff {
+            let bytes = if self.little_endian {
+                offset.to_le_bytes()
+            } else {
+                offset.to_be_bytes()
+            };
+            self.write_bytes(position, &bytes)
+        } else {
+            let offset = offset as u32;
+            let bytes = if self.little_endian {
+                offset.to_le_bytes()
+            } else {
+                offset.to_be_bytes()
+            };
+            self.write_bytes(position, &bytes)
+        }
+    }
+
+    fn read_bytes<const N: usize>(&mut self, position: u64) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.file.seek(SeekFrom::Start(position))?;
+        self.file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_u16(&mut self, position: u64) -> Result<u16> {
+        let bytes = self.read_bytes(position)?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&mut self, position: u64) -> Result<u32> {
+        let bytes = self.read_bytes(position)?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u64(&mut self, position: u64) -> Result<u64> {
+        let bytes = self.read_bytes(position)?;
+        Ok(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+
+    fn write_bytes(&mut self, position: u64, bytes: &[u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(position))?;
+        self.file.write_all(bytes)?;
+        Ok(())
+    }
+}
+
+/// The error for TIFF data running past the end of the file.
+fn past_end(position: u64, size: u64) -> OpenSlideError {
+    OpenSlideError::IoError(format!(
+        "TIFF data of {} bytes at offset {} runs past the end of the file",
+        size, position
+    ))
+}
+
+/// Size in bytes of a TIFF field type.
+fn type_size(field_type: u16) -> u64 {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 | 16 | 17 | 18 => 8,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A little endian classic TIFF with a single directory holding `entries`, whose
+    /// values larger than 4 bytes follow the directory.
+    fn build_tiff(entries: &[(u16, u16, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend(8u32.to_le_bytes());
+
+        let mut data_offset = 8 + 2 + entries.len() as u32 * 12 + 4;
+        let mut data: Vec<u8> = Vec::new();
+        bytes.extend((entries.len() as u16).to_le_bytes());
+        for (tag, field_type, count, value) in entries {
+            bytes.extend(tag.to_le_bytes());
+            bytes.extend(field_type.to_le_bytes());
+            bytes.extend(count.to_le_bytes());
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                bytes.extend(inline);
+            } else {
+                bytes.extend(data_offset.to_le_bytes());
+                data_offset += value.len() as u32;
+                data.extend(value);
+            }
+        }
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let profile: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let tiff = build_tiff(&[
+            (256, 3, 1, 300u16.to_le_bytes().to_vec()),
+            (270, 2, 6, b"hello\0".to_vec()),
+            (TAG_ICC_PROFILE, 7, 200, profile.clone()),
+        ]);
+        let path = Path::new("tests/artifacts/test_tiff_entries.tiff");
+        fs::write(path, tiff).unwrap();
+
+        let mut tiff = TiffFile::open(path, false).unwrap().unwrap();
+        assert!(tiff.little_endian);
+        assert!(!tiff.big_tiff);
+        let directories = tiff.directories().unwrap();
+        assert_eq!(directories.len(), 1);
+        let directory = &directories[0];
+        assert_eq!(directory.next, 0);
+
+        assert_eq!(
+            tiff.read_integers(directory.entry(256).unwrap()).unwrap(),
+            vec![300]
+        );
+        assert_eq!(
+            tiff.read_ascii(directory.entry(270).unwrap()).unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            tiff.read_raw(directory.entry(TAG_ICC_PROFILE).unwrap())
+                .unwrap(),
+            profile
+        );
+        assert!(directory.entry(257).is_none());
+    }
+
+    #[test]
+    fn test_oversized_count() {
+        let tiff = build_tiff(&[
+            (270, 2, u32::MAX, b"hello\0\0\0".to_vec()),
+            (TAG_ICC_PROFILE, 16, u32::MAX, vec![0u8; 8]),
+        ]);
+        let path = Path::new("tests/artifacts/test_tiff_oversized_count.tiff");
+        fs::write(path, tiff).unwrap();
+
+        let mut tiff = TiffFile::open(path, false).unwrap().unwrap();
+        let directories = tiff.directories().unwrap();
+        let directory = &directories[0];
+        assert!(tiff.read_ascii(directory.entry(270).unwrap()).is_err());
+        assert!(tiff
+            .read_raw(directory.entry(TAG_ICC_PROFILE).unwrap())
+            .is_err());
+        assert!(tiff
+            .read_integers(directory.entry(TAG_ICC_PROFILE).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_not_tiff() {
+        let path = Path::new("tests/artifacts/test_tiff_not_tiff.tiff");
+        fs::write(path, b"PK\x03\x04 not a tiff").unwrap();
+        assert!(TiffFile::open(path, false).unwrap().is_none());
+
+        fs::write(path, b"II").unwrap();
+        assert!(TiffFile::open(path, false).unwrap().is_none());
+    }
+}
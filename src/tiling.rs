@@ -0,0 +1,214 @@
+//! This module estimates the cost of tiling a slide before exporting it: how many
+//! tiles hold tissue, how large the export is in each format and how long it takes,
+//! from a dry run over a sample of the tiles.
+
+use std::time::{Duration, Instant};
+
+use crate::deepzoom::DeepZoom;
+use crate::encode::{encode, Format};
+use crate::openslide::{Address, OpenSlide, Size};
+use crate::pyramid::BackgroundFilter;
+use crate::Result;
+
+/// The maximum number of tiles read from each level.
+const SAMPLED_TILES: u64 = 16;
+
+/// The formats export sizes are estimated for.
+const FORMATS: [Format; 3] = [
+    Format::Jpeg { quality: 75 },
+    Format::Png,
+    Format::Webp { quality: 75 },
+];
+
+/// The estimated export cost of a level in a format.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FormatEstimate {
+    /// The format
+    pub format: Format,
+    /// The size of all the tiles
+    pub bytes: u64,
+    /// The size of the tissue tiles, when background tiles are skipped
+    pub tissue_bytes: u64,
+    /// The time spent encoding all the tiles
+    pub encode_time: Duration,
+}
+
+/// The estimated export cost of a Deep Zoom level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelReport {
+    /// The Deep Zoom level
+    pub level: usize,
+    /// The dimensions of the level
+    pub dimensions: Size,
+    /// The number of tiles
+    pub tiles: u64,
+    /// The number of tiles read by the dry run
+    pub sampled_tiles: u64,
+    /// The estimated number of tiles holding tissue
+    pub tissue_tiles: u64,
+    /// The estimated time spent reading all the tiles
+    pub read_time: Duration,
+    /// The estimates of each format
+    pub formats: Vec<FormatEstimate>,
+}
+
+/// The estimated export cost of a whole Deep Zoom pyramid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TilingReport {
+    /// The width and height of a single tile
+    pub tile_size: u32,
+    /// The Deep Zoom levels, from the smallest to the largest
+    pub levels: Vec<LevelReport>,
+}
+
+impl TilingReport {
+    /// The number of tiles of the pyramid.
+    pub fn tiles(&self) -> u64 {
+        self.levels.iter().map(|level| level.tiles).sum()
+    }
+
+    /// The estimated number of tiles holding tissue.
+    pub fn tissue_tiles(&self) -> u64 {
+        self.levels.iter().map(|level| level.tissue_tiles).sum()
+    }
+
+    /// The estimated number of empty background tiles.
+    pub fn empty_tiles(&self) -> u64 {
+        self.tiles() - self.tissue_tiles()
+    }
+
+    /// The estimated size of the pyramid in a format, or `None` if the format was not
+    /// estimated.
+    pub fn bytes(&self, format: Format) -> Option<u64> {
+        self.levels
+            .iter()
+            .map(|level| {
+                level
+                    .formats
+                    .iter()
+                    .find(|estimate| estimate.format == format)
+                    .map(|estimate| estimate.bytes)
+            })
+            .sum()
+    }
+
+    /// The estimated sequential time of reading every tile of the pyramid, without
+    /// encoding.
+    pub fn read_time(&self) -> Duration {
+        self.levels.iter().map(|level| level.read_time).sum()
+    }
+}
+
+/// Estimate the cost of exporting the Deep Zoom pyramid of a slide, without overlap.
+///
+/// Up to 16 tiles evenly spread over each level are read sequentially, timed,
+/// classified with the default [`BackgroundFilter`](struct.BackgroundFilter.html)
+/// and encoded in JPEG and WebP of quality 75 and in PNG. The counts, sizes and times
+/// of each level are extrapolated from its sample, and are exact for levels of at
+/// most 16 tiles.
+///
+/// # Arguments
+///
+/// * `slide` - the slide.
+/// * `tile_size` - the width and height of a single tile.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `tile_size` is 0.
+/// * [`OpenSlideError::ImageError`](enum.OpenSlideError.html#variant.ImageError): a tile could not be encoded.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn tiling_report(slide: &OpenSlide, tile_size: u32) -> Result<TilingReport> {
+    let deepzoom = DeepZoom::new(slide, tile_size, 0, false)?;
+    let filter = BackgroundFilter::default();
+
+    let levels = (0..deepzoom.level_count())
+        .map(|level| {
+            let grid = deepzoom.level_tiles()[level];
+            let tiles = deepzoom.tile_count_at(level)?;
+            let sampled_tiles = tiles.min(SAMPLED_TILES);
+
+            let mut tissue = 0;
+            let mut read_time = Duration::default();
+            let mut sizes = vec![(0, 0, Duration::default()); FORMATS.len()];
+            for index in sample(tiles, sampled_tiles) {
+                let address = Address {
+                    x: (index % u64::from(grid.w)) as u32,
+                    y: (index / u64::from(grid.w)) as u32,
+                };
+                let start = Instant::now();
+                let tile = deepzoom.read_tile(level, address)?;
+                read_time += start.elapsed();
+
+                let is_tissue = !filter.is_background(&tile);
+                if is_tissue {
+                    tissue += 1;
+                }
+                for (format, (bytes, tissue_bytes, encode_time)) in FORMATS.iter().zip(&mut sizes) {
+                    let start = Instant::now();
+                    let size = encode(&tile, *format)?.len() as u64;
+                    *encode_time += start.elapsed();
+                    *bytes += size;
+                    if is_tissue {
+                        *tissue_bytes += size;
+                    }
+                }
+            }
+
+            let scale_count = |sampled: u64| extrapolate(sampled, sampled_tiles, tiles);
+            let scale_time =
+                |sampled: Duration| sampled.mul_f64(tiles as f64 / sampled_tiles.max(1) as f64);
+            Ok(LevelReport {
+                level,
+                dimensions: deepzoom.level_dimensions()[level],
+                tiles,
+                sampled_tiles,
+                tissue_tiles: scale_count(tissue),
+                read_time: scale_time(read_time),
+                formats: FORMATS
+                    .iter()
+                    .zip(&sizes)
+                    .map(
+                        |(format, (bytes, tissue_bytes, encode_time))| FormatEstimate {
+                            format: *format,
+                            bytes: scale_count(*bytes),
+                            tissue_bytes: scale_count(*tissue_bytes),
+                            encode_time: scale_time(*encode_time),
+                        },
+                    )
+                    .collect(),
+            })
+        })
+        .collect::<Result<Vec<LevelReport>>>()?;
+
+    Ok(TilingReport { tile_size, levels })
+}
+
+/// The row-major indices of `count` tiles evenly spread over `tiles`.
+fn sample(tiles: u64, count: u64) -> impl Iterator<Item = u64> {
+    (0..count).map(move |index| index * tiles / count)
+}
+
+/// Scale a quantity measured over `sampled` tiles to `tiles` tiles.
+fn extrapolate(value: u64, sampled: u64, tiles: u64) -> u64 {
+    if sampled == 0 {
+        0
+    } else {
+        (value as f64 * tiles as f64 / sampled as f64).round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(sample(4, 4).collect::<Vec<u64>>(), vec![0, 1, 2, 3]);
+        assert_eq!(sample(100, 4).collect::<Vec<u64>>(), vec![0, 25, 50, 75]);
+        assert_eq!(sample(0, 0).count(), 0);
+
+        assert_eq!(extrapolate(3, 4, 100), 75);
+        assert_eq!(extrapolate(3, 4, 4), 3);
+        assert_eq!(extrapolate(0, 0, 0), 0);
+    }
+}
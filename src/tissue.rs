@@ -0,0 +1,319 @@
+//! This module provides tissue detection: a binary mask of the slide computed on a
+//! downsampled thumbnail, the usual first step of whole slide image pipelines.
+
+use image::{GrayImage, Luma, RgbaImage};
+
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::{OpenSlideError, Result};
+
+/// Mask value of tissue pixels.
+pub const TISSUE: u8 = 255;
+
+/// Mask value of background pixels.
+pub const BACKGROUND: u8 = 0;
+
+/// Parameters of [`tissue_mask()`](fn.tissue_mask.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TissueParams {
+    /// The longest side of the thumbnail the mask is computed on, in pixels
+    pub thumbnail_size: u32,
+    /// The luminance at or below which a pixel is tissue, or `None` to select it
+    /// with Otsu's method
+    pub threshold: Option<u8>,
+    /// The radius of the square opening removing isolated tissue pixels, 0 to disable
+    pub opening_radius: u32,
+    /// The radius of the square closing filling small holes in the tissue, 0 to
+    /// disable
+    pub closing_radius: u32,
+}
+
+impl Default for TissueParams {
+    fn default() -> Self {
+        TissueParams {
+            thumbnail_size: 1024,
+            threshold: None,
+            opening_radius: 1,
+            closing_radius: 2,
+        }
+    }
+}
+
+/// Compute a tissue mask of the slide.
+///
+/// The slide thumbnail is converted to luminance, transparent regions counting as
+/// white glass, and pixels darker than the threshold are tissue. The mask has the
+/// dimensions of the thumbnail: tissue pixels are [`TISSUE`](constant.TISSUE.html),
+/// other pixels [`BACKGROUND`](constant.BACKGROUND.html).
+///
+/// # Arguments
+///
+/// * `slide` - the slide.
+/// * `params` - the thumbnail size, threshold and morphology cleanup.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `thumbnail_size` is 0.
+pub fn tissue_mask(slide: &OpenSlide, params: TissueParams) -> Result<GrayImage> {
+    if params.thumbnail_size == 0 {
+        return Err(OpenSlideError::InvalidArgument(
+            "Thumbnail size must be positive".to_string(),
+        ));
+    }
+
+    // Thumbnails are never larger than level 0
+    let dimensions = slide.dimensions()?;
+    let side = params.thumbnail_size.min(dimensions.w.max(dimensions.h));
+    let thumbnail = slide.thumbnail(Size { w: side, h: side })?;
+
+    Ok(mask_from_luminance(&luminance(&thumbnail), params))
+}
+
+/// Select the threshold separating the two classes of `image` with Otsu's method:
+/// pixels at or below it form the darker class.
+///
+/// Returns 0 for images with less than two distinct values.
+pub fn otsu_threshold(image: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum();
+
+    let mut best = (0u8, 0f64);
+    let mut weight = 0u64;
+    let mut weighted_sum = 0f64;
+    for (value, count) in histogram.iter().enumerate().take(255) {
+        weight += count;
+        weighted_sum += value as f64 * *count as f64;
+        if weight == 0 || weight == total {
+            continue;
+        }
+
+        let dark_mean = weighted_sum / weight as f64;
+        let light_mean = (sum - weighted_sum) / (total - weight) as f64;
+        let variance = weight as f64 * (total - weight) as f64 * (dark_mean - light_mean).powi(2);
+        if variance > best.1 {
+            best = (value as u8, variance);
+        }
+    }
+    best.0
+}
+
+/// Return the level 0 region covering the tissue pixels of a mask of the whole
+/// slide, or an empty region at the origin if there is none.
+pub(crate) fn mask_bounds(mask: &GrayImage, dimensions: Size) -> Region {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in mask.enumerate_pixels() {
+        if pixel.0[0] == TISSUE {
+            bounds = Some(match bounds {
+                Some((min_x, min_y, max_x, max_y)) => {
+                    (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+                }
+                None => (x, y, x, y),
+            });
+        }
+    }
+
+    let (min_x, min_y, max_x, max_y) = match bounds {
+        Some(bounds) => bounds,
+        None => {
+            return Region {
+                address: Address { x: 0, y: 0 },
+                level: 0,
+                size: Size { w: 0, h: 0 },
+            }
+        }
+    };
+    // Mask pixels partially covering level 0 pixels count as a whole
+    let scale = |start: u32, end: u32, mask_limit: u32, limit: u32| {
+        let ratio = f64::from(limit) / f64::from(mask_limit);
+        let start = (f64::from(start) * ratio).floor() as u32;
+        let end = ((f64::from(end + 1) * ratio).ceil() as u32).min(limit);
+        (start, end - start)
+    };
+    let (x, w) = scale(min_x, max_x, mask.width(), dimensions.w);
+    let (y, h) = scale(min_y, max_y, mask.height(), dimensions.h);
+    Region {
+        address: Address { x, y },
+        level: 0,
+        size: Size { w, h },
+    }
+}
+
+/// Threshold a luminance image and clean the result up.
+fn mask_from_luminance(luminance: &GrayImage, params: TissueParams) -> GrayImage {
+    let threshold = params
+        .threshold
+        .unwrap_or_else(|| otsu_threshold(luminance));
+    let mut mask = GrayImage::from_fn(luminance.width(), luminance.height(), |x, y| {
+        if luminance.get_pixel(x, y).0[0] <= threshold {
+            Luma([TISSUE])
+        } else {
+            Luma([BACKGROUND])
+        }
+    });
+
+    if params.opening_radius > 0 {
+        mask = erode(&mask, params.opening_radius);
+        mask = dilate(&mask, params.opening_radius);
+    }
+    if params.closing_radius > 0 {
+        mask = dilate(&mask, params.closing_radius);
+        mask = erode(&mask, params.closing_radius);
+    }
+    mask
+}
+
+/// Return the luminance of `image`, composited over white.
+fn luminance(image: &RgbaImage) -> GrayImage {
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let y = 0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b);
+        let alpha = f32::from(a) / 255.;
+        Luma([(y * alpha + 255. * (1. - alpha)).round() as u8])
+    })
+}
+
+/// Erode a mask with a square of side `2 * radius + 1`, pixels outside the image
+/// being ignored.
+fn erode(mask: &GrayImage, radius: u32) -> GrayImage {
+    filter(&filter(mask, radius, true, u8::min), radius, false, u8::min)
+}
+
+/// Dilate a mask with a square of side `2 * radius + 1`.
+fn dilate(mask: &GrayImage, radius: u32) -> GrayImage {
+    filter(&filter(mask, radius, true, u8::max), radius, false, u8::max)
+}
+
+/// Combine each pixel with its neighbours within `radius` along one axis: square
+/// structuring elements are separable.
+fn filter(mask: &GrayImage, radius: u32, horizontal: bool, combine: fn(u8, u8) -> u8) -> GrayImage {
+    let (width, height) = mask.dimensions();
+    GrayImage::from_fn(width, height, |x, y| {
+        let (position, limit) = if horizontal { (x, width) } else { (y, height) };
+        let start = position.saturating_sub(radius);
+        let end = (position + radius).min(limit - 1);
+        Luma([(start..=end)
+            .map(|i| {
+                let (x, y) = if horizontal { (i, y) } else { (x, i) };
+                mask.get_pixel(x, y).0[0]
+            })
+            .fold(mask.get_pixel(x, y).0[0], combine)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A light image with a dark square of side `side` at (`x`, `y`).
+    fn square(size: u32, x: u32, y: u32, side: u32) -> GrayImage {
+        GrayImage::from_fn(size, size, |i, j| {
+            if (x..x + side).contains(&i) && (y..y + side).contains(&j) {
+                Luma([60 + (i % 3) as u8])
+            } else {
+                Luma([230 + (j % 5) as u8])
+            }
+        })
+    }
+
+    fn tissue_pixels(mask: &GrayImage) -> usize {
+        mask.pixels().filter(|pixel| pixel.0[0] == TISSUE).count()
+    }
+
+    #[test]
+    fn test_otsu_threshold() {
+        let threshold = otsu_threshold(&square(20, 5, 5, 10));
+        assert!((62..230).contains(&threshold), "{}", threshold);
+
+        assert_eq!(otsu_threshold(&GrayImage::new(0, 0)), 0);
+        assert_eq!(otsu_threshold(&GrayImage::from_pixel(4, 4, Luma([200]))), 0);
+    }
+
+    #[test]
+    fn test_mask() {
+        let params = TissueParams {
+            opening_radius: 0,
+            closing_radius: 0,
+            ..TissueParams::default()
+        };
+        let mask = mask_from_luminance(&square(20, 5, 5, 10), params);
+        assert_eq!(tissue_pixels(&mask), 100);
+        assert_eq!(mask.get_pixel(5, 5).0[0], TISSUE);
+        assert_eq!(mask.get_pixel(4, 5).0[0], BACKGROUND);
+
+        let fixed = TissueParams {
+            threshold: Some(10),
+            ..params
+        };
+        assert_eq!(
+            tissue_pixels(&mask_from_luminance(&square(20, 5, 5, 10), fixed)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_mask_bounds() {
+        let dimensions = Size { w: 100, h: 40 };
+        let mut mask = GrayImage::new(10, 4);
+        assert_eq!(mask_bounds(&mask, dimensions).size, Size { w: 0, h: 0 });
+
+        mask.put_pixel(2, 1, Luma([TISSUE]));
+        mask.put_pixel(6, 2, Luma([TISSUE]));
+        let bounds = mask_bounds(&mask, dimensions);
+        assert_eq!(bounds.address, Address { x: 20, y: 10 });
+        assert_eq!(bounds.size, Size { w: 50, h: 20 });
+
+        // The last mask pixels are clamped to the slide edge
+        let bounds = mask_bounds(&mask, Size { w: 95, h: 39 });
+        mask.put_pixel(9, 3, Luma([TISSUE]));
+        let bounds_edge = mask_bounds(&mask, Size { w: 95, h: 39 });
+        assert_eq!(bounds.address, Address { x: 19, y: 9 });
+        assert_eq!(bounds_edge.address.x + bounds_edge.size.w, 95);
+        assert_eq!(bounds_edge.address.y + bounds_edge.size.h, 39);
+    }
+
+    #[test]
+    fn test_morphology() {
+        let mut image = square(32, 4, 4, 16);
+        // An isolated speck, and a hole in the square
+        image.put_pixel(28, 28, Luma([0]));
+        image.put_pixel(12, 12, Luma([255]));
+
+        let raw = mask_from_luminance(
+            &image,
+            TissueParams {
+                opening_radius: 0,
+                closing_radius: 0,
+                ..TissueParams::default()
+            },
+        );
+        assert_eq!(raw.get_pixel(28, 28).0[0], TISSUE);
+        assert_eq!(raw.get_pixel(12, 12).0[0], BACKGROUND);
+
+        let cleaned = mask_from_luminance(&image, TissueParams::default());
+        assert_eq!(cleaned.get_pixel(28, 28).0[0], BACKGROUND);
+        assert_eq!(cleaned.get_pixel(12, 12).0[0], TISSUE);
+        assert_eq!(tissue_pixels(&cleaned), 256);
+    }
+
+    #[test]
+    fn test_luminance() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+        let luminance = luminance(&image);
+        assert_eq!(luminance.get_pixel(0, 0).0[0], 0);
+        assert_eq!(luminance.get_pixel(1, 0).0[0], 255);
+    }
+}
@@ -2,7 +2,7 @@ use std::ffi::CStr;
 use std::str;
 
 use byteorder::ByteOrder;
-use image::{Rgba, RgbaImage};
+use image::{Rgb, Rgba, RgbaImage};
 
 /// Calculates the width and height an image should be resized to.
 /// This preserves aspect ratio, and based on the `fill` parameter
@@ -76,13 +76,16 @@ pub(crate) fn parse_null_terminated_array(
 /// an Rgba image buffer.
 pub(crate) fn decode_buffer(buffer: &[u32], width: u32, height: u32) -> RgbaImage {
     let mut rgba_image = image::RgbaImage::new(width as _, height as _);
+    decode_buffer_into(buffer, &mut rgba_image);
+    rgba_image
+}
 
-    for (col, row, pixel) in rgba_image.enumerate_pixels_mut() {
-        let curr_pos = row * width + col;
-        let value = buffer[curr_pos as usize];
-
+/// This function takes a buffer, as the one obtained from `openslide::read_region`, and decodes it
+/// into `dest`, 4 bytes of RGBA per pixel.
+pub(crate) fn decode_buffer_into(buffer: &[u32], dest: &mut [u8]) {
+    for (value, pixel) in buffer.iter().zip(dest.chunks_exact_mut(4)) {
         let mut buf = [0; 4];
-        byteorder::BigEndian::write_u32(&mut buf, value);
+        byteorder::BigEndian::write_u32(&mut buf, *value);
         let [mut alpha, mut red, mut green, mut blue] = buf;
 
         if alpha != 0 && alpha != 255 {
@@ -106,7 +109,37 @@ pub(crate) fn decode_buffer(buffer: &[u32], width: u32, height: u32) -> RgbaImag
             alpha = 255;
         }
 
-        *pixel = Rgba([red, green, blue, alpha]);
+        pixel.copy_from_slice(&[red, green, blue, alpha]);
+    }
+}
+
+/// This function takes a buffer, as the one obtained from `openslide::read_region`, and composites
+/// it over a solid `background` color into an opaque Rgba image buffer.
+pub(crate) fn composite_buffer(
+    buffer: &[u32],
+    width: u32,
+    height: u32,
+    background: Rgb<u8>,
+) -> RgbaImage {
+    let mut rgba_image = image::RgbaImage::new(width as _, height as _);
+
+    for (value, pixel) in buffer.iter().zip(rgba_image.pixels_mut()) {
+        let mut buf = [0; 4];
+        byteorder::BigEndian::write_u32(&mut buf, *value);
+        let [alpha, red, green, blue] = buf;
+
+        // Pixels are premultiplied: add the background weighted by the transparency
+        let transparency = 255 - alpha as u32;
+        let blend = |channel: u8, background: u8| {
+            (channel as u32 + (background as u32 * transparency + 127) / 255).min(255) as u8
+        };
+
+        *pixel = Rgba([
+            blend(red, background[0]),
+            blend(green, background[1]),
+            blend(blue, background[2]),
+            255,
+        ]);
     }
 
     rgba_image
@@ -0,0 +1,223 @@
+//! This module provides functionality for exporting OpenSlide slides to
+//! [OME-Zarr](https://ngff.openmicroscopy.org/0.4/) stores.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde_json::json;
+
+use crate::openslide::{Address, OpenSlide, Region, Size};
+use crate::{OpenSlideError, Result};
+
+/// A key/value store holding a Zarr hierarchy.
+///
+/// Keys are `/` separated paths relative to the root of the hierarchy. Implement
+/// this trait to write to a destination other than the local filesystem, such as
+/// an object storage bucket.
+pub trait ZarrStore: Sync {
+    /// Return true if the store already holds a value for `key`.
+    fn contains(&self, key: &str) -> Result<bool>;
+
+    /// Store `value` under `key`, replacing any previous value.
+    fn set(&self, key: &str, value: &[u8]) -> Result<()>;
+}
+
+/// A Zarr store backed by a local directory.
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+impl DirectoryStore {
+    /// Create a store rooted at `root`. The directory is created if needed.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the directory could not be created.
+    pub fn new(root: &Path) -> Result<DirectoryStore> {
+        fs::create_dir_all(root)?;
+        Ok(DirectoryStore {
+            root: root.to_path_buf(),
+        })
+    }
+}
+
+impl ZarrStore for DirectoryStore {
+    fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.root.join(key).is_file())
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so that an interrupted export never
+        // leaves a truncated chunk behind.
+        let tmp_path = path.with_extension("partial");
+        fs::write(&tmp_path, value)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
+
+/// Export every level of a slide to an OME-Zarr multiscale image.
+///
+/// Each slide level is written as a `(c, y, x)` array of RGB `u8` pixels split in
+/// `chunk_size` x `chunk_size` uncompressed chunks. Chunks are read and written in
+/// parallel. Chunks already present in the store are skipped, so an interrupted
+/// export can be resumed by calling this function again with the same arguments.
+///
+/// # Arguments
+///
+/// * `slide` - a slide
+/// * `store` - the destination store
+/// * `chunk_size` - the width and height of a single chunk.
+///
+/// # Errors
+///
+/// * [`OpenSlideError::InvalidArgument`](enum.OpenSlideError.html#variant.InvalidArgument): `chunk_size` is 0.
+/// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the store could not be written to.
+/// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+pub fn write_ome_zarr<S: ZarrStore>(slide: &OpenSlide, store: &S, chunk_size: u32) -> Result<()> {
+    if chunk_size == 0 {
+        return Err(OpenSlideError::InvalidArgument(
+            "Chunk size must be positive".to_string(),
+        ));
+    }
+
+    let level_count = slide.level_count()?;
+    let level_dimensions = (0..level_count)
+        .map(|level| slide.level_dimensions(level))
+        .collect::<Result<Vec<Size>>>()?;
+    let level_downsamples = (0..level_count)
+        .map(|level| slide.level_downsample(level))
+        .collect::<Result<Vec<f32>>>()?;
+
+    store.set(
+        ".zgroup",
+        json!({ "zarr_format": 2 }).to_string().as_bytes(),
+    )?;
+    store.set(
+        ".zattrs",
+        multiscales_attributes(slide, &level_downsamples)?
+            .to_string()
+            .as_bytes(),
+    )?;
+
+    for (level, dimensions) in level_dimensions.iter().enumerate() {
+        let zarray = json!({
+            "zarr_format": 2,
+            "shape": [3, dimensions.h, dimensions.w],
+            "chunks": [3, chunk_size, chunk_size],
+            "dtype": "|u1",
+            "compressor": null,
+            "fill_value": 255,
+            "order": "C",
+            "filters": null,
+            "dimension_separator": "/",
+        });
+        store.set(&format!("{}/.zarray", level), zarray.to_string().as_bytes())?;
+
+        let chunks_x = (dimensions.w + chunk_size - 1) / chunk_size;
+        let chunks_y = (dimensions.h + chunk_size - 1) / chunk_size;
+        let downsample = level_downsamples[level];
+
+        (0..chunks_x * chunks_y)
+            .into_par_iter()
+            .try_for_each(|index| {
+                let address = Address {
+                    x: index % chunks_x,
+                    y: index / chunks_x,
+                };
+                let key = format!("{}/0/{}/{}", level, address.y, address.x);
+                if store.contains(&key)? {
+                    return Ok(());
+                }
+
+                let chunk = read_chunk(slide, level, downsample, &address, chunk_size)?;
+                store.set(&key, &chunk)
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Build the OME-NGFF `multiscales` attributes describing the slide levels.
+fn multiscales_attributes(
+    slide: &OpenSlide,
+    level_downsamples: &[f32],
+) -> Result<serde_json::Value> {
+    let mpp = |name: &str| -> Result<Option<f32>> {
+        Ok(slide.property(name)?.and_then(|v| v.parse::<f32>().ok()))
+    };
+    let (mpp_x, mpp_y) = match (mpp("openslide.mpp-x")?, mpp("openslide.mpp-y")?) {
+        (Some(x), Some(y)) => (Some(x), Some(y)),
+        _ => (None, None),
+    };
+
+    let space_axis = |name: &str| match mpp_x {
+        Some(_) => json!({ "name": name, "type": "space", "unit": "micrometer" }),
+        None => json!({ "name": name, "type": "space" }),
+    };
+
+    let datasets: Vec<serde_json::Value> = level_downsamples
+        .iter()
+        .enumerate()
+        .map(|(level, downsample)| {
+            let scale_x = downsample * mpp_x.unwrap_or(1.0);
+            let scale_y = downsample * mpp_y.unwrap_or(1.0);
+            json!({
+                "path": level.to_string(),
+                "coordinateTransformations": [
+                    { "type": "scale", "scale": [1.0, scale_y, scale_x] }
+                ],
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "multiscales": [{
+            "version": "0.4",
+            "axes": [
+                { "name": "c", "type": "channel" },
+                space_axis("y"),
+                space_axis("x"),
+            ],
+            "datasets": datasets,
+        }]
+    }))
+}
+
+/// Read a single chunk of a level and lay it out in planar RGB order.
+fn read_chunk(
+    slide: &OpenSlide,
+    level: usize,
+    downsample: f32,
+    address: &Address,
+    chunk_size: u32,
+) -> Result<Vec<u8>> {
+    let region = slide.read_region(Region {
+        address: Address {
+            x: (address.x as f32 * chunk_size as f32 * downsample) as _,
+            y: (address.y as f32 * chunk_size as f32 * downsample) as _,
+        },
+        level,
+        size: Size {
+            w: chunk_size,
+            h: chunk_size,
+        },
+    })?;
+
+    let plane = (chunk_size * chunk_size) as usize;
+    let mut chunk = vec![0u8; 3 * plane];
+    for (x, y, pixel) in region.enumerate_pixels() {
+        let offset = (y * chunk_size + x) as usize;
+        for channel in 0..3 {
+            chunk[channel * plane + offset] = pixel[channel];
+        }
+    }
+
+    Ok(chunk)
+}
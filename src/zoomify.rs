@@ -0,0 +1,205 @@
+//! This module provides functionality for generating Zoomify images from
+//! OpenSlide slides.
+
+use std::ops::Deref;
+use std::path::Path;
+
+use image::RgbaImage;
+
+use crate::deepzoom::DeepZoom;
+use crate::encode::Format;
+use crate::openslide::{Address, OpenSlide, Size};
+use crate::pyramid::{for_each_tile, DirectorySink, Parallelism, PyramidSink};
+use crate::{OpenSlideError, Result};
+
+/// The number of tiles stored in each `TileGroup` directory.
+const TILES_PER_GROUP: u64 = 256;
+
+/// Support for Zoomify images.
+///
+/// A Zoomify image is a Deep Zoom pyramid without overlap whose smallest tier is
+/// the largest level fitting in a single tile. Tiles are addressed by tier,
+/// counting from that smallest tier, and stored in `TileGroup{n}` directories of
+/// 256 tiles each.
+pub struct Zoomify<S: Deref<Target = OpenSlide>> {
+    deepzoom: DeepZoom<S>,
+    first_level: usize,
+}
+
+impl<S: Deref<Target = OpenSlide>> Zoomify<S> {
+    /// Create a Zoomify wrapping an OpenSlide object.
+    ///
+    /// # Arguments
+    ///
+    /// * `slide` - a slide, either borrowed or behind a smart pointer such as `Arc`.
+    /// * `tile_size` - the width and height of a single tile. Most viewers expect 256.
+    /// * `limit_bounds` - True to render only the non-empty slide region.
+    pub fn new(slide: S, tile_size: u32, limit_bounds: bool) -> Result<Zoomify<S>> {
+        let deepzoom = DeepZoom::new(slide, tile_size, 0, limit_bounds)?;
+
+        // Deep Zoom levels start at 1x1 pixel: Zoomify only keeps the largest of the
+        // single tile levels.
+        let first_level = deepzoom
+            .level_tiles()
+            .iter()
+            .rposition(|tiles| tiles.w == 1 && tiles.h == 1)
+            .unwrap_or(0);
+
+        Ok(Zoomify {
+            deepzoom,
+            first_level,
+        })
+    }
+
+    /// The Deep Zoom generator computing the tiles.
+    pub fn deepzoom(&self) -> &DeepZoom<S> {
+        &self.deepzoom
+    }
+
+    /// The Deep Zoom generator computing the tiles, to change its rendering options
+    /// such as the [tile order](struct.DeepZoom.html#method.set_tile_order).
+    pub fn deepzoom_mut(&mut self) -> &mut DeepZoom<S> {
+        &mut self.deepzoom
+    }
+
+    /// The number of tiers in the image.
+    pub fn tier_count(&self) -> usize {
+        self.deepzoom.level_count() - self.first_level
+    }
+
+    /// The number of tiles in each axis, for each tier.
+    pub fn tier_tiles(&self) -> &[Size] {
+        &self.deepzoom.level_tiles()[self.first_level..]
+    }
+
+    /// The size in pixels of each tier.
+    pub fn tier_dimensions(&self) -> &[Size] {
+        &self.deepzoom.level_dimensions()[self.first_level..]
+    }
+
+    /// The total number of tiles in the image.
+    pub fn tile_count(&self) -> u64 {
+        self.tier_tiles()
+            .iter()
+            .map(|tiles| u64::from(tiles.w) * u64::from(tiles.h))
+            .sum()
+    }
+
+    /// Return the index of the `TileGroup` directory holding the specified tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): tier out of range
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): address out of range
+    pub fn tile_group(&self, tier: usize, address: Address) -> Result<u64> {
+        let level = self.level(tier)?;
+        if !self.deepzoom.contains(level, address) {
+            return Err(OpenSlideError::OutOfBounds(address.to_string()));
+        }
+
+        let tiers = self.tier_tiles();
+        let previous: u64 = tiers[..tier]
+            .iter()
+            .map(|tiles| u64::from(tiles.w) * u64::from(tiles.h))
+            .sum();
+        let index =
+            previous + u64::from(address.y) * u64::from(tiers[tier].w) + u64::from(address.x);
+        Ok(index / TILES_PER_GROUP)
+    }
+
+    /// Return the path of the specified tile, relative to the image root:
+    /// `TileGroup{group}/{tier}-{col}-{row}.{extension}`.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IndexError`](enum.OpenSlideError.html#variant.IndexError): tier out of range
+    /// * [`OpenSlideError::OutOfBounds`](enum.OpenSlideError.html#variant.OutOfBounds): address out of range
+    pub fn tile_path(&self, tier: usize, address: Address, format: Format) -> Result<String> {
+        Ok(format!(
+            "TileGroup{}/{}-{}-{}.{}",
+            self.tile_group(tier, address)?,
+            tier,
+            address.x,
+            address.y,
+            format.extension()
+        ))
+    }
+
+    /// Return the XML metadata for the `ImageProperties.xml` file.
+    pub fn image_properties(&self) -> String {
+        let dimensions = self.deepzoom.level_dimensions()[self.deepzoom.level_count() - 1];
+        format!(
+            r#"<IMAGE_PROPERTIES WIDTH="{}" HEIGHT="{}" NUMTILES="{}" NUMIMAGES="1" VERSION="1.8" TILESIZE="{}" />"#,
+            dimensions.w,
+            dimensions.h,
+            self.tile_count(),
+            self.deepzoom.tile_edge()
+        )
+    }
+
+    /// Return a RGB tile
+    pub fn read_tile(&self, tier: usize, address: Address) -> Result<RgbaImage> {
+        self.deepzoom.read_tile(self.level(tier)?, address)
+    }
+
+    /// Return a tile encoded into the given format, ready to be served.
+    pub fn tile_bytes(&self, tier: usize, address: Address, format: Format) -> Result<Vec<u8>> {
+        self.deepzoom.tile_bytes(self.level(tier)?, address, format)
+    }
+
+    /// Return the Deep Zoom level of a tier.
+    fn level(&self, tier: usize) -> Result<usize> {
+        if tier >= self.tier_count() {
+            return Err(OpenSlideError::IndexError(tier.to_string()));
+        }
+        Ok(self.first_level + tier)
+    }
+}
+
+impl<S: Deref<Target = OpenSlide> + Sync> Zoomify<S> {
+    /// Write the complete image in the `dir` directory: `ImageProperties.xml` and every
+    /// tile in `TileGroup{group}/{tier}-{col}-{row}.{extension}`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - the output directory.
+    /// * `format` - the format of the individual tiles. Most viewers only support JPEG.
+    /// * `parallelism` - how many threads generate tiles.
+    /// * `progress` - called with the number of tiles written so far and the total
+    /// number of tiles, after each tile.
+    ///
+    /// # Errors
+    ///
+    /// * [`OpenSlideError::IoError`](enum.OpenSlideError.html#variant.IoError): the image could not be written.
+    /// * [`OpenSlideError::InternalError`](enum.OpenSlideError.html#variant.InternalError): an error occured in the C codebase.
+    pub fn write_zoomify<F>(
+        &self,
+        dir: &Path,
+        format: Format,
+        parallelism: Parallelism,
+        progress: F,
+    ) -> Result<()>
+    where
+        F: Fn(usize, usize) + Sync,
+    {
+        let sink = DirectorySink {
+            root: dir.to_path_buf(),
+        };
+        sink.write("ImageProperties.xml", self.image_properties().as_bytes())?;
+
+        let mut tiles = Vec::new();
+        for tier in 0..self.tier_count() {
+            let level = self.first_level + tier;
+            tiles.extend(
+                self.deepzoom
+                    .tile_addresses(level, self.deepzoom.tile_order())?
+                    .map(|address| (tier, address)),
+            );
+        }
+
+        for_each_tile(&tiles, parallelism, progress, |(tier, address)| {
+            let tile = self.tile_bytes(*tier, *address, format)?;
+            sink.write(&self.tile_path(*tier, *address, format)?, &tile)
+        })
+    }
+}
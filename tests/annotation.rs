@@ -0,0 +1,263 @@
+use openslide_rs::{
+    mask_contours, Address, AnnotationSet, OpenSlide, OpenSlideError, Point, Polygon, Region, Size,
+    ANNOTATED,
+};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_open_geojson() {
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+
+    // The point annotation has no area
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(annotations.classifications(), vec!["Stroma", "Tumor"]);
+
+    let tumor = &annotations.annotations[0];
+    assert_eq!(
+        tumor.id.as_deref(),
+        Some("5b3a0a4e-3f0d-4a8e-9a57-7d0d3c6a1f10")
+    );
+    assert_eq!(tumor.name.as_deref(), Some("Region 1"));
+    assert_eq!(tumor.classification.as_deref(), Some("Tumor"));
+    assert_eq!(tumor.color, Some([200, 0, 0]));
+    assert_eq!(tumor.polygons.len(), 1);
+    // Rings are not closed
+    assert_eq!(tumor.polygons[0].exterior.len(), 4);
+    assert_eq!(tumor.polygons[0].exterior[1], Point { x: 110., y: 10. });
+    assert_eq!(tumor.polygons[0].holes.len(), 1);
+    assert_eq!(tumor.measurements.len(), 1);
+    assert_eq!(tumor.measurements["Area"], 9600.);
+
+    let stroma = &annotations.annotations[1];
+    assert_eq!(stroma.id, None);
+    assert_eq!(stroma.name, None);
+    assert_eq!(stroma.color, Some([0, 128, 0]));
+    assert_eq!(stroma.polygons.len(), 2);
+    assert_eq!(stroma.polygons[0].exterior.len(), 3);
+    assert_eq!(stroma.measurements["Perimeter"], 640.5);
+}
+
+#[test]
+fn test_from_geojson_features() {
+    // Older QuPath versions export an array of features
+    let annotations = AnnotationSet::from_geojson(
+        r#"[{
+            "type": "Feature",
+            "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [5, 0], [5, 5]]]},
+            "properties": {"classification": {"name": "Tumor", "colorRGB": -3670016}}
+        }]"#,
+    )
+    .unwrap();
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations.annotations[0].color, Some([200, 0, 0]));
+    assert!(annotations.annotations[0].polygons[0].holes.is_empty());
+}
+
+#[test]
+fn test_from_geojson_errors() {
+    for text in [
+        "not json",
+        r#"{"type": "Polygon", "coordinates": []}"#,
+        r#"{"type": "Feature", "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [5, 0]]]}}"#,
+        r#"{"type": "Feature", "geometry": {"type": "Polygon", "coordinates": [[[0, "a"]]]}}"#,
+    ] {
+        assert!(matches!(
+            AnnotationSet::from_geojson(text),
+            Err(OpenSlideError::InvalidArgument(_))
+        ));
+    }
+    assert!(matches!(
+        AnnotationSet::open_geojson(common::missing_file()),
+        Err(OpenSlideError::IoError(_))
+    ));
+}
+
+#[test]
+fn test_to_geojson() {
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+    let path = std::path::Path::new("tests/artifacts/test_to_geojson.geojson");
+    annotations.save_geojson(path).unwrap();
+    assert_eq!(AnnotationSet::open_geojson(path).unwrap(), annotations);
+
+    let geojson: serde_json::Value = serde_json::from_str(&annotations.to_geojson()).unwrap();
+    assert_eq!(geojson["type"], "FeatureCollection");
+    let tumor = &geojson["features"][0];
+    assert_eq!(tumor["geometry"]["type"], "Polygon");
+    assert_eq!(tumor["properties"]["objectType"], "annotation");
+    assert_eq!(tumor["properties"]["classification"]["name"], "Tumor");
+    // Rings are closed
+    let exterior = tumor["geometry"]["coordinates"][0].as_array().unwrap();
+    assert_eq!(exterior.len(), 5);
+    assert_eq!(exterior.first(), exterior.last());
+    assert_eq!(geojson["features"][1]["geometry"]["type"], "MultiPolygon");
+
+    // Generated polygons round-trip as a single unclassified annotation
+    let generated = AnnotationSet::from(annotations.annotations[1].polygons.clone());
+    assert_eq!(
+        AnnotationSet::from_geojson(&generated.to_geojson()).unwrap(),
+        generated
+    );
+}
+
+#[test]
+fn test_rasterize() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+    let annotated = |mask: &image::GrayImage| {
+        mask.pixels()
+            .filter(|pixel| pixel.0[0] == ANNOTATED)
+            .count()
+    };
+
+    let region = Region {
+        address: Address { x: 0, y: 0 },
+        level: 0,
+        size: Size { w: 300, h: 250 },
+    };
+    let mask = annotations.rasterize(&slide, &region, false).unwrap();
+    assert_eq!(mask.dimensions(), (300, 250));
+    assert_eq!(mask.get_pixel(20, 20).0[0], ANNOTATED);
+    assert_eq!(mask.get_pixel(50, 50).0[0], 0);
+    assert_eq!(mask.get_pixel(5, 5).0[0], 0);
+    // The tumor square without its hole, the stroma triangle pixel centers and
+    // rectangle
+    assert_eq!(annotated(&mask), 9600 + 3010 + 7000);
+
+    // Level 1 pixels cover 2 level 0 pixels, from the region address on
+    let region = Region {
+        address: Address { x: 10, y: 10 },
+        level: 1,
+        size: Size { w: 50, h: 50 },
+    };
+    let mask = annotations.rasterize(&slide, &region, false).unwrap();
+    assert_eq!(mask.dimensions(), (50, 50));
+    assert_eq!(annotated(&mask), 50 * 50 - 10 * 10);
+    assert_eq!(mask.get_pixel(20, 20).0[0], 0);
+
+    // The slide has no bounds
+    assert_eq!(annotations.rasterize(&slide, &region, true).unwrap(), mask);
+
+    let region = Region {
+        address: Address { x: 0, y: 0 },
+        level: 10,
+        size: Size { w: 50, h: 50 },
+    };
+    assert!(matches!(
+        annotations.rasterize(&slide, &region, false),
+        Err(OpenSlideError::IndexError(_))
+    ));
+}
+
+#[test]
+fn test_mask_contours() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+    let region = Region {
+        address: Address { x: 0, y: 0 },
+        level: 0,
+        size: Size { w: 300, h: 250 },
+    };
+    let mask = annotations.rasterize(&slide, &region, false).unwrap();
+
+    // The tumor square with its hole, and the two stroma polygons
+    let polygons = mask_contours(&mask, slide.dimensions().unwrap(), 0.);
+    assert_eq!(polygons.len(), 3);
+    assert_eq!(
+        polygons
+            .iter()
+            .map(|polygon| polygon.holes.len())
+            .sum::<usize>(),
+        1
+    );
+
+    // Contours pass between pixel centers: rasterizing them gives the mask back
+    let vertices = |polygons: &[Polygon]| {
+        polygons
+            .iter()
+            .map(|polygon| {
+                polygon.exterior.len() + polygon.holes.iter().map(Vec::len).sum::<usize>()
+            })
+            .sum::<usize>()
+    };
+    let count = vertices(&polygons);
+    let traced = AnnotationSet::from(polygons);
+    assert_eq!(traced.rasterize(&slide, &region, false).unwrap(), mask);
+
+    // Simplification straightens the staircase of the triangle hypotenuse
+    let simplified = mask_contours(&mask, slide.dimensions().unwrap(), 1.);
+    assert_eq!(simplified.len(), 3);
+    assert!(vertices(&simplified) * 4 < count);
+}
+
+#[test]
+fn test_transforms() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+    let first = |annotations: &AnnotationSet| annotations.annotations[0].polygons[0].exterior[1];
+    assert_eq!(first(&annotations), Point { x: 110., y: 10. });
+
+    let moved = annotations.translate(5., -5.).scale(2., 3.);
+    assert_eq!(first(&moved), Point { x: 230., y: 15. });
+    assert_eq!(
+        moved.annotations[0].classification.as_deref(),
+        Some("Tumor")
+    );
+
+    // Level and micron conversions round trip
+    let level = slide.level_count().unwrap() - 1;
+    let downsample = f64::from(slide.level_downsample(level).unwrap());
+    let at_level = annotations.level0_to_level(&slide, level).unwrap();
+    assert!((first(&at_level).x - 110. / downsample).abs() < 1e-9);
+    let back = at_level.level_to_level0(&slide, level).unwrap();
+    assert!((first(&back).x - 110.).abs() < 1e-9);
+
+    let mpp: f64 = slide
+        .property("openslide.mpp-x")
+        .unwrap()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let microns = annotations.level0_to_microns(&slide).unwrap();
+    assert!((first(&microns).x - 110. * mpp).abs() < 1e-3);
+    let back = microns.microns_to_level0(&slide).unwrap();
+    assert!((first(&back).x - 110.).abs() < 1e-6);
+
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    assert!(matches!(
+        annotations.level0_to_microns(&slide),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        annotations.level0_to_level(&slide, 10),
+        Err(OpenSlideError::IndexError(_))
+    ));
+}
+
+#[test]
+fn test_crop() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+
+    // The level 1 region covers level 0 from (0, 0) to (100, 100)
+    let region = Region {
+        address: Address { x: 0, y: 0 },
+        level: 1,
+        size: Size { w: 50, h: 50 },
+    };
+    let cropped = annotations.crop(&slide, &region).unwrap();
+    assert_eq!(cropped.len(), 1);
+    let tumor = &cropped.annotations[0].polygons[0];
+    assert!(tumor
+        .exterior
+        .iter()
+        .all(|point| point.x <= 100. && point.y <= 100.));
+    assert_eq!(tumor.holes.len(), 1);
+
+    // The cropped annotations rasterize the same within the region
+    assert_eq!(
+        cropped.rasterize(&slide, &region, false).unwrap(),
+        annotations.rasterize(&slide, &region, false).unwrap()
+    );
+}
@@ -0,0 +1,85 @@
+use openslide_rs::anonymize::anonymize;
+use openslide_rs::OpenSlide;
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_anonymize() {
+    let dest = Path::new("tests/artifacts/test_anonymize.svs");
+    anonymize(common::small_svs(), dest).unwrap();
+
+    let original = OpenSlide::open(common::small_svs()).unwrap();
+    let slide = OpenSlide::open(dest).unwrap();
+
+    assert_eq!(
+        slide.level_count().unwrap(),
+        original.level_count().unwrap()
+    );
+    assert_eq!(slide.dimensions().unwrap(), original.dimensions().unwrap());
+
+    let names = slide.associated_image_names().unwrap();
+    assert!(!names.iter().any(|name| name == "label" || name == "macro"));
+    assert!(slide.property("aperio.Filename").unwrap().is_none());
+}
+
+#[test]
+fn test_anonymize_generic_tiff() {
+    let dest = Path::new("tests/artifacts/test_anonymize.tiff");
+    anonymize(common::boxes_tiff(), dest).unwrap();
+
+    let slide = OpenSlide::open(dest).unwrap();
+    assert_eq!(slide.level_count().unwrap(), 4);
+}
+
+#[test]
+fn test_anonymize_corrupt_directory() {
+    // Point the first directory of a copy of boxes.tiff to a next directory past
+    // the end of the file, so that anonymization fails after copying the slide.
+    let mut bytes = fs::read(common::boxes_tiff()).unwrap();
+    let little_endian = &bytes[..2] == b"II";
+    let read_u16 = |bytes: &[u8], at: usize| {
+        let value = [bytes[at], bytes[at + 1]];
+        usize::from(if little_endian {
+            u16::from_le_bytes(value)
+        } else {
+            u16::from_be_bytes(value)
+        })
+    };
+    let read_u32 = |bytes: &[u8], at: usize| {
+        let value = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        (if little_endian {
+            u32::from_le_bytes(value)
+        } else {
+            u32::from_be_bytes(value)
+        }) as usize
+    };
+    let first = read_u32(&bytes, 4);
+    let next_position = first + 2 + 12 * read_u16(&bytes, first);
+    let past_end = bytes.len() as u32 + 1024;
+    bytes[next_position..next_position + 4].copy_from_slice(&if little_endian {
+        past_end.to_le_bytes()
+    } else {
+        past_end.to_be_bytes()
+    });
+    let src = Path::new("tests/artifacts/test_anonymize_corrupt_src.tiff");
+    fs::write(src, bytes).unwrap();
+
+    let dest = Path::new("tests/artifacts/test_anonymize_corrupt.tiff");
+    let _ = fs::remove_file(dest);
+    assert!(anonymize(src, dest).is_err());
+    assert!(!dest.exists());
+    assert!(!Path::new("tests/artifacts/test_anonymize_corrupt.tiff.partial").exists());
+}
+
+#[test]
+#[should_panic(expected = "Unsupported format: Cargo.toml")]
+fn test_anonymize_unsupported() {
+    anonymize(
+        common::unsupported_file(),
+        Path::new("tests/artifacts/test_anonymize_unsupported"),
+    )
+    .unwrap();
+}
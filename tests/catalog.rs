@@ -0,0 +1,77 @@
+use openslide_rs::catalog::{load_json, save_json, scan, update};
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_scan() {
+    let records = scan(Path::new("tests/assets")).unwrap();
+    let paths: Vec<&Path> = records.iter().map(|record| record.path.as_path()).collect();
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(paths, sorted);
+
+    // Annotations are not slides, and unopenable slides are skipped
+    assert!(paths.contains(&Path::new("small.svs")));
+    assert!(paths.contains(&Path::new("boxes.tiff")));
+    assert!(!paths.contains(&Path::new("annotations.geojson")));
+    assert!(!paths.contains(&Path::new("unopenable.tiff")));
+
+    let svs = records
+        .iter()
+        .find(|record| record.path == Path::new("small.svs"))
+        .unwrap();
+    assert_eq!(svs.vendor, "aperio");
+    assert!(svs.quickhash.is_some());
+    assert!(svs.mpp.is_some());
+    assert!(svs.file_size > 0);
+
+    let boxes = records
+        .iter()
+        .find(|record| record.path == Path::new("boxes.tiff"))
+        .unwrap();
+    assert_eq!(boxes.vendor, "generic-tiff");
+    assert_eq!(boxes.level_count, 4);
+    assert_eq!(boxes.mpp, None);
+}
+
+#[test]
+fn test_update_and_persist() {
+    let records = scan(Path::new("tests/assets")).unwrap();
+
+    // Unchanged files keep their previous record
+    let mut previous = records.clone();
+    for record in previous.iter_mut() {
+        record.vendor = "cached".to_string();
+    }
+    let updated = update(Path::new("tests/assets"), &previous).unwrap();
+    assert_eq!(updated, previous);
+
+    // Stale records are described anew
+    previous[0].file_size += 1;
+    let updated = update(Path::new("tests/assets"), &previous).unwrap();
+    assert_eq!(updated[0], records[0]);
+    assert_eq!(updated[1..], previous[1..]);
+
+    let path = Path::new("tests/artifacts/test_catalog.json");
+    save_json(&records, path).unwrap();
+    assert_eq!(load_json(path).unwrap(), records);
+    assert!(load_json(common::boxes_tiff()).is_err());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_scan_symlink_cycle() {
+    let dir = Path::new("tests/artifacts/test_catalog_cycle");
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::copy(common::boxes_tiff(), dir.join("boxes.tiff")).unwrap();
+    std::os::unix::fs::symlink("..", dir.join("parent")).unwrap();
+    std::os::unix::fs::symlink(".", dir.join("self")).unwrap();
+
+    let records = scan(dir).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].path, Path::new("boxes.tiff"));
+    assert_eq!(records[0].error, None);
+}
@@ -0,0 +1,47 @@
+use openslide_rs::cohort::{scan_cohort, CohortTolerances};
+use openslide_rs::Parallelism;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_scan_cohort() {
+    let paths = [
+        common::small_svs(),
+        common::boxes_tiff(),
+        common::missing_file(),
+    ];
+    let report = scan_cohort(&paths, CohortTolerances::default(), Parallelism::Auto).unwrap();
+    assert_eq!(report.slides.len(), 3);
+    for (slide, path) in report.slides.iter().zip(&paths) {
+        assert_eq!(slide.path, *path);
+    }
+
+    // The only slide with a resolution is the reference
+    let svs = &report.slides[0];
+    assert_eq!(svs.vendor.as_deref(), Some("aperio"));
+    let mpp = (svs.mpp_x.unwrap() + svs.mpp_y.unwrap()) / 2.;
+    assert_eq!(report.reference_mpp, Some(mpp));
+    assert!(!svs.issues.iter().any(|issue| issue.contains("resolution")));
+
+    let boxes = &report.slides[1];
+    assert_eq!(boxes.level_downsamples.len(), 4);
+    assert!(boxes.issues.contains(&"No resolution".to_string()));
+
+    let missing = &report.slides[2];
+    assert!(missing.dimensions.is_none());
+    assert_eq!(missing.issues.len(), 1);
+    assert!(missing.issues[0].contains("does not exist"));
+    assert!(report.flagged().count() >= 2);
+
+    // Expected values override the cohort
+    let tolerances = CohortTolerances {
+        expected_mpp: Some(mpp * 2.),
+        ..CohortTolerances::default()
+    };
+    let report = scan_cohort(&paths[..1], tolerances, Parallelism::Sequential).unwrap();
+    assert!(report.slides[0]
+        .issues
+        .iter()
+        .any(|issue| issue.starts_with("Resolution")));
+}
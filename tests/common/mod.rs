@@ -27,3 +27,7 @@ pub fn small_svs() -> &'static Path {
 pub fn unreadable_svs() -> &'static Path {
     Path::new("tests/assets/unreadable.svs")
 }
+
+pub fn annotations_geojson() -> &'static Path {
+    Path::new("tests/assets/annotations.geojson")
+}
@@ -0,0 +1,38 @@
+use openslide_rs::anonymize::anonymize;
+use openslide_rs::compare::compare;
+use openslide_rs::{OpenSlide, OpenSlideError};
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_compare() {
+    // Anonymizing a slide leaves its pixels untouched
+    let dest = Path::new("tests/artifacts/test_compare.tiff");
+    anonymize(common::boxes_tiff(), dest).unwrap();
+    let original = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let copy = OpenSlide::open(dest).unwrap();
+
+    let report = compare(&original, &copy, 1, 0).unwrap();
+    assert!(report.is_match());
+    assert_eq!(report.dimensions, original.level_dimensions(1).unwrap());
+    assert_eq!(report.max_difference, 0);
+    assert_eq!(report.mean_difference, 0.);
+    assert!(report.mismatched_tiles.is_empty());
+}
+
+#[test]
+fn test_compare_errors() {
+    let boxes = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let svs = OpenSlide::open(common::small_svs()).unwrap();
+
+    assert!(matches!(
+        compare(&boxes, &svs, 0, 0),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        compare(&boxes, &boxes, 10, 0),
+        Err(OpenSlideError::IndexError(_))
+    ));
+}
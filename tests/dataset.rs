@@ -0,0 +1,348 @@
+use image::{GrayImage, Luma};
+use openslide_rs::tissue::TISSUE;
+use openslide_rs::{
+    export_patches, export_patches_resumable, DirectoryStore, Format, OpenSlide, OpenSlideError,
+    Parallelism, PatchRecord, PatchSampler, ZarrPatchWriter,
+};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_export_patches() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_fn(30, 25, |x, _| Luma([if x < 20 { TISSUE } else { 0 }]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.5).unwrap();
+
+    let dir = Path::new("tests/artifacts/test_export_patches");
+    let calls = AtomicUsize::new(0);
+    let records = export_patches(
+        &sampler,
+        "boxes",
+        dir,
+        Format::Png,
+        Parallelism::Auto,
+        |done, total| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            assert!(done <= total);
+        },
+    )
+    .unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(calls.into_inner(), 4);
+
+    assert_eq!(
+        records[1],
+        PatchRecord {
+            slide_id: "boxes".to_string(),
+            path: "boxes/100_0.png".to_string(),
+            x: 100,
+            y: 0,
+            l0_size: 100,
+            size: 100,
+            level: 0,
+            mpp: None,
+            tissue_fraction: 1.0,
+        }
+    );
+    for record in &records {
+        let patch = image::open(dir.join(&record.path)).unwrap();
+        assert_eq!((patch.width(), patch.height()), (100, 100));
+    }
+
+    let json: Vec<PatchRecord> =
+        serde_json::from_str(&fs::read_to_string(dir.join("boxes_manifest.json")).unwrap())
+            .unwrap();
+    assert_eq!(json, records);
+
+    let csv = fs::read_to_string(dir.join("boxes_manifest.csv")).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "slide_id,path,x,y,l0_size,size,level,mpp,tissue_fraction"
+    );
+    assert_eq!(lines[2], "boxes,boxes/100_0.png,100,0,100,100,0,,1");
+    assert_eq!(lines.len(), 5);
+}
+
+#[test]
+fn test_export_patches_mpp() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let mask = GrayImage::from_pixel(1, 1, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 512, 4096, None, 0.).unwrap();
+
+    let records = export_patches(
+        &sampler,
+        "small",
+        Path::new("tests/artifacts/test_export_patches_mpp"),
+        Format::Jpeg { quality: 75 },
+        Parallelism::Sequential,
+        |_, _| {},
+    )
+    .unwrap();
+    let mpp: f32 = slide
+        .property("openslide.mpp-x")
+        .unwrap()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(!records.is_empty());
+    assert!((records[0].mpp.unwrap() - mpp).abs() < mpp * 0.1);
+    assert!(records[0].path.ends_with(".jpg"));
+}
+
+#[test]
+fn test_export_patches_errors() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(1, 1, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.).unwrap();
+
+    for slide_id in ["", "a/b", ".", ".."] {
+        assert!(matches!(
+            export_patches(
+                &sampler,
+                slide_id,
+                Path::new("tests/artifacts/test_export_patches_errors"),
+                Format::Png,
+                Parallelism::Sequential,
+                |_, _| {}
+            ),
+            Err(OpenSlideError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[test]
+fn test_export_patches_resumable() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_fn(30, 25, |x, _| Luma([if x < 20 { TISSUE } else { 0 }]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.5).unwrap();
+    let dir = Path::new("tests/artifacts/test_export_patches_resumable");
+    let _ = fs::remove_dir_all(dir);
+    let export = |format: Format| {
+        let done = AtomicUsize::new(0);
+        let result = export_patches_resumable(
+            &sampler,
+            "boxes",
+            dir,
+            format,
+            Parallelism::Auto,
+            |count, total| {
+                assert!(count <= total);
+                done.fetch_max(count, Ordering::SeqCst);
+            },
+        );
+        result.map(|(records, stats)| {
+            if stats.written > 0 {
+                assert_eq!(done.into_inner(), stats.patches);
+            }
+            (records, stats)
+        })
+    };
+
+    let (records, stats) = export(Format::Png).unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!((stats.patches, stats.resumed, stats.written), (4, 0, 4));
+    let manifest = fs::read_to_string(dir.join("boxes_manifest.csv")).unwrap();
+
+    // A finished export is only verified
+    let (resumed, stats) = export(Format::Png).unwrap();
+    assert_eq!(resumed, records);
+    assert_eq!((stats.resumed, stats.invalid, stats.written), (4, 0, 0));
+
+    // Altered and missing patches are written again
+    fs::write(dir.join(&records[0].path), b"corrupted").unwrap();
+    fs::remove_file(dir.join(&records[1].path)).unwrap();
+    let (_, stats) = export(Format::Png).unwrap();
+    assert_eq!((stats.resumed, stats.invalid, stats.written), (2, 2, 2));
+    assert!(image::open(dir.join(&records[0].path)).is_ok());
+
+    // An interrupted session resumes after its last complete checkpoint
+    let session = dir.join("boxes_session.jsonl");
+    let content = fs::read_to_string(&session).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    fs::write(&session, format!("{}\n{}\n{{\"pa", lines[0], lines[1])).unwrap();
+    let (_, stats) = export(Format::Png).unwrap();
+    assert_eq!((stats.resumed, stats.written), (1, 3));
+    assert_eq!(
+        fs::read_to_string(dir.join("boxes_manifest.csv")).unwrap(),
+        manifest
+    );
+    let (_, stats) = export(Format::Png).unwrap();
+    assert_eq!(stats.resumed, 4);
+
+    // The session belongs to the PNG export
+    assert!(matches!(
+        export(Format::Jpeg { quality: 75 }),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_zarr_patch_writer() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_fn(30, 25, |x, _| Luma([if x < 20 { TISSUE } else { 0 }]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.5).unwrap();
+
+    let root = Path::new("tests/artifacts/test_zarr_patch_writer.zarr");
+    let store = DirectoryStore::new(root).unwrap();
+    let mut writer = ZarrPatchWriter::create(&store, 100).unwrap();
+    assert!(writer.is_empty());
+
+    let first = writer
+        .append(&sampler, "first", Parallelism::Auto, |_, _| {})
+        .unwrap();
+    let second = writer
+        .append(&sampler, "second", Parallelism::Sequential, |_, _| {})
+        .unwrap();
+    assert_eq!(first, 0..4);
+    assert_eq!(second, 4..8);
+    assert_eq!(writer.len(), 8);
+
+    let zarray: serde_json::Value =
+        serde_json::from_slice(&fs::read(root.join("patches/.zarray")).unwrap()).unwrap();
+    assert_eq!(zarray["shape"], serde_json::json!([8, 100, 100, 3]));
+    assert_eq!(zarray["chunks"], serde_json::json!([1, 100, 100, 3]));
+
+    // One chunk per patch, in append order
+    let chunk = fs::read(root.join("patches/5.0.0.0")).unwrap();
+    assert_eq!(chunk.len(), 100 * 100 * 3);
+    let patch = sampler.read_patch(&sampler.patches()[1]).unwrap();
+    assert_eq!(chunk[..3], patch.get_pixel(0, 0).0[..3]);
+
+    let coords = fs::read(root.join("coords/0.0")).unwrap();
+    let coord = |row: usize, column: usize| {
+        let offset = 4 * (2 * row + column);
+        u32::from_le_bytes([
+            coords[offset],
+            coords[offset + 1],
+            coords[offset + 2],
+            coords[offset + 3],
+        ])
+    };
+    assert_eq!((coord(5, 0), coord(5, 1)), (100, 0));
+    let indices = fs::read(root.join("slide_index/0")).unwrap();
+    assert_eq!((indices[4 * 3], indices[4 * 4]), (0, 1));
+
+    let zattrs: serde_json::Value =
+        serde_json::from_slice(&fs::read(root.join(".zattrs")).unwrap()).unwrap();
+    assert_eq!(zattrs["patch_size"], 100);
+    assert_eq!(zattrs["slides"][1]["id"], "second");
+    assert_eq!(zattrs["slides"][1]["l0_size"], 100);
+
+    // Patches of another size do not fit in the dataset
+    let small = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.5).unwrap();
+    assert!(matches!(
+        writer.append(&small, "small", Parallelism::Auto, |_, _| {}),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        ZarrPatchWriter::create(&store, 0),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[cfg(feature = "hdf5")]
+#[test]
+fn test_export_patches_hdf5() {
+    use hdf5_rust::types::VarLenUnicode;
+    use openslide_rs::export_patches_hdf5;
+
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_fn(30, 25, |x, _| Luma([if x < 20 { TISSUE } else { 0 }]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.5).unwrap();
+
+    let path = Path::new("tests/artifacts/test_export_patches.h5");
+    export_patches_hdf5(&sampler, "boxes", path, 4, Parallelism::Auto, |_, _| {}).unwrap();
+
+    let file = hdf5_rust::File::open(path).unwrap();
+    let patches = file.dataset("patches").unwrap();
+    assert_eq!(patches.shape(), vec![4, 100, 100, 3]);
+    assert_eq!(patches.chunk(), Some(vec![1, 100, 100, 3]));
+
+    // Patches are stored in sampler order
+    let pixels = patches.read_raw::<u8>().unwrap();
+    let second = sampler.read_patch(&sampler.patches()[1]).unwrap();
+    let offset = 100 * 100 * 3;
+    assert_eq!(pixels[offset..offset + 3], second.get_pixel(0, 0).0[..3]);
+
+    let coords = file.dataset("coords").unwrap().read_raw::<u32>().unwrap();
+    assert_eq!(coords, vec![0, 0, 100, 0, 0, 100, 100, 100]);
+    let fractions = file
+        .dataset("tissue_fraction")
+        .unwrap()
+        .read_raw::<f32>()
+        .unwrap();
+    assert_eq!(fractions, vec![1.0; 4]);
+
+    let slide_id: VarLenUnicode = file.attr("slide_id").unwrap().read_scalar().unwrap();
+    assert_eq!(slide_id.as_str(), "boxes");
+    let patch_size: u32 = file.attr("patch_size").unwrap().read_scalar().unwrap();
+    assert_eq!(patch_size, 100);
+    assert!(file.attr("mpp").is_err());
+
+    assert!(matches!(
+        export_patches_hdf5(&sampler, "boxes", path, 10, Parallelism::Auto, |_, _| {}),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn test_write_manifest_parquet() {
+    use openslide_rs::write_manifest_parquet;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_fn(30, 25, |x, _| Luma([if x < 20 { TISSUE } else { 0 }]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.5).unwrap();
+
+    // Exports write a Parquet manifest next to the CSV one
+    let dir = Path::new("tests/artifacts/test_write_manifest_parquet");
+    let records = export_patches(
+        &sampler,
+        "boxes",
+        dir,
+        Format::Png,
+        Parallelism::Auto,
+        |_, _| {},
+    )
+    .unwrap();
+    let column_names = |path: &Path| -> (i64, Vec<String>) {
+        let reader = SerializedFileReader::new(fs::File::open(path).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        let names = metadata
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|column| column.name().to_string())
+            .collect();
+        (metadata.num_rows(), names)
+    };
+    let (rows, names) = column_names(&dir.join("boxes_manifest.parquet"));
+    assert_eq!(rows, 4);
+    assert_eq!(
+        names.join(","),
+        "slide_id,path,x,y,l0_size,size,level,mpp,tissue_fraction"
+    );
+
+    let path = dir.join("labelled.parquet");
+    let labels = vec![
+        Some("tumor".to_string()),
+        None,
+        None,
+        Some("stroma".to_string()),
+    ];
+    write_manifest_parquet(&records, Some(&labels), &path).unwrap();
+    let (rows, names) = column_names(&path);
+    assert_eq!(rows, 4);
+    assert_eq!(names.last().unwrap(), "label");
+
+    assert!(matches!(
+        write_manifest_parquet(&records, Some(&labels[..2]), &path),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
@@ -1,5 +1,15 @@
-use openslide_rs::{Address, DeepZoom, OpenSlide, Region, Size};
+use image::{Rgb, Rgba, RgbaImage};
+use openslide_rs::{
+    Address, BackgroundFilter, BackgroundTiles, DeepZoom, Format, LevelInfo, OpenSlide,
+    OpenSlideError, Parallelism, PyramidInfo, Region, ResizeFilter, Size, TileBounds, TileOrder,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 
 #[allow(dead_code)]
 mod common;
@@ -44,6 +54,64 @@ fn test_metadata() {
     );
 }
 
+#[test]
+fn test_limit_bounds() {
+    // Neither slide has bounds properties: the whole slide is rendered
+    for path in [common::boxes_tiff(), common::small_svs()] {
+        let slide = OpenSlide::open(path).unwrap();
+        assert_eq!(slide.property("openslide.bounds-x").unwrap(), None);
+
+        let dz = DeepZoom::new(&slide, 254, 1, true).unwrap();
+        let full = DeepZoom::new(&slide, 254, 1, false).unwrap();
+        assert_eq!(dz.l0_offset(), Address { x: 0, y: 0 });
+        assert_eq!(dz.l0_dimensions(), slide.dimensions().unwrap());
+        assert_eq!(dz.level_dimensions(), full.level_dimensions());
+        assert_eq!(full.l0_offset(), Address { x: 0, y: 0 });
+        assert_eq!(full.l0_dimensions(), slide.dimensions().unwrap());
+    }
+}
+
+#[test]
+fn test_tile_count() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    assert_eq!(dz.tile_count(), 11);
+    assert_eq!(dz.tile_count_at(9).unwrap(), 2);
+    assert_eq!(dz.tile_count_at(0).unwrap(), 1);
+    assert!(dz.tile_count_at(10).is_err());
+
+    assert!(dz.contains(9, Address { x: 1, y: 0 }));
+    assert!(!dz.contains(9, Address { x: 2, y: 0 }));
+    assert!(!dz.contains(9, Address { x: 0, y: 1 }));
+    assert!(!dz.contains(10, Address { x: 0, y: 0 }));
+}
+
+#[test]
+fn test_level_info() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    assert_eq!(
+        dz.level_info(9).unwrap(),
+        LevelInfo {
+            dimensions: Size { w: 300, h: 250 },
+            tiles: Size { w: 2, h: 1 },
+            downsample: 1.,
+            slide_level: 0,
+        }
+    );
+    assert_eq!(dz.level_info(8).unwrap().downsample, 2.);
+    assert!(dz.level_info(10).is_none());
+    assert_eq!(dz.levels().len(), 10);
+
+    assert_eq!(dz.level_count(), dz.level_count);
+    assert_eq!(dz.level_dimensions(), dz.level_dimensions);
+    assert_eq!(dz.level_tiles(), dz.level_tiles);
+    assert_eq!(dz.tile_edge(), 254);
+    assert_eq!(dz.overlap(), 1);
+}
+
 #[test]
 fn test_get_tile() {
     let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
@@ -56,6 +124,128 @@ fn test_get_tile() {
     tile.save(Path::new("tests/artifacts/test_dz.png")).unwrap();
 }
 
+#[test]
+fn test_background_color() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    assert_eq!(dz.background_color(), Rgb([255, 255, 255]));
+
+    dz.set_background_color(Rgb([0, 0, 0]));
+    assert_eq!(dz.background_color(), Rgb([0, 0, 0]));
+
+    let tile = dz.read_tile(9, Address { x: 1, y: 0 }).unwrap();
+    assert!(tile.pixels().all(|pixel| pixel[3] == 255));
+
+    // Transparent pixels take the background color
+    let path = Path::new("tests/artifacts/test_dz_transparent.tiff");
+    write_half_transparent_tiff(path);
+    let slide = OpenSlide::open(path).unwrap();
+    let mut dz = DeepZoom::new(&slide, 254, 0, false).unwrap();
+    dz.set_background_color(Rgb([0, 0, 255]));
+
+    let tile = dz
+        .read_tile(dz.level_count - 1, Address { x: 0, y: 0 })
+        .unwrap();
+    assert_eq!(tile.dimensions(), (32, 16));
+    assert_eq!(*tile.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    assert_eq!(*tile.get_pixel(15, 15), Rgba([0, 0, 255, 255]));
+    assert_eq!(*tile.get_pixel(16, 0), Rgba([255, 0, 0, 255]));
+}
+
+/// Write a 32x16 tiled RGBA TIFF whose left tile is fully transparent and right tile
+/// opaque red.
+fn write_half_transparent_tiff(path: &Path) {
+    const DATA_OFFSET: u32 = 8 + 2 + 12 * 12 + 4;
+    const PIXELS_OFFSET: u32 = DATA_OFFSET + 24;
+    const TILE_BYTES: u32 = 16 * 16 * 4;
+
+    let short = |tag: u16, value: u16| (tag, 3u16, 1u32, u32::from(value));
+    let entries = [
+        short(256, 32),
+        short(257, 16),
+        (258, 3, 4, DATA_OFFSET),
+        short(259, 1),
+        short(262, 2),
+        short(277, 4),
+        short(284, 1),
+        short(322, 16),
+        short(323, 16),
+        (324, 4, 2, DATA_OFFSET + 8),
+        (325, 4, 2, DATA_OFFSET + 16),
+        // Unassociated alpha
+        short(338, 2),
+    ];
+
+    let mut bytes = b"II*\0".to_vec();
+    bytes.extend(8u32.to_le_bytes());
+    bytes.extend((entries.len() as u16).to_le_bytes());
+    for (tag, field_type, count, value) in entries {
+        bytes.extend(tag.to_le_bytes());
+        bytes.extend(field_type.to_le_bytes());
+        bytes.extend(count.to_le_bytes());
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend(0u32.to_le_bytes());
+
+    for _ in 0..4 {
+        bytes.extend(8u16.to_le_bytes());
+    }
+    for offset in [PIXELS_OFFSET, PIXELS_OFFSET + TILE_BYTES] {
+        bytes.extend(offset.to_le_bytes());
+    }
+    for _ in 0..2 {
+        bytes.extend(TILE_BYTES.to_le_bytes());
+    }
+
+    bytes.extend(vec![0u8; TILE_BYTES as _]);
+    for _ in 0..16 * 16 {
+        bytes.extend([255u8, 0, 0, 255]);
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn test_resize_filter() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    assert_eq!(dz.resize_filter(), ResizeFilter::Lanczos3);
+
+    dz.set_resize_filter(ResizeFilter::Nearest);
+    let tile = dz.read_tile(7, Address { x: 0, y: 0 }).unwrap();
+    assert_eq!(tile.dimensions(), (75, 63));
+}
+
+#[test]
+fn test_get_tile_owned() {
+    let slide = Arc::new(OpenSlide::open(common::boxes_tiff()).unwrap());
+    let dz = DeepZoom::new(slide, 254, 1, false).unwrap();
+
+    let tile = thread::spawn(move || dz.read_tile(9, Address { x: 1, y: 0 }).unwrap())
+        .join()
+        .unwrap();
+
+    assert_eq!(tile.dimensions(), (47, 250));
+}
+
+#[test]
+fn test_get_tile_bytes() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let jpeg = dz
+        .tile_bytes(9, Address { x: 1, y: 0 }, Format::Jpeg { quality: 75 })
+        .unwrap();
+    let tile = image::load_from_memory(&jpeg).unwrap();
+    assert_eq!((tile.width(), tile.height()), (47, 250));
+
+    let png = dz
+        .tile_bytes(9, Address { x: 1, y: 0 }, Format::Png)
+        .unwrap();
+    assert_eq!(png[..4], [0x89, b'P', b'N', b'G']);
+}
+
 #[test]
 fn test_get_tile_default() {
     let slide = OpenSlide::open(common::default()).unwrap();
@@ -78,6 +268,30 @@ fn test_get_tile_bad_level() {
     dz.read_tile(10, Address { x: 0, y: 0 }).unwrap();
 }
 
+#[test]
+#[should_panic(expected = "Address (2, 0) out of range")]
+fn test_get_tile_bad_address() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    dz.read_tile(9, Address { x: 2, y: 0 }).unwrap();
+}
+
+#[test]
+fn test_get_tile_errors() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    assert_eq!(
+        dz.tile_region(10, Address { x: 0, y: 0 }),
+        Err(OpenSlideError::IndexError("10".to_string()))
+    );
+    assert_eq!(
+        dz.tile_region(9, Address { x: 0, y: 1 }),
+        Err(OpenSlideError::OutOfBounds("(0, 1)".to_string()))
+    );
+}
+
 #[test]
 fn test_get_tile_coordinates() {
     let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
@@ -92,10 +306,544 @@ fn test_get_tile_coordinates() {
 }
 
 #[test]
+fn test_get_tile_bounds() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let expected = TileBounds {
+        l0_region: Region {
+            address: Address { x: 253, y: 0 },
+            level: 0,
+            size: Size { w: 47, h: 250 },
+        },
+        slide_level: 0,
+        output_size: Size { w: 47, h: 250 },
+    };
+    assert_eq!(dz.tile_bounds(9, Address { x: 1, y: 0 }).unwrap(), expected);
+
+    let bounds = dz.tile_bounds(8, Address { x: 0, y: 0 }).unwrap();
+    assert_eq!(bounds.slide_level, 1);
+    assert_eq!(bounds.l0_region.size, Size { w: 300, h: 250 });
+    assert_eq!(bounds.output_size, Size { w: 150, h: 125 });
+}
+
+#[test]
+#[allow(deprecated)]
 fn test_get_tile_dimensions() {
     let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
     let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
 
     let expected = Size { w: 47, h: 250 };
     assert_eq!(dz.tile_size(9, Address { x: 1, y: 0 }).unwrap(), expected);
+    assert_eq!(
+        dz.tile_dimensions(9, Address { x: 1, y: 0 }).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_get_dzi() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let dzi = dz.dzi(Format::Jpeg { quality: 75 });
+    assert!(dzi.contains("http://schemas.microsoft.com/deepzoom/2008"));
+    assert!(dzi.contains(r#"Format="jpg" Overlap="1" TileSize="254""#));
+    assert!(dzi.contains(r#"<Size Height="250" Width="300"/>"#));
+}
+
+#[test]
+fn test_write_pyramid() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    for (name, parallelism) in [
+        ("test_pyramid_sequential", Parallelism::Sequential),
+        ("test_pyramid_threads", Parallelism::Threads(2)),
+    ] {
+        let path = Path::new("tests/artifacts").join(name);
+        let written = AtomicUsize::new(0);
+        dz.write_pyramid(&path, Format::Png, parallelism, |done, total| {
+            assert_eq!(total, 11);
+            written.fetch_max(done, Ordering::SeqCst);
+        })
+        .unwrap();
+
+        assert_eq!(written.load(Ordering::SeqCst), 11);
+        assert!(path.with_extension("dzi").is_file());
+        let files = Path::new("tests/artifacts").join(format!("{}_files", name));
+        assert!(files.join("0/0_0.png").is_file());
+        assert!(files.join("9/1_0.png").is_file());
+    }
+}
+
+#[test]
+fn test_write_pyramid_zip() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let path = Path::new("tests/artifacts/test_pyramid.zip");
+    dz.write_pyramid_zip(
+        path,
+        Format::Jpeg { quality: 75 },
+        Parallelism::Auto,
+        |_, _| {},
+    )
+    .unwrap();
+
+    let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    assert_eq!(archive.len(), 12);
+    assert!(archive.by_name("test_pyramid.dzi").is_ok());
+    assert!(archive.by_name("test_pyramid_files/9/1_0.jpg").is_ok());
+}
+
+#[test]
+fn test_prefetch() {
+    let slide = Arc::new(OpenSlide::open(common::boxes_tiff()).unwrap());
+    let dz = DeepZoom::new(slide, 254, 1, false).unwrap();
+
+    let viewport = Region {
+        address: Address { x: 0, y: 0 },
+        level: 0,
+        size: Size { w: 100, h: 100 },
+    };
+    assert_eq!(
+        dz.viewport_tiles(9, &viewport, 0).unwrap(),
+        vec![Address { x: 0, y: 0 }]
+    );
+    assert_eq!(
+        dz.viewport_tiles(9, &viewport, 1).unwrap(),
+        vec![Address { x: 0, y: 0 }, Address { x: 1, y: 0 }]
+    );
+
+    let region = |x| Region {
+        address: Address { x, y: 0 },
+        level: 0,
+        size: Size { w: 100, h: 100 },
+    };
+    dz.prefetch(9, viewport, 1).unwrap();
+    // Supersedes the running prefetch
+    dz.prefetch(9, region(200), 0).unwrap();
+    assert!(dz.prefetch(10, region(0), 0).is_err());
+}
+
+#[test]
+fn test_read_mosaic() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    // Crosses the boundary between the two tiles of the last level
+    let region = Region {
+        address: Address { x: 200, y: 100 },
+        level: 0,
+        size: Size { w: 100, h: 50 },
+    };
+    let mosaic = dz.read_mosaic(9, &region).unwrap();
+    assert_eq!(mosaic.dimensions(), (100, 50));
+
+    let direct = slide
+        .read_region(Region {
+            address: Address { x: 200, y: 100 },
+            level: 0,
+            size: Size { w: 100, h: 50 },
+        })
+        .unwrap();
+    for (mosaic_pixel, direct_pixel) in mosaic.pixels().zip(direct.pixels()) {
+        assert_eq!(mosaic_pixel[..3], direct_pixel[..3]);
+    }
+
+    // Lower levels are assembled at their own resolution
+    assert_eq!(dz.read_mosaic(8, &region).unwrap().dimensions(), (50, 25));
+
+    // Outside of the image: background only
+    let outside = dz
+        .read_mosaic(
+            9,
+            &Region {
+                address: Address { x: 1000, y: 1000 },
+                level: 0,
+                size: Size { w: 10, h: 10 },
+            },
+        )
+        .unwrap();
+    assert!(outside
+        .pixels()
+        .all(|pixel| pixel.0 == [255, 255, 255, 255]));
+}
+
+#[test]
+fn test_read_mosaic_errors() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+    let region = Region {
+        address: Address { x: 0, y: 0 },
+        level: 1,
+        size: Size { w: 10, h: 10 },
+    };
+
+    assert_eq!(
+        dz.read_mosaic(10, &region),
+        Err(OpenSlideError::IndexError("10".to_string()))
+    );
+    assert!(matches!(
+        dz.read_mosaic(9, &region),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_scale_factor() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    let dz = DeepZoom::with_scale_factor(&slide, 254, 1, false, 4.0).unwrap();
+    assert_eq!(dz.scale_factor(), 4.0);
+    assert_eq!(dz.level_count(), 6);
+    assert_eq!(
+        dz.level_dimensions(),
+        &[
+            Size { w: 1, h: 1 },
+            Size { w: 2, h: 1 },
+            Size { w: 5, h: 4 },
+            Size { w: 19, h: 16 },
+            Size { w: 75, h: 63 },
+            Size { w: 300, h: 250 },
+        ]
+    );
+    assert_eq!(dz.level_info(4).unwrap().downsample, 4.0);
+    assert_eq!(dz.level_info(0).unwrap().downsample, 1024.0);
+    assert_eq!(
+        dz.read_tile(4, Address { x: 0, y: 0 })
+            .unwrap()
+            .dimensions(),
+        (75, 63)
+    );
+
+    let dz = DeepZoom::with_scale_factor(&slide, 254, 1, false, 1.5).unwrap();
+    assert_eq!(
+        dz.level_dimensions()[dz.level_count() - 2],
+        Size { w: 200, h: 167 }
+    );
+    // Sides of 2 still shrink with factors below 2
+    assert_eq!(dz.level_count(), 15);
+    assert_eq!(dz.level_dimensions()[0], Size { w: 1, h: 1 });
+    assert_eq!(dz.level_dimensions()[1], Size { w: 2, h: 2 });
+
+    assert!(matches!(
+        DeepZoom::with_scale_factor(&slide, 254, 1, false, 1.0),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert_eq!(
+        DeepZoom::new(&slide, 254, 1, false).unwrap().scale_factor(),
+        2.0
+    );
+}
+
+#[test]
+fn test_level_grid() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let grid = dz.level_grid(9).unwrap();
+    assert_eq!(grid.dimensions(), Size { w: 300, h: 250 });
+    assert_eq!(grid.tiles(), Size { w: 2, h: 1 });
+    assert_eq!(
+        grid.tile_dimensions(Address { x: 1, y: 0 }).unwrap(),
+        dz.tile_dimensions(9, Address { x: 1, y: 0 }).unwrap()
+    );
+    assert!(dz.level_grid(10).is_none());
+
+    assert!(matches!(
+        DeepZoom::new(&slide, 0, 1, false),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_background_filter() {
+    let filter = BackgroundFilter::default();
+    let white = RgbaImage::from_pixel(8, 8, Rgba([250, 250, 250, 255]));
+    assert!(filter.is_background(&white));
+
+    let mut pink = white.clone();
+    for pixel in pink.pixels_mut() {
+        *pixel = Rgba([230, 150, 200, 255]);
+    }
+    assert!(!filter.is_background(&pink));
+
+    let mut checkerboard = white;
+    for (x, y, pixel) in checkerboard.enumerate_pixels_mut() {
+        if (x + y) % 2 == 0 {
+            *pixel = Rgba([0, 0, 0, 255]);
+        }
+    }
+    assert!(!filter.is_background(&checkerboard));
+}
+
+#[test]
+fn test_write_pyramid_background() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    // Everything is background with permissive thresholds
+    dz.set_background_filter(Some(BackgroundFilter {
+        max_saturation: 1.0,
+        max_std_dev: 255.0,
+        mode: BackgroundTiles::Shared,
+    }));
+    let path = Path::new("tests/artifacts/test_pyramid_background");
+    dz.write_pyramid(path, Format::Png, Parallelism::Auto, |_, _| {})
+        .unwrap();
+
+    let files = Path::new("tests/artifacts/test_pyramid_background_files");
+    assert!(!files.join("9/0_0.png").exists());
+    let blank_tiles: HashMap<String, String> =
+        serde_json::from_slice(&std::fs::read(files.join("blank_tiles.json")).unwrap()).unwrap();
+    assert_eq!(blank_tiles.len(), 11);
+
+    // Each blank tile has the size of the tiles it replaces
+    assert_eq!(blank_tiles["9/0_0"], "blank_255x250.png");
+    assert_eq!(blank_tiles["9/1_0"], "blank_47x250.png");
+    assert_eq!(blank_tiles["0/0_0"], "blank_1x1.png");
+    for (tile, blank) in &blank_tiles {
+        let (level, address) = tile.split_once('/').unwrap();
+        let (x, y) = address.split_once('_').unwrap();
+        let size = dz
+            .tile_dimensions(
+                level.parse().unwrap(),
+                Address {
+                    x: x.parse().unwrap(),
+                    y: y.parse().unwrap(),
+                },
+            )
+            .unwrap();
+        let image = image::open(files.join(blank)).unwrap();
+        assert_eq!((image.width(), image.height()), (size.w, size.h));
+    }
+
+    dz.set_background_filter(Some(BackgroundFilter {
+        max_saturation: 0.0,
+        max_std_dev: 0.0,
+        mode: BackgroundTiles::Skip,
+    }));
+    assert_eq!(dz.background_filter().unwrap().mode, BackgroundTiles::Skip);
+}
+
+#[test]
+fn test_write_pyramid_deduplicate() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+    assert!(!dz.deduplicate());
+
+    let path = Path::new("tests/artifacts/test_pyramid_stats");
+    let stats = dz
+        .write_pyramid(path, Format::Png, Parallelism::Auto, |_, _| {})
+        .unwrap();
+    assert_eq!(stats.tiles, 11);
+    assert_eq!(stats.background, 0);
+    assert_eq!(stats.duplicates, 0);
+    assert_eq!(stats.bytes_saved, 0);
+    assert!(stats.bytes_written > 0);
+
+    dz.set_deduplicate(true);
+    let path = Path::new("tests/artifacts/test_pyramid_dedup.zip");
+    let dedup = dz
+        .write_pyramid_zip(path, Format::Png, Parallelism::Threads(2), |_, _| {})
+        .unwrap();
+    assert_eq!(dedup.tiles, 11);
+    assert_eq!(dedup.bytes_written + dedup.bytes_saved, stats.bytes_written);
+
+    // Duplicates are not stored in the archive, but listed in the manifest
+    let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let manifest = usize::from(dedup.duplicates > 0);
+    assert_eq!(archive.len(), 1 + 11 - dedup.duplicates + manifest);
+    if dedup.duplicates > 0 {
+        let mut json = String::new();
+        archive
+            .by_name("test_pyramid_dedup_files/duplicates.json")
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        let duplicates: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(duplicates.len(), dedup.duplicates);
+    }
+}
+
+#[test]
+fn test_tile_hook() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+    let original = dz.read_tile(9, Address { x: 1, y: 0 }).unwrap();
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    dz.set_tile_hook(move |tile, level, address| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        if level == 9 && address == (Address { x: 1, y: 0 }) {
+            for pixel in tile.pixels_mut() {
+                *pixel = Rgba([255, 0, 0, 255]);
+            }
+        }
+    });
+    assert!(dz.has_tile_hook());
+
+    let tile = dz.read_tile(9, Address { x: 1, y: 0 }).unwrap();
+    assert_eq!(tile.dimensions(), original.dimensions());
+    assert!(tile.pixels().all(|pixel| *pixel == Rgba([255, 0, 0, 255])));
+    assert_ne!(dz.read_tile(9, Address { x: 0, y: 0 }).unwrap(), tile);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Exports see the processed tiles
+    dz.write_pyramid_zip(
+        Path::new("tests/artifacts/test_pyramid_hook.zip"),
+        Format::Png,
+        Parallelism::Auto,
+        |_, _| {},
+    )
+    .unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2 + 11);
+
+    dz.clear_tile_hook();
+    assert!(!dz.has_tile_hook());
+    assert_eq!(dz.read_tile(9, Address { x: 1, y: 0 }).unwrap(), original);
+}
+
+#[test]
+fn test_describe() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let info = dz.describe();
+    assert_eq!(info.tile_size, 254);
+    assert_eq!(info.overlap, 1);
+    assert_eq!(info.scale_factor, 2.0);
+    assert_eq!(info.l0_offset, Address { x: 0, y: 0 });
+    assert_eq!(info.tile_count, 11);
+    assert_eq!(info.levels, dz.levels());
+    assert_eq!(info.slide_level_dimensions[0], Size { w: 300, h: 250 });
+    assert_eq!(info.slide_level_downsamples[0], 1.0);
+    assert!(info.quickhash.is_some());
+
+    let json = serde_json::to_string(&info).unwrap();
+    assert!(json.contains(r#""dimensions":{"h":250,"w":300}"#));
+    let parsed: PyramidInfo = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, info);
+}
+
+#[test]
+fn test_with_base_mpp() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let mpp: f32 = slide
+        .property("openslide.mpp-x")
+        .unwrap()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let dimensions = slide.dimensions().unwrap();
+
+    let dz = DeepZoom::with_base_mpp(&slide, 2.0 * mpp, 254, 1).unwrap();
+    assert!((dz.base_downsample() - 2.0).abs() < 0.01);
+    let largest = dz.level_dimensions()[dz.level_count() - 1];
+    assert!((largest.w as i64 - (dimensions.w as i64 + 1) / 2).abs() <= 1);
+    assert!((largest.h as i64 - (dimensions.h as i64 + 1) / 2).abs() <= 1);
+    let level = dz.level_info(dz.level_count() - 1).unwrap();
+    assert!((level.downsample - 2.0).abs() < 0.01);
+
+    let address = Address { x: 1, y: 1 };
+    let tile = dz.read_tile(dz.level_count() - 1, address).unwrap();
+    assert_eq!(tile.dimensions(), (256, 256));
+
+    // Finer than the slide: level 0 is upsampled
+    let dz = DeepZoom::with_base_mpp(&slide, mpp / 2.0, 254, 1).unwrap();
+    let largest = dz.level_dimensions()[dz.level_count() - 1];
+    assert!((largest.w as i64 - dimensions.w as i64 * 2).abs() <= 1);
+
+    assert!(matches!(
+        DeepZoom::with_base_mpp(&slide, 0.0, 254, 1),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+
+    let boxes = OpenSlide::open(common::boxes_tiff()).unwrap();
+    assert!(matches!(
+        DeepZoom::with_base_mpp(&boxes, 0.5, 254, 1),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert_eq!(
+        DeepZoom::new(&boxes, 254, 1, false)
+            .unwrap()
+            .base_downsample(),
+        1.0
+    );
+}
+
+#[cfg(feature = "color")]
+#[test]
+fn test_srgb_conversion() {
+    for path in [common::boxes_tiff(), common::small_svs()] {
+        let slide = OpenSlide::open(path).unwrap();
+        let mut dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+        assert!(!dz.srgb_conversion());
+
+        let level = dz.level_count() - 1;
+        let original = dz.read_tile(level, Address { x: 0, y: 0 }).unwrap();
+        match slide.icc_profile().unwrap() {
+            Some(_) => {
+                dz.set_srgb_conversion(true).unwrap();
+                assert!(dz.srgb_conversion());
+                let tile = dz.read_tile(level, Address { x: 0, y: 0 }).unwrap();
+                assert_eq!(tile.dimensions(), original.dimensions());
+            }
+            None => assert!(matches!(
+                dz.set_srgb_conversion(true),
+                Err(OpenSlideError::InvalidArgument(_))
+            )),
+        }
+
+        dz.set_srgb_conversion(false).unwrap();
+        assert_eq!(
+            dz.read_tile(level, Address { x: 0, y: 0 }).unwrap(),
+            original
+        );
+    }
+}
+
+#[test]
+fn test_tile_order() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut dz = DeepZoom::new(&slide, 100, 1, false).unwrap();
+    assert_eq!(dz.tile_order(), TileOrder::RowMajor);
+
+    let row_major: Vec<Address> = dz.tile_addresses(9, TileOrder::RowMajor).unwrap().collect();
+    assert_eq!(row_major.len(), 9);
+    assert_eq!(row_major[1], Address { x: 1, y: 0 });
+    let column_major: Vec<Address> = dz
+        .tile_addresses(9, TileOrder::ColumnMajor)
+        .unwrap()
+        .collect();
+    assert_eq!(column_major[1], Address { x: 0, y: 1 });
+    let hilbert: Vec<Address> = dz.tile_addresses(9, TileOrder::Hilbert).unwrap().collect();
+    assert_eq!(
+        &hilbert[..3],
+        &[
+            Address { x: 0, y: 0 },
+            Address { x: 1, y: 0 },
+            Address { x: 1, y: 1 },
+        ]
+    );
+    assert!(matches!(
+        dz.tile_addresses(10, TileOrder::Hilbert),
+        Err(OpenSlideError::IndexError(_))
+    ));
+
+    dz.set_tile_order(TileOrder::Hilbert);
+    let path = Path::new("tests/artifacts/test_pyramid_hilbert");
+    let stats = dz
+        .write_pyramid(path, Format::Png, Parallelism::Sequential, |_, _| {})
+        .unwrap();
+    assert_eq!(stats.tiles as u64, dz.tile_count());
+    let files = Path::new("tests/artifacts/test_pyramid_hilbert_files");
+    for address in hilbert {
+        assert!(files
+            .join(format!("9/{}_{}.png", address.x, address.y))
+            .is_file());
+    }
 }
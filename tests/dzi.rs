@@ -0,0 +1,147 @@
+use openslide_rs::{
+    Address, DeepZoom, DziDescriptor, Format, OpenSlide, OpenSlideError, Parallelism, Region, Size,
+};
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_parse_generated() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let descriptor: DziDescriptor = dz.dzi(Format::Jpeg { quality: 75 }).parse().unwrap();
+    assert_eq!(
+        descriptor,
+        DziDescriptor {
+            format: "jpg".to_string(),
+            overlap: 1,
+            tile_size: 254,
+            size: Size { w: 300, h: 250 },
+            url: None,
+        }
+    );
+    assert_eq!(descriptor.level_count(), dz.level_count());
+    assert_eq!(descriptor.level_dimensions(), dz.level_dimensions());
+    for level in 0..dz.level_count() {
+        assert_eq!(
+            descriptor.level_grid(level).unwrap(),
+            dz.level_grid(level).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_open() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let dz = DeepZoom::new(&slide, 254, 1, false).unwrap();
+
+    let path = Path::new("tests/artifacts/test_dzi");
+    dz.write_pyramid(path, Format::Png, Parallelism::Auto, |_, _| {})
+        .unwrap();
+    let descriptor = DziDescriptor::open(&path.with_extension("dzi")).unwrap();
+
+    let tile = descriptor.tile_path("test_dzi", 9, Address { x: 1, y: 0 });
+    assert_eq!(tile, "test_dzi_files/9/1_0.png");
+    assert!(Path::new("tests/artifacts").join(tile).is_file());
+}
+
+#[test]
+fn test_parse_json() {
+    let json = r#"{"Image": {
+        "xmlns": "http://schemas.microsoft.com/deepzoom/2008",
+        "Url": "http://example.com/slide_files/",
+        "Format": "jpeg",
+        "Overlap": "2",
+        "TileSize": 256,
+        "Size": {"Width": "1000", "Height": 500}
+    }}"#;
+
+    let descriptor = DziDescriptor::parse(json).unwrap();
+    assert_eq!(descriptor.format, "jpeg");
+    assert_eq!(descriptor.overlap, 2);
+    assert_eq!(descriptor.tile_size, 256);
+    assert_eq!(descriptor.size, Size { w: 1000, h: 500 });
+    assert_eq!(
+        descriptor.url.as_deref(),
+        Some("http://example.com/slide_files/")
+    );
+    assert_eq!(descriptor.level_count(), 11);
+}
+
+#[test]
+fn test_parse_xml_variants() {
+    let xml = concat!(
+        "<?xml version='1.0'?>\n",
+        "<Image TileSize='128' Overlap='0' Format='png'\n",
+        "       xmlns='http://schemas.microsoft.com/deepzoom/2008'>\n",
+        "  <Size Width='64' Height='64' />\n",
+        "</Image>"
+    );
+
+    let descriptor = DziDescriptor::parse(xml).unwrap();
+    assert_eq!(descriptor.tile_size, 128);
+    assert_eq!(descriptor.size, Size { w: 64, h: 64 });
+    assert_eq!(descriptor.level_count(), 7);
+}
+
+#[test]
+fn test_parse_errors() {
+    for text in [
+        "",
+        "<Image Format=\"jpg\" Overlap=\"1\" TileSize=\"254\"></Image>",
+        "<Image Format=\"jpg\" Overlap=\"-1\" TileSize=\"254\"><Size Width=\"1\" Height=\"1\"/></Image>",
+        "<Image Format=jpg><Size Width=\"1\" Height=\"1\"/></Image>",
+        "{\"Image\": {\"Format\": \"jpg\"}}",
+        "{not json",
+    ] {
+        assert!(
+            matches!(
+                DziDescriptor::parse(text),
+                Err(OpenSlideError::InvalidArgument(_))
+            ),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_tile_region() {
+    let descriptor = DziDescriptor {
+        format: "jpg".to_string(),
+        overlap: 1,
+        tile_size: 254,
+        size: Size { w: 300, h: 250 },
+        url: None,
+    };
+
+    // Largest level: the tile region itself
+    assert_eq!(
+        descriptor.tile_region(9, Address { x: 1, y: 0 }).unwrap(),
+        Region {
+            address: Address { x: 253, y: 0 },
+            level: 0,
+            size: Size { w: 47, h: 250 },
+        }
+    );
+    // Smaller level: scaled back, clipped to the image
+    assert_eq!(
+        descriptor.tile_region(8, Address { x: 0, y: 0 }).unwrap(),
+        Region {
+            address: Address { x: 0, y: 0 },
+            level: 0,
+            size: Size { w: 300, h: 250 },
+        }
+    );
+
+    assert_eq!(
+        descriptor.tile_region(10, Address { x: 0, y: 0 }),
+        Err(OpenSlideError::IndexError("10".to_string()))
+    );
+    assert_eq!(
+        descriptor.tile_region(9, Address { x: 2, y: 0 }),
+        Err(OpenSlideError::OutOfBounds("(2, 0)".to_string()))
+    );
+}
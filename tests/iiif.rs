@@ -0,0 +1,260 @@
+use openslide_rs::iiif::{
+    info_json, render, render_encoded, ImageRequest, Quality, RegionRequest, Rotation, SizeLimits,
+    SizeRequest,
+};
+use openslide_rs::{Address, Format, OpenSlide, OpenSlideError, Size};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_parse() {
+    let request = ImageRequest::parse("pct:10,20,30,40/^!100,50/!90/gray.png").unwrap();
+    assert_eq!(
+        request.region,
+        RegionRequest::Percent {
+            x: 10.,
+            y: 20.,
+            w: 30.,
+            h: 40.
+        }
+    );
+    assert_eq!(
+        request.size,
+        SizeRequest::BestFit {
+            w: 100,
+            h: 50,
+            upscale: true
+        }
+    );
+    assert_eq!(
+        request.rotation,
+        Rotation {
+            mirror: true,
+            degrees: 90
+        }
+    );
+    assert_eq!(request.quality, Quality::Gray);
+    assert_eq!(request.format, Format::Png);
+
+    let request = ImageRequest::parse("/0,0,128,128/64,/0/default.jpg").unwrap();
+    assert_eq!(
+        request.region,
+        RegionRequest::Pixels {
+            x: 0,
+            y: 0,
+            w: 128,
+            h: 128
+        }
+    );
+    assert_eq!(
+        request.size,
+        SizeRequest::Width {
+            w: 64,
+            upscale: false
+        }
+    );
+    assert_eq!(request.format, Format::Jpeg { quality: 75 });
+
+    assert_eq!(
+        ImageRequest::parse("square/,32/180/bitonal.webp")
+            .unwrap()
+            .size,
+        SizeRequest::Height {
+            h: 32,
+            upscale: false
+        }
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    for path in [
+        "full/max/0",
+        "full/max/0/default",
+        "full/max/0/default.tif",
+        "full/max/45/default.jpg",
+        "full/max/0/sepia.jpg",
+        "0,0,10/max/0/default.jpg",
+        "full/!10,/0/default.jpg",
+        "full/,/0/default.jpg",
+    ] {
+        assert!(
+            matches!(
+                ImageRequest::parse(path),
+                Err(OpenSlideError::InvalidArgument(_))
+            ),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn test_resolve() {
+    let dimensions = Size { w: 300, h: 250 };
+
+    assert_eq!(
+        RegionRequest::Square.resolve(dimensions).unwrap(),
+        (Address { x: 25, y: 0 }, Size { w: 250, h: 250 })
+    );
+    assert_eq!(
+        RegionRequest::Pixels {
+            x: 200,
+            y: 200,
+            w: 200,
+            h: 200
+        }
+        .resolve(dimensions)
+        .unwrap(),
+        (Address { x: 200, y: 200 }, Size { w: 100, h: 50 })
+    );
+    assert_eq!(
+        RegionRequest::Pixels {
+            x: 300,
+            y: 0,
+            w: 10,
+            h: 10
+        }
+        .resolve(dimensions),
+        Err(OpenSlideError::OutOfBounds("(300, 0)".to_string()))
+    );
+
+    assert_eq!(
+        SizeRequest::BestFit {
+            w: 100,
+            h: 100,
+            upscale: false
+        }
+        .resolve(dimensions, SizeLimits::default())
+        .unwrap(),
+        Size { w: 100, h: 83 }
+    );
+    assert_eq!(
+        SizeRequest::Percent {
+            pct: 50.,
+            upscale: false
+        }
+        .resolve(dimensions, SizeLimits::default())
+        .unwrap(),
+        Size { w: 150, h: 125 }
+    );
+    assert!(SizeRequest::Width {
+        w: 600,
+        upscale: false
+    }
+    .resolve(dimensions, SizeLimits::default())
+    .is_err());
+    assert_eq!(
+        SizeRequest::Width {
+            w: 600,
+            upscale: true
+        }
+        .resolve(dimensions, SizeLimits::default())
+        .unwrap(),
+        Size { w: 600, h: 500 }
+    );
+}
+
+#[test]
+fn test_render() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    let request = ImageRequest::parse("full/150,/0/default.png").unwrap();
+    assert_eq!(
+        render(&slide, &request, SizeLimits::default())
+            .unwrap()
+            .dimensions(),
+        (150, 125)
+    );
+
+    let request = ImageRequest::parse("0,0,100,50/max/90/gray.png").unwrap();
+    let image = render(&slide, &request, SizeLimits::default()).unwrap();
+    assert_eq!(image.dimensions(), (50, 100));
+    assert!(image.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+
+    let request = ImageRequest::parse("full/max/0/default.jpg").unwrap();
+    let bytes = render_encoded(&slide, &request, SizeLimits::default()).unwrap();
+    assert_eq!(&bytes[..2], &[0xff, 0xd8]);
+}
+
+#[test]
+fn test_info_json() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let info: serde_json::Value = serde_json::from_str(
+        &info_json(
+            &slide,
+            "https://example.org/iiif/boxes",
+            128,
+            SizeLimits::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(info["id"], "https://example.org/iiif/boxes");
+    assert_eq!(info["type"], "ImageService3");
+    assert_eq!(info["width"], 300);
+    assert_eq!(info["height"], 250);
+    assert_eq!(
+        info["tiles"][0]["scaleFactors"],
+        serde_json::json!([1, 2, 4])
+    );
+}
+
+#[test]
+fn test_size_limits() {
+    let limits = SizeLimits {
+        max_width: 200,
+        max_area: 100 * 100,
+        max_read_area: 1000,
+    };
+
+    // max fits in the limits, upscaling only with ^
+    let max = |upscale, dimensions| {
+        SizeRequest::Max { upscale }
+            .resolve(dimensions, limits)
+            .unwrap()
+    };
+    assert_eq!(max(false, Size { w: 300, h: 250 }), Size { w: 109, h: 91 });
+    assert_eq!(max(false, Size { w: 50, h: 40 }), Size { w: 50, h: 40 });
+    assert_eq!(max(true, Size { w: 50, h: 40 }), Size { w: 111, h: 89 });
+
+    assert!(SizeRequest::Width {
+        w: 150,
+        upscale: false
+    }
+    .resolve(Size { w: 300, h: 250 }, limits)
+    .is_err());
+    assert!(SizeRequest::Exact {
+        w: 250,
+        h: 10,
+        upscale: false
+    }
+    .resolve(Size { w: 300, h: 250 }, limits)
+    .is_err());
+
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let request = ImageRequest::parse("full/max/0/default.png").unwrap();
+    assert_eq!(
+        render(&slide, &request, SizeLimits::default())
+            .unwrap()
+            .dimensions(),
+        (300, 250)
+    );
+    // The smallest level has more pixels than the read limit
+    let request = ImageRequest::parse("full/10,/0/default.png").unwrap();
+    assert!(matches!(
+        render(&slide, &request, limits),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+
+    let info: serde_json::Value =
+        serde_json::from_str(&info_json(&slide, "boxes", 128, limits).unwrap()).unwrap();
+    assert_eq!(info["maxWidth"], 200);
+    assert_eq!(info["maxArea"], 10000);
+    let sizes = info["sizes"].as_array().unwrap();
+    assert!(sizes
+        .iter()
+        .all(|size| size["width"].as_u64().unwrap() <= 100));
+}
@@ -0,0 +1,103 @@
+use openslide_rs::jobs::{load_jobs, run_jobs, JobOptions, RegionJob};
+use openslide_rs::{Address, OpenSlide, Parallelism, Region, Size};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[allow(dead_code)]
+mod common;
+
+fn job(slide: &Path, level: Option<u32>, mpp: Option<f32>, output: &str) -> RegionJob {
+    RegionJob {
+        slide: slide.to_path_buf(),
+        level,
+        mpp,
+        x: 100,
+        y: 50,
+        w: 64,
+        h: 32,
+        output: PathBuf::from(format!("tests/artifacts/test_run_jobs/{}", output)),
+    }
+}
+
+#[test]
+fn test_run_jobs() {
+    let jobs = vec![
+        job(common::boxes_tiff(), Some(1), None, "level.png"),
+        job(common::small_svs(), None, Some(1.), "mpp.jpg"),
+        job(common::boxes_tiff(), Some(1), Some(1.), "both.png"),
+        job(common::missing_file(), None, None, "missing.png"),
+        job(common::boxes_tiff(), None, None, "unknown.bmp"),
+    ];
+    let done = AtomicUsize::new(0);
+    let report = run_jobs(&jobs, JobOptions::default(), |_, total| {
+        assert_eq!(total, 5);
+        done.fetch_add(1, Ordering::SeqCst);
+    })
+    .unwrap();
+    assert_eq!(done.into_inner(), 5);
+    assert_eq!(report.outcomes.len(), 5);
+    assert_eq!(report.succeeded(), 2);
+    assert_eq!(report.failed().count(), 3);
+
+    // Invalid jobs and missing slides are not retried
+    for outcome in report.failed() {
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(outcome.bytes_written, 0);
+    }
+
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let expected = slide
+        .read_region(Region {
+            address: Address { x: 100, y: 50 },
+            level: 1,
+            size: Size { w: 64, h: 32 },
+        })
+        .unwrap();
+    let written = image::open(&jobs[0].output).unwrap().to_rgba8();
+    assert_eq!(written, expected);
+    assert_eq!(
+        report.outcomes[0].bytes_written,
+        fs::metadata(&jobs[0].output).unwrap().len()
+    );
+
+    let resized = image::open(&jobs[1].output).unwrap();
+    assert_eq!((resized.width(), resized.height()), (64, 32));
+    assert_eq!(
+        report.bytes_written(),
+        report.outcomes[0].bytes_written + report.outcomes[1].bytes_written
+    );
+}
+
+#[test]
+fn test_load_jobs() {
+    let jobs = vec![
+        job(common::boxes_tiff(), Some(1), None, "a.png"),
+        job(common::small_svs(), None, Some(0.5), "b.webp"),
+    ];
+
+    let json = Path::new("tests/artifacts/test_load_jobs.json");
+    fs::write(json, serde_json::to_string(&jobs).unwrap()).unwrap();
+    assert_eq!(load_jobs(json).unwrap(), jobs);
+
+    let csv = Path::new("tests/artifacts/test_load_jobs.csv");
+    fs::write(
+        csv,
+        "slide,level,mpp,x,y,w,h,output\n\
+         tests/assets/boxes.tiff,1,,100,50,64,32,tests/artifacts/test_run_jobs/a.png\n\
+         tests/assets/small.svs,,0.5,100,50,64,32,tests/artifacts/test_run_jobs/b.webp\n",
+    )
+    .unwrap();
+    assert_eq!(load_jobs(csv).unwrap(), jobs);
+
+    let report = run_jobs(
+        &jobs,
+        JobOptions {
+            parallelism: Parallelism::Sequential,
+            ..JobOptions::default()
+        },
+        |_, _| {},
+    )
+    .unwrap();
+    assert_eq!(report.succeeded(), 2);
+}
@@ -0,0 +1,60 @@
+use openslide_rs::{export_level_memmap, Address, OpenSlide, Parallelism, Region, Size};
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_export_level_memmap() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let path = Path::new("tests/artifacts/test_export_level_memmap.npy");
+
+    let exported = export_level_memmap(&slide, 1, path, Parallelism::Auto).unwrap();
+    let dimensions = slide.level_dimensions(1).unwrap();
+    assert_eq!(exported.dimensions(), dimensions);
+    let view = exported.view();
+    assert_eq!(
+        view.dim(),
+        (dimensions.h as usize, dimensions.w as usize, 3)
+    );
+
+    let level = slide
+        .read_region(Region {
+            address: Address { x: 0, y: 0 },
+            level: 1,
+            size: dimensions,
+        })
+        .unwrap();
+    for (x, y, pixel) in level.enumerate_pixels() {
+        for channel in 0..3 {
+            assert_eq!(view[[y as usize, x as usize, channel]], pixel[channel]);
+        }
+    }
+
+    let offset = exported.offset();
+    assert_eq!(offset % 64, 0);
+    let mmap = exported.into_mmap();
+    assert_eq!(&mmap[..6], b"\x93NUMPY");
+    assert_eq!(
+        mmap.len() - offset,
+        (dimensions.w * dimensions.h * 3) as usize
+    );
+    assert_eq!(
+        mmap.len() % 64,
+        (dimensions.w * dimensions.h * 3) as usize % 64
+    );
+}
+
+#[test]
+fn test_export_level_memmap_raw() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let path = Path::new("tests/artifacts/test_export_level_memmap.raw");
+
+    let exported = export_level_memmap(&slide, 0, path, Parallelism::Sequential).unwrap();
+    let Size { w, h } = slide.dimensions().unwrap();
+    assert_eq!(exported.offset(), 0);
+    assert_eq!(exported.into_mmap().len(), (w * h * 3) as usize);
+    assert_eq!(std::fs::metadata(path).unwrap().len(), u64::from(w * h * 3));
+
+    assert!(export_level_memmap(&slide, 10, path, Parallelism::Sequential).is_err());
+}
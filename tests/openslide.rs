@@ -1,4 +1,5 @@
-use openslide_rs::{Address, OpenSlide, Region, Size};
+use openslide_rs::tissue::TissueParams;
+use openslide_rs::{Address, Cache, Format, OpenSlide, OpenSlideError, Region, ResizeFilter, Size};
 use std::path::Path;
 
 #[allow(dead_code)]
@@ -80,6 +81,62 @@ fn test_properties() {
     );
 }
 
+#[test]
+fn test_background_color() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    assert_eq!(
+        slide.background_color().unwrap(),
+        image::Rgb([255, 255, 255])
+    );
+}
+
+#[test]
+fn test_mpp_magnification() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    assert_eq!(slide.mpp().unwrap(), None);
+    assert_eq!(slide.magnification().unwrap(), None);
+
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let property = |name: &str| -> f32 { slide.property(name).unwrap().unwrap().parse().unwrap() };
+    assert_eq!(
+        slide.mpp().unwrap(),
+        Some((property("openslide.mpp-x"), property("openslide.mpp-y")))
+    );
+    let objective_power = slide
+        .property("openslide.objective-power")
+        .unwrap()
+        .and_then(|power| power.parse::<f32>().ok());
+    assert_eq!(slide.magnification().unwrap(), objective_power);
+}
+
+#[test]
+fn test_slide_properties() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    assert_eq!(slide.bounds().unwrap(), None);
+
+    let properties = slide.slide_properties().unwrap();
+    assert_eq!(properties.vendor.as_deref(), Some("generic-tiff"));
+    assert_eq!(
+        properties.quickhash,
+        slide.property("openslide.quickhash-1").unwrap()
+    );
+    assert_eq!(properties.mpp, None);
+    assert_eq!(properties.objective_power, None);
+    assert_eq!(properties.bounds, None);
+    assert_eq!(properties.levels.len(), 4);
+    assert_eq!(properties.levels[1].dimensions, Size { w: 150, h: 125 });
+    assert_eq!(properties.levels[1].downsample, 2.);
+    let tile_width = slide
+        .property("openslide.level[0].tile-width")
+        .unwrap()
+        .map(|width| width.parse::<u32>().unwrap());
+    assert_eq!(
+        properties.levels[0].tile_size.map(|size| size.w),
+        tile_width
+    );
+}
+
 #[test]
 fn test_read_region() {
     let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
@@ -97,6 +154,122 @@ fn test_read_region() {
         .unwrap();
 }
 
+#[test]
+fn test_set_cache() {
+    let mut slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mut other = OpenSlide::open(common::small_svs()).unwrap();
+    let region = || Region {
+        address: Address { x: 0, y: 0 },
+        level: 0,
+        size: Size { w: 64, h: 64 },
+    };
+    let before = slide.read_region(region()).unwrap();
+
+    let cache = Cache::new(1024 * 1024);
+    assert_eq!(cache.capacity(), 1024 * 1024);
+    slide.set_cache(&cache).unwrap();
+    other.set_cache(&cache).unwrap();
+    // The slides keep the cache alive
+    drop(cache);
+
+    assert_eq!(slide.read_region(region()).unwrap(), before);
+    slide.set_cache_size(0).unwrap();
+    assert_eq!(slide.read_region(region()).unwrap(), before);
+}
+
+#[test]
+fn test_read_region_into() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let region = || Region {
+        address: Address { x: 10, y: 20 },
+        level: 1,
+        size: Size { w: 100, h: 50 },
+    };
+
+    let mut buffer = vec![0; 100 * 50 * 4];
+    slide.read_region_into(region(), &mut buffer).unwrap();
+    assert_eq!(buffer, slide.read_region(region()).unwrap().into_raw());
+}
+
+#[test]
+fn test_read_region_raw() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let region = || Region {
+        address: Address { x: 10, y: 20 },
+        level: 1,
+        size: Size { w: 100, h: 50 },
+    };
+
+    let raw = slide.read_region_raw(region()).unwrap();
+    assert_eq!(raw.len(), 100 * 50);
+    let image = slide.read_region(region()).unwrap();
+    for (argb, pixel) in raw.iter().zip(image.pixels()) {
+        // Opaque pixels need no unpremultiplying
+        if argb >> 24 == 255 {
+            let [a, r, g, b] = argb.to_be_bytes();
+            assert_eq!(pixel.0, [r, g, b, a]);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "Buffer of 12 bytes cannot hold a 100x50 region")]
+fn test_read_region_into_bad_size() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    slide
+        .read_region_into(
+            Region {
+                address: Address { x: 0, y: 0 },
+                level: 0,
+                size: Size { w: 100, h: 50 },
+            },
+            &mut [0; 12],
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_read_region_encoded() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let region = || Region {
+        address: Address { x: 0, y: 0 },
+        level: 1,
+        size: Size { w: 100, h: 50 },
+    };
+
+    let jpeg = slide
+        .read_region_encoded(region(), Format::Jpeg { quality: 80 })
+        .unwrap();
+    assert_eq!(jpeg[..2], [0xFF, 0xD8]);
+    assert_eq!(image::load_from_memory(&jpeg).unwrap().width(), 100);
+
+    let png = slide.read_region_encoded(region(), Format::Png).unwrap();
+    assert_eq!(png[..4], [0x89, b'P', b'N', b'G']);
+
+    let webp = slide
+        .read_region_encoded(region(), Format::Webp { quality: 80 })
+        .unwrap();
+    assert_eq!(webp[8..12], *b"WEBP");
+}
+
+#[test]
+#[should_panic(expected = "Quality 0 is not in the 1-100 range")]
+fn test_read_region_encoded_bad_quality() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    slide
+        .read_region_encoded(
+            Region {
+                address: Address { x: 0, y: 0 },
+                level: 0,
+                size: Size { w: 16, h: 16 },
+            },
+            Format::Jpeg { quality: 0 },
+        )
+        .unwrap();
+}
+
 #[test]
 fn test_thumbnail() {
     let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
@@ -109,6 +282,158 @@ fn test_thumbnail() {
         .unwrap();
 }
 
+#[test]
+fn test_thumbnail_with_filter() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let size = Size { w: 100, h: 100 };
+
+    let nearest = slide
+        .thumbnail_with_filter(size, ResizeFilter::Nearest)
+        .unwrap();
+    assert_eq!(nearest.dimensions(), (100, 83));
+    assert_eq!(
+        slide
+            .thumbnail_with_filter(size, ResizeFilter::Lanczos3)
+            .unwrap(),
+        slide.thumbnail(size).unwrap()
+    );
+}
+
+#[test]
+fn test_content_bounds() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    // Every pixel is tissue
+    let params = TissueParams {
+        threshold: Some(255),
+        ..TissueParams::default()
+    };
+    assert_eq!(
+        slide.content_bounds(params).unwrap(),
+        Region {
+            address: Address { x: 0, y: 0 },
+            level: 0,
+            size: Size { w: 300, h: 250 },
+        }
+    );
+
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let dimensions = slide.dimensions().unwrap();
+    let bounds = slide.content_bounds(TissueParams::default()).unwrap();
+    assert_eq!(bounds.level, 0);
+    assert!(bounds.address.x + bounds.size.w <= dimensions.w);
+    assert!(bounds.address.y + bounds.size.h <= dimensions.h);
+}
+
+#[test]
+fn test_read_region_with_coverage() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    // The region extends past the bottom right corner of the slide
+    let region = || Region {
+        address: Address { x: 250, y: 200 },
+        level: 0,
+        size: Size { w: 100, h: 100 },
+    };
+    let (pixels, coverage) = slide.read_region_with_coverage(region()).unwrap();
+    assert_eq!(pixels.dimensions(), (100, 100));
+    assert_eq!(coverage.dimensions(), (100, 100));
+    assert_eq!(coverage.get_pixel(0, 0).0, [255]);
+    assert_eq!(coverage.get_pixel(49, 49).0, [255]);
+    assert_eq!(coverage.get_pixel(50, 0).0, [0]);
+    assert_eq!(coverage.get_pixel(0, 50).0, [0]);
+
+    // Pixels without data are the opaque background, the others are unchanged
+    assert_eq!(pixels.get_pixel(99, 99).0, [255, 255, 255, 255]);
+    let direct = slide.read_region(region()).unwrap();
+    assert_eq!(pixels.get_pixel(10, 10), direct.get_pixel(10, 10));
+}
+
+#[test]
+fn test_windows() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    let windows: Vec<(Address, Region)> = slide.windows(0, 100, 100).unwrap().collect();
+    assert_eq!(windows.len(), 9);
+    // The last row is aligned to the bottom edge
+    let (address, region) = &windows[8];
+    assert_eq!(*address, Address { x: 200, y: 150 });
+    assert_eq!(
+        *region,
+        Region {
+            address: Address { x: 200, y: 150 },
+            level: 0,
+            size: Size { w: 100, h: 100 },
+        }
+    );
+
+    // Regions are in level 0 coordinates
+    let windows: Vec<(Address, Region)> = slide.windows(1, 100, 100).unwrap().collect();
+    assert_eq!(windows.len(), 4);
+    assert_eq!(windows[3].0, Address { x: 50, y: 25 });
+    assert_eq!(windows[3].1.address, Address { x: 100, y: 50 });
+    assert_eq!(windows[3].1.level, 1);
+
+    // A level smaller than the window gives a single window
+    assert_eq!(slide.windows(3, 100, 10).unwrap().count(), 1);
+
+    assert!(matches!(
+        slide.windows(0, 0, 10),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(slide.windows(10, 100, 100).is_err());
+}
+
+#[test]
+fn test_read_windows() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    let windows: Vec<(Address, _)> = slide
+        .read_windows(0, 100, 50)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(windows.len(), 5 * 4);
+    let (address, pixels) = &windows[1];
+    assert_eq!(*address, Address { x: 50, y: 0 });
+    assert_eq!(pixels.dimensions(), (100, 100));
+    let expected = slide
+        .read_region(Region {
+            address: Address { x: 50, y: 0 },
+            level: 0,
+            size: Size { w: 100, h: 100 },
+        })
+        .unwrap();
+    assert_eq!(*pixels, expected);
+}
+
+#[test]
+fn test_downscale_to() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    // The size of a level gives the level itself
+    let level = slide
+        .read_region(Region {
+            address: Address { x: 0, y: 0 },
+            level: 1,
+            size: Size { w: 150, h: 125 },
+        })
+        .unwrap();
+    for filter in [ResizeFilter::Triangle, ResizeFilter::Lanczos3] {
+        assert_eq!(slide.downscale_to(150, 125, filter).unwrap(), level);
+    }
+
+    let overview = slide.downscale_to(64, 40, ResizeFilter::Lanczos3).unwrap();
+    assert_eq!(overview.dimensions(), (64, 40));
+    let nearest = slide.downscale_to(3000, 10, ResizeFilter::Nearest).unwrap();
+    assert_eq!(nearest.dimensions(), (3000, 10));
+
+    assert!(matches!(
+        slide.downscale_to(0, 10, ResizeFilter::Nearest),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
 #[test]
 fn test_associated_images() {
     let slide = OpenSlide::open(common::small_svs()).unwrap();
@@ -122,6 +447,15 @@ fn test_associated_images() {
         (16, 16)
     );
     assert!(slide.associated_image("__missing").unwrap().is_none());
+
+    assert_eq!(
+        slide.associated_image_dimensions("thumbnail").unwrap(),
+        Some(Size { w: 16, h: 16 })
+    );
+    assert!(slide
+        .associated_image_dimensions("__missing")
+        .unwrap()
+        .is_none());
 }
 
 #[test]
@@ -138,9 +472,43 @@ fn test_read_bad_region() {
         .unwrap();
 }
 
+#[test]
+fn test_error_reopen() {
+    let slide = OpenSlide::open(common::unreadable_svs()).unwrap();
+    let region = || Region {
+        address: Address { x: 0, y: 0 },
+        level: 0,
+        size: Size { w: 16, h: 16 },
+    };
+    assert_eq!(slide.error(), None);
+
+    assert!(slide.read_region(region()).is_err());
+    let error = slide.error().unwrap();
+    // The handle stays in error
+    match slide.level_count() {
+        Err(OpenSlideError::InternalError(message)) => assert_eq!(message, error),
+        result => panic!("Unexpected {:?}", result),
+    }
+
+    let slide = slide.reopen().unwrap();
+    assert_eq!(slide.error(), None);
+    assert!(slide.level_count().is_ok());
+}
+
 #[test]
 #[should_panic(expected = "TIFFRGBAImageGet failed")]
 fn test_read_bad_associated_image() {
     let slide = OpenSlide::open(common::unreadable_svs()).unwrap();
     slide.associated_image("thumbnail").unwrap();
 }
+
+#[test]
+fn test_icc_profile() {
+    for path in [common::boxes_tiff(), common::small_svs()] {
+        let slide = OpenSlide::open(path).unwrap();
+        if let Some(profile) = slide.icc_profile().unwrap() {
+            // ICC header signature
+            assert_eq!(&profile[36..40], b"acsp");
+        }
+    }
+}
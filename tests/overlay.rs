@@ -0,0 +1,112 @@
+use ndarray::Array2;
+use openslide_rs::overlay::{render, Colormap};
+use openslide_rs::{Address, OpenSlide, OpenSlideError, Region, Size};
+
+#[allow(dead_code)]
+mod common;
+
+fn whole_slide(level: usize, size: Size) -> Region {
+    Region {
+        address: Address { x: 0, y: 0 },
+        level,
+        size,
+    }
+}
+
+#[test]
+fn test_render() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let size = Size { w: 150, h: 125 };
+    let heatmap = Array2::from_elem((5, 6), 1f32);
+
+    // An opaque heatmap hides the slide
+    let image = render(
+        &slide,
+        whole_slide(1, size),
+        heatmap.view(),
+        Colormap::Gray,
+        1.,
+    )
+    .unwrap();
+    assert_eq!(image.dimensions(), (150, 125));
+    assert!(image.pixels().all(|pixel| pixel.0 == [255, 255, 255, 255]));
+
+    // A transparent one only composites the slide over white
+    let slide_pixels = slide.read_region(whole_slide(1, size)).unwrap();
+    let image = render(
+        &slide,
+        whole_slide(1, size),
+        heatmap.view(),
+        Colormap::Jet,
+        0.,
+    )
+    .unwrap();
+    for (rendered, original) in image.pixels().zip(slide_pixels.pixels()) {
+        if original.0[3] == 255 {
+            assert_eq!(rendered, original);
+        }
+    }
+
+    // Missing values leave the slide visible
+    let missing = Array2::from_elem((5, 6), f32::NAN);
+    assert_eq!(
+        render(
+            &slide,
+            whole_slide(1, size),
+            missing.view(),
+            Colormap::Gray,
+            1.
+        )
+        .unwrap(),
+        render(
+            &slide,
+            whole_slide(1, size),
+            heatmap.view(),
+            Colormap::Gray,
+            0.
+        )
+        .unwrap()
+    );
+
+    image
+        .save(std::path::Path::new("tests/artifacts/test_overlay.png"))
+        .unwrap();
+}
+
+#[test]
+fn test_render_errors() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let size = Size { w: 10, h: 10 };
+    let heatmap = Array2::from_elem((5, 6), 0.5f32);
+
+    assert!(matches!(
+        render(
+            &slide,
+            whole_slide(0, size),
+            Array2::<f32>::zeros((0, 6)).view(),
+            Colormap::Viridis,
+            0.5
+        ),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        render(
+            &slide,
+            whole_slide(0, size),
+            heatmap.view(),
+            Colormap::Viridis,
+            2.
+        ),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        render(
+            &slide,
+            whole_slide(10, size),
+            heatmap.view(),
+            Colormap::Viridis,
+            0.5
+        ),
+        Err(OpenSlideError::IndexError(_))
+    ));
+}
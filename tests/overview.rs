@@ -0,0 +1,36 @@
+use openslide_rs::overview::{overview, GRID_COLOR};
+use openslide_rs::{DeepZoom, OpenSlide, OpenSlideError};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_overview() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    let mut rendered = overview(&slide, 100).unwrap();
+    assert_eq!(rendered.image.dimensions(), (100, 83));
+    assert!((rendered.scale.0 - 1. / 3.).abs() < 1e-9);
+    assert_eq!(
+        rendered.tissue_bounds,
+        slide.content_bounds(Default::default()).unwrap()
+    );
+
+    // The largest Deep Zoom level has 2 x 1 tiles of 254 pixels
+    let deepzoom = DeepZoom::new(&slide, 254, 1, false).unwrap();
+    let level = deepzoom.level_count() - 1;
+    rendered.draw_grid(&deepzoom, level).unwrap();
+    for (x, y) in [(0, 40), (85, 40), (99, 40), (50, 0), (50, 82)] {
+        assert_eq!(*rendered.image.get_pixel(x, y), GRID_COLOR, "{} {}", x, y);
+    }
+    assert_ne!(*rendered.image.get_pixel(40, 40), GRID_COLOR);
+
+    assert!(matches!(
+        rendered.draw_grid(&deepzoom, level + 1),
+        Err(OpenSlideError::IndexError(_))
+    ));
+    assert!(matches!(
+        overview(&slide, 0),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
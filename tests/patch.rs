@@ -0,0 +1,336 @@
+use image::{GrayImage, Luma};
+use openslide_rs::tissue::TISSUE;
+use openslide_rs::{
+    read_context_patches, Address, AnnotationSet, OpenSlide, OpenSlideError, Parallelism,
+    PatchLoader, PatchSampler, Point, Polygon, Region, Size,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_grid() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 100, None, 0.5).unwrap();
+    assert_eq!(sampler.len(), 6);
+    assert_eq!(sampler.patch_size(), 100);
+    assert_eq!(sampler.downsample(), 1.0);
+
+    let patch = sampler.patches()[4];
+    assert_eq!(patch.address, Address { x: 100, y: 100 });
+    assert_eq!(patch.level, 0);
+    assert_eq!(patch.size, Size { w: 100, h: 100 });
+    assert_eq!(patch.tissue_fraction, 1.0);
+
+    // Overlapping patches
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 100, 50, None, 0.5).unwrap();
+    assert_eq!(sampler.len(), 5 * 4);
+}
+
+#[test]
+fn test_grid_tissue_fraction() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    // Only the left third of the slide is tissue
+    let mask = GrayImage::from_fn(30, 25, |x, _| Luma([if x < 10 { TISSUE } else { 0 }]));
+
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 1.0).unwrap();
+    assert_eq!(sampler.len(), 2 * 5);
+    assert!(sampler.patches().iter().all(|patch| patch.address.x < 100));
+
+    let all = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.0).unwrap();
+    assert_eq!(all.len(), 6 * 5);
+}
+
+#[test]
+fn test_restrict_to_annotations() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(1, 1, Luma([TISSUE]));
+    let annotations = AnnotationSet::open_geojson(common::annotations_geojson()).unwrap();
+    let tumor = annotations.with_classifications(&["Tumor"]);
+    assert_eq!(tumor.len(), 1);
+
+    // Only the patch inside the tumor square, around its hole corner, is covered enough
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.)
+        .unwrap()
+        .restrict_to_annotations(&tumor, 0.9, false)
+        .unwrap();
+    assert_eq!(sampler.len(), 1);
+    let patch = sampler.patches()[0];
+    assert_eq!(patch.address, Address { x: 50, y: 50 });
+    let overlap = patch.annotation_fraction.unwrap();
+    assert!(overlap > 0.9 && overlap < 1., "{}", overlap);
+
+    // A zero overlap only records it
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.)
+        .unwrap()
+        .restrict_to_annotations(&tumor, 0., false)
+        .unwrap();
+    assert_eq!(sampler.len(), 6 * 5);
+    assert_eq!(sampler.patches()[5].annotation_fraction, Some(0.));
+
+    // Arbitrary polygons
+    let square = Polygon {
+        exterior: vec![
+            Point { x: 200., y: 0. },
+            Point { x: 300., y: 0. },
+            Point { x: 300., y: 100. },
+            Point { x: 200., y: 100. },
+        ],
+        holes: vec![],
+    };
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.)
+        .unwrap()
+        .restrict_to_annotations(&AnnotationSet::from(vec![square]), 1., false)
+        .unwrap();
+    assert_eq!(sampler.len(), 4);
+    assert!(sampler
+        .patches()
+        .iter()
+        .all(|patch| patch.annotation_fraction == Some(1.)));
+
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.).unwrap();
+    assert!(matches!(
+        sampler.restrict_to_annotations(&tumor, 1.5, false),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_grid_target_mpp() {
+    let slide = Arc::new(OpenSlide::open(common::small_svs()).unwrap());
+    let mpp: f32 = slide
+        .property("openslide.mpp-x")
+        .unwrap()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mask = GrayImage::from_pixel(1, 1, Luma([TISSUE]));
+
+    let sampler =
+        PatchSampler::grid_with_mask(slide.clone(), &mask, 64, 64, Some(mpp * 4.), 0.).unwrap();
+    assert!((sampler.downsample() - 4.).abs() < 0.1);
+    assert!(!sampler.is_empty());
+
+    let patch = sampler.patches()[1];
+    assert!((patch.address.x as f64 - 64. * sampler.downsample()).abs() <= 1.);
+    let pixels = sampler.read_patch(&patch).unwrap();
+    assert_eq!(pixels.dimensions(), (64, 64));
+
+    let (first, pixels) = sampler.iter_pixels().next().unwrap().unwrap();
+    assert_eq!(first, sampler.patches()[0]);
+    assert_eq!(pixels.dimensions(), (64, 64));
+}
+
+#[test]
+fn test_read_context_patches() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let mpp: f32 = slide
+        .property("openslide.mpp-x")
+        .unwrap()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let dimensions = slide.dimensions().unwrap();
+    let center = Address {
+        x: dimensions.w / 2,
+        y: dimensions.h / 2,
+    };
+    let scales = [(64, mpp), (32, mpp * 4.)];
+
+    let patches = read_context_patches(&slide, center, &scales, Parallelism::Sequential).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].dimensions(), (64, 64));
+    assert_eq!(patches[1].dimensions(), (32, 32));
+    let region = slide
+        .read_region(Region {
+            address: Address {
+                x: center.x - 32,
+                y: center.y - 32,
+            },
+            level: 0,
+            size: Size { w: 64, h: 64 },
+        })
+        .unwrap();
+    assert_eq!(patches[0], region);
+    assert_eq!(
+        read_context_patches(&slide, center, &scales, Parallelism::Threads(2)).unwrap(),
+        patches
+    );
+
+    // The parts beyond the slide edge are transparent
+    let corner = read_context_patches(
+        &slide,
+        Address { x: 0, y: 0 },
+        &[(64, mpp)],
+        Parallelism::Auto,
+    )
+    .unwrap();
+    let origin = slide
+        .read_region(Region {
+            address: Address { x: 0, y: 0 },
+            level: 0,
+            size: Size { w: 32, h: 32 },
+        })
+        .unwrap();
+    assert_eq!(corner[0].get_pixel(0, 0).0[3], 0);
+    assert_eq!(corner[0].get_pixel(40, 40), origin.get_pixel(8, 8));
+}
+
+#[test]
+fn test_read_context_patches_errors() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let center = Address { x: 100, y: 100 };
+    for scales in [[(0, 0.5)], [(64, 0.)], [(64, f32::NAN)]] {
+        assert!(matches!(
+            read_context_patches(&slide, center, &scales, Parallelism::Sequential),
+            Err(OpenSlideError::InvalidArgument(_))
+        ));
+    }
+
+    // The slide resolution is unknown
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    assert!(matches!(
+        read_context_patches(&slide, center, &[(64, 0.5)], Parallelism::Sequential),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_grid_tissue_mask() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let sampler = PatchSampler::grid(&slide, 256, 256, None, 0.0).unwrap();
+    let dimensions = slide.dimensions().unwrap();
+    assert_eq!(
+        sampler.len(),
+        ((dimensions.w / 256) * (dimensions.h / 256)) as usize
+    );
+    assert!(sampler
+        .patches()
+        .iter()
+        .all(|patch| (0.0..=1.0).contains(&patch.tissue_fraction)));
+}
+
+#[test]
+fn test_grid_errors() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+    let invalid = |result: Result<PatchSampler<&OpenSlide>, OpenSlideError>| {
+        matches!(result, Err(OpenSlideError::InvalidArgument(_)))
+    };
+
+    assert!(invalid(PatchSampler::grid_with_mask(
+        &slide, &mask, 0, 10, None, 0.
+    )));
+    assert!(invalid(PatchSampler::grid_with_mask(
+        &slide, &mask, 10, 0, None, 0.
+    )));
+    assert!(invalid(PatchSampler::grid_with_mask(
+        &slide, &mask, 10, 10, None, 1.5
+    )));
+    assert!(invalid(PatchSampler::grid_with_mask(
+        &slide,
+        &GrayImage::new(0, 0),
+        10,
+        10,
+        None,
+        0.
+    )));
+    // boxes.tiff has no resolution
+    assert!(invalid(PatchSampler::grid_with_mask(
+        &slide,
+        &mask,
+        10,
+        10,
+        Some(0.5),
+        0.
+    )));
+    assert!(invalid(PatchSampler::grid_with_mask(
+        &slide,
+        &mask,
+        10,
+        10,
+        Some(-1.),
+        0.
+    )));
+}
+
+#[test]
+fn test_shard() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.).unwrap();
+
+    let shards: Vec<_> = (0..3)
+        .map(|index| sampler.clone().shard(index, 3).unwrap())
+        .collect();
+    assert_eq!(
+        shards.iter().map(|shard| shard.len()).sum::<usize>(),
+        sampler.len()
+    );
+    assert_eq!(shards[1].patches()[0], sampler.patches()[1]);
+    assert_eq!(shards[1].patches()[1], sampler.patches()[4]);
+    assert_eq!(sampler.clone().shard(0, 1).unwrap().len(), sampler.len());
+
+    assert!(matches!(
+        sampler.clone().shard(3, 3),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        sampler.shard(0, 0),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_loader() {
+    let slide = Arc::new(OpenSlide::open(common::boxes_tiff()).unwrap());
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(slide, &mask, 50, 25, None, 0.).unwrap();
+
+    let loader = sampler.loader(3, 2).unwrap();
+    let mut addresses = HashSet::new();
+    for result in loader {
+        let (patch, pixels) = result.unwrap();
+        assert_eq!(pixels.dimensions(), (50, 50));
+        assert_eq!(pixels, sampler.read_patch(&patch).unwrap());
+        assert!(addresses.insert((patch.address.x, patch.address.y)));
+    }
+    assert_eq!(addresses.len(), sampler.len());
+}
+
+#[test]
+fn test_loader_handles() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 10, 10, None, 0.).unwrap();
+
+    let handles = (0..2)
+        .map(|_| Box::new(OpenSlide::open(common::boxes_tiff()).unwrap()))
+        .collect();
+    let loader = PatchLoader::with_handles(&sampler, handles, 1).unwrap();
+    assert_eq!(loader.count(), sampler.len());
+
+    assert!(matches!(
+        PatchLoader::with_handles(&sampler, Vec::<Box<OpenSlide>>::new(), 1),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_loader_shutdown() {
+    let slide = Arc::new(OpenSlide::open(common::boxes_tiff()).unwrap());
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(slide, &mask, 10, 1, None, 0.).unwrap();
+
+    // Threads blocked on the full queue are released
+    let mut loader = sampler.loader(4, 1).unwrap();
+    assert!(loader.next().unwrap().is_ok());
+    loader.shutdown();
+
+    let loader = sampler.loader(2, 0).unwrap();
+    drop(loader);
+}
@@ -0,0 +1,52 @@
+use openslide_rs::{OpenSlideError, SlidePool};
+use std::path::Path;
+use std::sync::Arc;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_slide_pool() {
+    let pool = SlidePool::new(1);
+    assert_eq!(pool.capacity(), 1);
+    assert!(pool.is_empty());
+
+    let boxes = pool.get(common::boxes_tiff()).unwrap();
+    assert_eq!(boxes.dimensions().unwrap().w, 300);
+    assert!(Arc::ptr_eq(
+        &boxes,
+        &pool.get(common::boxes_tiff()).unwrap()
+    ));
+    assert!(pool.contains(common::boxes_tiff()));
+
+    // The least recently used slide is closed
+    pool.get(common::small_svs()).unwrap();
+    assert_eq!(pool.len(), 1);
+    assert!(!pool.contains(common::boxes_tiff()));
+    assert!(!Arc::ptr_eq(
+        &boxes,
+        &pool.get(common::boxes_tiff()).unwrap()
+    ));
+
+    pool.remove(common::boxes_tiff());
+    assert!(pool.is_empty());
+    pool.get(common::boxes_tiff()).unwrap();
+    pool.clear();
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn test_slide_pool_errors() {
+    let pool = SlidePool::new(0);
+    assert_eq!(pool.capacity(), 1);
+
+    assert!(matches!(
+        pool.get(Path::new("missing.tiff")),
+        Err(OpenSlideError::MissingFile(_))
+    ));
+    assert!(matches!(
+        pool.get(common::unopenable_tiff()),
+        Err(OpenSlideError::InternalError(_))
+    ));
+    assert!(pool.is_empty());
+}
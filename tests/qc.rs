@@ -0,0 +1,22 @@
+use openslide_rs::qc::{analyze, analyze_region};
+use openslide_rs::{Address, OpenSlide, Region, Size};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_analyze_region() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let region = || Region {
+        address: Address { x: 0, y: 0 },
+        level: 0,
+        size: Size { w: 100, h: 100 },
+    };
+
+    let report = analyze_region(&slide, region()).unwrap();
+    assert_eq!(report, analyze(&slide.read_region(region()).unwrap()));
+    assert!(report.sharpness >= 0.);
+    for value in [report.brightness, report.saturation, report.pen_fraction] {
+        assert!((0. ..=1.).contains(&value), "{}", value);
+    }
+}
@@ -0,0 +1,289 @@
+#![cfg(feature = "server")]
+
+use axum::body::Body;
+use axum::http::request::Builder;
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::Router;
+use openslide_rs::server::{
+    router, router_with_authorizer, Decision, ServerConfig, SlideSource, SlideStore,
+};
+use openslide_rs::OpenSlide;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+#[allow(dead_code)]
+mod common;
+
+fn boxes_router() -> Router {
+    let mut slides = HashMap::new();
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    slides.insert("boxes".to_string(), Arc::new(slide));
+
+    router(
+        slides,
+        ServerConfig {
+            tile_size: 254,
+            overlap: 1,
+            limit_bounds: false,
+            ..ServerConfig::default()
+        },
+    )
+}
+
+async fn get(router: Router, uri: &str) -> (StatusCode, Option<String>, Vec<u8>) {
+    let (status, headers, body) = send(router, Request::builder().uri(uri)).await;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .map(|v| v.to_str().unwrap().to_string());
+    (status, content_type, body)
+}
+
+async fn send(router: Router, request: Builder) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let response = router
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, headers, body.to_vec())
+}
+
+#[tokio::test]
+async fn test_dzi() {
+    let (status, content_type, body) = get(boxes_router(), "/slide/boxes.dzi").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("application/xml"));
+    assert!(String::from_utf8(body)
+        .unwrap()
+        .contains(r#"<Size Height="250" Width="300"/>"#));
+}
+
+#[tokio::test]
+async fn test_tile() {
+    let (status, content_type, body) = get(boxes_router(), "/slide/boxes_files/9/1_0.jpg").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("image/jpeg"));
+    assert_eq!(&body[..2], &[0xff, 0xd8]);
+}
+
+#[tokio::test]
+async fn test_tile_encoding_query() {
+    let router = boxes_router();
+
+    let (status, content_type, body) = get(
+        router.clone(),
+        "/slide/boxes_files/9/1_0.jpg?format=webp&quality=50",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("image/webp"));
+    assert_eq!(&body[..4], b"RIFF");
+
+    let (status, content_type, _) =
+        get(router.clone(), "/slide/boxes_files/9/1_0.png?format=png").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("image/png"));
+
+    // Lower quality, smaller tiles and a different ETag
+    let (_, high, high_body) = send(
+        router.clone(),
+        Request::builder().uri("/slide/boxes_files/9/0_0.jpg?quality=95"),
+    )
+    .await;
+    let (status, low, low_body) = send(
+        router.clone(),
+        Request::builder().uri("/slide/boxes_files/9/0_0.jpg?quality=5"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(low_body.len() < high_body.len());
+    assert_ne!(low[header::ETAG], high[header::ETAG]);
+
+    for uri in [
+        "/slide/boxes_files/9/0_0.jpg?quality=0",
+        "/slide/boxes_files/9/0_0.jpg?quality=101",
+        "/slide/boxes_files/9/0_0.jpg?quality=high",
+        "/slide/boxes_files/9/0_0.jpg?format=gif",
+    ] {
+        let (status, _, _) = get(router.clone(), uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_tile_caching() {
+    let router = boxes_router();
+    let uri = "/slide/boxes_files/9/0_0.jpg";
+
+    let (status, headers, _) = send(router.clone(), Request::builder().uri(uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=86400");
+    let etag = headers[header::ETAG].to_str().unwrap().to_string();
+    assert!(etag.starts_with('"') && etag.ends_with("-9-0-0\""));
+
+    let (_, other, _) = send(
+        router.clone(),
+        Request::builder().uri("/slide/boxes_files/9/1_0.jpg"),
+    )
+    .await;
+    assert_ne!(other[header::ETAG], etag.as_str());
+
+    let (status, headers, body) = send(
+        router.clone(),
+        Request::builder()
+            .uri(uri)
+            .header(header::IF_NONE_MATCH, etag.as_str()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(headers[header::ETAG], etag.as_str());
+    assert!(body.is_empty());
+
+    let (status, _, body) = get(router, "/metrics").await;
+    assert_eq!(status, StatusCode::OK);
+    let metrics = String::from_utf8(body).unwrap();
+    assert!(metrics.contains("openslide_tiles_served_total 2\n"));
+    assert!(metrics.contains("openslide_tiles_not_modified_total 1\n"));
+    assert!(metrics.contains("openslide_tile_duration_seconds_count 3\n"));
+    assert!(metrics.contains("openslide_tile_cache_hit_ratio 0.3333"));
+}
+
+#[tokio::test]
+async fn test_not_found() {
+    for uri in [
+        "/slide/missing.dzi",
+        "/slide/boxes",
+        "/slide/boxes_files/9/1_0.png",
+        "/slide/boxes_files/9/2_0.jpg",
+        "/slide/boxes_files/10/0_0.jpg",
+        "/slide/boxes/9/0_0.jpg",
+    ] {
+        let (status, _, _) = get(boxes_router(), uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+#[test]
+fn test_slide_store() {
+    let store = SlideStore::new(1);
+    store.insert("boxes", common::boxes_tiff());
+    store.insert("small", common::small_svs());
+    assert_eq!(store.ids(), vec!["boxes", "small"]);
+    assert_eq!(store.open_count(), 0);
+
+    let boxes = store.get("boxes").unwrap().unwrap();
+    assert_eq!(boxes.dimensions().unwrap().w, 300);
+    assert!(Arc::ptr_eq(&boxes, &store.get("boxes").unwrap().unwrap()));
+
+    // The least recently used slide is closed
+    store.get("small").unwrap().unwrap();
+    assert_eq!(store.open_count(), 1);
+    assert!(!Arc::ptr_eq(&boxes, &store.get("boxes").unwrap().unwrap()));
+
+    store.remove("small");
+    assert!(store.get("small").unwrap().is_none());
+    assert!(store.get("missing").unwrap().is_none());
+}
+
+#[test]
+fn test_slide_store_directory() {
+    let directory = Path::new("tests/artifacts/slide_store");
+    let _ = fs::remove_dir_all(directory);
+    fs::create_dir_all(directory).unwrap();
+    fs::copy(common::boxes_tiff(), directory.join("boxes.tiff")).unwrap();
+    fs::write(directory.join("notes.txt"), "not a slide").unwrap();
+
+    let store = SlideStore::from_directory(directory, 4).unwrap();
+    assert_eq!(store.ids(), vec!["boxes"]);
+    assert!(store.get("boxes").unwrap().is_some());
+
+    // New files are picked up by the next refresh, not by requests
+    fs::copy(common::small_svs(), directory.join("small.svs")).unwrap();
+    assert!(store.get("small").unwrap().is_none());
+    store.refresh().unwrap();
+    assert!(store.get("small").unwrap().is_some());
+    assert_eq!(store.ids(), vec!["boxes", "small"]);
+
+    fs::remove_file(directory.join("boxes.tiff")).unwrap();
+    store.refresh().unwrap();
+    assert_eq!(store.ids(), vec!["small"]);
+    assert!(store.get("boxes").unwrap().is_none());
+
+    // Deleted files stop being served on their next request
+    let store = SlideStore::from_directory(directory, 4).unwrap();
+    fs::remove_file(directory.join("small.svs")).unwrap();
+    assert!(store.get("small").unwrap().is_none());
+    assert!(store.ids().is_empty());
+}
+
+#[tokio::test]
+async fn test_router_with_store() {
+    let store = SlideStore::new(4);
+    store.insert("boxes", common::boxes_tiff());
+
+    let (status, _, _) = get(router(store, ServerConfig::default()), "/slide/boxes.dzi").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_router_replaced_slide() {
+    let store = Arc::new(SlideStore::new(4));
+    store.insert("slide", common::boxes_tiff());
+    let router = router(store.clone(), ServerConfig::default());
+
+    let (status, _, boxes) = get(router.clone(), "/slide/slide.dzi").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(get(router.clone(), "/slide/slide.dzi").await.2, boxes);
+
+    // The generator is rebuilt for the new handle
+    store.insert("slide", common::small_svs());
+    let (status, _, small) = get(router, "/slide/slide.dzi").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(small, boxes);
+}
+
+#[tokio::test]
+async fn test_authorizer() {
+    let mut slides = HashMap::new();
+    let slide = Arc::new(OpenSlide::open(common::boxes_tiff()).unwrap());
+    slides.insert("boxes".to_string(), slide.clone());
+    slides.insert("private".to_string(), slide);
+
+    let authorizer = |request: &Request<()>, slide_id: &str| match request
+        .headers()
+        .get(header::AUTHORIZATION)
+    {
+        None => Decision::Unauthenticated,
+        Some(_) if slide_id == "private" => Decision::Deny,
+        Some(_) => Decision::Allow,
+    };
+    let router = router_with_authorizer(slides, ServerConfig::default(), authorizer);
+
+    for uri in ["/slide/boxes.dzi", "/slide/boxes_files/9/0_0.jpg"] {
+        let (status, _, _) = get(router.clone(), uri).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::AUTHORIZATION, "Bearer token");
+        let (status, _, _) = send(router.clone(), request).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+
+        let request = Request::builder()
+            .uri(uri.replace("boxes", "private"))
+            .header(header::AUTHORIZATION, "Bearer token");
+        let (status, _, _) = send(router.clone(), request).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", uri);
+    }
+
+    // Denied before the slide is looked up
+    let (status, _, _) = get(router, "/slide/missing.dzi").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
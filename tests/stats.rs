@@ -0,0 +1,41 @@
+use image::{GrayImage, Luma};
+use openslide_rs::stats::{tile_stats, StatsAccumulator};
+use openslide_rs::tissue::TISSUE;
+use openslide_rs::{OpenSlide, PatchSampler};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_tile_stats() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let mask = GrayImage::from_pixel(30, 25, Luma([TISSUE]));
+    let sampler = PatchSampler::grid_with_mask(&slide, &mask, 50, 50, None, 0.).unwrap();
+
+    let stats = tile_stats(
+        sampler
+            .iter_pixels()
+            .map(|result| result.map(|(_, pixels)| pixels)),
+        true,
+    )
+    .unwrap();
+    assert!(stats.count > 0);
+    for (mean, std) in stats.mean.iter().zip(&stats.std) {
+        assert!((0. ..=1.).contains(mean), "{}", mean);
+        assert!((0. ..=0.5).contains(std), "{}", std);
+    }
+    let histograms = stats.histograms.unwrap();
+    assert_eq!(histograms.len(), 3);
+    for histogram in &histograms {
+        assert_eq!(histogram.len(), 256);
+        assert_eq!(histogram.iter().sum::<u64>(), stats.count);
+    }
+
+    // Accumulating patch by patch gives the same statistics
+    let mut accumulator = StatsAccumulator::new(false);
+    for patch in sampler.patches() {
+        accumulator.add(&sampler.read_patch(patch).unwrap());
+    }
+    assert_eq!(accumulator.count(), stats.count);
+    assert_eq!(accumulator.stats().mean, stats.mean);
+}
@@ -0,0 +1,36 @@
+use openslide_rs::tiling::tiling_report;
+use openslide_rs::{DeepZoom, Format, OpenSlide};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_tiling_report() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let report = tiling_report(&slide, 254).unwrap();
+    let deepzoom = DeepZoom::new(&slide, 254, 0, false).unwrap();
+
+    assert_eq!(report.tile_size, 254);
+    assert_eq!(report.levels.len(), deepzoom.level_count());
+    assert_eq!(report.tiles(), deepzoom.tile_count());
+    assert_eq!(report.tiles(), report.tissue_tiles() + report.empty_tiles());
+
+    for level in &report.levels {
+        assert_eq!(level.dimensions, deepzoom.level_dimensions()[level.level]);
+        assert!(level.sampled_tiles <= 16);
+        assert!(level.tissue_tiles <= level.tiles);
+        assert_eq!(level.formats.len(), 3);
+        for estimate in &level.formats {
+            assert!(estimate.bytes > 0);
+            assert!(estimate.tissue_bytes <= estimate.bytes);
+        }
+    }
+
+    // The smallest levels are read whole: their estimates are exact
+    let smallest = &report.levels[0];
+    assert_eq!(smallest.sampled_tiles, smallest.tiles);
+    assert!(report.bytes(Format::Png).unwrap() > 0);
+    assert_eq!(report.bytes(Format::Jpeg { quality: 10 }), None);
+
+    assert!(tiling_report(&slide, 0).is_err());
+}
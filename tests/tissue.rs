@@ -0,0 +1,57 @@
+use openslide_rs::tissue::{tissue_mask, TissueParams, BACKGROUND, TISSUE};
+use openslide_rs::{OpenSlide, OpenSlideError, Size};
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_tissue_mask() {
+    let slide = OpenSlide::open(common::small_svs()).unwrap();
+    let thumbnail = slide.thumbnail(Size { w: 256, h: 256 }).unwrap();
+
+    let mask = tissue_mask(
+        &slide,
+        TissueParams {
+            thumbnail_size: 256,
+            ..TissueParams::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(mask.dimensions(), thumbnail.dimensions());
+    assert!(mask
+        .pixels()
+        .all(|pixel| pixel.0[0] == TISSUE || pixel.0[0] == BACKGROUND));
+}
+
+#[test]
+fn test_tissue_mask_threshold() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+
+    // The thumbnail is never larger than the slide
+    let mask = tissue_mask(
+        &slide,
+        TissueParams {
+            thumbnail_size: 10_000,
+            threshold: Some(255),
+            ..TissueParams::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(mask.dimensions(), (300, 250));
+    assert!(mask.pixels().all(|pixel| pixel.0[0] == TISSUE));
+}
+
+#[test]
+fn test_tissue_mask_errors() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    assert!(matches!(
+        tissue_mask(
+            &slide,
+            TissueParams {
+                thumbnail_size: 0,
+                ..TissueParams::default()
+            }
+        ),
+        Err(OpenSlideError::InvalidArgument(_))
+    ));
+}
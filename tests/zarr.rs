@@ -0,0 +1,38 @@
+use openslide_rs::{write_ome_zarr, DirectoryStore, OpenSlide};
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_write_ome_zarr() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let root = Path::new("tests/artifacts/test_write_ome_zarr.zarr");
+    let store = DirectoryStore::new(root).unwrap();
+
+    write_ome_zarr(&slide, &store, 128).unwrap();
+
+    assert!(root.join(".zgroup").is_file());
+    assert!(root.join(".zattrs").is_file());
+    for level in 0..4 {
+        assert!(root.join(format!("{}/.zarray", level)).is_file());
+    }
+
+    // Level 0 is 300x250, so it is split into 3x2 chunks of 3 * 128 * 128 bytes.
+    let last_chunk = root.join("0/0/1/2");
+    assert_eq!(fs::metadata(&last_chunk).unwrap().len(), 3 * 128 * 128);
+    assert!(!root.join("0/0/2/0").exists());
+
+    // Exporting again resumes from the existing chunks.
+    write_ome_zarr(&slide, &store, 128).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "Chunk size must be positive")]
+fn test_write_ome_zarr_bad_chunk_size() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let store = DirectoryStore::new(Path::new("tests/artifacts/test_bad_chunk_size.zarr")).unwrap();
+
+    write_ome_zarr(&slide, &store, 0).unwrap();
+}
@@ -0,0 +1,97 @@
+use openslide_rs::{Address, Format, OpenSlide, OpenSlideError, Parallelism, Size, Zoomify};
+use std::path::Path;
+
+#[allow(dead_code)]
+mod common;
+
+#[test]
+fn test_metadata() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let zoomify = Zoomify::new(&slide, 254, false).unwrap();
+
+    assert_eq!(zoomify.tier_count(), 2);
+    assert_eq!(
+        zoomify.tier_tiles(),
+        &[Size { w: 1, h: 1 }, Size { w: 2, h: 1 }]
+    );
+    assert_eq!(
+        zoomify.tier_dimensions(),
+        &[Size { w: 150, h: 125 }, Size { w: 300, h: 250 }]
+    );
+    assert_eq!(zoomify.tile_count(), 3);
+    assert_eq!(
+        zoomify.image_properties(),
+        r#"<IMAGE_PROPERTIES WIDTH="300" HEIGHT="250" NUMTILES="3" NUMIMAGES="1" VERSION="1.8" TILESIZE="254" />"#
+    );
+}
+
+#[test]
+fn test_tile_path() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let zoomify = Zoomify::new(&slide, 254, false).unwrap();
+    let format = Format::Jpeg { quality: 75 };
+
+    assert_eq!(
+        zoomify
+            .tile_path(0, Address { x: 0, y: 0 }, format)
+            .unwrap(),
+        "TileGroup0/0-0-0.jpg"
+    );
+    assert_eq!(
+        zoomify
+            .tile_path(1, Address { x: 1, y: 0 }, format)
+            .unwrap(),
+        "TileGroup0/1-1-0.jpg"
+    );
+    assert_eq!(
+        zoomify.tile_group(2, Address { x: 0, y: 0 }),
+        Err(OpenSlideError::IndexError("2".to_string()))
+    );
+    assert_eq!(
+        zoomify.tile_group(1, Address { x: 0, y: 1 }),
+        Err(OpenSlideError::OutOfBounds("(0, 1)".to_string()))
+    );
+}
+
+#[test]
+fn test_read_tile() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let zoomify = Zoomify::new(&slide, 254, false).unwrap();
+
+    assert_eq!(
+        zoomify
+            .read_tile(0, Address { x: 0, y: 0 })
+            .unwrap()
+            .dimensions(),
+        (150, 125)
+    );
+    assert_eq!(
+        zoomify
+            .read_tile(1, Address { x: 1, y: 0 })
+            .unwrap()
+            .dimensions(),
+        (46, 250)
+    );
+}
+
+#[test]
+fn test_write_zoomify() {
+    let slide = OpenSlide::open(common::boxes_tiff()).unwrap();
+    let zoomify = Zoomify::new(&slide, 254, false).unwrap();
+
+    let dir = Path::new("tests/artifacts/test_zoomify");
+    zoomify
+        .write_zoomify(
+            dir,
+            Format::Jpeg { quality: 75 },
+            Parallelism::Auto,
+            |_, total| {
+                assert_eq!(total, 3);
+            },
+        )
+        .unwrap();
+
+    assert!(dir.join("ImageProperties.xml").is_file());
+    assert!(dir.join("TileGroup0/0-0-0.jpg").is_file());
+    assert!(dir.join("TileGroup0/1-1-0.jpg").is_file());
+}